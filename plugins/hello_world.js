@@ -1,12 +1,19 @@
+// @name        示例插件
+// @version     1.0.0
+// @description 输入 time / date / week 插入当前时间、日期、星期
+// @author      AiPinyin
+
 /**
  * AiPinyin 示例插件 — hello_world.js
  *
  * 插件 API:
- *   function on_candidates(raw, candidates)
+ *   function on_candidates(raw, candidates, app)
  *     @param raw        当前输入的拼音字母串，如 "shi"
  *     @param candidates 引擎给出的候选词数组，如 ["是","时","十",...]
+ *     @param app        前台程序的 exe 文件名，如 "Code.exe"（可按程序区分处理）
  *     @return           修改后的候选词数组（可以完全替换或追加）
  *
+ * 元数据：文件开头的 // @name / @version / @description / @author 注释行
  * 调试：console.log() 输出到控制台
  */
 
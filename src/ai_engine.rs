@@ -15,12 +15,15 @@
 
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crate::config::{AiConfig, SegmentationConfig};
 
 // 全局 jieba 实例（懒加载，只初始化一次）
 static JIEBA: OnceLock<jieba_rs::Jieba> = OnceLock::new();
 
-fn get_jieba() -> &'static jieba_rs::Jieba {
+/// jieba 分词器，首次使用时才加载（`segmentation.use_jieba = false` 时词图不会触发加载）
+pub(crate) fn get_jieba() -> &'static jieba_rs::Jieba {
     JIEBA.get_or_init(|| {
         eprintln!("[词图] jieba 初始化...");
         let j = jieba_rs::Jieba::new();
@@ -33,6 +36,9 @@ fn get_jieba() -> &'static jieba_rs::Jieba {
 // 上下文缓冲区
 // ============================================================
 
+/// 句末标点（全角、半角）与换行: 上下文到此为止
+const SENTENCE_END: &[char] = &['。', '！', '？', '.', '!', '?', '\n'];
+
 pub struct HistoryBuffer {
     buf: Vec<String>,
     capacity: usize,
@@ -51,7 +57,23 @@ impl HistoryBuffer {
         let start = self.buf.len().saturating_sub(n);
         self.buf[start..].iter().map(|s| s.as_str()).collect()
     }
-    pub fn context_string(&self) -> String { self.buf.join("") }
+    /// 撤销最近一次上屏时移除
+    pub fn pop(&mut self) -> Option<String> { self.buf.pop() }
+    /// 当前句的已上屏部分: 从最近的句末标点之后拼到末尾，作为 AI 上下文
+    pub fn current_sentence(&self) -> String {
+        let mut parts: Vec<&str> = Vec::new();
+        for text in self.buf.iter().rev() {
+            match text.rfind(SENTENCE_END) {
+                Some(i) => {
+                    let end_len = text[i..].chars().next().map_or(1, char::len_utf8);
+                    parts.push(&text[i + end_len..]);
+                    break;
+                }
+                None => parts.push(text),
+            }
+        }
+        parts.into_iter().rev().collect()
+    }
     pub fn is_empty(&self) -> bool { self.buf.is_empty() }
 }
 
@@ -59,19 +81,24 @@ impl HistoryBuffer {
 // 词表索引
 // ============================================================
 
+/// 词表索引
+///
+/// 各映射里的字和拼音都是驻留的 `Arc<str>`: 同一个字 / 拼音全表只存一份，
+/// pinyin2char 与 char2pinyin 的数万项只是指针（按 `&str` 查询即可）。
 pub struct VocabIndex {
     /// 无声调拼音 → token ID: "ni" → 21128
-    pub pinyin2id: HashMap<String, i64>,
+    pub pinyin2id: HashMap<Arc<str>, i64>,
     /// 汉字 → token ID
-    pub char2id: HashMap<String, i64>,
+    pub char2id: HashMap<Arc<str>, i64>,
     /// token ID → 汉字
-    pub id2char: HashMap<i64, String>,
+    pub id2char: HashMap<i64, Arc<str>>,
     /// 拼音 → 候选汉字列表: "ni" → ["你","尼","泥",...]
-    pub pinyin2char: HashMap<String, Vec<String>>,
+    pub pinyin2char: HashMap<Arc<str>, Vec<Arc<str>>>,
     /// 拼音 → 候选汉字 token IDs (预计算)
-    pub pinyin2char_ids: HashMap<String, Vec<i64>>,
-    /// 汉字 → 拼音 (反向映射, 用于构建 Concat 上下文)
-    pub char2pinyin: HashMap<String, String>,
+    pub pinyin2char_ids: HashMap<Arc<str>, Vec<i64>>,
+    /// 汉字 → 全部读音 (反向映射, 用于给文本标注拼音)
+    /// 多音字保留所有读音: "行" → ["hang","xing"]，由 annotate_pinyin 按词消歧
+    pub char2pinyin: HashMap<Arc<str>, Vec<Arc<str>>>,
     /// 声母 → 候选汉字 token IDs (首字母模式用)
     /// 'b' → [不的id, 把的id, 被的id, ...]
     pub initial_chars: HashMap<char, Vec<i64>>,
@@ -82,6 +109,19 @@ pub struct VocabIndex {
     pub unk_id: i64,  // [UNK] = 100
 }
 
+/// 字符串驻留池: 相同内容返回同一个 `Arc<str>`（只在加载词表时用）
+#[derive(Default)]
+struct Interner(std::collections::HashSet<Arc<str>>);
+
+impl Interner {
+    fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(existing) = self.0.get(s) { return Arc::clone(existing); }
+        let interned: Arc<str> = Arc::from(s);
+        self.0.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
 impl VocabIndex {
     fn load_from_dir(dir: &Path) -> Option<Self> {
         let py_path = dir.join("pinyin2id.json");
@@ -94,22 +134,28 @@ impl VocabIndex {
         }
 
         let ch_text = std::fs::read_to_string(&ch_path).ok()?;
+        let mut pool = Interner::default();
 
         // pinyin2id 可选 (GPT2-Chinese 不需要)
-        let pinyin2id: HashMap<String, i64> = if py_path.exists() {
+        let pinyin2id: HashMap<Arc<str>, i64> = if py_path.exists() {
             let py_text = std::fs::read_to_string(&py_path).ok()?;
-            serde_json::from_str(&py_text).ok()?
+            let raw: HashMap<String, i64> = serde_json::from_str(&py_text).ok()?;
+            raw.into_iter().map(|(py, id)| (pool.intern(&py), id)).collect()
         } else {
             eprintln!("[AI] pinyin2id.json 不存在 (GPT2-Chinese 模式)");
             HashMap::new()
         };
-        let char2id: HashMap<String, i64> = serde_json::from_str(&ch_text).ok()?;
-        let id2char: HashMap<i64, String> = char2id.iter().map(|(k, v)| (*v, k.clone())).collect();
+        let raw: HashMap<String, i64> = serde_json::from_str(&ch_text).ok()?;
+        let char2id: HashMap<Arc<str>, i64> = raw.into_iter().map(|(ch, id)| (pool.intern(&ch), id)).collect();
+        let id2char: HashMap<i64, Arc<str>> = char2id.iter().map(|(k, v)| (*v, Arc::clone(k))).collect();
 
         // 加载 pinyin2char 映射
-        let pinyin2char: HashMap<String, Vec<String>> = if p2c_path.exists() {
+        let pinyin2char: HashMap<Arc<str>, Vec<Arc<str>>> = if p2c_path.exists() {
             let p2c_text = std::fs::read_to_string(&p2c_path).ok()?;
-            serde_json::from_str(&p2c_text).ok()?
+            let raw: HashMap<String, Vec<String>> = serde_json::from_str(&p2c_text).ok()?;
+            raw.into_iter()
+                .map(|(py, chars)| (pool.intern(&py), chars.iter().map(|ch| pool.intern(ch)).collect()))
+                .collect()
         } else {
             HashMap::new()
         };
@@ -123,17 +169,21 @@ impl VocabIndex {
                 .filter(|&id| id != unk_id)
                 .collect();
             if !ids.is_empty() {
-                pinyin2char_ids.insert(py.clone(), ids);
+                pinyin2char_ids.insert(Arc::clone(py), ids);
             }
         }
 
-        // 构建 char → pinyin 反向映射 (取第一个匹配的拼音)
-        let mut char2pinyin = HashMap::new();
+        // 构建 char → pinyin 反向映射 (多音字保留全部读音, 排序保证确定性)
+        let mut char2pinyin: HashMap<Arc<str>, Vec<Arc<str>>> = HashMap::new();
         for (py, chars) in &pinyin2char {
             for ch in chars {
-                char2pinyin.entry(ch.clone()).or_insert_with(|| py.clone());
+                char2pinyin.entry(Arc::clone(ch)).or_default().push(Arc::clone(py));
             }
         }
+        for readings in char2pinyin.values_mut() {
+            readings.sort();
+            readings.dedup();
+        }
 
         let cls_id = *char2id.get("<sos>").unwrap_or(&101);
         let sep_id = *char2id.get("<eos>").unwrap_or(&102);
@@ -160,6 +210,111 @@ impl VocabIndex {
             initial_chars, cls_id, sep_id, pad_id, unk_id,
         })
     }
+
+    /// 为文本逐字标注拼音（多音字按所在词消歧，见 [`annotate_pinyin`]）
+    pub fn annotate_pinyin(&self, text: &str) -> Vec<Option<String>> {
+        let chars: Vec<char> = text.chars().collect();
+        match crate::pinyin::get_dict() {
+            Some(dict) => annotate_pinyin(&chars, &self.char2pinyin, dict),
+            None => chars.iter()
+                .map(|&c| self.char2pinyin.get(c.encode_utf8(&mut [0; 4]) as &str)
+                    .and_then(|r| r.first().map(|py| py.to_string())))
+                .collect(),
+        }
+    }
+
+    /// 单个汉字的 token ID（不分配字符串）
+    pub fn char_id(&self, ch: char) -> Option<i64> {
+        self.char2id.get(ch.encode_utf8(&mut [0; 4]) as &str).copied()
+    }
+}
+
+// ============================================================
+// 多音字消歧
+// ============================================================
+
+/// 一个词最多尝试的读音组合数（防止长词组合爆炸）
+const MAX_READING_COMBOS: usize = 64;
+
+/// 逐字标注拼音, 多音字按所在词消歧
+///
+/// 从左到右贪心匹配字典中最长的词 (4→2 字)，词的拼音即各字读音：
+///   "银行" → 字典 yinhang,银行 → [yin, hang]
+///   "行走" → 字典 xingzou,行走 → [xing, zou]
+/// 不成词的单字取字典中单字权重最高的读音，不在 char2pinyin 中的字返回 None。
+pub fn annotate_pinyin(
+    chars: &[char],
+    char2pinyin: &HashMap<Arc<str>, Vec<Arc<str>>>,
+    dict: &crate::pinyin::Dictionary,
+) -> Vec<Option<String>> {
+    let mut result: Vec<Option<String>> = Vec::with_capacity(chars.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let max_len = std::cmp::min(4, chars.len() - i);
+        let word_match = (2..=max_len).rev()
+            .find_map(|len| word_readings(&chars[i..i + len], char2pinyin, dict));
+
+        match word_match {
+            Some(readings) => {
+                i += readings.len();
+                result.extend(readings.into_iter().map(Some));
+            }
+            None => {
+                result.push(char_reading(chars[i], char2pinyin, dict));
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// 枚举词中各字读音组合，返回字典中确有此词的那一组读音
+fn word_readings(
+    word: &[char],
+    char2pinyin: &HashMap<Arc<str>, Vec<Arc<str>>>,
+    dict: &crate::pinyin::Dictionary,
+) -> Option<Vec<String>> {
+    let per_char: Vec<&Vec<Arc<str>>> = word.iter()
+        .map(|&c| char2pinyin.get(c.encode_utf8(&mut [0; 4]) as &str))
+        .collect::<Option<Vec<_>>>()?;
+    let word_str: String = word.iter().collect();
+
+    let mut combos: Vec<Vec<String>> = vec![vec![]];
+    for readings in &per_char {
+        let mut next = Vec::new();
+        for prefix in &combos {
+            for py in readings.iter() {
+                if next.len() >= MAX_READING_COMBOS { break; }
+                let mut c = prefix.clone();
+                c.push(py.to_string());
+                next.push(c);
+            }
+        }
+        combos = next;
+    }
+
+    combos.into_iter()
+        .find(|combo| dict.lookup(&combo.concat()).iter().any(|c| c.word == word_str))
+}
+
+/// 单字读音: 多音字取字典中单字权重最高的读音
+fn char_reading(
+    ch: char,
+    char2pinyin: &HashMap<Arc<str>, Vec<Arc<str>>>,
+    dict: &crate::pinyin::Dictionary,
+) -> Option<String> {
+    let ch_str = ch.to_string();
+    let readings = char2pinyin.get(ch_str.as_str())?;
+    if readings.len() == 1 { return readings.first().map(|py| py.to_string()); }
+    readings.iter()
+        .max_by_key(|py| {
+            dict.lookup(py).iter()
+                .find(|c| c.word == ch_str)
+                .map(|c| c.weight)
+                .unwrap_or(0)
+        })
+        .map(|py| py.to_string())
 }
 
 // ============================================================
@@ -175,25 +330,179 @@ pub struct AIPredictor {
     state: AIState,
     vocab: Option<VocabIndex>,
     model_path: PathBuf,
-    pub ai_first: bool,
+    /// 最近一次上下文的 token ids（一句输入期间上下文不变，逐键推理复用）
+    ctx_cache: ContextCache,
+    /// 词图分词参数（`[segmentation]`）
+    segmentation: SegmentationConfig,
+    /// 连续推理失败次数，达到上限后停用 AI
+    failures: FailureStreak,
+    /// 各类推理的耗时采样（`ai.latency_stats` 开启时才有）
+    latency: Option<LatencyStats>,
+}
+
+/// 连续推理失败达到该次数后停用 AI，回退纯字典
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// 连续失败计数（任一次成功即清零）
+#[derive(Default)]
+struct FailureStreak {
+    count: u32,
+}
+
+impl FailureStreak {
+    /// 记一次失败，恰好达到上限时返回 true（只触发一次）
+    fn fail(&mut self) -> bool {
+        self.count += 1;
+        self.count == MAX_CONSECUTIVE_FAILURES
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// 推理类型（耗时分别统计）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferenceKind {
+    /// 单音节约束解码
+    Single,
+    /// 多音节 Beam Search
+    Beam,
+    /// 字典候选重排
+    Rerank,
+    /// 首字母缩写
+    Abbrev,
+    /// 上屏后联想下一个字
+    Associate,
+}
+
+impl InferenceKind {
+    pub const ALL: [Self; 5] = [Self::Single, Self::Beam, Self::Rerank, Self::Abbrev, Self::Associate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Single => "single",
+            Self::Beam => "beam",
+            Self::Rerank => "rerank",
+            Self::Abbrev => "abbrev",
+            Self::Associate => "associate",
+        }
+    }
+}
+
+/// 每类推理保留的最近采样数
+const LATENCY_SAMPLES: usize = 256;
+
+/// 一类推理的耗时汇总（`/v1/status` 的 latency 字段）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub kind: InferenceKind,
+    /// 参与统计的采样数（最多 [`LATENCY_SAMPLES`]）
+    pub count: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+}
+
+/// 最近推理耗时的环形缓冲: 记录只是一次写入，排序留到查询时
+#[derive(Default)]
+struct LatencyStats {
+    rings: [Vec<Duration>; InferenceKind::ALL.len()],
+    /// 各环下一个写入位置（写满后覆盖最旧的）
+    next: [usize; InferenceKind::ALL.len()],
+}
+
+impl LatencyStats {
+    fn record(&mut self, kind: InferenceKind, d: Duration) {
+        let (ring, next) = (&mut self.rings[kind as usize], &mut self.next[kind as usize]);
+        if ring.len() < LATENCY_SAMPLES { ring.push(d); } else { ring[*next] = d; }
+        *next = (*next + 1) % LATENCY_SAMPLES;
+    }
+
+    fn summary(&self) -> Vec<LatencySummary> {
+        InferenceKind::ALL.iter().filter_map(|&kind| {
+            let mut sorted = self.rings[kind as usize].clone();
+            if sorted.is_empty() { return None; }
+            sorted.sort_unstable();
+            Some(LatencySummary {
+                kind, count: sorted.len(),
+                p50: percentile(&sorted, 50),
+                p95: percentile(&sorted, 95),
+            })
+        }).collect()
+    }
+}
+
+/// 最近秩法百分位（`sorted` 非空且已升序）
+pub(crate) fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// 按输入形态判断推理类型: 全是声母的算首字母，其余按音节数（空输入不推理，返回 None）
+fn predict_kind(pinyin: &str) -> Option<InferenceKind> {
+    if pinyin.len() >= 2 && pinyin.chars().all(|c| "bpmfdtnlgkhjqxzcsryw".contains(c)) {
+        return Some(InferenceKind::Abbrev);
+    }
+    match crate::pinyin::split_pinyin_pub(pinyin).len() {
+        0 => None,
+        1 => Some(InferenceKind::Single),
+        _ => Some(InferenceKind::Beam),
+    }
+}
+
+/// 全进程共用一个模型实例: 输入法与本地 HTTP 服务都通过它推理，模型只加载一次
+pub type SharedPredictor = Arc<Mutex<AIPredictor>>;
+
+/// `build_context` 结果缓存，按上下文字符串失效（上屏后上下文改变即重建）
+#[derive(Default)]
+struct ContextCache {
+    context: String,
+    ids: Vec<i64>,
+}
+
+impl ContextCache {
+    fn get(&mut self, vocab: &VocabIndex, context: &str) -> &[i64] {
+        if self.ids.is_empty() || self.context != context {
+            self.ids = build_context(vocab, context);
+            self.context.clear();
+            self.context.push_str(context);
+        }
+        &self.ids
+    }
 }
 
 impl AIPredictor {
-    pub fn new() -> Self {
-        match std::panic::catch_unwind(|| Self::try_init()) {
+    /// 按 `[ai]` 的 model_path / vocab_dir 加载模型与词表（空 = 默认位置），warmup 开启时预热
+    pub fn new(cfg: &AiConfig) -> Self {
+        match std::panic::catch_unwind(|| Self::try_init(cfg)) {
             Ok(p) => p,
             Err(_) => {
+                // panic 详情与调用栈已由 crash::install_panic_hook 写入 crash.log
                 eprintln!("[AI] ⚠ ort panic, 回退字典模式");
-                Self { state: AIState::Unavailable("ort panic".into()),
-                    vocab: None, model_path: PathBuf::new(), ai_first: false }
+                Self::unavailable("ort panic".into())
             }
         }
     }
 
-    fn try_init() -> Self {
-        let model_path = find_model_path();
+    fn unavailable(reason: String) -> Self {
+        Self { state: AIState::Unavailable(reason),
+            vocab: None, model_path: PathBuf::new(), ctx_cache: ContextCache::default(),
+            segmentation: SegmentationConfig::default(), failures: FailureStreak::default(),
+            latency: None }
+    }
+
+    fn try_init(cfg: &AiConfig) -> Self {
         let exe_dir = std::env::current_exe()
             .ok().and_then(|p| p.parent().map(|d| d.to_path_buf()));
+        // 配置了就只用配置的，不再回退默认位置（方便对比不同模型）
+        let model_path = match configured_path(&cfg.model_path, exe_dir.as_deref()) {
+            Some(p) if p.exists() => Some(p),
+            Some(p) => {
+                eprintln!("[AI] ⚠ ai.model_path 不存在: {:?}", p);
+                return Self::unavailable(format!("ai.model_path not found: {}", p.display()));
+            }
+            None => find_model_path(),
+        };
 
         if std::env::var("ORT_DYLIB_PATH").is_err() {
             if let Some(dir) = &exe_dir {
@@ -205,7 +514,11 @@ impl AIPredictor {
             }
         }
 
-        let vocab = exe_dir.as_ref().and_then(|d| VocabIndex::load_from_dir(d));
+        let vocab_dir = configured_path(&cfg.vocab_dir, exe_dir.as_deref()).or(exe_dir);
+        let vocab = vocab_dir.as_ref().and_then(|d| VocabIndex::load_from_dir(d));
+        if vocab.is_some() {
+            eprintln!("[AI] ✅ 词表: {:?}", vocab_dir.unwrap_or_default());
+        }
 
         let state = match &model_path {
             Some(path) => match load_model(path) {
@@ -222,8 +535,33 @@ impl AIPredictor {
             }
         };
 
-        let ai_first = matches!(&state, AIState::Ready(_));
-        Self { state, vocab, model_path: model_path.unwrap_or_default(), ai_first }
+        let mut predictor = Self {
+            state, vocab, model_path: model_path.unwrap_or_default(),
+            ctx_cache: ContextCache::default(),
+            segmentation: SegmentationConfig::default(),
+            failures: FailureStreak::default(),
+            latency: cfg.latency_stats.then(LatencyStats::default),
+        };
+        if cfg.warmup {
+            predictor.warm_up();
+        }
+        predictor
+    }
+
+    /// 用只含 [CLS] 的序列跑一次推理，让 ORT 在启动时完成首次分配，
+    /// 第一次按键不再卡顿。失败只记日志，不影响模型可用
+    fn warm_up(&mut self) {
+        let (AIState::Ready(session), Some(vocab)) = (&mut self.state, &self.vocab) else { return };
+        let start = std::time::Instant::now();
+        match run_inference(session, &[vocab.cls_id]) {
+            Ok(_) => eprintln!("[AI] 🔥 预热完成: {:?}", start.elapsed()),
+            Err(e) => eprintln!("[AI] ⚠ 预热失败（不影响使用）: {}", e),
+        }
+    }
+
+    /// 设置词图分词参数（启动时按 `[segmentation]` 设置一次）
+    pub fn set_segmentation(&mut self, cfg: SegmentationConfig) {
+        self.segmentation = cfg;
     }
 
     pub fn is_available(&self) -> bool {
@@ -232,20 +570,53 @@ impl AIPredictor {
 
     pub fn model_path(&self) -> &Path { &self.model_path }
 
+    /// 推理使用的执行后端（未加载时为 None；目前只用 CPU）
+    pub fn execution_provider(&self) -> Option<&'static str> {
+        self.is_available().then_some("cpu")
+    }
+
+    /// AI 不可用的原因（可用时为 None）
+    pub fn unavailable_reason(&self) -> Option<String> {
+        match &self.state {
+            AIState::Unavailable(e) => Some(e.clone()),
+            AIState::Ready(_) if self.vocab.is_none() => Some("词表文件 (char2id.json 等) 缺失".into()),
+            AIState::Ready(_) => None,
+        }
+    }
+
     /// AI 主导: 字典引导的上下文感知预测
     pub fn predict(
         &mut self, pinyin: &str, context: &str, top_k: usize,
         dict_words: &[String],
     ) -> Vec<String> {
+        self.predict_scored(pinyin, context, top_k, dict_words)
+            .into_iter().map(|(w, _)| w).collect()
+    }
+
+    /// 同 `predict`，附带 AI 分数（beam 累计 logit）
+    ///
+    /// 字典/词图补位的候选未经 AI 打分，分数为 `f32::NEG_INFINITY`。
+    pub fn predict_scored(
+        &mut self, pinyin: &str, context: &str, top_k: usize,
+        dict_words: &[String],
+    ) -> Vec<(String, f32)> {
         let session = match &mut self.state {
             AIState::Ready(s) => s, _ => return vec![],
         };
         let vocab = match &self.vocab {
             Some(v) => v, None => return vec![],
         };
-        match run_predict(session, vocab, pinyin, top_k, context, dict_words) {
-            Ok(c) => c,
-            Err(e) => { eprintln!("[AI] predict: {}", e); vec![] }
+        let ctx_prefix = self.ctx_cache.get(vocab, context);
+        let start = Instant::now();
+        match run_predict(session, vocab, pinyin, top_k, ctx_prefix, dict_words, &self.segmentation) {
+            Ok(c) => {
+                self.failures.reset();
+                if let (Some(stats), Some(kind)) = (&mut self.latency, predict_kind(pinyin)) {
+                    stats.record(kind, start.elapsed());
+                }
+                c
+            }
+            Err(e) => { self.record_failure("predict", &e); vec![] }
         }
     }
 
@@ -259,13 +630,84 @@ impl AIPredictor {
         let vocab = match &self.vocab {
             Some(v) => v, None => return candidates,
         };
-        match run_rerank(session, vocab, pinyin, &candidates, context) {
-            Ok(r) => r,
-            Err(e) => { eprintln!("[AI] rerank: {}", e); candidates }
+        let ctx_prefix = self.ctx_cache.get(vocab, context);
+        let start = Instant::now();
+        match run_rerank(session, vocab, pinyin, &candidates, context, ctx_prefix) {
+            Ok(r) => {
+                self.failures.reset();
+                if let Some(stats) = &mut self.latency {
+                    stats.record(InferenceKind::Rerank, start.elapsed());
+                }
+                r
+            }
+            Err(e) => { self.record_failure("rerank", &e); candidates }
+        }
+    }
+
+    /// 联想: 不带拼音约束，按上下文预测下一个字（整个词表里分数最高的 top_k 个汉字）
+    ///
+    /// 上下文为空或其中没有词表认识的字时不推理
+    pub fn predict_association(&mut self, context: &str, top_k: usize) -> Vec<String> {
+        if context.is_empty() || top_k == 0 { return vec![]; }
+        let session = match &mut self.state {
+            AIState::Ready(s) => s, _ => return vec![],
+        };
+        let vocab = match &self.vocab {
+            Some(v) => v, None => return vec![],
+        };
+        let ctx_prefix = self.ctx_cache.get(vocab, context);
+        if ctx_prefix.len() <= 1 { return vec![]; }
+        let start = Instant::now();
+        match run_inference(session, ctx_prefix) {
+            Ok(logits) => {
+                self.failures.reset();
+                if let Some(stats) = &mut self.latency {
+                    stats.record(InferenceKind::Associate, start.elapsed());
+                }
+                top_k_tokens(&logits, vocab, top_k, is_han_token)
+                    .into_iter().map(|(_, ch)| ch).collect()
+            }
+            Err(e) => { self.record_failure("associate", &e); vec![] }
+        }
+    }
+
+    /// 各类推理最近耗时的 p50 / p95（只含有采样的类型）；未开启 `ai.latency_stats` 时为 None
+    pub fn stats(&self) -> Option<Vec<LatencySummary>> {
+        self.latency.as_ref().map(LatencyStats::summary)
+    }
+
+    /// 清空耗时采样（调参前后分别统计）
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = &mut self.latency {
+            *stats = LatencyStats::default();
+        }
+    }
+
+    /// 推理出错: 记日志；连续失败达到上限时释放会话、转为不可用，之后全走字典
+    fn record_failure(&mut self, what: &str, err: &str) {
+        eprintln!("[AI] {}: {}", what, err);
+        if self.failures.fail() {
+            let reason = format!("连续 {} 次推理失败，已停用 AI: {}", MAX_CONSECUTIVE_FAILURES, err);
+            eprintln!("[AI] ⚠ {}（重启后重新加载模型）", reason);
+            self.state = AIState::Unavailable(reason);
         }
     }
 }
 
+/// 按置信度过滤 AI 候选 (`ai.min_confidence`)
+///
+/// 置信度 = 累计分 / 字数（平均每字 logit），低于阈值的候选丢弃；
+/// 未经 AI 打分的补位候选 (`NEG_INFINITY`) 原样保留。
+pub fn filter_confident(scored: Vec<(String, f32)>, min_confidence: Option<f32>) -> Vec<String> {
+    scored.into_iter()
+        .filter(|(w, score)| match min_confidence {
+            Some(min) if score.is_finite() => score / w.chars().count().max(1) as f32 >= min,
+            _ => true,
+        })
+        .map(|(w, _)| w)
+        .collect()
+}
+
 // ============================================================
 // ONNX 推理
 // ============================================================
@@ -308,9 +750,8 @@ fn build_context(vocab: &VocabIndex, context: &str) -> Vec<i64> {
     let ctx_chars: Vec<char> = context.chars().rev().take(50).collect::<Vec<_>>()
         .into_iter().rev().collect();
     
-    for ch in &ctx_chars {
-        let ch_str = ch.to_string();
-        if let Some(&ch_id) = vocab.char2id.get(&ch_str) {
+    for &ch in &ctx_chars {
+        if let Some(ch_id) = vocab.char_id(ch) {
             ids.push(ch_id);
         }
     }
@@ -325,9 +766,10 @@ fn run_predict(
     vocab: &VocabIndex,
     pinyin: &str,
     top_k: usize,
-    context: &str,
+    ctx_prefix: &[i64],
     dict_words: &[String],
-) -> Result<Vec<String>, String> {
+    seg: &SegmentationConfig,
+) -> Result<Vec<(String, f32)>, String> {
     let syllables = crate::pinyin::split_pinyin_pub(pinyin);
     if syllables.is_empty() {
         // 首字母模式: AI beam search + 声母约束
@@ -336,17 +778,16 @@ fn run_predict(
         
         if is_abbrev {
             let initials = parse_initials(pinyin);
-            let ctx_prefix = build_context(vocab, context);
             eprintln!("[AI] 首字母beam: initials={:?}, dict_words={}", initials, dict_words.len());
             
             // AI beam search: 逐字生成, 用声母约束
             let beam_results = abbreviation_beam_search(
-                session, vocab, &initials, &ctx_prefix, 5,
+                session, vocab, &initials, ctx_prefix, seg.abbrev_beam_width,
             )?;
             
             // === 缩写词图: 把首字母拆成词段匹配字典 ===
             // "bzdzmb" → "bzd"(不知道) + "zmb"(怎么办) → "不知道怎么办"
-            let abbrev_graph_cands = abbreviation_word_graph(&initials);
+            let abbrev_graph_cands = abbreviation_word_graph(&initials, seg);
             
             // 合并: 词图结果 + beam结果 + 字典缩写候选
             let mut all_cands: Vec<String> = Vec::new();
@@ -367,50 +808,23 @@ fn run_predict(
                 abbrev_graph_cands.len(), beam_results.len(), 
                 dict_words.len().min(10), all_cands.len());
             
-            // 对候选统一 AI 打分，最多评 4 个（避免首字母长串过慢）
-            let score_cap = std::cmp::min(4, all_cands.len());
-            let mut scored: Vec<(String, f32)> = Vec::new();
-            for word in &all_cands[..score_cap] {
-                let chars: Vec<char> = word.chars().collect();
-                let score_len = std::cmp::min(3, chars.len()); // 最多看前3字
-                
-                // GPT2-Chinese 评分: 逐字步进，每步把已生成字符拼到ctx_prefix后推理
-                let mut total = 0.0f32;
-                let mut current_ctx = ctx_prefix.clone();
-                let mut valid = true;
-
-                for ch in chars.iter().take(score_len) {
-                    let ch_str = ch.to_string();
-                    let ch_id = match vocab.char2id.get(&ch_str) {
-                        Some(&id) => id,
-                        None => { valid = false; break; }
-                    };
-
-                    // 用当前上下文推理，取 ch_id 对应的 logit 分数
-                    let logits = match run_inference(session, &current_ctx) {
-                        Ok(l) => l,
-                        Err(_) => { valid = false; break; }
-                    };
-
-                    if ch_id as usize >= logits.len() { valid = false; break; }
-                    total += logits[ch_id as usize];
-
-                    // 把已预测的字符追加到上下文中
-                    current_ctx.push(ch_id);
-                }
-                if valid { scored.push((word.clone(), total)); }
-            }
+            // 对候选统一 AI 打分，最多评 ABBREV_SCORE_CANDIDATES 个（避免首字母长串过慢）
+            let score_cap = std::cmp::min(ABBREV_SCORE_CANDIDATES, all_cands.len());
+            let scores = score_abbrev_candidates(
+                vocab, ctx_prefix, &all_cands[..score_cap], |ids| run_inference(session, ids));
+            let mut scored: Vec<(String, f32)> = all_cands[..score_cap].iter().zip(scores)
+                .filter_map(|(w, s)| s.map(|s| (w.clone(), s)))
+                .collect();
             // 未评分的候选直接追加
             for word in &all_cands[score_cap..] {
                 scored.push((word.clone(), f32::NEG_INFINITY));
             }
-            scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            return Ok(scored.into_iter().take(top_k).map(|(w, _)| w).collect());
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            return Ok(scored.into_iter().take(top_k).collect());
         }
         return Ok(vec![]);
     }
 
-    let ctx_prefix = build_context(vocab, context);
     let ctx_len = ctx_prefix.len() - 1;
 
     if ctx_len > 0 {
@@ -420,9 +834,11 @@ fn run_predict(
 
     // === 单音节: 直接约束解码 ===
     if syllables.len() == 1 {
-        let logits = run_inference(session, &ctx_prefix)?;
+        let logits = run_inference(session, ctx_prefix)?;
         let chars = get_top_k_constrained(&logits, vocab, &syllables[0], top_k);
-        return Ok(chars.into_iter().map(|(_, ch)| ch).collect());
+        return Ok(chars.into_iter()
+            .map(|(id, ch)| (ch, logits.get(id as usize).copied().unwrap_or(f32::NEG_INFINITY)))
+            .collect());
     }
 
     // === 2+音节: Beam Search 主导 + 词图兜底 ===
@@ -431,28 +847,28 @@ fn run_predict(
     // Beam Search 输出已按累计 AI 分排好序，直接使用即可。
     if syllables.len() >= 2 {
         // AI Beam Search: 已按 AI 分从高到低排列
-        let beam_results = run_predict_greedy(session, vocab, &syllables, &ctx_prefix, top_k)
+        let beam_results = run_predict_greedy(session, vocab, &syllables, ctx_prefix, top_k)
             .unwrap_or_default();
 
         // 词图分词：字典多词覆盖（纯查表，O(1)，无推理开销）
-        let graph_cands = word_graph_segment(&syllables, 5);
+        let graph_cands = word_graph_segment(&syllables, 5, seg);
 
-        // 合并: AI beam 优先，词图 + 字典补充剩余位置
-        let mut result: Vec<String> = Vec::new();
+        // 合并: AI beam 优先，词图 + 字典补充剩余位置（补位项无 AI 分）
+        let mut result: Vec<(String, f32)> = Vec::new();
         let mut seen = std::collections::HashSet::new();
 
         // 1. AI beam（最高质量）
-        for w in &beam_results {
-            if seen.insert(w.clone()) { result.push(w.clone()); }
+        for (w, score) in &beam_results {
+            if seen.insert(w.clone()) { result.push((w.clone(), *score)); }
         }
         // 2. 字典精确匹配（长度一致的词）
         let target_len = syllables.len();
         for w in dict_words.iter().filter(|w| w.chars().count() == target_len).take(3) {
-            if seen.insert(w.clone()) { result.push(w.clone()); }
+            if seen.insert(w.clone()) { result.push((w.clone(), f32::NEG_INFINITY)); }
         }
         // 3. 词图（短词拼接兜底）
         for w in &graph_cands {
-            if seen.insert(w.clone()) { result.push(w.clone()); }
+            if seen.insert(w.clone()) { result.push((w.clone(), f32::NEG_INFINITY)); }
         }
 
         if !result.is_empty() {
@@ -467,17 +883,61 @@ fn run_predict(
     Ok(vec![])
 }
 
+/// 首字母模式最多评分的候选数
+const ABBREV_SCORE_CANDIDATES: usize = 4;
+/// 每个候选最多看前几个字
+const ABBREV_SCORE_CHARS: usize = 3;
+/// 一次首字母评分的推理次数上限
+const ABBREV_SCORE_MAX_INFERENCES: usize = 8;
+
+/// 首字母候选打分: 逐字步进，每个候选取前 ABBREV_SCORE_CHARS 字的 logit 之和
+///
+/// 同一前缀的推理结果共用: 所有候选共用上下文本身那一次，首字相同的再共用第二次，依此类推，
+/// 推理次数 = 不同前缀数，且不超过 ABBREV_SCORE_MAX_INFERENCES。
+/// 例: "bzdzmb" 的 4 个候选（不知道怎么办 / 不知道怎么吧 / 不知道这么办 / 不在的）
+/// 逐个推理要 12 次，共用前缀后只要 3 次（上下文、不、不知）。
+///
+/// 有字不在词表、推理失败或超出次数上限的候选返回 None（不参与 AI 排序）
+fn score_abbrev_candidates(
+    vocab: &VocabIndex,
+    ctx_prefix: &[i64],
+    words: &[String],
+    mut infer: impl FnMut(&[i64]) -> Result<Vec<f32>, String>,
+) -> Vec<Option<f32>> {
+    // 已生成字符 ids → 在其后推理得到的 logits
+    let mut memo: HashMap<Vec<i64>, Vec<f32>> = HashMap::new();
+    let mut calls = 0;
+
+    words.iter().map(|word| {
+        let mut ids = Vec::new();
+        let mut total = 0.0f32;
+        for ch in word.chars().take(ABBREV_SCORE_CHARS) {
+            let ch_id = vocab.char_id(ch)?;
+            if !memo.contains_key(&ids) {
+                if calls >= ABBREV_SCORE_MAX_INFERENCES { return None; }
+                calls += 1;
+                let ctx: Vec<i64> = ctx_prefix.iter().chain(&ids).copied().collect();
+                memo.insert(ids.clone(), infer(&ctx).ok()?);
+            }
+            total += *memo[&ids].get(ch_id as usize)?;
+            ids.push(ch_id);
+        }
+        Some(total)
+    }).collect()
+}
+
 /// 真正的 Beam Search (GPT2-Chinese: 纯字符自回归)
 ///
 /// 每步维护 beam_width 条路径，每条路径记录 (text, ids, cumulative_score)。
 /// 每步对每条 beam 用拼音约束取 top-k，扩展后保留全局最优 beam_width 条。
+/// 返回 (text, cumulative_score)，按分数降序。
 fn run_predict_greedy(
     session: &mut ort::session::Session,
     vocab: &VocabIndex,
     syllables: &[String],
     ctx_prefix: &[i64],
     beam_width: usize,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<(String, f32)>, String> {
     if syllables.is_empty() { return Ok(vec![]); }
 
     // beams: Vec<(text, ids, cumulative_score)>
@@ -502,7 +962,7 @@ fn run_predict_greedy(
                 let mut new_text = text.clone();
                 new_text.push_str(&ch);
                 let mut new_ctx_ids = current_ctx_ids.clone();
-                if let Some(&id) = vocab.char2id.get(&ch) {
+                if let Some(&id) = vocab.char2id.get(ch.as_str()) {
                     new_ctx_ids.push(id);
                 }
                 next_beams.push((new_text, new_ctx_ids, score + char_score));
@@ -512,16 +972,16 @@ fn run_predict_greedy(
         if next_beams.is_empty() { break; }
 
         // 保留全局最优 beam_width 条（按累计分数降序）
-        next_beams.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        next_beams.sort_by(|a, b| b.2.total_cmp(&a.2));
         next_beams.truncate(beam_width);
         beams = next_beams;
     }
 
     // 提取结果，去重
     let mut seen = std::collections::HashSet::new();
-    let results: Vec<String> = beams.into_iter()
-        .map(|(text, _, _)| text)
-        .filter(|s| !s.is_empty() && seen.insert(s.clone()))
+    let results: Vec<(String, f32)> = beams.into_iter()
+        .map(|(text, _, score)| (text, score))
+        .filter(|(s, _)| !s.is_empty() && seen.insert(s.clone()))
         .collect();
     Ok(results)
 }
@@ -545,9 +1005,9 @@ fn get_top_k_constrained(
                 if idx < logits.len() { Some((id, logits[idx])) } else { None }
             })
             .collect();
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
         return scored.iter().take(top_k)
-            .filter_map(|(id, _)| vocab.id2char.get(id).map(|ch| (*id, ch.clone())))
+            .filter_map(|(id, _)| vocab.id2char.get(id).map(|ch| (*id, ch.to_string())))
             .collect();
     }
 
@@ -570,24 +1030,41 @@ fn get_top_k_constrained(
                     if idx < logits.len() { Some((id, logits[idx])) } else { None }
                 })
                 .collect();
-            scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
             return scored.iter().take(top_k)
-                .filter_map(|(id, _)| vocab.id2char.get(id).map(|ch| (*id, ch.clone())))
+                .filter_map(|(id, _)| vocab.id2char.get(id).map(|ch| (*id, ch.to_string())))
                 .collect();
         }
     }
 
     // 3. 真正无约束 fallback（极少触发，仅当声母也查不到时）
+    top_k_tokens(logits, vocab, top_k, |_| true)
+}
+
+/// 无约束 top-K: 全词表中 `keep` 接受的 token 按 logit 降序取前 top_k 个（跳过前 4 个特殊 token）
+fn top_k_tokens(
+    logits: &[f32],
+    vocab: &VocabIndex,
+    top_k: usize,
+    keep: impl Fn(&str) -> bool,
+) -> Vec<(i64, String)> {
     let mut scored: Vec<(i64, f32)> = logits.iter().enumerate()
         .filter(|(i, _)| *i >= 4)
         .map(|(i, &s)| (i as i64, s))
         .collect();
-    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    scored.iter().take(top_k)
-        .filter_map(|(id, _)| vocab.id2char.get(id).map(|ch| (*id, ch.clone())))
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.iter()
+        .filter_map(|(id, _)| vocab.id2char.get(id).filter(|ch| keep(ch)).map(|ch| (*id, ch.to_string())))
+        .take(top_k)
         .collect()
 }
 
+/// 单个常用汉字 token（联想只出汉字，不出标点、字母、[UNK] 等）
+fn is_han_token(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if ('\u{4E00}'..='\u{9FFF}').contains(&c))
+}
+
 /// 上下文感知重排
 ///
 /// 实验验证 (test_rerank.py):
@@ -601,6 +1078,7 @@ fn run_rerank(
     pinyin: &str,
     candidates: &[String],
     context: &str,
+    ctx_prefix: &[i64],
 ) -> Result<Vec<String>, String> {
     let syllables = crate::pinyin::split_pinyin_pub(pinyin);
     if syllables.is_empty() || candidates.is_empty() {
@@ -609,18 +1087,17 @@ fn run_rerank(
 
     let n = candidates.len();
 
-    // 构建纯字符上下文
-    let input_ids = build_context(vocab, context);
-    let ctx_len = input_ids.len() - 1;
+    // 纯字符上下文（调用方缓存）
+    let ctx_len = ctx_prefix.len() - 1;
 
     // 直接推理 (GPT2-Chinese: 返回最后位置logits)
-    let logits = run_inference(session, &input_ids)?;
+    let logits = run_inference(session, ctx_prefix)?;
 
     // 提取每个候选首字的 AI 分数 (logits 已是最后位置的 vocab_size 向量)
     let ai_scores: Vec<f32> = candidates.iter().map(|cand| {
         cand.chars().next()
-            .and_then(|ch| vocab.char2id.get(&ch.to_string()))
-            .and_then(|&cid| {
+            .and_then(|ch| vocab.char_id(ch))
+            .and_then(|cid| {
                 let idx = cid as usize;
                 if idx < logits.len() { Some(logits[idx]) } else { None }
             })
@@ -677,7 +1154,7 @@ fn run_rerank(
         scored.push((idx, final_score));
     }
 
-    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
     Ok(scored.into_iter()
         .filter_map(|(i, _)| candidates.get(i).cloned())
         .collect())
@@ -687,6 +1164,17 @@ fn run_rerank(
 // 辅助
 // ============================================================
 
+/// 配置项里的路径: 空 = 未配置；相对路径相对 exe 目录
+fn configured_path(value: &str, exe_dir: Option<&Path>) -> Option<PathBuf> {
+    let value = value.trim();
+    if value.is_empty() { return None; }
+    let path = PathBuf::from(value);
+    match exe_dir {
+        Some(dir) if path.is_relative() => Some(dir.join(path)),
+        _ => Some(path),
+    }
+}
+
 fn find_model_path() -> Option<PathBuf> {
     let exe_dir = std::env::current_exe()
         .ok().and_then(|p| p.parent().map(|d| d.to_path_buf()));
@@ -723,7 +1211,7 @@ fn load_model(path: &Path) -> Result<ort::session::Session, String> {
 ///   位置0: "bzd" → 字典缩写查到 [不知道(900), 办证的(100)]
 ///   位置3: "zmb" → 字典缩写查到 [怎么办(800)]
 ///   → 组合: "不知道怎么办"
-fn abbreviation_word_graph(initials: &[String]) -> Vec<String> {
+fn abbreviation_word_graph(initials: &[String], seg: &SegmentationConfig) -> Vec<String> {
     let n = initials.len();
     if n == 0 { return vec![]; }
     
@@ -781,7 +1269,7 @@ fn abbreviation_word_graph(initials: &[String]) -> Vec<String> {
             };
             // 多字词加分
             let word_len = j - i;
-            let score = weight as i64 + (word_len as i64) * 500;
+            let score = weight as i64 + (word_len as i64) * seg.abbrev_multi_char_bonus;
             
             for (rest_score, rest_path) in rest.iter().take(3) {
                 let total = score + rest_score;
@@ -793,7 +1281,7 @@ fn abbreviation_word_graph(initials: &[String]) -> Vec<String> {
         
         if !candidates.is_empty() {
             candidates.sort_by(|a, b| b.0.cmp(&a.0));
-            candidates.truncate(5);
+            candidates.truncate(seg.abbrev_dp_width);
             best[i] = Some(candidates);
         }
     }
@@ -891,7 +1379,7 @@ fn abbreviation_beam_search(
                     if idx < logits.len() { Some((cid, logits[idx])) } else { None }
                 })
                 .collect();
-            char_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            char_scores.sort_by(|a, b| b.1.total_cmp(&a.1));
 
             for &(char_id, char_score) in char_scores.iter().take(beam_width) {
                 if let Some(ch_str) = vocab.id2char.get(&char_id) {
@@ -905,7 +1393,7 @@ fn abbreviation_beam_search(
         }
 
         // 保留 top beam_width 条路径
-        new_beams.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        new_beams.sort_by(|a, b| b.2.total_cmp(&a.2));
         new_beams.truncate(beam_width);
         beams = new_beams;
 
@@ -927,21 +1415,96 @@ fn abbreviation_beam_search(
 ///
 /// 例: ["bu","zhi","dao","zhe","ci","xiao","guo","ru","he"]
 ///   → "不知道这次效果如何" (不知道+这次+效果+如何)
-pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
-    let n = syllables.len();
-    if n == 0 { return vec![]; }
+///
+/// 超过 `GRAPH_CHUNK_THRESHOLD` 个音节的长输入按 `graph_chunks` 分块，
+/// 逐块分词后拼接，DP 宽度不随输入长度增长。
+pub fn word_graph_segment(syllables: &[String], top_k: usize, seg: &SegmentationConfig) -> Vec<String> {
+    if syllables.is_empty() { return vec![]; }
 
     let dict = match crate::pinyin::get_dict() {
         Some(d) => d,
         None => return vec![],
     };
-    let jieba = get_jieba();
+    let jieba = seg.use_jieba.then(get_jieba);
+
+    if syllables.len() <= GRAPH_CHUNK_THRESHOLD {
+        return segment_span(dict, jieba, syllables, top_k, seg);
+    }
+
+    // 前面各块取最优, 末块(用户正在输入的部分)保留 top_k 个备选
+    let ranges = graph_chunks(dict, syllables, seg.chunk_boundary_weight);
+    let mut prefix = String::new();
+    for range in &ranges[..ranges.len() - 1] {
+        match segment_span(dict, jieba, &syllables[range.clone()], 1, seg).into_iter().next() {
+            Some(best) => prefix.push_str(&best),
+            None => return vec![],
+        }
+    }
+    let last = ranges[ranges.len() - 1].clone();
+    segment_span(dict, jieba, &syllables[last], top_k, seg)
+        .into_iter()
+        .map(|tail| format!("{}{}", prefix, tail))
+        .collect()
+}
+
+/// 不分块的最长输入（音节数），不超过时输出与整体 DP 完全一致
+const GRAPH_CHUNK_THRESHOLD: usize = 12;
+/// 分块时每块的音节数范围
+const GRAPH_CHUNK_MIN: usize = 4;
+const GRAPH_CHUNK_MAX: usize = 8;
+
+/// 长输入分块: 每块 `GRAPH_CHUNK_MIN..=GRAPH_CHUNK_MAX` 个音节
+///
+/// 在允许范围内优先切在权重 ≥ `boundary_weight` 的多字词(2~4 音节)结尾处，权重相同取更靠后的位置；
+/// 找不到这样的词就在 `GRAPH_CHUNK_MAX` 处硬切。最后一块可能少于 `GRAPH_CHUNK_MIN`。
+fn graph_chunks(
+    dict: &crate::pinyin::Dictionary,
+    syllables: &[String],
+    boundary_weight: u32,
+) -> Vec<std::ops::Range<usize>> {
+    let n = syllables.len();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while n - start > GRAPH_CHUNK_MAX {
+        let mut cut = start + GRAPH_CHUNK_MAX;
+        let mut cut_weight = 0;
+        for end in start + GRAPH_CHUNK_MIN..=start + GRAPH_CHUNK_MAX {
+            for len in 2..=4.min(end - start) {
+                let key: String = syllables[end - len..end].concat();
+                let weight = dict.lookup(&key).iter()
+                    .filter(|c| c.word.chars().count() == len)
+                    .map(|c| c.weight)
+                    .max()
+                    .unwrap_or(0);
+                if weight >= boundary_weight && weight >= cut_weight {
+                    cut = end;
+                    cut_weight = weight;
+                }
+            }
+        }
+        ranges.push(start..cut);
+        start = cut;
+    }
+    ranges.push(start..n);
+    ranges
+}
+
+/// 对一段音节做整体词图 DP
+fn segment_span(
+    dict: &crate::pinyin::Dictionary,
+    jieba: Option<&jieba_rs::Jieba>,
+    syllables: &[String],
+    top_k: usize,
+    seg: &SegmentationConfig,
+) -> Vec<String> {
+    let n = syllables.len();
 
     // === 第一步: 构建候选词表 ===
     // word_at[i] = Vec<(end_pos, word, combined_score, syllable_count)>
     //
     // combined_score = 拼音词典权重 + jieba词频加成
-    // jieba词频加成: 若 jieba 认为该词是独立词汇，加权最多 +3000
+    // jieba词频加成: 若 jieba 认为该词是独立词汇，加 jieba_boost × 字数（最多按 4 字计）
     let mut word_at: Vec<Vec<(usize, String, i64, usize)>> = vec![vec![]; n];
 
     for i in 0..n {
@@ -952,11 +1515,11 @@ pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
             let entries = dict.lookup(&py_key);
             if entries.is_empty() { continue; }
 
-            let mut sorted: Vec<&crate::pinyin::Candidate> = entries.iter().collect();
-            sorted.sort_by(|a, b| b.weight.cmp(&a.weight));
+            let mut sorted: Vec<&crate::pinyin::Candidate> = entries;
+            sorted.sort_by(|a, b| crate::pinyin::cmp_candidates(a, b));
             for entry in sorted.iter().take(5) {
                 // jieba 词频增强: 用 jieba 对该词分词，若结果是单词（未被拆开）说明是高频词
-                let jieba_boost = jieba_word_score(jieba, &entry.word);
+                let jieba_boost = jieba_word_score(jieba, &entry.word, seg.jieba_boost);
                 let score = entry.weight as i64 + jieba_boost;
                 word_at[i].push((j, entry.word.clone(), score, length));
             }
@@ -967,10 +1530,10 @@ pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
             let py_key = &syllables[i];
             let entries = dict.lookup(py_key);
             if !entries.is_empty() {
-                let mut sorted: Vec<&crate::pinyin::Candidate> = entries.iter().collect();
-                sorted.sort_by(|a, b| b.weight.cmp(&a.weight));
+                let mut sorted: Vec<&crate::pinyin::Candidate> = entries;
+                sorted.sort_by(|a, b| crate::pinyin::cmp_candidates(a, b));
                 for entry in sorted.iter().take(5) {
-                    let jieba_boost = jieba_word_score(jieba, &entry.word) / 4; // 单字 jieba 加成缩减
+                    let jieba_boost = jieba_word_score(jieba, &entry.word, seg.jieba_boost) / 4; // 单字 jieba 加成缩减
                     let score = entry.weight as i64 + jieba_boost;
                     word_at[i].push((i + 1, entry.word.clone(), score, 1));
                 }
@@ -993,7 +1556,7 @@ pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
 
             // 多字词大幅加分（避免单字路径淹没词组）
             let score = if syl_count >= 2 {
-                word_score + (syl_count as i64) * 1000
+                word_score + (syl_count as i64) * seg.multi_char_bonus
             } else {
                 word_score
             };
@@ -1013,7 +1576,7 @@ pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
                 let key: String = path.concat();
                 seen.insert(key)
             });
-            candidates.truncate(15);
+            candidates.truncate(seg.dp_width);
             best[i] = Some(candidates);
         }
     }
@@ -1032,8 +1595,9 @@ pub fn word_graph_segment(syllables: &[String], top_k: usize) -> Vec<String> {
 /// 用 jieba 评估一个词的分词质量
 ///
 /// 如果 jieba 把整个词当单一词汇（不拆分），说明它是高频、正规词汇，返回加分。
-/// 加分范围 0~3000，与词长和 jieba 置信度相关。
-fn jieba_word_score(jieba: &jieba_rs::Jieba, word: &str) -> i64 {
+/// 加分 = 每字 `boost` × 字数（最多按 4 字计），默认 0~3000。未启用 jieba 时为 0。
+fn jieba_word_score(jieba: Option<&jieba_rs::Jieba>, word: &str, boost: i64) -> i64 {
+    let Some(jieba) = jieba else { return 0 };
     let char_count = word.chars().count();
     if char_count == 1 {
         // 单字不需要 jieba 验证
@@ -1043,7 +1607,7 @@ fn jieba_word_score(jieba: &jieba_rs::Jieba, word: &str) -> i64 {
     let segments = jieba.cut(word, false);
     if segments.len() == 1 && segments[0] == word {
         // jieba 认为这就是一个完整词 → 高频词加成
-        (char_count as i64).min(4) * 750  // 2字:1500, 3字:2250, 4+字:3000
+        (char_count as i64).min(4) * boost  // 默认 2字:1500, 3字:2250, 4+字:3000
     } else {
         // jieba 把它拆开了 → 低频或非词，不加分
         0
@@ -1065,23 +1629,270 @@ fn log_model_info(session: &ort::session::Session) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_failure_streak() {
+        let mut f = FailureStreak::default();
+        assert!(!f.fail());
+        assert!(!f.fail());
+        // 成功一次即清零
+        f.reset();
+        for _ in 1..MAX_CONSECUTIVE_FAILURES { assert!(!f.fail()); }
+        assert!(f.fail());
+        // 只在达到上限时触发一次
+        assert!(!f.fail());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let ms = Duration::from_millis;
+        let mut stats = LatencyStats::default();
+        assert!(stats.summary().is_empty());
+        for i in 1..=100 { stats.record(InferenceKind::Beam, ms(i)); }
+        stats.record(InferenceKind::Single, ms(7));
+        assert_eq!(stats.summary(), [
+            LatencySummary { kind: InferenceKind::Single, count: 1, p50: ms(7), p95: ms(7) },
+            LatencySummary { kind: InferenceKind::Beam, count: 100, p50: ms(50), p95: ms(95) },
+        ]);
+
+        // 写满后覆盖最旧的采样: 只剩最近 LATENCY_SAMPLES 个 1000ms
+        for _ in 0..LATENCY_SAMPLES { stats.record(InferenceKind::Beam, ms(1000)); }
+        let beam = stats.summary()[1];
+        assert_eq!((beam.count, beam.p50, beam.p95), (LATENCY_SAMPLES, ms(1000), ms(1000)));
+
+        assert_eq!(predict_kind("zhong"), Some(InferenceKind::Single));
+        assert_eq!(predict_kind("nihao"), Some(InferenceKind::Beam));
+        assert_eq!(predict_kind("bzd"), Some(InferenceKind::Abbrev));
+        assert_eq!(predict_kind(""), None);
+    }
+
+    #[test]
+    fn test_configured_path() {
+        let exe = Path::new("/opt/aipinyin");
+        assert_eq!(configured_path("", Some(exe)), None);
+        assert_eq!(configured_path("  ", Some(exe)), None);
+        assert_eq!(configured_path("models/fp16.onnx", Some(exe)),
+            Some(exe.join("models/fp16.onnx")));
+        assert_eq!(configured_path("/data/m.onnx", Some(exe)), Some(PathBuf::from("/data/m.onnx")));
+        assert_eq!(configured_path("m.onnx", None), Some(PathBuf::from("m.onnx")));
+    }
+
     #[test]
     fn test_history_buffer() {
         let mut h = HistoryBuffer::new(3);
         assert!(h.is_empty());
         h.push("\u{4f60}"); h.push("\u{597d}"); h.push("\u{4e16}");
-        assert_eq!(h.context_string(), "\u{4f60}\u{597d}\u{4e16}");
+        assert_eq!(h.current_sentence(), "\u{4f60}\u{597d}\u{4e16}");
         h.push("\u{754c}");
-        assert_eq!(h.context_string(), "\u{597d}\u{4e16}\u{754c}");
+        assert_eq!(h.current_sentence(), "\u{597d}\u{4e16}\u{754c}");
+    }
+
+    #[test]
+    fn test_context_resets_after_sentence_end() {
+        let mut h = HistoryBuffer::new(10);
+        h.push("今天"); h.push("天气"); h.push("很好");
+        h.push("。");
+        assert_eq!(h.current_sentence(), "");
+        h.push("我们"); h.push("去");
+        assert_eq!(h.current_sentence(), "我们去");
+
+        // 标点在上屏文字中间、换行、半角问号
+        h.push("好吗？那就");
+        assert_eq!(h.current_sentence(), "那就");
+        h.push("\n");
+        h.push("明天");
+        assert_eq!(h.current_sentence(), "明天");
+        h.push("?");
+        assert_eq!(h.current_sentence(), "");
+    }
+
+    fn tiny_vocab() -> VocabIndex {
+        let char2id: HashMap<Arc<str>, i64> = [("你", 872), ("好", 1962), ("世", 686), ("界", 4518)]
+            .into_iter().map(|(c, id)| (Arc::from(c), id)).collect();
+        VocabIndex {
+            pinyin2id: HashMap::new(),
+            id2char: char2id.iter().map(|(c, &id)| (id, Arc::clone(c))).collect(),
+            char2id,
+            pinyin2char: HashMap::new(),
+            pinyin2char_ids: HashMap::new(),
+            char2pinyin: HashMap::new(),
+            initial_chars: HashMap::new(),
+            cls_id: 101, sep_id: 102, pad_id: 0, unk_id: 100,
+        }
+    }
+
+    #[test]
+    fn test_context_cache() {
+        let vocab = tiny_vocab();
+        let mut cache = ContextCache::default();
+
+        let first = cache.get(&vocab, "你好").to_vec();
+        assert_eq!(first, vec![101, 872, 1962]);
+        // 上下文不变: 命中缓存，结果与重新构建一致
+        let ptr = cache.get(&vocab, "你好").as_ptr();
+        assert_eq!(cache.get(&vocab, "你好"), build_context(&vocab, "你好").as_slice());
+        assert_eq!(cache.get(&vocab, "你好").as_ptr(), ptr);
+
+        // 上屏后上下文变化: 重建
+        assert_eq!(cache.get(&vocab, "你好世界"), &[101, 872, 1962, 686, 4518]);
+        assert_eq!(cache.get(&vocab, ""), &[101]);
+    }
+
+    #[test]
+    fn test_vocab_strings_interned() {
+        let dir = std::env::temp_dir().join(format!("aipinyin_vocab_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("char2id.json"), r#"{"行": 6121, "走": 6624, "<unk>": 100}"#).unwrap();
+        std::fs::write(dir.join("pinyin2char.json"), r#"{"xing": ["行"], "hang": ["行"], "zou": ["走", "邹"]}"#).unwrap();
+        let vocab = VocabIndex::load_from_dir(&dir).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // 同一个字在各映射里是同一份
+        let (key, _) = vocab.char2id.get_key_value("行").unwrap();
+        assert!(Arc::ptr_eq(key, &vocab.id2char[&6121]));
+        assert!(Arc::ptr_eq(key, &vocab.pinyin2char["xing"][0]));
+        assert!(Arc::ptr_eq(key, &vocab.pinyin2char["hang"][0]));
+        assert!(Arc::ptr_eq(vocab.char2pinyin.get_key_value("行").unwrap().0, key));
+        assert_eq!(vocab.char2pinyin["行"].iter().map(|p| &**p).collect::<Vec<_>>(), ["hang", "xing"]);
+
+        // 按字 / 字符串查询照常
+        assert_eq!(vocab.char_id('走'), Some(6624));
+        assert_eq!(vocab.char_id('邹'), None);
+        assert_eq!(vocab.pinyin2char_ids["zou"], [6624]);
+        assert_eq!(vocab.initial_chars[&'x'], [6121]);
+    }
+
+    #[test]
+    fn test_top_k_tokens_for_association() {
+        let mut vocab = tiny_vocab();
+        vocab.id2char.insert(8024, "，".into());
+        vocab.id2char.insert(100, "[UNK]".into());
+        let mut logits = vec![0.0; 9000];
+        for (id, score) in [(8024, 9.0), (100, 8.0), (1962, 7.0), (686, 5.0), (872, 6.0), (4518, 1.0)] {
+            logits[id] = score;
+        }
+        let han = |k| top_k_tokens(&logits, &vocab, k, is_han_token).into_iter().map(|(_, c)| c).collect::<Vec<_>>();
+        assert_eq!(han(3), ["好", "你", "世"]);
+        assert_eq!(han(10), ["好", "你", "世", "界"]);
+        // 不过滤时标点、[UNK] 照常排进来
+        assert_eq!(top_k_tokens(&logits, &vocab, 2, |_| true), [(8024, "，".to_string()), (100, "[UNK]".to_string())]);
+    }
+
+    #[test]
+    fn test_abbrev_scoring_shares_prefixes() {
+        let vocab = tiny_vocab();
+        let words = |ws: &[&str]| ws.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        // 假推理: 每个字的 logit = 上下文长度
+        let mut calls = 0;
+        let scores = score_abbrev_candidates(&vocab, &[101], &words(&["你好世", "你好界", "你世", "好", "你X"]),
+            |ctx| { calls += 1; Ok(vec![ctx.len() as f32; 5000]) });
+        assert_eq!(scores, vec![Some(6.0), Some(6.0), Some(3.0), Some(1.0), None]);
+        // 上下文、你、你好 各一次
+        assert_eq!(calls, 3);
+
+        // 前缀各不相同时推理次数封顶，超出的不打分
+        let mut calls = 0;
+        let scores = score_abbrev_candidates(&vocab, &[101],
+            &words(&["你好世", "你世界", "好你世", "好界世", "世你好", "世界好"]),
+            |ctx| { calls += 1; Ok(vec![ctx.len() as f32; 5000]) });
+        assert_eq!(calls, ABBREV_SCORE_MAX_INFERENCES);
+        assert!(scores[0].is_some() && scores[5].is_none());
+    }
+
+    fn polyphone_fixture() -> (HashMap<Arc<str>, Vec<Arc<str>>>, crate::pinyin::Dictionary) {
+        let mut char2pinyin: HashMap<Arc<str>, Vec<Arc<str>>> = HashMap::new();
+        for (ch, pys) in [
+            ("银", vec!["yin"]), ("行", vec!["hang", "xing"]), ("走", vec!["zou"]), ("不", vec!["bu"]),
+        ] {
+            char2pinyin.insert(ch.into(), pys.into_iter().map(Arc::from).collect());
+        }
+        let dict = crate::pinyin::Dictionary::from_text(
+            "yinhang,银行,800\nxingzou,行走,600\nbuxing,不行,700\nxing,行,900\nhang,行,300\n"
+        );
+        (char2pinyin, dict)
+    }
+
+    #[test]
+    fn test_filter_confident() {
+        let scored = vec![
+            ("问题".to_string(), 16.0),   // 平均 8.0
+            ("文体".to_string(), 9.0),    // 平均 4.5
+            ("温蒂".to_string(), f32::NEG_INFINITY), // 补位, 未打分
+        ];
+        assert_eq!(filter_confident(scored.clone(), None), vec!["问题", "文体", "温蒂"]);
+        assert_eq!(filter_confident(scored, Some(5.0)), vec!["问题", "温蒂"]);
+    }
+
+    #[test]
+    fn test_polyphone_yinhang() {
+        let (c2p, dict) = polyphone_fixture();
+        let chars: Vec<char> = "银行".chars().collect();
+        assert_eq!(annotate_pinyin(&chars, &c2p, &dict),
+            vec![Some("yin".to_string()), Some("hang".to_string())]);
+    }
+
+    #[test]
+    fn test_polyphone_xingzou() {
+        let (c2p, dict) = polyphone_fixture();
+        let chars: Vec<char> = "行走".chars().collect();
+        assert_eq!(annotate_pinyin(&chars, &c2p, &dict),
+            vec![Some("xing".to_string()), Some("zou".to_string())]);
+    }
+
+    #[test]
+    fn test_polyphone_buxing() {
+        let (c2p, dict) = polyphone_fixture();
+        let chars: Vec<char> = "不行银行".chars().collect();
+        assert_eq!(annotate_pinyin(&chars, &c2p, &dict),
+            ["bu", "xing", "yin", "hang"].iter().map(|p| Some(p.to_string())).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_polyphone_single_char_fallback() {
+        // 不成词的单个多音字取单字权重最高的读音
+        let (c2p, dict) = polyphone_fixture();
+        assert_eq!(annotate_pinyin(&['行'], &c2p, &dict), vec![Some("xing".to_string())]);
+    }
+
+    fn syllables(s: &str) -> Vec<String> {
+        s.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_graph_chunks_cut_at_word_end() {
+        let dict = crate::pinyin::Dictionary::from_text(
+            "women,我们,500\njintian,今天,800\ngongyuan,公园,600\nkanhua,看花,50\n"
+        );
+        // 20 音节: 切在高权重词 "今天"/"公园" 之后; "看花" 权重太低不作切分点; 之后无词则硬切
+        let syl = syllables("wo men jin tian xia wu qu gong yuan kan hua ran hou zai jia li chi fan shui jiao");
+        assert_eq!(graph_chunks(&dict, &syl, 100), vec![0..4, 4..9, 9..17, 17..20]);
+        // 提高切分点权重后 "公园" 不再作为切分点，改为硬切
+        assert_eq!(graph_chunks(&dict, &syl, 700), vec![0..4, 4..12, 12..20]);
+    }
+
+    /// 基准: 20 音节长句分词耗时（需要 dict.txt, 运行: cargo test bench_ -- --ignored --nocapture）
+    #[test]
+    #[ignore]
+    fn bench_word_graph_20_syllables() {
+        crate::pinyin::global_dict();
+        let syl = syllables("wo men jin tian xia wu qu gong yuan kan hua ran hou zai jia li chi fan shui jiao");
+        let seg = SegmentationConfig::default();
+        word_graph_segment(&syl[..2], 1, &seg); // 预热 jieba
+
+        let start = std::time::Instant::now();
+        let out = word_graph_segment(&syl, 5, &seg);
+        let elapsed = start.elapsed();
+        eprintln!("[词图] 20 音节: {:?} → {:?}", elapsed, out.first());
+        assert!(!out.is_empty());
+        assert!(elapsed < std::time::Duration::from_millis(200));
     }
 
     #[test]
     fn test_ai_fallback() {
-        let mut ai = AIPredictor::new();
+        let mut ai = AIPredictor::new(&AiConfig::default());
         assert!(!ai.is_available());
         let history = HistoryBuffer::new(10);
         let cands = vec!["\u{662f}".into(), "\u{65f6}".into(), "\u{5341}".into()];
-        let result = ai.rerank("shi", cands.clone(), &history);
+        let result = ai.rerank("shi", cands.clone(), &history.current_sentence());
         assert_eq!(result, cands);
     }
 }
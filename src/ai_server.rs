@@ -3,14 +3,17 @@
 //! 单端口同时支持两类请求：
 //! - `POST /v1/chat/completions`：AI 推理（OpenAI 格式，与 Ollama/LMStudio 一致）
 //! - `GET  /ui/*`：静态 UI 文件（index.html / style.css / script.js 等）
-//! - `GET  /v1/status`：健康检查
+//! - `GET  /v1/status`：健康检查与运行诊断（模型、词库、用户词典、插件、运行时长、推理耗时；
+//!   `?reset_latency` 返回后清空耗时采样）
 //!
 //! 启动时自动从 8760 起寻找空闲端口，返回实际端口号。
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::io::Read;
+use std::time::Instant;
 use serde::{Deserialize, Serialize};
-use crate::ai_engine::{AIPredictor, HistoryBuffer};
+use crate::ai_engine::{AIPredictor, HistoryBuffer, LatencySummary, SharedPredictor};
 
 // ============================================================
 // OpenAI 格式结构体
@@ -47,17 +50,27 @@ struct Choice {
     finish_reason: &'static str,
 }
 
+/// 只能在主线程读取的运行数据，由主线程写入快照供 `/v1/status` 读取
+#[derive(Debug, Default)]
+pub struct RuntimeStats {
+    pub user_dict_entries: AtomicUsize,
+    pub plugins_loaded: AtomicUsize,
+    pub plugins_active: AtomicUsize,
+}
+
 // ============================================================
 // 启动服务
 // ============================================================
 
 /// 启动本地服务，返回实际绑定端口（0 = 失败）。
 pub fn start(
-    predictor: Arc<Mutex<AIPredictor>>,
+    predictor: SharedPredictor,
     history: Arc<Mutex<HistoryBuffer>>,
+    stats: Arc<RuntimeStats>,
     ui_dir: Option<std::path::PathBuf>,
     _system_prompt: String,
 ) -> u16 {
+    let started = Instant::now();
     let server = (0u16..40).find_map(|i| {
         let port = 8760 + i;
         tiny_http::Server::http(format!("127.0.0.1:{}", port))
@@ -78,7 +91,7 @@ pub fn start(
     let _ = std::thread::Builder::new()
         .name("ai-server".into())
         .stack_size(8 * 1024 * 1024)
-        .spawn(move || server_loop(server, predictor, history, ui_dir));
+        .spawn(move || server_loop(server, predictor, history, stats, started, ui_dir));
 
     port
 }
@@ -105,8 +118,10 @@ fn send_400(req: tiny_http::Request, msg: &str) {
 
 fn server_loop(
     server: tiny_http::Server,
-    predictor: Arc<Mutex<AIPredictor>>,
+    predictor: SharedPredictor,
     history: Arc<Mutex<HistoryBuffer>>,
+    stats: Arc<RuntimeStats>,
+    started: Instant,
     ui_dir: Option<std::path::PathBuf>,
 ) {
     const MODEL: &str = "gpt2-chinese-int8";
@@ -138,8 +153,15 @@ fn server_loop(
 
         // ── GET /v1/status ───────────────────────────────────────
         if method == "GET" && (path.starts_with("/v1/status") || path == "/status") {
-            let avail = predictor.lock().map(|p| p.is_available()).unwrap_or(false);
-            send_json(req, 200, format!(r#"{{"model":"{}","available":{}}}"#, MODEL, avail));
+            let reset_latency = url.split_once('?')
+                .is_some_and(|(_, q)| q.split('&').any(|kv| kv.split('=').next() == Some("reset_latency")));
+            let status = predictor.lock().ok().map(|mut p| {
+                let json = status_json(MODEL, &p, &stats, started);
+                // 先返回清空前的统计，再开始新一轮采样
+                if reset_latency { p.reset_stats(); }
+                json
+            });
+            send_json(req, 200, status.unwrap_or_else(|| format!(r#"{{"model":"{}","available":false}}"#, MODEL)));
             continue;
         }
 
@@ -184,7 +206,7 @@ fn server_loop(
             // 推理
             let candidates: Vec<String> = {
                 let ctx_str = if context.is_empty() {
-                    history.lock().map(|h| h.context_string()).unwrap_or_default()
+                    history.lock().map(|h| h.current_sentence()).unwrap_or_default()
                 } else {
                     context
                 };
@@ -218,6 +240,36 @@ fn server_loop(
     }
 }
 
+/// `/v1/status` 响应: 原有的 model / available 之外追加诊断字段
+fn status_json(model: &str, pred: &AIPredictor, stats: &RuntimeStats, started: Instant) -> String {
+    let provider = pred.execution_provider();
+    serde_json::json!({
+        "model": model,
+        "available": pred.is_available(),
+        "gpu": provider.is_some_and(|p| p != "cpu"),
+        "provider": provider,
+        "model_path": pred.model_path().display().to_string(),
+        "unavailable_reason": pred.unavailable_reason(),
+        "dict_entries": crate::pinyin::get_dict().map(|d| d.entry_count()).unwrap_or(0),
+        "user_dict_entries": stats.user_dict_entries.load(Ordering::Relaxed),
+        "plugins_loaded": stats.plugins_loaded.load(Ordering::Relaxed),
+        "plugins_active": stats.plugins_active.load(Ordering::Relaxed),
+        "uptime_secs": started.elapsed().as_secs(),
+        "latency": pred.stats().map(|stats| latency_json(&stats)),
+    }).to_string()
+}
+
+/// `{"beam": {"count": 120, "p50_ms": 18.2, "p95_ms": 41.0}, ...}`
+fn latency_json(stats: &[LatencySummary]) -> serde_json::Value {
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    stats.iter()
+        .map(|s| (s.kind.name().to_string(), serde_json::json!({
+            "count": s.count, "p50_ms": ms(s.p50), "p95_ms": ms(s.p95),
+        })))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
 // ============================================================
 // 解析 user message
 // ============================================================
@@ -254,23 +306,70 @@ fn try_strip<'a>(s: &'a str, prefixes: &[&str]) -> Option<&'a str> {
 // 解析外部 LLM 响应 → 有序候选词列表
 // ============================================================
 
+/// 单个候选的最大字数（超过视为解释性语句）
+const MAX_CANDIDATE_CHARS: usize = 16;
+/// 一次响应最多取的候选数
+const MAX_COMPLETION_CANDIDATES: usize = 9;
+
 /// 解析 chat/completions 响应 content，提取候选词（按返回顺序）
+///
+/// 外部模型的输出不可信，逐行清洗后只保留像候选词的行:
+/// - 去掉列表前缀、Markdown 标记（`**` / `` ` `` / `>`，标题行整行丢弃）、HTML 标签与引号
+/// - 不超过 [`MAX_CANDIDATE_CHARS`] 个字，只含汉字与字母数字（带标点、空格的多半是解释）
+/// - 汉字至少占一半（"卡拉OK" 可以，"hello" 不行）
+/// - 去重，最多 [`MAX_COMPLETION_CANDIDATES`] 个
 pub fn parse_completion_content(content: &str) -> Vec<String> {
-    content.lines()
-        .filter_map(|line| {
-            let line = strip_list_prefix(line.trim());
-            if line.is_empty() { return None; }
-            // 若带分数（词语:数字），去掉分数部分
-            if let Some(pos) = line.rfind(|c| c == ':' || c == '：') {
-                let maybe_score = line[pos+1..].trim();
-                if maybe_score.parse::<f32>().is_ok() {
-                    let word = line[..pos].trim().to_string();
-                    if !word.is_empty() { return Some(word); }
-                }
-            }
-            Some(line.to_string())
-        })
-        .collect()
+    let mut words: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let line = strip_markup(line);
+        let line = strip_list_prefix(line.trim());
+        let word = strip_score(line).trim_matches(['"', '\'', '“', '”', '「', '」', '‘', '’']).trim();
+        if is_plausible_candidate(word) && !words.iter().any(|w| w == word) {
+            words.push(word.to_string());
+            if words.len() >= MAX_COMPLETION_CANDIDATES { break; }
+        }
+    }
+    words
+}
+
+/// 若带分数（词语:数字），去掉分数部分
+fn strip_score(line: &str) -> &str {
+    if let Some((pos, sep)) = line.char_indices().rev().find(|&(_, c)| c == ':' || c == '：') {
+        if line[pos + sep.len_utf8()..].trim().parse::<f32>().is_ok() {
+            return line[..pos].trim();
+        }
+    }
+    line
+}
+
+/// 去掉 HTML 标签与行内 / 行首的 Markdown 标记
+fn strip_markup(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {}
+            '*' | '`' | '~' | '_' => {}
+            _ => out.push(c),
+        }
+    }
+    let out = out.trim_start();
+    // 标题行整行丢弃
+    if out.starts_with('#') { return String::new(); }
+    out.trim_start_matches('>').to_string()
+}
+
+fn is_plausible_candidate(word: &str) -> bool {
+    let total = word.chars().count();
+    if total == 0 || total > MAX_CANDIDATE_CHARS { return false; }
+    if !word.chars().all(|c| is_han(c) || c.is_ascii_alphanumeric()) { return false; }
+    word.chars().filter(|&c| is_han(c)).count() * 2 >= total
+}
+
+fn is_han(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c) || ('\u{3400}'..='\u{4dbf}').contains(&c) || c == '〇'
 }
 
 fn strip_list_prefix(s: &str) -> &str {
@@ -280,6 +379,170 @@ fn strip_list_prefix(s: &str) -> &str {
     s.trim_start()
 }
 
+// ============================================================
+// 外部 AI 客户端
+// ============================================================
+
+/// 向外部 OpenAI 兼容接口请求候选 (`ai.endpoint` 非空时使用)
+///
+/// - 超时由 `ai.request_timeout_ms` 控制，超时不重试（避免拖慢候选刷新）
+/// - 连接失败自动重试一次
+/// - 失败返回 Err(原因)，由调用方保留屏幕上已有的本地候选
+pub fn query_external(
+    cfg: &crate::config::AiConfig,
+    pinyin: &str,
+    context: &str,
+    dict_words: &[String],
+    top_k: usize,
+) -> Result<Vec<String>, String> {
+    let messages = serde_json::json!([
+        { "role": "system", "content": cfg.effective_system_prompt() },
+        { "role": "user", "content": build_user_message(pinyin, context, dict_words, top_k) },
+    ]);
+    let content = post_chat(cfg, messages, 64)?;
+    Ok(parse_completion_content(&content).into_iter().take(top_k).collect())
+}
+
+/// 整句模式的系统提示词
+const SENTENCE_PROMPT: &str = "你是拼音输入法的整句转换引擎。用户给出一串无空格的拼音和上文，\
+请输出最可能的一句中文，每个音节对应一个汉字，只输出这句话本身，不要标点、解释或多个备选。";
+
+/// 向外部接口请求整句候选（云拼音）
+///
+/// 返回 Ok(None) 表示接口正常但结果不可用（非中文 / 字数与音节数不符）
+pub fn query_sentence(
+    cfg: &crate::config::AiConfig,
+    pinyin: &str,
+    context: &str,
+    syllable_count: usize,
+) -> Result<Option<String>, String> {
+    let messages = serde_json::json!([
+        { "role": "system", "content": SENTENCE_PROMPT },
+        { "role": "user", "content": format!("拼音：{}，上文：{}", pinyin, context) },
+    ]);
+    let content = post_chat(cfg, messages, (syllable_count * 4).max(32) as u32)?;
+    Ok(validate_sentence(&content, syllable_count))
+}
+
+/// 校验 LLM 给出的整句: 取第一行非空内容，去掉列表前缀、引号和句末标点，
+/// 必须全部是汉字且字数等于音节数（上屏后按字数消耗音节）
+pub fn validate_sentence(content: &str, syllable_count: usize) -> Option<String> {
+    let line = content.lines().map(|l| strip_list_prefix(l.trim())).find(|l| !l.is_empty())?;
+    let sentence = line
+        .trim_matches(['"', '\'', '“', '”', '「', '」'])
+        .trim_end_matches(['。', '！', '？', '.', '!', '?']);
+    let all_han = sentence.chars().all(is_han);
+    (all_han && sentence.chars().count() == syllable_count).then(|| sentence.to_string())
+}
+
+/// POST `{endpoint}/chat/completions`，返回 choices[0].message.content
+fn post_chat(
+    cfg: &crate::config::AiConfig,
+    messages: serde_json::Value,
+    max_tokens: u32,
+) -> Result<String, String> {
+    let url = format!("{}/chat/completions", cfg.endpoint.trim_end_matches('/'));
+    let mut body = serde_json::json!({
+        "messages": messages,
+        "max_tokens": max_tokens,
+        "temperature": 0,
+    });
+    if !cfg.model.is_empty() {
+        body["model"] = serde_json::Value::String(cfg.model.clone());
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_millis(cfg.request_timeout_ms))
+        .build();
+
+    let mut retried = false;
+    let resp = loop {
+        let mut req = agent.post(&url);
+        if !cfg.api_key.is_empty() {
+            req = req.set("Authorization", &format!("Bearer {}", cfg.api_key));
+        }
+        match req.send_json(body.clone()) {
+            Ok(r) => break r,
+            Err(ureq::Error::Transport(t)) if !retried && is_retryable(&t) =>
+            {
+                eprintln!("[AI] 外部接口连接失败, 重试一次: {}", t);
+                retried = true;
+            }
+            Err(e) => return Err(format!("{}", e)),
+        }
+    };
+
+    let json: serde_json::Value = resp.into_json()
+        .map_err(|e| format!("响应解析失败: {}", e))?;
+    json["choices"][0]["message"]["content"].as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| "响应缺少 choices[0].message.content".to_string())
+}
+
+/// 只重试连接被拒绝 / 被重置（服务刚启动、连接池里的旧连接）；超时再等一轮只会让候选更晚出来
+///
+/// 新建连接失败报 `ConnectionFailed`，复用的 keep-alive 连接被对端重置报 `Io`
+fn is_retryable(t: &ureq::Transport) -> bool {
+    matches!(t.kind(), ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io) && is_refused_or_reset(t)
+}
+
+/// 沿 source 链找底层 io::Error，判断是否为拒绝 / 重置
+fn is_refused_or_reset(err: &dyn std::error::Error) -> bool {
+    use std::io::ErrorKind;
+    let mut cause = err.source();
+    while let Some(e) = cause {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            return matches!(io.kind(), ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted);
+        }
+        cause = e.source();
+    }
+    false
+}
+
+/// 构造 user message，格式与 parse_user_message 对应
+fn build_user_message(pinyin: &str, context: &str, dict_words: &[String], top_k: usize) -> String {
+    format!("拼音：{}，上文：{}，候选：{}，需要{}个",
+        pinyin, context, dict_words.join("|"), top_k)
+}
+
+/// 合并本地与外部候选 (`ai.source = "ensemble"`)
+///
+/// 规则:
+/// 1. 两路都给出的词 (共识) 排最前，按两路名次之和升序，相同时本地名次靠前者优先
+/// 2. 其余词按 本地、外部、本地、外部… 交替插入，某一路用完后接上另一路剩余部分
+/// 3. 跨来源去重，保留首次出现的位置
+pub fn ensemble_merge(local: &[String], external: &[String]) -> Vec<String> {
+    let ext_rank: std::collections::HashMap<&str, usize> = external.iter().enumerate()
+        .rev()
+        .map(|(i, w)| (w.as_str(), i))
+        .collect();
+
+    let mut agreed: Vec<(usize, usize, &String)> = local.iter().enumerate()
+        .filter_map(|(i, w)| ext_rank.get(w.as_str()).map(|&j| (i + j, i, w)))
+        .collect();
+    agreed.sort();
+
+    let mut merged = Vec::with_capacity(local.len() + external.len());
+    let mut seen = std::collections::HashSet::new();
+    for (_, _, w) in agreed {
+        if seen.insert(w.as_str()) { merged.push(w.clone()); }
+    }
+
+    let rest = |v: &[String]| -> Vec<String> {
+        v.iter().filter(|w| !seen.contains(w.as_str())).cloned().collect()
+    };
+    let (local_rest, external_rest) = (rest(local), rest(external));
+    let (mut li, mut ei) = (local_rest.iter(), external_rest.iter());
+    loop {
+        let (l, e) = (li.next(), ei.next());
+        if l.is_none() && e.is_none() { break; }
+        for w in [l, e].into_iter().flatten() {
+            if seen.insert(w.as_str()) { merged.push(w.clone()); }
+        }
+    }
+    merged
+}
+
 // ============================================================
 // 工具函数
 // ============================================================
@@ -302,4 +565,100 @@ fn mime_type(path: &str) -> &'static str {
     else { "application/octet-stream" }
 }
 
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_message_roundtrip() {
+        let words = vec!["你好".to_string(), "拟好".to_string()];
+        let msg = build_user_message("nihao", "我今天", &words, 5);
+        assert_eq!(parse_user_message(&msg),
+            ("nihao".to_string(), "我今天".to_string(), words, 5));
+    }
 
+    #[test]
+    fn test_parse_completion_content() {
+        let content = "1. 你好:0.9\n2、拟好\n- 逆号：0.1\n";
+        assert_eq!(parse_completion_content(content), vec!["你好", "拟好", "逆号"]);
+    }
+
+    #[test]
+    fn test_parse_completion_content_messy() {
+        let content = "好的，以下是\"nihao\"的候选：\n\n\
+            ## 候选\n\
+            1. **你好**：0.92\n\
+            2. `拟好`\n\
+            3. <b>泥好</b>\n\
+            4. “你号”\n\
+            - 你好\n\
+            > 卡拉OK\n\
+            5. hello\n\
+            6. 你好，这是一个非常常见的问候语，适用于各种场合\n\
+            7. 你 好\n\
+            <script>alert(1)</script>\n\
+            希望对你有帮助！";
+        assert_eq!(parse_completion_content(content), vec!["你好", "拟好", "泥好", "你号", "卡拉OK"]);
+
+        // 超长与总数上限
+        assert!(parse_completion_content(&"字".repeat(MAX_CANDIDATE_CHARS + 1)).is_empty());
+        let many: String = (0..20).map(|i| format!("{}. 词{}\n", i, i)).collect();
+        assert_eq!(parse_completion_content(&many).len(), MAX_COMPLETION_CANDIDATES);
+    }
+
+    #[test]
+    fn test_ensemble_merge() {
+        let v = |xs: &[&str]| xs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let local = v(&["你好", "拟好", "泥好", "你号"]);
+        let external = v(&["拟好", "逆号", "你好", "你好"]);
+        // 共识: 拟好(1+0) 你好(0+2); 其余交替: 泥好 逆号 你号
+        assert_eq!(ensemble_merge(&local, &external), v(&["拟好", "你好", "泥好", "逆号", "你号"]));
+        assert_eq!(ensemble_merge(&local, &[]), local);
+    }
+
+    #[test]
+    fn test_validate_sentence() {
+        let ok = Some("我们今天去公园".to_string());
+        assert_eq!(validate_sentence("我们今天去公园。\n", 7), ok);
+        assert_eq!(validate_sentence("\n1. “我们今天去公园”", 7), ok);
+        // 字数不符 / 夹杂非汉字 / 解释性回复 → 丢弃
+        assert_eq!(validate_sentence("我们今天去公园", 6), None);
+        assert_eq!(validate_sentence("我们今天去park", 7), None);
+        assert_eq!(validate_sentence("好的，这句拼音的意思是：我们今天去公园", 7), None);
+        assert_eq!(validate_sentence("", 4), None);
+    }
+
+    #[test]
+    fn test_retry_only_refused_or_reset() {
+        /// 模拟 ureq 的传输错误: 底层原因是 io::Error
+        #[derive(Debug)]
+        struct Transport(std::io::Error);
+        impl std::fmt::Display for Transport {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { write!(f, "connection failed") }
+        }
+        impl std::error::Error for Transport {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { Some(&self.0) }
+        }
+        let err = |kind| Transport(std::io::Error::from(kind));
+        assert!(is_refused_or_reset(&err(std::io::ErrorKind::ConnectionRefused)));
+        assert!(is_refused_or_reset(&err(std::io::ErrorKind::ConnectionReset)));
+        assert!(!is_refused_or_reset(&err(std::io::ErrorKind::TimedOut)));
+    }
+
+    #[test]
+    fn test_retry_reset_on_reused_connection() {
+        // 连接池里的旧连接被重置时 ureq 报 ErrorKind::Io
+        let io = |kind| match ureq::Error::from(std::io::Error::from(kind)) {
+            ureq::Error::Transport(t) => t,
+            e => panic!("expected transport error, got {}", e),
+        };
+        let reset = io(std::io::ErrorKind::ConnectionReset);
+        assert_eq!(reset.kind(), ureq::ErrorKind::Io);
+        assert!(is_retryable(&reset));
+        assert!(!is_retryable(&io(std::io::ErrorKind::TimedOut)));
+    }
+}
@@ -0,0 +1,165 @@
+//! # 英文直通: 句首大写与双击空格
+//!
+//! 英文模式下按键原样放行，这里只跟踪句子状态并在两处改写（`engine.english_autocaps`）:
+//! - 句末标点 `.` `!` `?` 之后隔了空格（或回车）的第一个小写字母 → 大写
+//! - 单词后连按两次空格 → 第二次改为 ". "（手机键盘的习惯）
+//!
+//! 只看按键，不知道光标前原有的文字，所以焦点切换、方向键、鼠标等无法跟踪的操作
+//! 一律回到"未知"状态，不做改写。自动大写后紧跟退格视为用户不想要，重打的字母不再改写。
+
+/// 改写动作: 先退格 `backspaces` 次，再输出 `text`（原按键吃掉）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rewrite {
+    pub backspaces: usize,
+    pub text: String,
+}
+
+/// 英文模式下一次按键的归类
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    /// 字母或数字（已按 Shift / Caps Lock 算好大小写）
+    Char(char),
+    /// 句末标点 . ! ?
+    Terminator,
+    Space,
+    Enter,
+    Backspace,
+    /// 修饰键、自己注入的字符等，不影响状态
+    Neutral,
+    /// 其他键（方向键、快捷键、其他标点…），光标位置不再可知
+    Other,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AutoCaps {
+    /// 下一个字母位于句首
+    sentence_start: bool,
+    /// 上一键是句末标点
+    after_terminator: bool,
+    /// 上一键是字母 / 数字
+    after_word: bool,
+    /// 上一键是紧跟在单词后的空格
+    space_after_word: bool,
+    /// 上一键被自动大写
+    just_capitalized: bool,
+    /// 自动大写被退格撤销，下一个字母保持原样
+    suppress: bool,
+}
+
+impl AutoCaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 回到未知状态（焦点切换、切换中英文时调用）
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// 处理一次按键，需要改写时返回改写动作
+    pub fn on_key(&mut self, key: Key) -> Option<Rewrite> {
+        let just_capitalized = std::mem::take(&mut self.just_capitalized);
+        match key {
+            Key::Neutral => {
+                self.just_capitalized = just_capitalized;
+                None
+            }
+            Key::Char(ch) => {
+                let rewrite = (self.sentence_start && !self.suppress && ch.is_ascii_lowercase())
+                    .then(|| Rewrite { backspaces: 0, text: ch.to_ascii_uppercase().to_string() });
+                *self = Self { after_word: true, just_capitalized: rewrite.is_some(), ..Self::default() };
+                rewrite
+            }
+            Key::Terminator => {
+                *self = Self { after_terminator: true, ..Self::default() };
+                None
+            }
+            Key::Space => {
+                if self.space_after_word {
+                    *self = Self { sentence_start: true, ..Self::default() };
+                    return Some(Rewrite { backspaces: 1, text: ". ".to_string() });
+                }
+                *self = Self {
+                    sentence_start: self.sentence_start || self.after_terminator,
+                    space_after_word: self.after_word,
+                    ..Self::default()
+                };
+                None
+            }
+            Key::Enter => {
+                *self = Self { sentence_start: true, ..Self::default() };
+                None
+            }
+            Key::Backspace if just_capitalized => {
+                *self = Self { sentence_start: true, suppress: true, ..Self::default() };
+                None
+            }
+            Key::Backspace | Key::Other => {
+                self.reset();
+                None
+            }
+        }
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 逐键模拟，返回应用实际收到的文本
+    fn typed(caps: &mut AutoCaps, text: &str) -> String {
+        let mut out = String::new();
+        for ch in text.chars() {
+            let key = match ch {
+                '.' | '!' | '?' => Key::Terminator,
+                ' ' => Key::Space,
+                '\n' => Key::Enter,
+                '\u{8}' => Key::Backspace,
+                c if c.is_ascii_alphanumeric() => Key::Char(c),
+                _ => Key::Other,
+            };
+            match caps.on_key(key) {
+                Some(r) => {
+                    for _ in 0..r.backspaces { out.pop(); }
+                    out.push_str(&r.text);
+                }
+                None if ch == '\u{8}' => { out.pop(); }
+                None => out.push(ch),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_capitalize_after_terminator() {
+        let mut c = AutoCaps::new();
+        assert_eq!(typed(&mut c, "hi. how are you? fine!  ok"), "hi. How are you? Fine!  Ok");
+        // 标点后没有空格的不算句末: 小数、网址
+        c.reset();
+        assert_eq!(typed(&mut c, "pi 3.14 a.com"), "pi 3.14 a.com");
+        c.reset();
+        assert_eq!(typed(&mut c, "line\nnext"), "line\nNext");
+    }
+
+    #[test]
+    fn test_double_space_inserts_period() {
+        let mut c = AutoCaps::new();
+        assert_eq!(typed(&mut c, "hello  world"), "hello. World");
+        // 标点后的双空格不再补句号
+        c.reset();
+        assert_eq!(typed(&mut c, "hi.  there"), "hi.  There");
+    }
+
+    #[test]
+    fn test_backspace_undoes_autocaps() {
+        let mut c = AutoCaps::new();
+        assert_eq!(typed(&mut c, "ok. i\u{8}iphone"), "ok. iphone");
+        // 其他键让状态回到未知
+        c.reset();
+        assert_eq!(typed(&mut c, "ok. ,x"), "ok. ,x");
+    }
+}
@@ -0,0 +1,80 @@
+//! # 开机自启
+//!
+//! 在 `HKCU\Software\Microsoft\Windows\CurrentVersion\Run` 下写入 / 删除 `AiPinyin` 项，
+//! 值为当前 exe 的完整路径（带引号）。只写当前用户，不需要管理员权限。
+//!
+//! 开启自启（`engine.autostart = true`）时每次启动都重写一遍，exe 被移动后旧路径自动修正。
+
+use std::path::Path;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+use windows::Win32::System::Registry::*;
+
+const RUN_KEY: PCWSTR = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+const VALUE_NAME: PCWSTR = w!("AiPinyin");
+
+/// Run 项中是否已有 AiPinyin（设置页打开时据此显示开关状态）
+pub fn is_enabled() -> bool {
+    unsafe {
+        RegGetValueW(HKEY_CURRENT_USER, RUN_KEY, VALUE_NAME, RRF_RT_REG_SZ, None, None, None)
+            == ERROR_SUCCESS
+    }
+}
+
+/// 写入（指向当前 exe）或删除 Run 项；删除时项本就不存在视为成功
+pub fn set_enabled(enabled: bool) -> Result<(), String> {
+    let command = if enabled {
+        let exe = std::env::current_exe().map_err(|e| format!("无法获取 exe 路径: {}", e))?;
+        Some(run_command(&exe))
+    } else {
+        None
+    };
+
+    unsafe {
+        let mut key = HKEY::default();
+        RegOpenKeyExW(HKEY_CURRENT_USER, RUN_KEY, 0, KEY_SET_VALUE, &mut key)
+            .ok().map_err(|e| format!("打开 Run 键失败: {}", e))?;
+
+        let result = match &command {
+            Some(cmd) => {
+                let wide: Vec<u16> = cmd.encode_utf16().chain(Some(0)).collect();
+                let bytes = std::slice::from_raw_parts(wide.as_ptr() as *const u8, wide.len() * 2);
+                RegSetValueExW(key, VALUE_NAME, 0, REG_SZ, Some(bytes))
+            }
+            None => match RegDeleteValueW(key, VALUE_NAME) {
+                ERROR_FILE_NOT_FOUND => ERROR_SUCCESS,
+                e => e,
+            },
+        };
+        let _ = RegCloseKey(key);
+        result.ok().map_err(|e| format!("写入 Run 项失败: {}", e))?;
+    }
+
+    match &command {
+        Some(cmd) => eprintln!("[Autostart] ✅ 已登记开机自启: {}", cmd),
+        None => eprintln!("[Autostart] 已取消开机自启"),
+    }
+    Ok(())
+}
+
+/// Run 项的值: 带引号的 exe 路径（路径含空格时也能正确启动）
+fn run_command(exe: &Path) -> String {
+    format!("\"{}\"", exe.display())
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command_quoted() {
+        assert_eq!(
+            run_command(Path::new(r"C:\Program Files\AiPinyin\aipinyin.exe")),
+            r#""C:\Program Files\AiPinyin\aipinyin.exe""#
+        );
+    }
+}
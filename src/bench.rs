@@ -0,0 +1,107 @@
+//! # 候选生成基准
+//!
+//! `aipinyin.exe --bench [轮数]`: 用固定的拼音语料依次跑 `PinyinEngine::get_candidates`
+//! 和（模型可用时）`AIPredictor::predict`，打印每条输入与汇总的 p50 / p95 / 总耗时。
+//! 加载的是正式的 dict.bin 与模型，数字可以直接和其他版本对比。
+
+use std::time::{Duration, Instant};
+use crate::ai_engine::{percentile, AIPredictor};
+use crate::pinyin::PinyinEngine;
+
+/// 固定语料: 单字、词、缩写、长句各占一部分
+pub const CORPUS: &[&str] = &[
+    "ni", "hao", "shi", "de", "zhong",
+    "nihao", "women", "shijian", "zhongguo", "xiexie", "diannao", "xian", "pinyin",
+    "nh", "sj", "zgrm", "bjdx",
+    "jintiantianqibucuo", "wojuedezhegefangfakeyi", "pinyinshurufa", "ruanjiankaifagongchengshi",
+    "womenyiqiquchifanba", "zhegewentiyijingjiejuele",
+];
+
+/// 一条输入各轮的耗时
+pub struct Sample {
+    pub input: String,
+    pub dict: Vec<Duration>,
+    /// 模型不可用时为空
+    pub ai: Vec<Duration>,
+    /// 最后一轮的字典候选数
+    pub candidates: usize,
+}
+
+/// 每条输入跑 `rounds` 轮（另有一轮预热不计入）；AI 预测以字典候选为参考，不带上下文
+pub fn run(engine: &mut PinyinEngine, mut ai: Option<&mut AIPredictor>, corpus: &[&str], rounds: usize, top_k: usize) -> Vec<Sample> {
+    corpus.iter().map(|&input| {
+        engine.clear();
+        for ch in input.chars() { engine.push(ch); }
+        let mut sample = Sample { input: input.to_string(), dict: vec![], ai: vec![], candidates: 0 };
+        for round in 0..=rounds {
+            let t = Instant::now();
+            let dict = engine.get_candidates();
+            let dict_time = t.elapsed();
+            let ai_time = ai.as_deref_mut().map(|pred| {
+                let t = Instant::now();
+                pred.predict(input, "", top_k, &dict);
+                t.elapsed()
+            });
+            if round == 0 { continue; }
+            sample.dict.push(dict_time);
+            sample.ai.extend(ai_time);
+            sample.candidates = dict.len();
+        }
+        sample
+    }).collect()
+}
+
+/// 每条输入一行（各轮 p50），最后是全部采样的 p50 / p95 / 总计
+pub fn report(samples: &[Sample]) -> String {
+    let mut out = format!("  {:<28} {:>6} {:>12} {:>12}\n", "输入", "候选", "字典 p50", "AI p50");
+    for s in samples {
+        out += &format!("  {:<28} {:>6} {:>12} {:>12}\n", s.input, s.candidates, fmt(p50(&s.dict)), fmt(p50(&s.ai)));
+    }
+    let dict: Vec<Duration> = samples.iter().flat_map(|s| s.dict.iter().copied()).collect();
+    let ai: Vec<Duration> = samples.iter().flat_map(|s| s.ai.iter().copied()).collect();
+    for (name, mut all) in [("字典", dict), ("AI", ai)] {
+        if all.is_empty() {
+            out += &format!("  {}: 未运行\n", name);
+            continue;
+        }
+        all.sort_unstable();
+        out += &format!("  {}: {} 次  p50 {}  p95 {}  总计 {}\n", name, all.len(),
+            fmt(Some(percentile(&all, 50))), fmt(Some(percentile(&all, 95))), fmt(Some(all.iter().sum::<Duration>())));
+    }
+    out
+}
+
+fn p50(samples: &[Duration]) -> Option<Duration> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    (!sorted.is_empty()).then(|| percentile(&sorted, 50))
+}
+
+fn fmt(d: Option<Duration>) -> String {
+    d.map_or_else(|| "-".to_string(), |d| format!("{:.3}ms", d.as_secs_f64() * 1000.0))
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pinyin::Dictionary;
+
+    #[test]
+    fn test_run_and_report_dict_only() {
+        let mut engine = PinyinEngine::with_dict(Dictionary::from_text("nihao,你好,900\nni,你,900\nhao,好,800\n"));
+        let samples = run(&mut engine, None, &["nihao", "ni"], 3, 9);
+        assert_eq!(samples.len(), 2);
+        assert!(samples.iter().all(|s| s.dict.len() == 3 && s.ai.is_empty()));
+        assert_eq!(samples[0].input, "nihao");
+        assert!(samples.iter().all(|s| s.candidates > 0));
+
+        let text = report(&samples);
+        assert_eq!(text.lines().count(), 1 + 2 + 2);
+        assert!(text.contains("nihao") && text.contains("字典: 6 次"));
+        assert!(text.contains("AI: 未运行"));
+    }
+}
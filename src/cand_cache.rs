@@ -0,0 +1,82 @@
+//! # 候选缓存
+//!
+//! 一次输入过程中按拼音记住最终拼好的候选列表（字典 + AI + 插件 + 学习提权），
+//! 退格回到之前出现过的前缀时直接重绘，不再查字典、跑 AI。
+//!
+//! 上屏、学习 / 撤销学习、切换引擎模式、配置变化等会改变排序的操作后整体清空。
+
+use std::collections::VecDeque;
+
+/// 最多记住的拼音前缀数
+const MAX_ENTRIES: usize = 32;
+
+#[derive(Debug, Default)]
+pub struct CandidateCache {
+    /// (拼音, 候选列表)，最近写入的在队尾
+    entries: VecDeque<(String, Vec<String>)>,
+}
+
+impl CandidateCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, raw: &str) -> Option<&[String]> {
+        self.entries.iter().find(|(r, _)| r == raw).map(|(_, c)| c.as_slice())
+    }
+
+    /// 记录（或更新）某个拼音的候选；超出上限时丢掉最早的
+    pub fn put(&mut self, raw: &str, candidates: &[String]) {
+        self.entries.retain(|(r, _)| r != raw);
+        if candidates.is_empty() { return; }
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((raw.to_string(), candidates.to_vec()));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(ws: &[&str]) -> Vec<String> {
+        ws.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_put_get_update() {
+        let mut c = CandidateCache::new();
+        c.put("ni", &words(&["你", "泥"]));
+        c.put("nih", &words(&["你好"]));
+        assert_eq!(c.get("ni"), Some(&words(&["你", "泥"])[..]));
+        assert_eq!(c.get("nihao"), None);
+
+        // AI 结果到达后覆盖同一拼音
+        c.put("ni", &words(&["泥", "你"]));
+        assert_eq!(c.get("ni"), Some(&words(&["泥", "你"])[..]));
+        assert_eq!(c.entries.len(), 2);
+
+        c.clear();
+        assert_eq!(c.get("nih"), None);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut c = CandidateCache::new();
+        for i in 0..MAX_ENTRIES + 5 {
+            c.put(&format!("k{}", i), &words(&["x"]));
+        }
+        assert_eq!(c.entries.len(), MAX_ENTRIES);
+        assert_eq!(c.get("k0"), None);
+        assert!(c.get(&format!("k{}", MAX_ENTRIES + 4)).is_some());
+    }
+}
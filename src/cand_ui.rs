@@ -0,0 +1,92 @@
+//! # 候选窗接口
+//!
+//! 输入逻辑 (main.rs) 通过 [`CandidateUi`] 操作候选窗，不关心后端:
+//!
+//!   后端                                 `ui.renderer`
+//!   `webview_ui::WebViewUI`（默认）      "webview" — 透明 WebView2 输入条，支持主题与拖动
+//!   `gdi_ui::CandidateWindow`            "gdi" — 不透明的经典 GDI 窗口；WebView 创建失败时也自动退回
+//!
+//! 新后端（如外部浮层）实现这几个方法即可；`ImeState::cand_win` 始终有一个后端，调用方不必判空。
+
+pub trait CandidateUi {
+    /// 在 (x, y) 显示；`caret` 表示坐标来自真实光标（此时叠加用户拖出来的偏移）
+    fn show(&self, x: i32, y: i32, caret: bool);
+
+    fn hide(&self);
+
+    /// `sentence`: 首个候选为整句建议（候选窗给出标记）
+    /// `frequency`: 各候选的相对词频 (0~1)，为空则不显示
+    /// `selected`: 高亮的候选（页内序号）
+    fn update_candidates_with_page(
+        &self, raw: &str, candidates: &[&str], page_info: Option<(usize, usize)>,
+        sentence: bool, frequency: &[f32], selected: usize,
+    );
+
+    /// 有插件激活时显示 JS 标记
+    fn set_plugins_active(&self, active: bool);
+
+    /// 热键切换引擎模式后更新模式标记
+    fn set_engine_mode(&self, ai: bool, available: bool);
+
+    /// 只显示一行文字、不带候选（中/英切换等模式提示）
+    fn show_text(&self, text: &str) {
+        self.update_candidates_with_page(text, &[], None, false, &[], 0);
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// 记录收到的调用
+    #[derive(Default)]
+    struct Recorder {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl CandidateUi for Recorder {
+        fn show(&self, x: i32, y: i32, caret: bool) {
+            self.calls.borrow_mut().push(format!("show {} {} {}", x, y, caret));
+        }
+        fn hide(&self) {
+            self.calls.borrow_mut().push("hide".into());
+        }
+        fn update_candidates_with_page(
+            &self, raw: &str, candidates: &[&str], page_info: Option<(usize, usize)>,
+            _sentence: bool, _frequency: &[f32], selected: usize,
+        ) {
+            self.calls.borrow_mut().push(format!("update {} {:?} {:?} {}", raw, candidates, page_info, selected));
+        }
+        fn set_plugins_active(&self, active: bool) {
+            self.calls.borrow_mut().push(format!("plugins {}", active));
+        }
+        fn set_engine_mode(&self, ai: bool, available: bool) {
+            self.calls.borrow_mut().push(format!("mode {} {}", ai, available));
+        }
+    }
+
+    #[test]
+    fn test_dyn_dispatch_and_show_text() {
+        let rec = Recorder::default();
+        {
+            let ui: &dyn CandidateUi = &rec;
+            ui.update_candidates_with_page("nihao", &["你好", "拟好"], Some((1, 2)), false, &[], 1);
+            ui.show(10, 20, true);
+            ui.show_text("中");
+            ui.set_engine_mode(true, false);
+            ui.hide();
+        }
+        assert_eq!(rec.calls.into_inner(), [
+            "update nihao [\"你好\", \"拟好\"] Some((1, 2)) 1",
+            "show 10 20 true",
+            "update 中 [] None 0",
+            "mode true false",
+            "hide",
+        ]);
+    }
+}
@@ -0,0 +1,142 @@
+//! # 剪贴板粘贴上屏
+//!
+//! 个别程序（部分游戏、老式控件）完全不认 `KEYEVENTF_UNICODE`，上屏的中文不会出现。
+//! 前台程序在 `inject.paste_fallback_apps` 中时改为: 暂存剪贴板 → 写入文字 → 发送 Ctrl+V → 稍后恢复原内容。
+//!
+//! 原内容按格式逐个复制（文本、CF_DIB 图片、HTML、文件列表等基于全局内存的格式）；
+//! CF_BITMAP 等 GDI 句柄格式无法按字节复制，恢复后缺失（同一张图的 CF_DIB 仍在）。
+
+use std::time::Duration;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HANDLE, HGLOBAL, HWND};
+use windows::Win32::System::DataExchange::*;
+use windows::Win32::System::Memory::*;
+use windows::Win32::UI::WindowsAndMessaging::{CreateWindowExW, DestroyWindow, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE};
+
+const CF_UNICODETEXT: u32 = 13;
+/// 目标程序处理完 Ctrl+V 才会去读剪贴板，等这么久再恢复
+pub const RESTORE_DELAY: Duration = Duration::from_millis(300);
+/// 剪贴板被其他程序占用时的重试次数（每次间隔 10ms）
+const OPEN_RETRIES: usize = 5;
+
+/// 剪贴板原内容: (格式, 数据)
+pub struct Snapshot(Vec<(u32, Vec<u8>)>);
+
+/// 数据是 GDI 句柄或私有句柄而不是全局内存的格式，不能按字节复制
+fn is_handle_format(format: u32) -> bool {
+    // CF_BITMAP CF_METAFILEPICT CF_PALETTE CF_ENHMETAFILE CF_OWNERDISPLAY CF_DSP*
+    matches!(format, 2 | 3 | 9 | 14 | 0x80 | 0x82 | 0x83 | 0x8E)
+        // CF_PRIVATEFIRST..CF_GDIOBJLAST
+        || (0x200..=0x3FF).contains(&format)
+}
+
+/// 以临时的消息窗口为所有者打开剪贴板执行 `f`（所有者为空时 SetClipboardData 会失败）
+unsafe fn with_clipboard<R>(f: impl FnOnce() -> R) -> Option<R> {
+    let owner = CreateWindowExW(
+        WINDOW_EX_STYLE(0), w!("STATIC"), PCWSTR::null(), WINDOW_STYLE(0),
+        0, 0, 0, 0, HWND_MESSAGE, None, None, None,
+    ).ok()?;
+    let mut opened = false;
+    for i in 0..OPEN_RETRIES {
+        if i > 0 { std::thread::sleep(Duration::from_millis(10)); }
+        if OpenClipboard(owner).is_ok() { opened = true; break; }
+    }
+    let result = opened.then(|| {
+        let r = f();
+        let _ = CloseClipboard();
+        r
+    });
+    let _ = DestroyWindow(owner);
+    result
+}
+
+/// 复制当前剪贴板的全部可复制格式（剪贴板打不开时 None）
+pub unsafe fn snapshot() -> Option<Snapshot> {
+    with_clipboard(|| {
+        let mut items = Vec::new();
+        let mut format = EnumClipboardFormats(0);
+        while format != 0 {
+            if !is_handle_format(format) {
+                if let Some(bytes) = GetClipboardData(format).ok().and_then(|h| read_global(HGLOBAL(h.0))) {
+                    items.push((format, bytes));
+                }
+            }
+            format = EnumClipboardFormats(format);
+        }
+        Snapshot(items)
+    })
+}
+
+/// 用 `text` 替换剪贴板内容，返回写入后的剪贴板序号（恢复前用来判断期间是否被别人改过）
+pub unsafe fn set_text(text: &str) -> Option<u32> {
+    let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    let bytes = std::slice::from_raw_parts(wide.as_ptr() as *const u8, wide.len() * 2);
+    let ok = with_clipboard(|| {
+        EmptyClipboard().is_ok() && put_global(CF_UNICODETEXT, bytes)
+    })?;
+    ok.then(GetClipboardSequenceNumber)
+}
+
+/// 放回暂存的内容；`seq` 之后剪贴板又被改过（用户复制了别的东西）则不动
+pub unsafe fn restore(snapshot: Snapshot, seq: u32) {
+    if GetClipboardSequenceNumber() != seq {
+        eprintln!("[Paste] 剪贴板已被其他程序修改，不恢复原内容");
+        return;
+    }
+    let restored = with_clipboard(|| {
+        let _ = EmptyClipboard();
+        snapshot.0.iter().filter(|(format, bytes)| put_global(*format, bytes)).count()
+    });
+    match restored {
+        Some(n) => log::debug!("[Paste] 已恢复剪贴板 {}/{} 种格式", n, snapshot.0.len()),
+        None => eprintln!("[Paste] ⚠ 剪贴板被占用，未能恢复原内容"),
+    }
+}
+
+unsafe fn read_global(h: HGLOBAL) -> Option<Vec<u8>> {
+    let size = GlobalSize(h);
+    let ptr = GlobalLock(h) as *const u8;
+    if ptr.is_null() { return None; }
+    let bytes = std::slice::from_raw_parts(ptr, size).to_vec();
+    let _ = GlobalUnlock(h);
+    Some(bytes)
+}
+
+/// 复制到新分配的全局内存并交给剪贴板（成功后内存归系统所有）
+unsafe fn put_global(format: u32, bytes: &[u8]) -> bool {
+    let Ok(h) = GlobalAlloc(GMEM_MOVEABLE, bytes.len().max(1)) else { return false };
+    let ptr = GlobalLock(h) as *mut u8;
+    if ptr.is_null() {
+        let _ = GlobalFree(h);
+        return false;
+    }
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+    let _ = GlobalUnlock(h);
+    if SetClipboardData(format, HANDLE(h.0)).is_ok() {
+        true
+    } else {
+        let _ = GlobalFree(h);
+        false
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_formats_skipped() {
+        // CF_TEXT、CF_DIB、CF_UNICODETEXT、CF_HDROP 与注册格式（HTML Format 等）按字节复制
+        for f in [1, 8, 13, 15, 0xC0F0] {
+            assert!(!is_handle_format(f), "{}", f);
+        }
+        // CF_BITMAP、CF_ENHMETAFILE、私有 / GDI 对象格式跳过
+        for f in [2, 14, 0x200, 0x300, 0x3FF] {
+            assert!(is_handle_format(f), "{}", f);
+        }
+    }
+}
@@ -4,7 +4,15 @@
 //! 文件不存在时使用默认值。
 
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// 共享的当前生效配置（热重载时整体替换）
+pub type SharedConfig = Arc<Mutex<Config>>;
+
+/// config.toml 修改检测间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// 顶层配置
 #[derive(Debug, Deserialize, Clone)]
@@ -17,6 +25,26 @@ pub struct Config {
     pub ui: UiConfig,
     #[serde(default)]
     pub dict: DictConfig,
+    #[serde(default)]
+    pub guardian: crate::guardian::GuardianConfig,
+    #[serde(default)]
+    pub keys: KeysConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub fuzzy: FuzzyConfig,
+    #[serde(default)]
+    pub inject: InjectConfig,
+    #[serde(default)]
+    pub user_dict: UserDictConfig,
+    #[serde(default)]
+    pub segmentation: SegmentationConfig,
+    #[serde(default)]
+    pub symbols: SymbolsConfig,
+    #[serde(default)]
+    pub merge: MergeConfig,
+    #[serde(default)]
+    pub stream: crate::stream::StreamConfig,
 }
 
 /// 引擎模式
@@ -36,14 +64,185 @@ impl Default for EngineMode {
 pub struct EngineConfig {
     #[serde(default)]
     pub mode: EngineMode,
+    /// 前台为全屏窗口（游戏等）时暂停按键拦截
+    ///
+    /// 以系统的"全屏程序运行中"通知状态为准（D3D 独占全屏 / 全屏演示）
+    #[serde(default = "default_pause_in_fullscreen")]
+    pub pause_in_fullscreen: bool,
+    /// 铺满显示器的无边框窗口也算全屏（最大化或带标题栏的窗口除外）
+    ///
+    /// 部分无边框窗口化游戏系统不报全屏；但 F11 浏览器、一些编辑器也是这样，默认关闭
+    #[serde(default)]
+    pub pause_in_borderless: bool,
+    /// 焦点在密码框时不拦截任何按键
+    #[serde(default = "default_disable_in_password")]
+    pub disable_in_password: bool,
+    /// 只剩唯一候选且音节闭合时自动上屏（短暂延迟内继续输入则取消）
+    #[serde(default)]
+    pub auto_commit_single: bool,
+    /// 双拼方案（"none" = 全拼）
+    #[serde(default)]
+    pub shuangpin: ShuangpinScheme,
+    /// 字典候选总数上限（供翻页）；调大可多翻几页，调小查找更快
+    #[serde(default = "default_max_candidates")]
+    pub max_candidates: usize,
+    /// 歧义切分备选数上限（"xian" 另查 "xi'an" 等）；0 = 不做歧义切分
+    #[serde(default = "default_max_ambiguous_splits")]
+    pub max_ambiguous_splits: usize,
+    /// 儿化: "nar" / "zher" 末尾的 r 视为儿化，补出 哪儿 / 这儿
+    #[serde(default)]
+    pub erhua: bool,
+    /// 英文直通: 句末标点加空格后的首字母自动大写，单词后双击空格补 ". "
+    #[serde(default)]
+    pub english_autocaps: bool,
+    /// 登录 Windows 时自动启动（写入当前用户的 Run 注册表项）
+    #[serde(default)]
+    pub autostart: bool,
+    /// 输入为空时打开符号面板的按键: "/" "semicolon" "backquote"，"none" 关闭
+    #[serde(default = "default_symbol_key")]
+    pub symbol_key: String,
+    /// 输入中超过这么多秒没有按键，下一个键先丢弃旧输入（0 = 不启用）
+    #[serde(default)]
+    pub compose_timeout_secs: u64,
+    /// 联想: 上屏后按上下文预测下一个字（AI 可用时用模型，否则取学习词），数字键选择
+    #[serde(default)]
+    pub associate: bool,
+    /// 焦点被弹窗等抢走后这么多毫秒内回到原窗口，恢复未上屏拼音（0 = 离开即丢弃）
+    #[serde(default = "default_focus_restore_ms")]
+    pub focus_restore_ms: u64,
+    /// 按前台程序记住上次用的中 / 英文模式，切回该程序时恢复
+    #[serde(default)]
+    pub remember_mode_per_app: bool,
+    /// 记住的各程序模式（切换中英文时自动写入），键见 [`app_key`]
+    #[serde(default)]
+    pub app_modes: std::collections::HashMap<String, InputMode>,
 }
 
+fn default_pause_in_fullscreen() -> bool { true }
+fn default_disable_in_password() -> bool { true }
+fn default_focus_restore_ms() -> u64 { 3000 }
+fn default_max_candidates() -> usize { crate::pinyin::DEFAULT_MAX_CANDIDATES }
+fn default_max_ambiguous_splits() -> usize { crate::pinyin::DEFAULT_MAX_AMBIGUOUS_SPLITS }
+fn default_symbol_key() -> String { "/".to_string() }
+
 impl Default for EngineConfig {
     fn default() -> Self {
-        Self { mode: EngineMode::Ai }
+        Self {
+            mode: EngineMode::Ai,
+            pause_in_fullscreen: default_pause_in_fullscreen(),
+            pause_in_borderless: false,
+            disable_in_password: default_disable_in_password(),
+            auto_commit_single: false,
+            shuangpin: ShuangpinScheme::Off,
+            max_candidates: default_max_candidates(),
+            max_ambiguous_splits: default_max_ambiguous_splits(),
+            erhua: false,
+            english_autocaps: false,
+            autostart: false,
+            symbol_key: default_symbol_key(),
+            compose_timeout_secs: 0,
+            associate: false,
+            focus_restore_ms: default_focus_restore_ms(),
+            remember_mode_per_app: false,
+            app_modes: std::collections::HashMap::new(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// 符号面板按键的虚拟键码（"none" 或无法识别 = 不启用）
+    pub fn symbol_vk(&self) -> Option<u32> {
+        match self.symbol_key.trim().to_ascii_lowercase().as_str() {
+            "/" | "slash" => Some(0xBF),
+            ";" | "semicolon" => Some(0xBA),
+            "`" | "backquote" => Some(0xC0),
+            "'" | "quote" => Some(0xDE),
+            _ => None,
+        }
+    }
+
+    /// 某程序记住的模式（true = 中文）；未开启 remember_mode_per_app 或没记过时 None
+    pub fn remembered_mode(&self, app: &str) -> Option<bool> {
+        if !self.remember_mode_per_app { return None; }
+        self.app_modes.get(&app_key(app)).map(|&m| m == InputMode::Chinese)
+    }
+
+    /// 记住某程序当前的模式（只改内存中的表）
+    ///
+    /// 表有变化时返回 `(键, 模式)`，由调用方放开配置锁后交给 [`persist_app_mode`] 写盘
+    pub fn remember_mode(&mut self, app: &str, chinese: bool) -> Option<(String, InputMode)> {
+        if !self.remember_mode_per_app { return None; }
+        let key = app_key(app);
+        if key.is_empty() { return None; }
+        let mode = if chinese { InputMode::Chinese } else { InputMode::English };
+        if self.app_modes.insert(key.clone(), mode) == Some(mode) { return None; }
+        Some((key, mode))
     }
 }
 
+/// 把 [`EngineConfig::remember_mode`] 记下的条目写回 `[engine.app_modes]`
+///
+/// 内存中的配置已是最新，这次写入不触发热重载（见 [`watch`]）
+pub fn persist_app_mode(key: &str, mode: InputMode) -> std::io::Result<()> {
+    let path = Config::config_path();
+    let mut self_write = SELF_WRITE.lock().unwrap_or_else(|e| e.into_inner());
+    let before = file_mtime(&path);
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    std::fs::write(&path, set_app_mode_text(&text, key, mode))?;
+    *self_write = Some((before, file_mtime(&path)));
+    Ok(())
+}
+
+/// 在 config.toml 文本里设置 `[engine.app_modes]` 的一项
+fn set_app_mode_text(text: &str, key: &str, mode: InputMode) -> String {
+    let value = match mode {
+        InputMode::Chinese => "\"chinese\"",
+        InputMode::English => "\"english\"",
+    };
+    set_toml_value(text, "engine.app_modes", key, value)
+}
+
+/// 中 / 英文输入模式（`engine.app_modes` 的值）
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMode {
+    Chinese,
+    English,
+}
+
+/// 进程名 (`Code.exe`) → 可作 TOML 裸键的名字 (`code`)
+pub fn app_key(exe: &str) -> String {
+    let lower = exe.to_ascii_lowercase();
+    lower.strip_suffix(".exe").unwrap_or(&lower)
+        .chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-').collect()
+}
+
+/// 双拼方案
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShuangpinScheme {
+    /// 全拼（不启用双拼）
+    #[default]
+    #[serde(rename = "none")]
+    Off,
+    /// 小鹤双拼
+    Xiaohe,
+    /// 自然码
+    Ziranma,
+}
+
+/// AI 候选来源
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AiSource {
+    /// 仅本地 ONNX 模型
+    Local,
+    /// 仅外部 OpenAI 兼容接口
+    External,
+    /// 本地先出结果, 外部结果到达后合并重排
+    Ensemble,
+}
+
 /// AI 配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct AiConfig {
@@ -61,12 +260,49 @@ pub struct AiConfig {
     /// 外部 AI 服务 API Key（本地服务留空）
     #[serde(default)]
     pub api_key: String,
+    /// 外部 AI 模型名（如 Ollama 的 "qwen2.5:7b"，空 = 不指定）
+    #[serde(default)]
+    pub model: String,
+    /// 外部 AI 单次请求超时（毫秒），超时即放弃，保留本地候选
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
     /// 发送给 AI 的系统提示词（空 = 使用内置默认中文提示词）
     #[serde(default)]
     pub system_prompt: String,
+    /// AI 候选来源（不填: 配置了 endpoint 则 external, 否则 local）
+    #[serde(default)]
+    pub source: Option<AiSource>,
+    /// AI 推理防抖（毫秒）: 停止按键这么久后才推理，期间新按键会取消上一次
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// 本地 AI 候选最低置信度（平均每字 logit），低于此值不参与 AI 排序（不填 = 不过滤）
+    #[serde(default)]
+    pub min_confidence: Option<f32>,
+    /// 整句模式（云拼音）: 输入达到 4 个音节且配置了 endpoint 时，
+    /// 额外请求外部接口给出整句，作为首选候选显示
+    #[serde(default)]
+    pub sentence: bool,
+    /// 本地模型文件（空 = exe 同目录 / 当前目录下的 gpt2_int8.onnx；相对路径相对 exe 目录）
+    #[serde(default)]
+    pub model_path: String,
+    /// 词表 JSON 所在目录（空 = exe 同目录；相对路径相对 exe 目录）
+    #[serde(default)]
+    pub vocab_dir: String,
+    /// 启动时用一次空推理预热模型，避免第一次按键卡顿（内存紧张时可关闭）
+    #[serde(default = "default_warmup")]
+    pub warmup: bool,
+    /// 统计各类本地推理的耗时 (p50 / p95)，在 `/v1/status` 的 latency 字段里查看
+    #[serde(default)]
+    pub latency_stats: bool,
 }
 
 fn default_top_k() -> usize { 9 }
+fn default_request_timeout_ms() -> u64 { 1500 }
+fn default_debounce_ms() -> u64 { 60 }
+fn default_warmup() -> bool { true }
+
+/// 整句模式的最少音节数
+pub const SENTENCE_MIN_SYLLABLES: usize = 4;
 
 fn default_system_prompt() -> &'static str {
     "你是拼音输入法候选词排序助手。根据上下文和拼音，从候选列表中选出最合适的词语并排序。\
@@ -81,9 +317,40 @@ impl Default for AiConfig {
             rerank: false,
             endpoint: String::new(),
             api_key: String::new(),
+            model: String::new(),
+            request_timeout_ms: default_request_timeout_ms(),
             system_prompt: String::new(),
+            source: None,
+            debounce_ms: default_debounce_ms(),
+            min_confidence: None,
+            sentence: false,
+            model_path: String::new(),
+            vocab_dir: String::new(),
+            warmup: default_warmup(),
+            latency_stats: false,
+        }
+    }
+}
+
+impl AiConfig {
+    /// 实际发送的系统提示词（未配置时用内置默认提示词）
+    pub fn effective_system_prompt(&self) -> &str {
+        if self.system_prompt.is_empty() { default_system_prompt() } else { &self.system_prompt }
+    }
+
+    /// 实际生效的候选来源（未配置 endpoint 时 external/ensemble 均退化为 local）
+    pub fn effective_source(&self) -> AiSource {
+        match (self.source, self.endpoint.is_empty()) {
+            (_, true) => AiSource::Local,
+            (None, false) => AiSource::External,
+            (Some(s), false) => s,
         }
     }
+
+    /// 该音节数的输入是否请求整句候选
+    pub fn wants_sentence(&self, syllable_count: usize) -> bool {
+        self.sentence && !self.endpoint.is_empty() && syllable_count >= SENTENCE_MIN_SYLLABLES
+    }
 }
 
 
@@ -94,20 +361,101 @@ pub struct UiConfig {
     pub font_size: u32,
     #[serde(default = "default_opacity")]
     pub opacity: u8,
+    /// 每页候选数（选字键只有 1–9，超出范围按 3–9 截断）
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+    /// AI 模型未加载时启动提示一次（提示框中选"不再提示"后写回 false）
+    #[serde(default = "default_ai_unavailable_notice")]
+    pub ai_unavailable_notice: bool,
+    /// 界面文字语言: "zh" / "en"（启动横幅、插件授权对话框等）
+    #[serde(default)]
+    pub language: crate::i18n::Language,
+    /// 在每个候选下方显示本页内的相对词频条
+    #[serde(default)]
+    pub show_frequency: bool,
+    /// 候选窗最大宽度（逻辑像素，0 = 不限制）；超出时把最长的候选截短加省略号，上屏仍是完整文字
+    #[serde(default)]
+    pub max_width_px: u32,
+    /// 候选窗相对光标的偏移（拖动候选窗后自动写入；找不到光标时不使用）
+    #[serde(default)]
+    pub offset_x: i32,
+    #[serde(default)]
+    pub offset_y: i32,
+    /// 各显示器单独记住的偏移 `[x, y]`，键为显示器名（如 DISPLAY1），没有时用 offset_x / offset_y
+    #[serde(default)]
+    pub monitor_offsets: std::collections::HashMap<String, [i32; 2]>,
+    /// 候选窗后端（改后需重启）
+    #[serde(default)]
+    pub renderer: Renderer,
+}
+
+/// 候选窗后端
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Renderer {
+    /// 透明 WebView2 输入条（支持主题、拖动）
+    #[default]
+    Webview,
+    /// 不透明的经典 GDI 窗口（远程桌面 / 老显卡上透明窗口显示成白块时用）
+    Gdi,
 }
 
 fn default_font_size() -> u32 { 16 }
 fn default_opacity() -> u8 { 240 }
+fn default_page_size() -> usize { 9 }
+fn default_ai_unavailable_notice() -> bool { true }
+
+/// 每页候选数允许范围
+pub const PAGE_SIZE_RANGE: std::ops::RangeInclusive<usize> = 3..=9;
 
 impl Default for UiConfig {
     fn default() -> Self {
         Self {
             font_size: default_font_size(),
             opacity: default_opacity(),
+            page_size: default_page_size(),
+            ai_unavailable_notice: default_ai_unavailable_notice(),
+            language: crate::i18n::Language::Zh,
+            show_frequency: false,
+            max_width_px: 0,
+            offset_x: 0,
+            offset_y: 0,
+            monitor_offsets: std::collections::HashMap::new(),
+            renderer: Renderer::Webview,
         }
     }
 }
 
+impl UiConfig {
+    /// 截断到 3–9 后的每页候选数
+    pub fn effective_page_size(&self) -> usize {
+        self.page_size.clamp(*PAGE_SIZE_RANGE.start(), *PAGE_SIZE_RANGE.end())
+    }
+
+    /// 某显示器上候选窗相对光标的偏移: 该显示器记过的优先，否则用全局偏移
+    pub fn window_offset(&self, monitor: &str) -> (i32, i32) {
+        match self.monitor_offsets.get(monitor) {
+            Some(&[x, y]) => (x, y),
+            None => (self.offset_x, self.offset_y),
+        }
+    }
+
+    /// 拖动结束后记住偏移: 写入该显示器的条目，并作为其他显示器的默认偏移
+    pub fn persist_window_offset(monitor: &str, (x, y): (i32, i32)) -> std::io::Result<()> {
+        persist_value("ui", "offset_x", &x.to_string())?;
+        persist_value("ui", "offset_y", &y.to_string())?;
+        if !monitor.is_empty() {
+            persist_value("ui.monitor_offsets", monitor, &format!("[{}, {}]", x, y))?;
+        }
+        Ok(())
+    }
+}
+
+/// 显示器设备名 (`\\.\DISPLAY1`) → 可作 TOML 裸键的名字 (`DISPLAY1`)
+pub fn monitor_key(device: &str) -> String {
+    device.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-').collect()
+}
+
 /// 字典配置
 #[derive(Debug, Deserialize, Clone)]
 pub struct DictConfig {
@@ -122,6 +470,435 @@ impl Default for DictConfig {
     }
 }
 
+/// 上屏键
+///
+/// - `space`（默认）: 空格上屏首选，回车上屏原始字母
+/// - `enter`: 回车上屏首选，空格上屏原始字母并补一个空格（适合中英混打）
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitKey {
+    #[default]
+    Space,
+    Enter,
+}
+
+/// 按键配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeysConfig {
+    /// 按住临时输入英文的修饰键: "rctrl" / "lctrl" / "ralt" / "lalt" / "none"（默认）
+    /// 按住期间打字时该键被吃掉，不作为修饰键传给应用；单独点按照常传给应用
+    #[serde(default = "default_english_hold")]
+    pub english_hold: String,
+    /// 上屏首选的按键
+    #[serde(default)]
+    pub commit: CommitKey,
+    /// 运行时切换 AI / 字典优先的全局热键，如 "ctrl+shift+a"；"none" = 不启用（默认）
+    #[serde(default = "default_toggle_engine")]
+    pub toggle_engine: String,
+    /// 上一页 / 下一页的按键: "minus" "equal" "comma" "period" "lbracket" "rbracket"
+    /// 或对应字符 "-" "=" "," "." "[" "]"；PageUp / PageDown 始终可用
+    #[serde(default = "default_page_prev")]
+    pub page_prev: String,
+    #[serde(default = "default_page_next")]
+    pub page_next: String,
+    /// 两次空格间隔不超过此值 (ms) 视为双击: 上屏高亮候选整句并清空剩余拼音；0 = 不启用
+    #[serde(default = "default_double_space_ms")]
+    pub double_space_ms: u64,
+    /// 单独按一下左 / 右 Shift 的动作（通用 Shift 键码按左 Shift 处理）
+    #[serde(default)]
+    pub left_shift: ShiftAction,
+    #[serde(default)]
+    pub right_shift: ShiftAction,
+    /// Caps Lock 的用途（默认不处理，照常切换大小写）
+    #[serde(default)]
+    pub capslock: CapsLockAction,
+    /// 输入拼音时数字键 1-9 选字；false = 数字连同已输入的字母原样上屏（便于输入 "a4" 这类型号），选字改用空格 / Tab / 方向键
+    #[serde(default = "default_digit_select")]
+    pub digit_select: bool,
+}
+
+fn default_english_hold() -> String { "none".to_string() }
+fn default_toggle_engine() -> String { "none".to_string() }
+fn default_page_prev() -> String { "minus".to_string() }
+fn default_page_next() -> String { "equal".to_string() }
+fn default_double_space_ms() -> u64 { 300 }
+fn default_digit_select() -> bool { true }
+
+/// 默认翻页键 `-` / `=` 的虚拟键码
+const VK_MINUS: u32 = 0xBD;
+const VK_EQUAL: u32 = 0xBB;
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            english_hold: default_english_hold(),
+            commit: CommitKey::Space,
+            toggle_engine: default_toggle_engine(),
+            page_prev: default_page_prev(),
+            page_next: default_page_next(),
+            double_space_ms: default_double_space_ms(),
+            left_shift: ShiftAction::Mode,
+            right_shift: ShiftAction::Mode,
+            capslock: CapsLockAction::Off,
+            digit_select: default_digit_select(),
+        }
+    }
+}
+
+/// Caps Lock 的用途
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CapsLockAction {
+    /// 不处理，照常切换系统大写锁定（中文模式下字母一律按小写拼音处理）
+    #[default]
+    #[serde(rename = "none")]
+    Off,
+    /// 单独按 Caps Lock 切换中英文，不改变系统大写状态；Shift+Caps Lock 仍切换大写锁定
+    ToggleMode,
+}
+
+/// 单独按一下 Shift 的动作
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShiftAction {
+    /// 切换中英文（双击同一侧 Shift 锁定英文）
+    #[default]
+    Mode,
+    /// 切换 AI 优先 / 字典优先（同 toggle_engine 热键）
+    Engine,
+    /// 不做任何事
+    #[serde(rename = "none")]
+    Off,
+}
+
+/// 解析翻页键名为虚拟键码；字母、选字数字、空格 / 回车等已有用途的键返回错误原因
+fn parse_page_key(name: &str) -> Result<u32, String> {
+    let name = name.trim().to_ascii_lowercase();
+    match name.as_str() {
+        "-" | "minus" => Ok(VK_MINUS),
+        "=" | "equal" => Ok(VK_EQUAL),
+        "," | "comma" => Ok(0xBC),
+        "." | "period" => Ok(0xBE),
+        "[" | "lbracket" => Ok(0xDB),
+        "]" | "rbracket" => Ok(0xDD),
+        "pageup" => Ok(0x21),
+        "pagedown" => Ok(0x22),
+        n if n.len() == 1 && n.as_bytes()[0].is_ascii_lowercase() => Err("字母键用于输入拼音".to_string()),
+        n if n.len() == 1 && matches!(n.as_bytes()[0], b'1'..=b'9') => Err("与选字键 1–9 冲突".to_string()),
+        " " | "space" | "enter" | "esc" | "escape" | "backspace" => Err("与上屏 / 取消键冲突".to_string()),
+        _ => Err("无法识别".to_string()),
+    }
+}
+
+/// 组合热键: 修饰键 + 一个主键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// 主键虚拟键码
+    pub vk: u32,
+}
+
+impl Hotkey {
+    /// 解析 "ctrl+shift+a" 形式（不区分大小写）；主键支持 A-Z、0-9、F1-F12，
+    /// 至少需要一个修饰键，避免吞掉普通输入
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut key = Hotkey { ctrl: false, shift: false, alt: false, vk: 0 };
+        for part in s.split('+').map(|p| p.trim().to_ascii_lowercase()) {
+            match part.as_str() {
+                "ctrl" | "control" => key.ctrl = true,
+                "shift" => key.shift = true,
+                "alt" => key.alt = true,
+                p if key.vk == 0 => key.vk = Self::key_vk(p)?,
+                _ => return None,
+            }
+        }
+        (key.vk != 0 && (key.ctrl || key.alt)).then_some(key)
+    }
+
+    fn key_vk(name: &str) -> Option<u32> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c @ ('a'..='z' | '0'..='9')), None) => Some(c.to_ascii_uppercase() as u32),
+            (Some('f'), Some(_)) => match name[1..].parse::<u32>() {
+                Ok(n @ 1..=12) => Some(0x70 + n - 1),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, vk: u32, ctrl: bool, shift: bool, alt: bool) -> bool {
+        self.vk == vk && self.ctrl == ctrl && self.shift == shift && self.alt == alt
+    }
+}
+
+impl KeysConfig {
+    /// 临时英文键的虚拟键码（"none" 或无法识别 = 不启用）
+    pub fn english_hold_vk(&self) -> Option<u32> {
+        match self.english_hold.to_lowercase().as_str() {
+            "rctrl" => Some(0xA3),
+            "lctrl" => Some(0xA2),
+            "ralt" => Some(0xA5),
+            "lalt" => Some(0xA4),
+            _ => None,
+        }
+    }
+
+    /// 翻页键 (上一页, 下一页) 的虚拟键码；无法识别、冲突或两键相同时回退默认的 `-` / `=`
+    pub fn page_keys(&self) -> (u32, u32) {
+        let prev = parse_page_key(&self.page_prev).unwrap_or(VK_MINUS);
+        let next = parse_page_key(&self.page_next).unwrap_or(VK_EQUAL);
+        if prev == next { (VK_MINUS, VK_EQUAL) } else { (prev, next) }
+    }
+
+    /// 翻页键配置的问题（加载配置时提示）
+    pub fn page_key_warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = [("page_prev", &self.page_prev), ("page_next", &self.page_next)]
+            .into_iter()
+            .filter_map(|(key, name)| parse_page_key(name).err()
+                .map(|why| format!("keys.{}=\"{}\" {}，使用默认键", key, name, why)))
+            .collect();
+        if warnings.is_empty() && parse_page_key(&self.page_prev) == parse_page_key(&self.page_next) {
+            warnings.push("keys.page_prev 与 keys.page_next 相同，使用默认 - / =".to_string());
+        }
+        warnings
+    }
+
+    /// 切换引擎模式的热键（"none" 或无法解析 = 不启用）
+    pub fn toggle_engine_hotkey(&self) -> Option<Hotkey> {
+        Hotkey::parse(&self.toggle_engine)
+    }
+
+    /// 单独按下的 Shift（0xA1 = 右，0xA0 左 / 0x10 通用按左）对应的动作
+    pub fn shift_action(&self, vkey: u32) -> ShiftAction {
+        if vkey == 0xA1 { self.right_shift } else { self.left_shift }
+    }
+}
+
+/// 日志配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct LogConfig {
+    /// 日志级别: "off" / "error" / "warn" / "info" / "debug" / "trace"
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// 是否写入 exe 同目录的 aipinyin.log（超过 1MB 轮转，保留 2 个文件）
+    #[serde(default = "default_log_to_file")]
+    pub to_file: bool,
+}
+
+fn default_log_level() -> String { "warn".to_string() }
+fn default_log_to_file() -> bool { true }
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: default_log_level(), to_file: default_log_to_file() }
+    }
+}
+
+/// 上屏文字注入配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct InjectConfig {
+    /// 逐字发送间隔（毫秒，0 = 整段一次 SendInput）；个别程序高负载时会丢字可设 1–10
+    #[serde(default)]
+    pub char_delay_ms: u64,
+    /// SendInput 未能完整发送（被 UIPI 拦截等）时，剩余文字改用 WM_CHAR 投递到焦点窗口
+    #[serde(default = "default_wm_char_fallback")]
+    pub wm_char_fallback: bool,
+    /// 直接使用 WM_CHAR 的进程名（不认合成按键的程序），如 ["legacy.exe"]
+    #[serde(default)]
+    pub wm_char_apps: Vec<String>,
+    /// 经剪贴板 + Ctrl+V 上屏的进程名（完全不认 Unicode 按键的游戏等），原剪贴板内容随后恢复
+    #[serde(default)]
+    pub paste_fallback_apps: Vec<String>,
+}
+
+fn default_wm_char_fallback() -> bool { true }
+
+impl Default for InjectConfig {
+    fn default() -> Self {
+        Self {
+            char_delay_ms: 0,
+            wm_char_fallback: default_wm_char_fallback(),
+            wm_char_apps: vec![],
+            paste_fallback_apps: vec![],
+        }
+    }
+}
+
+/// 用户词典排序配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserDictConfig {
+    /// 每学习一次，候选前移的名次（0 = 学习不影响排序）
+    #[serde(default = "default_boost_scale")]
+    pub boost_scale: f32,
+    /// 次数最多的学习词达到此次数时直接置顶
+    #[serde(default = "default_pin_threshold")]
+    pub pin_threshold: u32,
+}
+
+fn default_boost_scale() -> f32 { 2.0 }
+fn default_pin_threshold() -> u32 { 3 }
+
+impl Default for UserDictConfig {
+    fn default() -> Self {
+        Self { boost_scale: default_boost_scale(), pin_threshold: default_pin_threshold() }
+    }
+}
+
+/// 候选合并时各来源的权重（见 `engine::score_merge`）
+///
+/// 每路按名次折算为 0~1 再乘权重相加，几路都给出的词排在前面；某路设 0 则不参与排序
+#[derive(Debug, Deserialize, Clone)]
+pub struct MergeConfig {
+    #[serde(default = "default_merge_weight")]
+    pub ai_weight: f32,
+    #[serde(default = "default_merge_weight")]
+    pub dict_weight: f32,
+    /// 学习词（按 次数 / 该拼音下最多次数 折算）；之后仍按 `[user_dict]` 前移 / 置顶
+    #[serde(default = "default_merge_weight")]
+    pub user_weight: f32,
+}
+
+fn default_merge_weight() -> f32 { 1.0 }
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            ai_weight: default_merge_weight(),
+            dict_weight: default_merge_weight(),
+            user_weight: default_merge_weight(),
+        }
+    }
+}
+
+/// 符号面板的分类（`[[symbols.categories]]`，不配置则用内置分类）
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SymbolsConfig {
+    #[serde(default)]
+    pub categories: Vec<crate::symbols::SymbolCategory>,
+}
+
+/// 词图分词调参（长拼音整句 / 首字母缩写拆词，启动时读取一次）
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SegmentationConfig {
+    /// 多字词每个音节的加分，越大越倾向词组而不是单字拼接
+    #[serde(default = "default_multi_char_bonus")]
+    pub multi_char_bonus: i64,
+    /// jieba 认可为完整词时每字的加分（最多按 4 字计）
+    #[serde(default = "default_jieba_boost")]
+    pub jieba_boost: i64,
+    /// 词图 DP 每个位置保留的路径数
+    #[serde(default = "default_dp_width")]
+    pub dp_width: usize,
+    /// 长输入分块时，作为切分点的多字词最低权重
+    #[serde(default = "default_chunk_boundary_weight")]
+    pub chunk_boundary_weight: u32,
+    /// 首字母缩写: 多字词每个声母的加分
+    #[serde(default = "default_abbrev_multi_char_bonus")]
+    pub abbrev_multi_char_bonus: i64,
+    /// 首字母缩写: DP 每个位置保留的路径数
+    #[serde(default = "default_abbrev_dp_width")]
+    pub abbrev_dp_width: usize,
+    /// 首字母缩写: AI 逐字 beam search 的宽度
+    #[serde(default = "default_abbrev_beam_width")]
+    pub abbrev_beam_width: usize,
+    /// 用 jieba 给完整词加分；关闭后不加载 jieba 词典，省内存和首次分词时间
+    #[serde(default = "default_use_jieba")]
+    pub use_jieba: bool,
+}
+
+fn default_multi_char_bonus() -> i64 { 1000 }
+fn default_jieba_boost() -> i64 { 750 }
+fn default_dp_width() -> usize { 15 }
+fn default_chunk_boundary_weight() -> u32 { 100 }
+fn default_abbrev_multi_char_bonus() -> i64 { 500 }
+fn default_abbrev_dp_width() -> usize { 5 }
+fn default_abbrev_beam_width() -> usize { 5 }
+fn default_use_jieba() -> bool { true }
+
+impl Default for SegmentationConfig {
+    fn default() -> Self {
+        Self {
+            multi_char_bonus: default_multi_char_bonus(),
+            jieba_boost: default_jieba_boost(),
+            dp_width: default_dp_width(),
+            chunk_boundary_weight: default_chunk_boundary_weight(),
+            abbrev_multi_char_bonus: default_abbrev_multi_char_bonus(),
+            abbrev_dp_width: default_abbrev_dp_width(),
+            abbrev_beam_width: default_abbrev_beam_width(),
+            use_jieba: default_use_jieba(),
+        }
+    }
+}
+
+/// 模糊音配置: 每项开启后两种写法互相匹配（如 z_zh: "zi" 也出 "zhi" 的候选）
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FuzzyConfig {
+    pub z_zh: bool,
+    pub c_ch: bool,
+    pub s_sh: bool,
+    pub n_l: bool,
+    pub f_h: bool,
+    pub r_l: bool,
+    pub an_ang: bool,
+    pub en_eng: bool,
+    pub in_ing: bool,
+    pub ian_iang: bool,
+    pub uan_uang: bool,
+    /// 模糊音候选与精确候选一起排序前，先从词频里减去的量（词频约 0–1000；越大越靠后）
+    pub penalty: u32,
+}
+
+fn default_fuzzy_penalty() -> u32 { 1000 }
+
+impl Default for FuzzyConfig {
+    fn default() -> Self {
+        Self {
+            z_zh: false,
+            c_ch: false,
+            s_sh: false,
+            n_l: false,
+            f_h: false,
+            r_l: false,
+            an_ang: false,
+            en_eng: false,
+            in_ing: false,
+            ian_iang: false,
+            uan_uang: false,
+            penalty: default_fuzzy_penalty(),
+        }
+    }
+}
+
+impl FuzzyConfig {
+    /// 全部模糊音开关名（与 config.toml 中的键一致）
+    pub const KEYS: [&'static str; 11] = [
+        "z_zh", "c_ch", "s_sh", "n_l", "f_h", "r_l",
+        "an_ang", "en_eng", "in_ing", "ian_iang", "uan_uang",
+    ];
+
+    /// 已开启的声母对
+    pub fn initial_pairs(&self) -> Vec<(&'static str, &'static str)> {
+        [(self.z_zh, ("z", "zh")), (self.c_ch, ("c", "ch")), (self.s_sh, ("s", "sh")),
+         (self.n_l, ("n", "l")), (self.f_h, ("f", "h")), (self.r_l, ("r", "l"))]
+            .into_iter().filter(|(on, _)| *on).map(|(_, p)| p).collect()
+    }
+
+    /// 已开启的韵母对
+    pub fn final_pairs(&self) -> Vec<(&'static str, &'static str)> {
+        [(self.an_ang, ("an", "ang")), (self.en_eng, ("en", "eng")), (self.in_ing, ("in", "ing")),
+         (self.ian_iang, ("ian", "iang")), (self.uan_uang, ("uan", "uang"))]
+            .into_iter().filter(|(on, _)| *on).map(|(_, p)| p).collect()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.initial_pairs().is_empty() || !self.final_pairs().is_empty()
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -129,6 +906,16 @@ impl Default for Config {
             ai: AiConfig::default(),
             ui: UiConfig::default(),
             dict: DictConfig::default(),
+            guardian: crate::guardian::GuardianConfig::default(),
+            keys: KeysConfig::default(),
+            log: LogConfig::default(),
+            fuzzy: FuzzyConfig::default(),
+            inject: InjectConfig::default(),
+            user_dict: UserDictConfig::default(),
+            segmentation: SegmentationConfig::default(),
+            symbols: SymbolsConfig::default(),
+            merge: MergeConfig::default(),
+            stream: crate::stream::StreamConfig::default(),
         }
     }
 }
@@ -144,6 +931,20 @@ impl Config {
                         eprintln!("[Config] ✅ 已加载 {:?}", config_path);
                         eprintln!("[Config]   mode={:?}, top_k={}, rerank={}, font={}",
                             cfg.engine.mode, cfg.ai.top_k, cfg.ai.rerank, cfg.ui.font_size);
+                        if !cfg.ai.endpoint.is_empty() {
+                            eprintln!("[Config]   ai endpoint={}, timeout={}ms, source={:?}",
+                                cfg.ai.endpoint, cfg.ai.request_timeout_ms, cfg.ai.effective_source());
+                        }
+                        if !PAGE_SIZE_RANGE.contains(&cfg.ui.page_size) {
+                            eprintln!("[Config] ⚠ ui.page_size={} 超出 3–9，按 {} 处理",
+                                cfg.ui.page_size, cfg.ui.effective_page_size());
+                        }
+                        for w in cfg.keys.page_key_warnings() {
+                            eprintln!("[Config] ⚠ {}", w);
+                        }
+                        if cfg.engine.symbol_vk().is_none() && !matches!(cfg.engine.symbol_key.trim(), "none" | "") {
+                            eprintln!("[Config] ⚠ engine.symbol_key=\"{}\" 无法识别，符号面板不启用", cfg.engine.symbol_key);
+                        }
                         if !cfg.dict.extra.is_empty() {
                             eprintln!("[Config]   extra dicts: {:?}", cfg.dict.extra);
                         }
@@ -162,6 +963,27 @@ impl Config {
         }
     }
 
+    /// 符号面板实际使用的分类；`engine.symbol_key` 关闭时为空（引擎不进入符号输入）
+    pub fn symbol_categories(&self) -> Vec<crate::symbols::SymbolCategory> {
+        if self.engine.symbol_vk().is_none() { return vec![]; }
+        if self.symbols.categories.is_empty() { crate::symbols::builtin() } else { self.symbols.categories.clone() }
+    }
+
+    /// 热重载时无法即时生效、需要重启的配置项
+    pub fn restart_required(&self, new: &Config) -> Vec<&'static str> {
+        let mut items = Vec::new();
+        if self.dict.extra != new.dict.extra { items.push("dict.extra"); }
+        if self.ai.system_prompt != new.ai.system_prompt { items.push("ai.system_prompt (本地服务)"); }
+        if self.ai.model_path != new.ai.model_path || self.ai.vocab_dir != new.ai.vocab_dir {
+            items.push("ai.model_path / ai.vocab_dir");
+        }
+        if self.guardian != new.guardian { items.push("guardian"); }
+        if self.stream != new.stream { items.push("stream"); }
+        if self.segmentation != new.segmentation { items.push("segmentation"); }
+        if self.log.level != new.log.level || self.log.to_file != new.log.to_file { items.push("log"); }
+        items
+    }
+
     fn config_path() -> PathBuf {
         std::env::current_exe()
             .ok()
@@ -169,3 +991,225 @@ impl Config {
             .unwrap_or_else(|| PathBuf::from("config.toml"))
     }
 }
+
+/// 把单个键写回 config.toml（保留文件其余内容与注释），用于程序内开关如"不再提示"
+pub fn persist_value(section: &str, key: &str, value: &str) -> std::io::Result<()> {
+    let path = Config::config_path();
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    std::fs::write(&path, set_toml_value(&text, section, key, value))
+}
+
+/// 在 `[section]` 中设置 `key = value`: 已有则替换该行，否则插到段首，段不存在则追加
+fn set_toml_value(text: &str, section: &str, key: &str, value: &str) -> String {
+    let header = format!("[{}]", section);
+    let entry = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    let is_header = |l: &str| l.trim_start().starts_with('[');
+    let Some(start) = lines.iter().position(|l| l.trim() == header) else {
+        if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(header);
+        lines.push(entry);
+        return lines.join("\n") + "\n";
+    };
+    let end = lines[start + 1..].iter().position(|l| is_header(l))
+        .map_or(lines.len(), |i| start + 1 + i);
+    let existing = lines[start + 1..end].iter().position(|l| {
+        l.split_once('=').is_some_and(|(k, _)| k.trim() == key)
+    });
+    match existing {
+        Some(i) => lines[start + 1 + i] = entry,
+        None => lines.insert(start + 1, entry),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// 程序自己写 config.toml 且内存已同步时记下 (写前, 写后) 的修改时间，热重载据此跳过这次变化
+static SELF_WRITE: Mutex<Option<(Option<SystemTime>, Option<SystemTime>)>> = Mutex::new(None);
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 启动配置热重载线程
+///
+/// 轮询 config.toml 的修改时间，变化后重新解析：
+/// 成功则替换 `live` 并回调 `on_change(旧配置, 新配置)`，解析失败保留当前配置。
+/// 两次轮询之间只有 [`persist_app_mode`] 写过文件时不重新加载。
+pub fn watch<F>(live: SharedConfig, on_change: F)
+where
+    F: Fn(&Config, &Config) + Send + 'static,
+{
+    let path = Config::config_path();
+
+    std::thread::spawn(move || {
+        let mut last = file_mtime(&path);
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            let now = file_mtime(&path);
+            if now == last { continue; }
+            let self_write = SELF_WRITE.lock().unwrap_or_else(|e| e.into_inner()).take();
+            let skip = self_write == Some((last, now));
+            last = now;
+            if skip { continue; }
+
+            let text = match std::fs::read_to_string(&path) {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            match toml::from_str::<Config>(&text) {
+                Ok(new_cfg) => {
+                    let old = std::mem::replace(&mut *live.lock().unwrap(), new_cfg.clone());
+                    eprintln!("[Config] 🔄 config.toml 已重新加载 (mode={:?}, top_k={})",
+                        new_cfg.engine.mode, new_cfg.ai.top_k);
+                    on_change(&old, &new_cfg);
+                }
+                Err(e) => eprintln!("[Config] ⚠ 重新加载失败: {}, 保留当前配置", e),
+            }
+        }
+    });
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hotkey() {
+        let k = Hotkey::parse("Ctrl+Shift+A").unwrap();
+        assert_eq!(k, Hotkey { ctrl: true, shift: true, alt: false, vk: 0x41 });
+        assert!(k.matches(0x41, true, true, false));
+        assert!(!k.matches(0x41, true, false, false));
+        assert_eq!(Hotkey::parse("alt+f12").map(|k| k.vk), Some(0x7B));
+        assert_eq!(Hotkey::parse("ctrl+1").map(|k| k.vk), Some(0x31));
+        // 无修饰键、多个主键、无法识别 → 不启用
+        assert_eq!(Hotkey::parse("a"), None);
+        assert_eq!(Hotkey::parse("shift+a"), None);
+        assert_eq!(Hotkey::parse("ctrl+a+b"), None);
+        assert_eq!(Hotkey::parse("ctrl+f13"), None);
+        assert_eq!(Hotkey::parse("none"), None);
+    }
+
+    #[test]
+    fn test_shift_actions() {
+        let keys: KeysConfig = toml::from_str("right_shift = \"engine\"").unwrap();
+        assert_eq!(keys.shift_action(0xA0), ShiftAction::Mode);
+        assert_eq!(keys.shift_action(0x10), ShiftAction::Mode);
+        assert_eq!(keys.shift_action(0xA1), ShiftAction::Engine);
+        let keys: KeysConfig = toml::from_str("left_shift = \"none\"").unwrap();
+        assert_eq!(keys.shift_action(0x10), ShiftAction::Off);
+        assert_eq!(keys.shift_action(0xA1), ShiftAction::Mode);
+        assert_eq!(keys.capslock, CapsLockAction::Off);
+        let keys: KeysConfig = toml::from_str("capslock = \"toggle_mode\"").unwrap();
+        assert_eq!(keys.capslock, CapsLockAction::ToggleMode);
+    }
+
+    #[test]
+    fn test_page_keys() {
+        let keys = |prev: &str, next: &str| KeysConfig {
+            page_prev: prev.to_string(), page_next: next.to_string(), ..Default::default()
+        };
+        assert_eq!(KeysConfig::default().page_keys(), (0xBD, 0xBB));
+        assert_eq!(keys(",", "period").page_keys(), (0xBC, 0xBE));
+        assert_eq!(keys("LBracket", "]").page_keys(), (0xDB, 0xDD));
+        assert!(keys("comma", "period").page_key_warnings().is_empty());
+        // 与选字 / 输入键冲突、无法识别 → 回退默认并提示
+        assert_eq!(keys("1", "period").page_keys(), (0xBD, 0xBE));
+        assert_eq!(keys("comma", "x").page_keys(), (0xBC, 0xBB));
+        assert_eq!(keys("2", "what").page_key_warnings().len(), 2);
+        // 两键相同
+        assert_eq!(keys("comma", ",").page_keys(), (0xBD, 0xBB));
+        assert_eq!(keys("comma", ",").page_key_warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_set_toml_value() {
+        let text = "[ui]\n# 字体\nfont_size = 16\n\n[dict]\nextra = []\n";
+        let added = set_toml_value(text, "ui", "ai_unavailable_notice", "false");
+        assert_eq!(added, "[ui]\nai_unavailable_notice = false\n# 字体\nfont_size = 16\n\n[dict]\nextra = []\n");
+        assert_eq!(set_toml_value(&added, "ui", "ai_unavailable_notice", "true"),
+            added.replace("= false", "= true"));
+
+        let appended = set_toml_value("[dict]\nextra = []", "ui", "opacity", "200");
+        assert_eq!(appended, "[dict]\nextra = []\n\n[ui]\nopacity = 200\n");
+        let cfg: Config = toml::from_str(&appended).unwrap();
+        assert_eq!(cfg.ui.opacity, 200);
+        assert!(cfg.ui.ai_unavailable_notice);
+    }
+
+    #[test]
+    fn test_window_offset_per_monitor() {
+        let key = monitor_key("\\\\.\\DISPLAY2");
+        assert_eq!(key, "DISPLAY2");
+
+        let text = set_toml_value("[ui]\noffset_x = 5\noffset_y = -3\n", "ui.monitor_offsets", &key, "[40, 12]");
+        let cfg: Config = toml::from_str(&text).unwrap();
+        assert_eq!(cfg.ui.window_offset("DISPLAY2"), (40, 12));
+        assert_eq!(cfg.ui.window_offset("DISPLAY1"), (5, -3));
+        assert_eq!(Config::default().ui.window_offset("DISPLAY1"), (0, 0));
+    }
+
+    #[test]
+    fn test_remembered_app_mode() {
+        assert_eq!(app_key("Code.exe"), "code");
+        assert_eq!(app_key("WeChat.EXE"), "wechat");
+        assert_eq!(app_key("my app.exe"), "myapp");
+
+        let text = set_toml_value("[engine]\nremember_mode_per_app = true\n", "engine.app_modes", &app_key("Code.exe"), "\"english\"");
+        let text = set_toml_value(&text, "engine.app_modes", &app_key("WeChat.exe"), "\"chinese\"");
+        let mut cfg: Config = toml::from_str(&text).unwrap();
+        assert_eq!(cfg.engine.remembered_mode("code.exe"), Some(false));
+        assert_eq!(cfg.engine.remembered_mode("WeChat.exe"), Some(true));
+        assert_eq!(cfg.engine.remembered_mode("notepad.exe"), None);
+
+        // 关闭时不恢复（记过的表保留）
+        cfg.engine.remember_mode_per_app = false;
+        assert_eq!(cfg.engine.remembered_mode("code.exe"), None);
+        assert!(Config::default().engine.app_modes.is_empty());
+    }
+
+    #[test]
+    fn test_remember_mode_round_trip() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.engine.remember_mode("Code.exe", false), None, "未开启时不记");
+
+        cfg.engine.remember_mode_per_app = true;
+        let code = cfg.engine.remember_mode("Code.exe", false);
+        assert_eq!(code, Some(("code".to_string(), InputMode::English)));
+        assert_eq!(cfg.engine.remember_mode("code.EXE", false), None, "没变化不写盘");
+        let wechat = cfg.engine.remember_mode("WeChat.exe", true).unwrap();
+        assert_eq!(cfg.engine.remembered_mode("code.exe"), Some(false));
+        assert_eq!(cfg.engine.remembered_mode("WeChat.exe"), Some(true));
+
+        // 写回的文本重新解析后得到同一张表
+        let (key, mode) = code.unwrap();
+        let text = set_app_mode_text("[engine]\nremember_mode_per_app = true\n", &key, mode);
+        let text = set_app_mode_text(&text, &wechat.0, wechat.1);
+        let reloaded: Config = toml::from_str(&text).unwrap();
+        assert_eq!(reloaded.engine.app_modes, cfg.engine.app_modes);
+        assert_eq!(reloaded.engine.remembered_mode("Code.exe"), Some(false));
+    }
+
+    #[test]
+    fn test_symbol_categories() {
+        let default = Config::default();
+        assert_eq!(default.engine.symbol_vk(), Some(0xBF));
+        assert_eq!(default.symbol_categories(), crate::symbols::builtin());
+
+        let text = "[engine]\nsymbol_key = \"semicolon\"\n\n[[symbols.categories]]\ncode = \"dw\"\nname = \"单位\"\nsymbols = \"㎡ ㎏ ℃\"\n";
+        let cfg: Config = toml::from_str(text).unwrap();
+        assert_eq!(cfg.engine.symbol_vk(), Some(0xBA));
+        let cats = cfg.symbol_categories();
+        assert_eq!(cats.len(), 1);
+        assert_eq!(cats[0].items().collect::<Vec<_>>(), ["㎡", "㎏", "℃"]);
+
+        let off: Config = toml::from_str("[engine]\nsymbol_key = \"none\"\n").unwrap();
+        assert!(off.symbol_categories().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! # 崩溃日志
+//!
+//! 安装全局 panic hook，把 panic 信息和调用栈追加写入 exe 同目录的 `crash.log`。
+//! 键盘钩子和后台线程里的 panic 不会弹窗，没有这份日志用户只会看到"输入法失灵"。
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 安装 panic hook（保留默认 hook 的 stderr 输出）
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        append(&format!(
+            "panic 于线程 '{}': {}\n{}",
+            thread.name().unwrap_or("<unnamed>"), info, backtrace
+        ));
+        default_hook(info);
+    }));
+}
+
+/// 追加一条记录到 crash.log（带时间戳与版本号），写入失败静默忽略
+pub fn append(message: &str) {
+    let entry = format!(
+        "==== {} | AiPinyin v{} ====\n{}\n\n",
+        format_timestamp(SystemTime::now()), env!("CARGO_PKG_VERSION"), message.trim_end()
+    );
+    if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open(log_path()) {
+        let _ = f.write_all(entry.as_bytes());
+    }
+}
+
+/// 从 catch_unwind 的 payload 中取出 panic 消息
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<非字符串 panic>".to_string()
+    }
+}
+
+fn log_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.join("crash.log")))
+        .unwrap_or_else(|| PathBuf::from("crash.log"))
+}
+
+/// UTC 时间格式化为 `YYYY-MM-DD HH:MM:SS UTC`（不引入日期库）
+pub fn format_timestamp(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // 公历换算 (Howard Hinnant, days_from_civil 的逆运算)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            "2023-11-14 22:13:20 UTC");
+        // 闰日
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29 00:00:00 UTC");
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let err = std::panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+        assert_eq!(panic_message(err.as_ref()), "boom 1");
+    }
+}
@@ -0,0 +1,327 @@
+//! # 输入引擎（库入口）
+//!
+//! 把拼音切分、字典、AI 预测和用户词典组合成一个不依赖键盘钩子的 [`Engine`]:
+//! `push` / `pop` 编辑拼音，`candidates` 取候选，`commit` 上屏并学习。
+//!
+//! 候选顺序与输入法一致: AI 候选在前、字典补充（去重），再按用户学习次数调整。
+//! 这里同步推理、只用本地模型；异步更新、外部接口、插件与整句候选由前端自己处理。
+
+use std::sync::{Arc, Mutex};
+use crate::ai_engine::{self, AIPredictor, HistoryBuffer, SharedPredictor};
+use crate::config::{Config, EngineMode, MergeConfig};
+use crate::pinyin::{self, PinyinEngine};
+use crate::symbols;
+use crate::user_dict::UserDict;
+
+/// 上屏历史保留条数（AI 上下文从这里取）
+const HISTORY_CAPACITY: usize = 100;
+
+pub struct Engine {
+    pinyin: PinyinEngine,
+    ai: SharedPredictor,
+    user_dict: UserDict,
+    history: HistoryBuffer,
+    cfg: Config,
+    /// 当前拼音的候选（push / pop / commit 后重算）
+    candidates: Option<Vec<String>>,
+}
+
+impl Engine {
+    /// 按配置加载字典（含额外词库）、AI 模型与用户词典（exe 同目录）
+    pub fn new(cfg: Config) -> Self {
+        pinyin::init_global_dict(&cfg.dict.extra);
+        let ai = Arc::new(Mutex::new(AIPredictor::new(&cfg.ai)));
+        Self::with_parts(cfg, ai, UserDict::load())
+    }
+
+    /// 用已有的推理实例与用户词典组装（与其他组件共用模型，或把用户词典放在别处）
+    pub fn with_parts(cfg: Config, ai: SharedPredictor, user_dict: UserDict) -> Self {
+        Self::with_pinyin(cfg, PinyinEngine::new(), ai, user_dict)
+    }
+
+    /// 同 `with_parts`，拼音引擎由调用方给定（如 `PinyinEngine::with_dict` 的固定词表）
+    fn with_pinyin(cfg: Config, mut pinyin: PinyinEngine, ai: SharedPredictor, user_dict: UserDict) -> Self {
+        pinyin.set_fuzzy(cfg.fuzzy.clone());
+        pinyin.set_shuangpin(cfg.engine.shuangpin);
+        pinyin.set_max_candidates(cfg.engine.max_candidates);
+        pinyin.set_max_ambiguous_splits(cfg.engine.max_ambiguous_splits);
+        pinyin.set_erhua(cfg.engine.erhua);
+        pinyin.set_symbols(cfg.symbol_categories());
+        if let Ok(mut pred) = ai.lock() {
+            pred.set_segmentation(cfg.segmentation.clone());
+        }
+        Self {
+            pinyin, ai, user_dict,
+            history: HistoryBuffer::new(HISTORY_CAPACITY),
+            cfg,
+            candidates: None,
+        }
+    }
+
+    /// 追加一个拼音字母（u 模式下也接受数字；输入为空时 `/` 进入符号输入）
+    pub fn push(&mut self, ch: char) {
+        self.pinyin.push(ch);
+        self.candidates = None;
+    }
+
+    /// 删除最后一个字母
+    pub fn pop(&mut self) {
+        self.pinyin.pop();
+        self.candidates = None;
+    }
+
+    /// 放弃当前拼音
+    pub fn clear(&mut self) {
+        self.pinyin.clear();
+        self.candidates = None;
+    }
+
+    pub fn raw_input(&self) -> &str { self.pinyin.raw_input() }
+    pub fn syllables(&self) -> &[String] { self.pinyin.syllables() }
+    pub fn is_empty(&self) -> bool { self.pinyin.is_empty() }
+
+    pub fn user_dict(&self) -> &UserDict { &self.user_dict }
+    pub fn history(&self) -> &HistoryBuffer { &self.history }
+
+    /// 当前拼音的全部候选（首次调用时计算，之后到下一次编辑前直接返回）
+    pub fn candidates(&mut self) -> &[String] {
+        if self.candidates.is_none() {
+            self.candidates = Some(self.compute_candidates());
+        }
+        self.candidates.as_deref().unwrap_or_default()
+    }
+
+    /// 上屏第 `index` 个候选: 学习、记入上下文，只消耗该词用掉的音节（剩余拼音留在缓冲里）
+    pub fn commit(&mut self, index: usize) -> Option<String> {
+        self.commit_with(index, false)
+    }
+
+    /// 整句上屏第 `index` 个候选并清空剩余拼音（输入法里的双击空格）
+    pub fn commit_whole(&mut self, index: usize) -> Option<String> {
+        self.commit_with(index, true)
+    }
+
+    fn commit_with(&mut self, index: usize, whole: bool) -> Option<String> {
+        let text = self.candidates().get(index).cloned()?;
+        let raw = self.pinyin.raw_input().to_string();
+        if !pinyin::is_unicode_input(&raw) && !symbols::is_symbol_input(&raw) {
+            self.user_dict.learn(&raw, &text);
+            if text.chars().count() >= 3 {
+                pinyin::cache_ai_word(&raw, &text);
+            }
+        }
+        self.history.push(&text);
+        if whole {
+            self.pinyin.clear();
+        } else {
+            self.pinyin.consume_word(&text);
+        }
+        self.candidates = None;
+        Some(text)
+    }
+
+    fn compute_candidates(&self) -> Vec<String> {
+        if self.pinyin.is_empty() { return vec![]; }
+        let raw = self.pinyin.raw_input();
+        let dict = self.pinyin.get_candidates();
+        // u 模式只出码位对应的字符，符号输入只出符号
+        if self.pinyin.is_unicode_mode() || self.pinyin.is_symbol_mode() { return dict; }
+        let ai = self.predict(raw, &dict);
+        let learned = self.user_dict.get_learned_words(raw);
+        let merged = score_merge(&ai, &dict, &learned, &self.cfg.merge);
+        self.user_dict.rank(raw, merged, &self.cfg.user_dict)
+    }
+
+    /// 上屏后的联想候选（未开启 `engine.associate` 或还有拼音时为空）
+    pub fn associations(&self) -> Vec<String> {
+        if !self.cfg.engine.associate || !self.pinyin.is_empty() { return vec![]; }
+        let ctx = self.history.current_sentence();
+        let mut pred = self.ai.lock().ok();
+        association(pred.as_deref_mut(), &self.user_dict, &ctx, self.cfg.ui.effective_page_size())
+    }
+
+    /// 上屏一个联想候选（只记入上下文，不学习），之后可再取 `associations`
+    pub fn commit_association(&mut self, text: &str) {
+        self.history.push(text);
+    }
+
+    /// 本地模型预测（字典优先模式或模型不可用时为空）
+    fn predict(&self, raw: &str, dict: &[String]) -> Vec<String> {
+        if self.cfg.engine.mode != EngineMode::Ai { return vec![]; }
+        let Ok(mut pred) = self.ai.lock() else { return vec![] };
+        if !pred.is_available() { return vec![]; }
+        let ctx = self.history.current_sentence();
+        let scored = pred.predict_scored(raw, &ctx, self.cfg.ai.top_k.min(9), dict);
+        ai_engine::filter_confident(scored, self.cfg.ai.min_confidence)
+    }
+}
+
+/// 联想候选（`engine.associate`）: 模型可用时按上下文预测下一个字，否则取学习词中上下文末字之后的部分
+pub fn association(ai: Option<&mut AIPredictor>, user_dict: &UserDict, context: &str, n: usize) -> Vec<String> {
+    match ai {
+        Some(pred) if pred.is_available() => pred.predict_association(context, n),
+        _ => user_dict.predict_next(context, n),
+    }
+}
+
+/// AI 候选在前、字典候选补充，按首次出现去重
+pub fn merge_candidates(ai: &[String], dict: &[String]) -> Vec<String> {
+    let mut merged = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for w in ai.iter().chain(dict) {
+        if seen.insert(w.as_str()) { merged.push(w.clone()); }
+    }
+    merged
+}
+
+/// 按来源打分合并 AI 候选、字典候选与学习词 (`[merge]`)
+///
+/// 各路按名次折算到 0~1（第一名 1，往后等差递减），学习词按 次数 / 最多次数 折算，
+/// 乘以各自权重后相加: 几路都给出的词分数叠加，排到只有一路给出的词前面。
+/// 同分保持首次出现的顺序（AI → 字典 → 学习词）。
+pub fn score_merge(ai: &[String], dict: &[String], learned: &[(String, u32)], weights: &MergeConfig) -> Vec<String> {
+    let rank_score = |i: usize, n: usize| (n - i) as f32 / n as f32;
+    let max_count = learned.iter().map(|(_, c)| *c).max().unwrap_or(0).max(1) as f32;
+    let sources: [Vec<(&String, f32)>; 3] = [
+        ai.iter().enumerate().map(|(i, w)| (w, weights.ai_weight * rank_score(i, ai.len()))).collect(),
+        dict.iter().enumerate().map(|(i, w)| (w, weights.dict_weight * rank_score(i, dict.len()))).collect(),
+        learned.iter().map(|(w, c)| (w, weights.user_weight * *c as f32 / max_count)).collect(),
+    ];
+
+    let mut index: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    let mut scored: Vec<(f32, &String)> = Vec::new();
+    for source in sources {
+        // 同一路里重复的词只算第一次
+        let mut seen = std::collections::HashSet::new();
+        for (w, score) in source.into_iter().filter(|(w, _)| seen.insert(*w)) {
+            match index.get(w.as_str()) {
+                Some(&i) => scored[i].0 += score,
+                None => {
+                    index.insert(w.as_str(), scored.len());
+                    scored.push((score, w));
+                }
+            }
+        }
+    }
+    // 稳定排序: 同分按首次出现
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, w)| w.clone()).collect()
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pinyin::Dictionary;
+
+    fn words(ws: &[&str]) -> Vec<String> {
+        ws.iter().map(|w| w.to_string()).collect()
+    }
+
+    /// 不加载模型（指向不存在的路径）、用户词典放临时目录、固定词表（不依赖 dict.bin）
+    fn dict_only_engine(name: &str) -> Engine {
+        let mut cfg = Config::default();
+        cfg.ai.model_path = "aipinyin_no_such_model.onnx".into();
+        let path = std::env::temp_dir().join(format!("aipinyin_{}_{}.txt", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ai = Arc::new(Mutex::new(AIPredictor::new(&cfg.ai)));
+        let dict = Dictionary::from_text(
+            "ni,你,900\nhao,好,900\nma,吗,900\nnihao,你好,900\n\
+             shi,是,900\njian,见,800\nshijian,时间,900\nzai,在,900\nzaijian,再见,900\n");
+        Engine::with_pinyin(cfg, PinyinEngine::with_dict(dict), ai, UserDict::load_from(path))
+    }
+
+    #[test]
+    fn test_merge_candidates() {
+        assert_eq!(
+            merge_candidates(&words(&["你好", "拟好"]), &words(&["你好", "你", "拟好", "泥"])),
+            words(&["你好", "拟好", "你", "泥"])
+        );
+    }
+
+    #[test]
+    fn test_score_merge_agreement() {
+        let w = MergeConfig::default();
+        let ai = words(&["拟好", "你号", "你好"]);
+        let dict = words(&["泥好", "逆号", "你好"]);
+        // 没有重合时按名次交错，同名次 AI 在前
+        assert_eq!(score_merge(&ai[..2], &dict[..2], &[], &w), words(&["拟好", "泥好", "你号", "逆号"]));
+
+        // "你好" 在三路都出现（AI 与字典都只排第三），胜过只在 AI 排第一的 "拟好"
+        let learned = vec![("你好".to_string(), 2)];
+        assert_eq!(score_merge(&ai, &dict, &learned, &w)[0], "你好");
+        // 只有 AI 与字典两路靠后的名次，不足以超过第一名
+        assert_eq!(score_merge(&ai, &dict, &[], &w)[0], "拟好");
+        // 学习词不在 AI / 字典中也会补上
+        let learned = vec![("妮好".to_string(), 1)];
+        assert!(score_merge(&ai, &dict, &learned, &w).contains(&"妮好".to_string()));
+    }
+
+    #[test]
+    fn test_engine_push_commit_learn() {
+        let mut e = dict_only_engine("engine");
+        for ch in "nihaoma".chars() { e.push(ch); }
+        assert_eq!(e.syllables(), ["ni", "hao", "ma"]);
+        let idx = e.candidates().iter().position(|w| w == "你好").expect("你好");
+
+        // 只吃掉 "nihao"，"ma" 留在缓冲里
+        assert_eq!(e.commit(idx).as_deref(), Some("你好"));
+        assert_eq!(e.raw_input(), "ma");
+        assert_eq!(e.user_dict().get_weight("nihaoma", "你好"), 1);
+        assert_eq!(e.history().current_sentence(), "你好");
+
+        e.pop();
+        e.pop();
+        assert!(e.is_empty());
+        assert!(e.candidates().is_empty());
+        assert_eq!(e.commit(0), None);
+    }
+
+    #[test]
+    fn test_symbol_commit_not_learned() {
+        let mut e = dict_only_engine("engine_symbol");
+        for ch in "/jt".chars() { e.push(ch); }
+        assert_eq!(e.candidates()[0], "→");
+        assert_eq!(e.commit(0).as_deref(), Some("→"));
+        assert!(e.is_empty());
+        assert_eq!(e.user_dict().entry_count(), 0);
+    }
+
+    #[test]
+    fn test_commit_whole_clears_rest() {
+        let mut e = dict_only_engine("engine_whole");
+        for ch in "shijianzaijian".chars() { e.push(ch); }
+        let idx = e.candidates().iter().position(|w| w == "时间").expect("时间");
+
+        // 普通上屏只吃掉 "shijian"；整句上屏连同剩下的 "zaijian" 一起清空
+        let mut partial = dict_only_engine("engine_partial");
+        for ch in "shijianzaijian".chars() { partial.push(ch); }
+        assert_eq!(partial.commit(idx).as_deref(), Some("时间"));
+        assert_eq!(partial.raw_input(), "zaijian");
+
+        assert_eq!(e.commit_whole(idx).as_deref(), Some("时间"));
+        assert!(e.is_empty());
+        assert!(e.candidates().is_empty());
+    }
+
+    #[test]
+    fn test_associations_from_learned() {
+        let mut e = dict_only_engine("engine_assoc");
+        assert!(e.associations().is_empty());
+        e.cfg.engine.associate = true;
+        for ch in "nihao".chars() { e.push(ch); }
+        let idx = e.candidates().iter().position(|w| w == "你好").expect("你好");
+        e.commit(idx);
+        // 上下文末字 "好" 没有学过以它开头的词
+        assert!(e.associations().is_empty());
+
+        e.commit_association("你");
+        assert_eq!(e.associations().first().map(String::as_str), Some("好"));
+        // 有拼音时不联想
+        e.push('n');
+        assert!(e.associations().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! # 焦点被抢时暂存输入
+//!
+//! 通知、弹窗等短暂抢走焦点时，未上屏的拼音不直接丢弃，而是按原窗口 (HWND) 暂存；
+//! 焦点在 `engine.focus_restore_ms` 内回到同一窗口就恢复，超时或回到别的窗口则丢弃。
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+pub struct FocusStash {
+    /// 暂存的有效期（0 = 不暂存，离开即丢弃）
+    timeout: Duration,
+    /// (窗口 HWND.0, 拼音, 离开时刻)，只保留最近一次
+    saved: Option<(isize, String, Instant)>,
+}
+
+impl FocusStash {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, saved: None }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+        if timeout.is_zero() { self.saved = None; }
+    }
+
+    /// 焦点离开 `hwnd` 时暂存它的拼音（空拼音或未启用时只清掉旧的暂存）
+    pub fn stash(&mut self, hwnd: isize, raw: &str) {
+        self.stash_at(hwnd, raw, Instant::now());
+    }
+
+    fn stash_at(&mut self, hwnd: isize, raw: &str, now: Instant) {
+        self.saved = (!self.timeout.is_zero() && !raw.is_empty())
+            .then(|| (hwnd, raw.to_string(), now));
+    }
+
+    /// 焦点回到 `hwnd`: 仍在有效期内且是同一窗口则取回拼音；无论是否取回，暂存都作废
+    pub fn restore(&mut self, hwnd: isize) -> Option<String> {
+        self.restore_at(hwnd, Instant::now())
+    }
+
+    fn restore_at(&mut self, hwnd: isize, now: Instant) -> Option<String> {
+        let (saved_hwnd, raw, at) = self.saved.take()?;
+        (saved_hwnd == hwnd && now.duration_since(at) <= self.timeout).then_some(raw)
+    }
+
+    pub fn clear(&mut self) {
+        self.saved = None;
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_same_window_within_timeout() {
+        let t0 = Instant::now();
+        let mut s = FocusStash::new(Duration::from_secs(3));
+
+        // 弹窗抢走焦点，1 秒后回到原窗口
+        s.stash_at(100, "nihao", t0);
+        assert_eq!(s.restore_at(100, t0 + Duration::from_secs(1)).as_deref(), Some("nihao"));
+        // 只恢复一次
+        assert_eq!(s.restore_at(100, t0 + Duration::from_secs(1)), None);
+
+        // 超时丢弃
+        s.stash_at(100, "nihao", t0);
+        assert_eq!(s.restore_at(100, t0 + Duration::from_secs(4)), None);
+
+        // 焦点先去了别的窗口: 暂存作废，之后回到原窗口也不恢复
+        s.stash_at(100, "nihao", t0);
+        assert_eq!(s.restore_at(200, t0 + Duration::from_secs(1)), None);
+        assert_eq!(s.restore_at(100, t0 + Duration::from_secs(2)), None);
+
+        // 离开时没有拼音: 清掉之前的暂存
+        s.stash_at(100, "nihao", t0);
+        s.stash_at(100, "", t0);
+        assert_eq!(s.restore_at(100, t0), None);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let t0 = Instant::now();
+        let mut s = FocusStash::new(Duration::ZERO);
+        s.stash_at(100, "nihao", t0);
+        assert_eq!(s.restore_at(100, t0), None);
+
+        let mut s = FocusStash::new(Duration::from_secs(3));
+        s.stash_at(100, "nihao", t0);
+        s.set_timeout(Duration::ZERO);
+        assert_eq!(s.restore_at(100, t0), None);
+    }
+}
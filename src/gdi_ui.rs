@@ -0,0 +1,309 @@
+//! # GDI 候选窗（不透明回退）
+//!
+//! 远程桌面或老显卡上 WebView2 的透明窗口可能画成一块白框，`ui.renderer = "gdi"` 时改用
+//! 这个经典的 Win32 弹出窗口: 纯 GDI 绘制、不透明背景，不支持主题、拖动和 `max_width_px`。
+//! WebView 创建失败时也会自动退回到这里。
+//!
+//! 窗口建在主线程上，消息由 tao 的事件循环一并派发；显示内容放在 [`CONTENT`] 里，
+//! 更新后按新内容量出窗口大小再重绘。AI 线程也会调用这里，移动 / 显示 / 隐藏一律异步，
+//! 不等主线程处理消息（主线程退出时会等后台线程，同步发消息会互相卡住）。
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::*;
+use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::*;
+use crate::cand_ui::CandidateUi;
+
+const CLASS_NAME: PCWSTR = w!("AiPinyinGdiCandidate");
+
+/// 内边距 / 候选之间的间距 / 拼音行与候选行的间距（像素）
+const PADDING: i32 = 6;
+const CAND_GAP: i32 = 14;
+const LINE_GAP: i32 = 2;
+
+/// 颜色 (0x00BBGGRR)
+const BG_COLOR: COLORREF = COLORREF(0x00FFFFFF);
+const BORDER_COLOR: COLORREF = COLORREF(0x00C8C8C8);
+const TEXT_COLOR: COLORREF = COLORREF(0x00202020);
+const RAW_COLOR: COLORREF = COLORREF(0x00808080);
+const HIGHLIGHT_COLOR: COLORREF = COLORREF(0x00FFE8D6);
+
+/// 当前显示的内容（窗口过程与更新方可能不在同一线程）
+static CONTENT: Mutex<Content> = Mutex::new(Content::new());
+
+/// 候选字体 (HFONT)
+static FONT: AtomicIsize = AtomicIsize::new(0);
+
+struct Content {
+    raw: String,
+    candidates: Vec<String>,
+    page_info: Option<(usize, usize)>,
+    sentence: bool,
+    selected: usize,
+    plugins_active: bool,
+    /// 引擎模式标记 (ai, available)，收到热键切换前不显示
+    mode: Option<(bool, bool)>,
+}
+
+impl Content {
+    const fn new() -> Self {
+        Self {
+            raw: String::new(),
+            candidates: Vec::new(),
+            page_info: None,
+            sentence: false,
+            selected: 0,
+            plugins_active: false,
+            mode: None,
+        }
+    }
+
+    fn header(&self) -> String {
+        header_text(&self.raw, self.page_info, self.sentence, self.plugins_active, self.mode)
+    }
+}
+
+/// 拼音行: 拼音 + 页码 + 标记（与 WebView 输入条的标记一致）
+fn header_text(
+    raw: &str, page_info: Option<(usize, usize)>, sentence: bool,
+    plugins_active: bool, mode: Option<(bool, bool)>,
+) -> String {
+    let mut s = raw.to_string();
+    if let Some((page, total)) = page_info {
+        if total > 1 { s.push_str(&format!("  {}/{}", page, total)); }
+    }
+    if sentence { s.push_str("  整句"); }
+    if let Some((ai, available)) = mode {
+        s.push_str(if !available { "  AI 不可用" } else if ai { "  AI" } else { "  词库" });
+    }
+    if plugins_active { s.push_str("  JS"); }
+    s
+}
+
+/// 候选行的一项: "1.你好"
+fn numbered(index: usize, cand: &str) -> String {
+    format!("{}.{}", index + 1, cand)
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+/// 按当前字体量出的布局
+struct Layout {
+    width: i32,
+    height: i32,
+    /// 候选行的 y 坐标
+    row_y: i32,
+    line_h: i32,
+    /// 各候选的 (x, 宽度)
+    items: Vec<(i32, i32)>,
+}
+
+unsafe fn measure(hdc: HDC, c: &Content) -> Layout {
+    let text_size = |s: &str| {
+        let mut size = SIZE::default();
+        let _ = GetTextExtentPoint32W(hdc, &wide(s), &mut size);
+        size
+    };
+    let header = text_size(&c.header());
+    let line_h = header.cy.max(text_size("中").cy);
+    let row_y = PADDING + line_h + LINE_GAP;
+
+    let mut items = Vec::with_capacity(c.candidates.len());
+    let mut x = PADDING;
+    for (i, cand) in c.candidates.iter().enumerate() {
+        let w = text_size(&numbered(i, cand)).cx;
+        items.push((x, w));
+        x += w + CAND_GAP;
+    }
+    let row_w = items.last().map(|&(x, w)| x + w).unwrap_or(0);
+    let width = (PADDING + header.cx).max(row_w) + PADDING;
+    let height = if items.is_empty() { row_y - LINE_GAP + PADDING } else { row_y + line_h + PADDING };
+    Layout { width, height, row_y, line_h, items }
+}
+
+fn font() -> HFONT {
+    HFONT(FONT.load(Ordering::Relaxed) as *mut _)
+}
+
+unsafe extern "system" fn wnd_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_PAINT => {
+            paint(hwnd);
+            LRESULT(0)
+        }
+        // 背景在 WM_PAINT 里一并画，避免闪烁
+        WM_ERASEBKGND => LRESULT(1),
+        // 点击候选窗不抢走输入焦点
+        WM_MOUSEACTIVATE => LRESULT(MA_NOACTIVATE as isize),
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+unsafe fn paint(hwnd: HWND) {
+    let mut ps = PAINTSTRUCT::default();
+    let hdc = BeginPaint(hwnd, &mut ps);
+    let mut rc = RECT::default();
+    let _ = GetClientRect(hwnd, &mut rc);
+
+    let bg = CreateSolidBrush(BG_COLOR);
+    FillRect(hdc, &rc, bg);
+    let _ = DeleteObject(bg);
+    let border = CreateSolidBrush(BORDER_COLOR);
+    FrameRect(hdc, &rc, border);
+    let _ = DeleteObject(border);
+
+    let old = SelectObject(hdc, font());
+    SetBkMode(hdc, TRANSPARENT);
+    if let Ok(c) = CONTENT.lock() {
+        let layout = measure(hdc, &c);
+        SetTextColor(hdc, RAW_COLOR);
+        let _ = TextOutW(hdc, PADDING, PADDING, &wide(&c.header()));
+
+        let highlight = CreateSolidBrush(HIGHLIGHT_COLOR);
+        SetTextColor(hdc, TEXT_COLOR);
+        for (i, (cand, &(x, w))) in c.candidates.iter().zip(&layout.items).enumerate() {
+            if i == c.selected {
+                let r = RECT { left: x - 3, top: layout.row_y - 1, right: x + w + 3, bottom: layout.row_y + layout.line_h + 1 };
+                FillRect(hdc, &r, highlight);
+            }
+            let _ = TextOutW(hdc, x, layout.row_y, &wide(&numbered(i, cand)));
+        }
+        let _ = DeleteObject(highlight);
+    }
+    SelectObject(hdc, old);
+    let _ = EndPaint(hwnd, &ps);
+}
+
+pub struct CandidateWindow {
+    hwnd: HWND,
+}
+
+impl CandidateWindow {
+    /// 创建（隐藏的）候选窗；在运行消息循环的主线程上调用
+    pub fn new(font_size: u32) -> windows::core::Result<Self> {
+        unsafe {
+            let hinstance: HINSTANCE = GetModuleHandleW(None)?.into();
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(wnd_proc),
+                hInstance: hinstance,
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                lpszClassName: CLASS_NAME,
+                ..Default::default()
+            };
+            // 重复注册会失败，由下面的 CreateWindowExW 判断能否使用
+            RegisterClassW(&class);
+
+            let hwnd = CreateWindowExW(
+                WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE | WS_EX_LAYERED,
+                CLASS_NAME, w!("AiPinyin"), WS_POPUP,
+                0, 0, 1, 1, None, None, hinstance, None,
+            )?;
+
+            if FONT.load(Ordering::Relaxed) == 0 {
+                let font = CreateFontW(
+                    -(font_size as i32), 0, 0, 0, FW_NORMAL.0 as i32, 0, 0, 0,
+                    DEFAULT_CHARSET.0 as u32, OUT_DEFAULT_PRECIS.0 as u32, CLIP_DEFAULT_PRECIS.0 as u32,
+                    CLEARTYPE_QUALITY.0 as u32, DEFAULT_PITCH.0 as u32, w!("Microsoft YaHei UI"),
+                );
+                FONT.store(font.0 as isize, Ordering::Relaxed);
+            }
+            eprintln!("[GDI UI] 候选窗已创建");
+            Ok(Self { hwnd })
+        }
+    }
+
+    /// 按新内容调整窗口大小并重绘
+    unsafe fn relayout(&self) {
+        let hdc = GetDC(self.hwnd);
+        let old = SelectObject(hdc, font());
+        let size = CONTENT.lock().ok().map(|c| {
+            let l = measure(hdc, &c);
+            (l.width, l.height)
+        });
+        SelectObject(hdc, old);
+        ReleaseDC(self.hwnd, hdc);
+        if let Some((w, h)) = size {
+            let _ = SetWindowPos(self.hwnd, HWND_TOPMOST, 0, 0, w, h, SWP_NOMOVE | SWP_NOACTIVATE | SWP_ASYNCWINDOWPOS);
+        }
+        let _ = InvalidateRect(self.hwnd, None, FALSE);
+    }
+}
+
+impl CandidateUi for CandidateWindow {
+    fn show(&self, x: i32, y: i32, caret: bool) {
+        unsafe {
+            let (dx, dy) = if caret { crate::webview_ui::caret_offset(x, y) } else { (0, 0) };
+            let (mut x, y) = (x + dx, y + dy);
+            // 右边超出屏幕时左移
+            let mut rc = RECT::default();
+            let _ = GetWindowRect(self.hwnd, &mut rc);
+            let cx = GetSystemMetrics(SM_CXSCREEN);
+            if x + (rc.right - rc.left) > cx {
+                x = cx - (rc.right - rc.left) - 10;
+            }
+            // ui.opacity（热重载后下次显示生效）
+            let _ = SetLayeredWindowAttributes(self.hwnd, COLORREF(0), crate::webview_ui::opacity(), LWA_ALPHA);
+            let _ = SetWindowPos(
+                self.hwnd, HWND_TOPMOST, x, y, 0, 0,
+                SWP_NOSIZE | SWP_NOACTIVATE | SWP_SHOWWINDOW | SWP_ASYNCWINDOWPOS,
+            );
+        }
+    }
+
+    fn hide(&self) {
+        crate::webview_ui::publish_hide();
+        unsafe { let _ = ShowWindowAsync(self.hwnd, SW_HIDE); }
+    }
+
+    fn update_candidates_with_page(
+        &self, raw: &str, candidates: &[&str], page_info: Option<(usize, usize)>,
+        sentence: bool, frequency: &[f32], selected: usize,
+    ) {
+        let candidates: Vec<String> = candidates.iter().map(|s| s.to_string()).collect();
+        crate::webview_ui::publish_update(raw, &candidates, page_info, sentence, frequency, selected);
+        if let Ok(mut c) = CONTENT.lock() {
+            c.raw = raw.to_string();
+            c.candidates = candidates;
+            c.page_info = page_info;
+            c.sentence = sentence;
+            c.selected = selected;
+        }
+        unsafe { self.relayout(); }
+    }
+
+    fn set_plugins_active(&self, active: bool) {
+        if let Ok(mut c) = CONTENT.lock() { c.plugins_active = active; }
+        unsafe { self.relayout(); }
+    }
+
+    fn set_engine_mode(&self, ai: bool, available: bool) {
+        if let Ok(mut c) = CONTENT.lock() { c.mode = Some((ai, available)); }
+        unsafe { self.relayout(); }
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_text() {
+        assert_eq!(header_text("nihao", None, false, false, None), "nihao");
+        assert_eq!(header_text("nihao", Some((1, 1)), false, false, None), "nihao");
+        assert_eq!(
+            header_text("nihao", Some((2, 3)), true, true, Some((true, true))),
+            "nihao  2/3  整句  AI  JS"
+        );
+        assert_eq!(header_text("", None, false, false, Some((true, false))), "  AI 不可用");
+        assert_eq!(numbered(0, "你好"), "1.你好");
+    }
+}
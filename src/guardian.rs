@@ -1,6 +1,6 @@
 //! # Guardian 模块 - ctfmon.exe 守护进程
 //!
-//! 监控 Windows 输入法服务进程 `ctfmon.exe`，
+//! 监控 Windows 输入法服务进程（默认 `ctfmon.exe`，可由 `[guardian]` 配置），
 //! 当检测到进程消失时自动重启，确保输入法服务永远在线。
 //!
 //! ## 设计理念
@@ -11,9 +11,16 @@ use std::process::Command;
 use std::thread;
 use std::time::Duration;
 use log::{info, warn, error};
+use serde::Deserialize;
 
-/// 守护进程配置
+/// 守护进程配置（对应 config.toml 的 `[guardian]` 段）
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct GuardianConfig {
+    /// 是否启用守护（精简版 Windows 没有 ctfmon 时可关闭）
+    pub enabled: bool,
+    /// 被守护的进程名
+    pub target_process: String,
     /// 巡检间隔（秒）
     pub check_interval_secs: u64,
     /// 最大连续重启次数（防止无限重启风暴）
@@ -23,27 +30,29 @@ pub struct GuardianConfig {
 impl Default for GuardianConfig {
     fn default() -> Self {
         Self {
+            enabled: true,
+            target_process: "ctfmon.exe".to_string(),
             check_interval_secs: 5,
             max_consecutive_restarts: 3,
         }
     }
 }
 
-/// 检查 ctfmon.exe 是否正在运行
+/// 检查指定进程是否正在运行
 ///
 /// 通过调用 `tasklist` 命令并过滤进程名来判断。
 /// 返回 `true` 表示进程存活，`false` 表示进程消失。
-fn is_ctfmon_running() -> bool {
+fn is_process_running(name: &str) -> bool {
     let output = Command::new("tasklist")
-        .args(["/FI", "IMAGENAME eq ctfmon.exe", "/FO", "CSV", "/NH"])
+        .args(["/FI", &format!("IMAGENAME eq {}", name), "/FO", "CSV", "/NH"])
         .output();
 
     match output {
         Ok(result) => {
             let stdout = String::from_utf8_lossy(&result.stdout);
-            // tasklist 找到进程时输出包含 "ctfmon.exe"
+            // tasklist 找到进程时输出包含进程名
             // 找不到时输出 "INFO: No tasks are running..."
-            stdout.to_lowercase().contains("ctfmon.exe")
+            stdout.to_lowercase().contains(&name.to_lowercase())
         }
         Err(e) => {
             error!("[Guardian] 执行 tasklist 失败: {}", e);
@@ -53,14 +62,14 @@ fn is_ctfmon_running() -> bool {
     }
 }
 
-/// 尝试重启 ctfmon.exe
+/// 尝试重启指定进程
 ///
 /// 使用 `cmd /c start` 启动进程，避免阻塞当前线程。
-fn restart_ctfmon() -> bool {
-    info!("[Guardian] 正在重启 ctfmon.exe ...");
+fn restart_process(name: &str) -> bool {
+    info!("[Guardian] 正在重启 {} ...", name);
 
     let result = Command::new("cmd")
-        .args(["/C", "start", "", "ctfmon.exe"])
+        .args(["/C", "start", "", name])
         .spawn();
 
     match result {
@@ -68,16 +77,16 @@ fn restart_ctfmon() -> bool {
             // 等一小段时间让进程启动
             thread::sleep(Duration::from_millis(500));
 
-            if is_ctfmon_running() {
-                info!("[Guardian] ✅ ctfmon.exe 重启成功！");
+            if is_process_running(name) {
+                info!("[Guardian] ✅ {} 重启成功！", name);
                 true
             } else {
-                warn!("[Guardian] ⚠️ ctfmon.exe 重启后未检测到进程");
+                warn!("[Guardian] ⚠️ {} 重启后未检测到进程", name);
                 false
             }
         }
         Err(e) => {
-            error!("[Guardian] ❌ 启动 ctfmon.exe 失败: {}", e);
+            error!("[Guardian] ❌ 启动 {} 失败: {}", name, e);
             false
         }
     }
@@ -85,8 +94,9 @@ fn restart_ctfmon() -> bool {
 
 /// 启动守护线程
 ///
-/// 在后台持续监控 ctfmon.exe，发现消失时自动重启。
+/// 在后台持续监控目标进程，发现消失时自动重启。
 /// 连续重启失败超过阈值后暂停巡检，避免重启风暴。
+/// `enabled = false` 时不启动线程，返回 `None`。
 ///
 /// # 示例
 /// ```no_run
@@ -95,11 +105,17 @@ fn restart_ctfmon() -> bool {
 /// // 使用默认配置启动守护线程
 /// let handle = start_guardian(GuardianConfig::default());
 /// ```
-pub fn start_guardian(config: GuardianConfig) -> thread::JoinHandle<()> {
-    thread::spawn(move || {
+pub fn start_guardian(config: GuardianConfig) -> Option<thread::JoinHandle<()>> {
+    if !config.enabled {
+        info!("[Guardian] 守护已关闭");
+        return None;
+    }
+
+    Some(thread::spawn(move || {
+        let target = config.target_process.as_str();
         info!(
-            "[Guardian] 守护线程已启动 | 巡检间隔: {}s | 最大连续重启: {}次",
-            config.check_interval_secs, config.max_consecutive_restarts
+            "[Guardian] 守护线程已启动 | 目标: {} | 巡检间隔: {}s | 最大连续重启: {}次",
+            target, config.check_interval_secs, config.max_consecutive_restarts
         );
 
         let mut consecutive_failures: u32 = 0;
@@ -110,14 +126,14 @@ pub fn start_guardian(config: GuardianConfig) -> thread::JoinHandle<()> {
         loop {
             thread::sleep(check_interval);
 
-            if is_ctfmon_running() {
+            if is_process_running(target) {
                 // 进程正常，重置失败计数
                 if consecutive_failures > 0 {
-                    info!("[Guardian] ctfmon.exe 已恢复正常运行");
+                    info!("[Guardian] {} 已恢复正常运行", target);
                     consecutive_failures = 0;
                 }
             } else {
-                warn!("[Guardian] ⚠️ 检测到 ctfmon.exe 已消失！");
+                warn!("[Guardian] ⚠️ 检测到 {} 已消失！", target);
 
                 if consecutive_failures >= config.max_consecutive_restarts {
                     error!(
@@ -129,14 +145,14 @@ pub fn start_guardian(config: GuardianConfig) -> thread::JoinHandle<()> {
                     continue;
                 }
 
-                if restart_ctfmon() {
+                if restart_process(target) {
                     consecutive_failures = 0;
                 } else {
                     consecutive_failures += 1;
                 }
             }
         }
-    })
+    }))
 }
 
 #[cfg(test)]
@@ -146,7 +162,7 @@ mod tests {
     #[test]
     fn test_ctfmon_detection() {
         // 在 Windows 环境下 ctfmon.exe 通常是运行的
-        let running = is_ctfmon_running();
+        let running = is_process_running("ctfmon.exe");
         println!("ctfmon.exe 运行状态: {}", running);
         // 不做硬断言，因为 CI 环境可能没有此进程
     }
@@ -154,7 +170,18 @@ mod tests {
     #[test]
     fn test_default_config() {
         let config = GuardianConfig::default();
+        assert!(config.enabled);
+        assert_eq!(config.target_process, "ctfmon.exe");
         assert_eq!(config.check_interval_secs, 5);
         assert_eq!(config.max_consecutive_restarts, 3);
     }
+
+    #[test]
+    fn test_partial_config() {
+        // 只写部分字段时其余取默认值
+        let config: GuardianConfig = toml::from_str("enabled = false\ncheck_interval_secs = 30").unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.check_interval_secs, 30);
+        assert_eq!(config.target_process, "ctfmon.exe");
+    }
 }
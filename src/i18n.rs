@@ -0,0 +1,164 @@
+//! # 界面文字
+//!
+//! 面向用户的文字（启动横幅、插件授权对话框、AI 模型缺失提示）集中在这里，
+//! 按 `ui.language` 选择中文或英文。日志 (`eprintln!`) 不翻译。
+//!
+//! 带参数的文字用 `{}` 占位，由 [`fill`] 依次替换。
+
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// 界面语言
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Zh,
+    En,
+}
+
+/// 当前语言（启动时与配置热重载时设置）
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_language(lang: Language) {
+    LANGUAGE.store(lang as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::En,
+        _ => Language::Zh,
+    }
+}
+
+/// 文字条目
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Text {
+    BannerTagline,
+    BannerHint,
+    BannerKeys,
+    HookInstalled,
+    HookShiftHelp,
+    AiMissingNotice,
+    PluginAuthCaption,
+    PluginAuthName,
+    PluginAuthAuthor,
+    PluginAuthMissingMeta,
+    PluginAuthAccess,
+    PluginAuthNetwork,
+    PluginAuthQuestion,
+    PluginManageCaption,
+    PluginSlotsFull,
+}
+
+/// 当前语言下的文字
+pub fn tr(text: Text) -> &'static str {
+    let (zh, en) = entry(text);
+    match language() {
+        Language::Zh => zh,
+        Language::En => en,
+    }
+}
+
+/// 按顺序把 `{}` 替换为参数
+pub fn fill(template: &str, args: &[&str]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() { out.push_str(first); }
+    for (i, part) in parts.enumerate() {
+        out.push_str(args.get(i).copied().unwrap_or(""));
+        out.push_str(part);
+    }
+    out
+}
+
+/// 文字表: (中文, English)
+fn entry(text: Text) -> (&'static str, &'static str) {
+    match text {
+        Text::BannerTagline => (
+            "AI驱动 · 向量引擎 · 本地推理",
+            "AI-powered · local inference",
+        ),
+        Text::BannerHint => (
+            "在任意窗口直接打拼音即可！",
+            "Type pinyin in any window!",
+        ),
+        Text::BannerKeys => (
+            "A-Z: 输入 | 空格/数字: 上屏 | 退格: 删除 | ESC: 取消",
+            "A-Z: type | Space/digits: commit | Backspace: delete | Esc: cancel",
+        ),
+        Text::HookInstalled => (
+            "✅ 全局钩子已安装，请切换到其他窗口打字...",
+            "✅ Keyboard hook installed, switch to another window to type...",
+        ),
+        Text::HookShiftHelp => (
+            "【Shift】切换中/英文模式  【双击 Shift】锁定英文",
+            "[Shift] toggle Chinese/English  [Double Shift] lock English",
+        ),
+        Text::AiMissingNotice => (
+            "AI 模型未加载，当前仅使用词库候选。\n原因: {}\n\n\
+如需 AI 预测，请将 gpt2_int8.onnx、onnxruntime.dll 和词表文件\n\
+(char2id.json / pinyin2id.json / pinyin2char.json) 放到:\n{}\n\n\
+以后不再显示此提示？",
+            "The AI model is not loaded; only dictionary candidates are used.\nReason: {}\n\n\
+For AI prediction, put gpt2_int8.onnx, onnxruntime.dll and the vocabulary files\n\
+(char2id.json / pinyin2id.json / pinyin2char.json) in:\n{}\n\n\
+Don't show this again?",
+        ),
+        Text::PluginAuthCaption => ("AiPinyin 插件授权", "AiPinyin plugin authorization"),
+        Text::PluginAuthName => ("插件「{}」", "Plugin \"{}\""),
+        Text::PluginAuthAuthor => ("（作者: {}）", " (by {})"),
+        Text::PluginAuthMissingMeta => (
+            "\n⚠ 该插件未完整声明版本/说明/作者信息。",
+            "\n⚠ This plugin does not fully declare its version/description/author.",
+        ),
+        Text::PluginAuthAccess => (
+            "\n\n将访问您的输入流，读取并可能修改每次输入的候选词，\
+并可读取最近上屏的文字（输入历史）。",
+            "\n\nIt will access your input, read and possibly change the candidates \
+of every keystroke, and read recently committed text (input history).",
+        ),
+        Text::PluginAuthNetwork => (
+            "\n\n该插件还将联网访问以下主机:\n{}",
+            "\n\nIt will also connect to these hosts:\n{}",
+        ),
+        Text::PluginAuthQuestion => ("\n\n是否授权该插件？", "\n\nAuthorize this plugin?"),
+        Text::PluginManageCaption => ("AiPinyin 插件管理", "AiPinyin plugins"),
+        Text::PluginSlotsFull => (
+            "插件槽位已满（最多 {} 个同时激活）。\n请先禁用一个插件再启用新插件。",
+            "All plugin slots are in use (at most {} active).\nDisable a plugin before enabling another.",
+        ),
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_placeholders() {
+        assert_eq!(fill("插件「{}」", &["快捷词"]), "插件「快捷词」");
+        assert_eq!(fill("{} / {}", &["a", "b"]), "a / b");
+        // 参数不足时留空
+        assert_eq!(fill("x{}y{}", &["1"]), "x1y");
+    }
+
+    #[test]
+    fn test_placeholder_counts_match() {
+        let all = [
+            Text::BannerTagline, Text::BannerHint, Text::BannerKeys, Text::HookInstalled,
+            Text::HookShiftHelp, Text::AiMissingNotice, Text::PluginAuthCaption,
+            Text::PluginAuthName, Text::PluginAuthAuthor, Text::PluginAuthMissingMeta,
+            Text::PluginAuthAccess, Text::PluginAuthNetwork, Text::PluginAuthQuestion,
+            Text::PluginManageCaption, Text::PluginSlotsFull,
+        ];
+        for text in all {
+            let (zh, en) = entry(text);
+            assert_eq!(zh.matches("{}").count(), en.matches("{}").count(), "{:?}", text);
+        }
+    }
+}
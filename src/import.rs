@@ -0,0 +1,149 @@
+//! # 外部词库导入
+//!
+//! 把其他输入法的词库文本转换为 AiPinyin 的 `拼音,汉字,权重` 行格式，
+//! 结果可直接交给 `Dictionary::merge_text` 或追加到 dict.txt。
+//!
+//! ## 支持格式
+//! - Rime `.dict.yaml`: YAML 头 + `词\tpin yin\t权重`（权重可省略）
+//! - 搜狗 scel 导出文本: `'ni'hao 你好` / `ni'hao 你好 120` / `你好 ni hao`
+
+/// 源词库缺失权重时使用的默认值（与 dict.txt 解析默认值一致）
+pub const DEFAULT_WEIGHT: u32 = 50;
+
+/// 导入 Rime `.dict.yaml` 词库
+///
+/// 跳过 `---` 到 `...` 之间的 YAML 头和 `#` 注释；
+/// 每行按 Tab 切分为 词 / 拼音(空格分隔) / 权重，权重支持 `5%` 写法。
+pub fn import_rime(text: &str) -> String {
+    let mut out = String::new();
+    let has_header = text.lines().any(|l| l.trim() == "...");
+    let mut in_body = !has_header;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if !in_body {
+            if trimmed == "..." { in_body = true; }
+            continue;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') { continue; }
+
+        let mut cols = line.split('\t');
+        let word = match cols.next() { Some(w) => w.trim(), None => continue };
+        let code = match cols.next() { Some(c) => c, None => continue };
+        let weight = cols.next().and_then(parse_weight).unwrap_or(DEFAULT_WEIGHT);
+
+        push_entry(&mut out, code, word, weight);
+    }
+    out
+}
+
+/// 导入搜狗 scel 导出的文本词库
+///
+/// 每行由空白分隔: 含汉字的一段是词, 纯数字是权重, 其余 ASCII 段拼起来是拼音
+/// (音节间可用 `'` 或空格分隔)。
+pub fn import_sogou_txt(text: &str) -> String {
+    let mut out = String::new();
+
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        let mut word = "";
+        let mut code = String::new();
+        let mut weight = DEFAULT_WEIGHT;
+
+        for token in line.split_whitespace() {
+            if !token.is_ascii() {
+                word = token;
+            } else if token.chars().all(|c| c.is_ascii_digit()) {
+                weight = token.parse().unwrap_or(DEFAULT_WEIGHT);
+            } else {
+                code.push_str(token);
+            }
+        }
+
+        push_entry(&mut out, &code, word, weight);
+    }
+    out
+}
+
+/// 解析权重列: 整数 / 小数 / 百分比 (`5%` → 5)
+fn parse_weight(s: &str) -> Option<u32> {
+    let s = s.trim().trim_end_matches('%');
+    s.parse::<u32>().ok()
+        .or_else(|| s.parse::<f64>().ok().filter(|v| *v >= 0.0).map(|v| v.round() as u32))
+}
+
+/// 规范化拼音 (去空格/隔音符, ü→v) 后追加一行 `拼音,汉字,权重`
+fn push_entry(out: &mut String, code: &str, word: &str, weight: u32) {
+    if word.is_empty() || word.contains(',') { return; }
+    let pinyin = match crate::pinyin::sanitize_pinyin(&code.to_lowercase()) {
+        Some(p) => p,
+        None => return,
+    };
+    out.push_str(&format!("{},{},{}\n", pinyin, word, weight));
+}
+
+/// 把转换结果追加到 dict.txt，前面加一行 `# 导入自 <来源>` 注释
+///
+/// 原文件末尾没有换行时先补一个，避免首条导入词条粘到最后一行上。
+pub fn append_to_dict(path: &std::path::Path, source: &str, lines: &str) -> std::io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    let mut f = std::fs::OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    if f.seek(SeekFrom::End(0))? > 0 {
+        let mut last = [0u8; 1];
+        f.seek(SeekFrom::End(-1))?;
+        f.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            f.write_all(b"\n")?;
+        }
+    }
+    writeln!(f, "# 导入自 {}", source)?;
+    f.write_all(lines.as_bytes())
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_rime() {
+        let text = "# Rime dictionary\n---\nname: luna\nversion: \"1\"\n...\n\n\
+你好\tni hao\t120\n银行\tyin hang\n绿色\tlü se\t3%\n";
+        assert_eq!(import_rime(text), "nihao,你好,120\nyinhang,银行,50\nlvse,绿色,3\n");
+    }
+
+    #[test]
+    fn test_import_rime_without_header() {
+        assert_eq!(import_rime("时间\tshi jian\t80\n"), "shijian,时间,80\n");
+    }
+
+    #[test]
+    fn test_import_sogou_txt() {
+        let text = "'ni'hao 你好\nshi'jian 时间 300\n我们 wo men\n坏行\n";
+        assert_eq!(import_sogou_txt(text), "nihao,你好,50\nshijian,时间,300\nwomen,我们,50\n");
+    }
+
+    #[test]
+    fn test_import_feeds_merge_text() {
+        let mut dict = crate::pinyin::Dictionary::from_text("");
+        let added = dict.merge_text(&import_rime("你好\tni hao\t120\n"));
+        assert_eq!(added, 1);
+        assert_eq!(dict.lookup("nihao")[0].word, "你好");
+    }
+
+    #[test]
+    fn test_append_to_dict_adds_missing_newline() {
+        let path = std::env::temp_dir().join(format!("aipinyin_import_{}.txt", std::process::id()));
+        std::fs::write(&path, "nihao,你好,100").unwrap();
+        append_to_dict(&path, "a.txt", "shijian,时间,80\n").unwrap();
+        append_to_dict(&path, "b.txt", "women,我们,50\n").unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(text, "nihao,你好,100\n# 导入自 a.txt\nshijian,时间,80\n# 导入自 b.txt\nwomen,我们,50\n");
+    }
+}
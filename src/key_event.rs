@@ -3,10 +3,12 @@
 //! 实现 ITfKeyEventSink 接口，处理按键→拼音→候选的核心逻辑。
 
 use std::cell::RefCell;
+use std::time::{Duration, Instant};
 use log::info;
 use windows::core::*;
 use windows::Win32::Foundation::*;
 use windows::Win32::UI::TextServices::*;
+use crate::config::CommitKey;
 use crate::pinyin::PinyinEngine;
 
 
@@ -17,11 +19,52 @@ use crate::pinyin::PinyinEngine;
 pub struct InputState {
     pub engine: PinyinEngine,
     pub committed: String,
+    /// 上屏首选的按键（来自 keys.commit）
+    pub commit_key: CommitKey,
+    /// 双击空格的最大间隔（keys.double_space_ms，0 = 不启用）
+    pub double_space: Duration,
+    /// 上一次按空格上屏的时刻（其他键会清掉）
+    last_space: Option<Instant>,
+    /// 打开符号面板的键（engine.symbol_key，None = 不启用）
+    pub symbol_vk: Option<u32>,
+    /// 输入中两次按键的最长间隔，超过则丢弃旧输入（engine.compose_timeout_secs，0 = 不启用）
+    pub compose_timeout: Duration,
+    /// 输入中最后一次被吃掉的按键时刻
+    last_key: Option<Instant>,
+    /// 输入中数字键选字（keys.digit_select）；false 时数字连同已输入字母原样上屏
+    pub digit_select: bool,
 }
 
 impl InputState {
     pub fn new() -> Self {
-        Self { engine: PinyinEngine::new(), committed: String::new() }
+        Self {
+            engine: PinyinEngine::new(), committed: String::new(), commit_key: CommitKey::Space,
+            double_space: Duration::ZERO, last_space: None, symbol_vk: None,
+            compose_timeout: Duration::ZERO, last_key: None, digit_select: true,
+        }
+    }
+
+    /// 数字键此时按字面输入而不是选字（digit_select 关闭、有拼音；u 模式与符号面板里数字另有用途）
+    pub fn literal_digits(&self) -> bool {
+        !self.digit_select && !self.engine.is_empty()
+            && !self.engine.is_unicode_mode() && !self.engine.is_symbol_mode()
+    }
+
+    /// 输入已搁置超过 compose_timeout 时清空并返回 true（在处理下一个键之前调用）
+    pub fn expire_stale(&mut self) -> bool {
+        self.expire_stale_at(Instant::now())
+    }
+
+    fn expire_stale_at(&mut self, now: Instant) -> bool {
+        let stale = !self.compose_timeout.is_zero() && !self.engine.is_empty()
+            && self.last_key.is_some_and(|t| now.duration_since(t) > self.compose_timeout);
+        if stale {
+            info!("[Key] 输入超时，丢弃 {:?}", self.engine.raw_input());
+            self.engine.clear();
+            self.last_space = None;
+            self.last_key = None;
+        }
+        stale
     }
 }
 
@@ -40,11 +83,29 @@ pub struct KeyResult {
 pub enum CommitAction {
     /// 上屏当前显示候选列表中的第 i 项（由调用方从缓存中取）
     Index(usize),
+    /// 上屏当前高亮的候选（Tab / 方向键移动过的选中项，默认第一项）
+    Selected,
+    /// 双击空格: 上屏高亮候选后清空剩余拼音（不按字数只消耗部分音节）
+    SelectedWhole,
     /// 直接上屏指定文本（Enter 原始字母）
     Text(String),
 }
 
 pub fn handle_key_down(state: &mut InputState, vkey: u32) -> KeyResult {
+    handle_key_down_at(state, vkey, Instant::now())
+}
+
+/// 同 `handle_key_down`，按键时刻由调用方给出（双击空格、输入超时的判断用）
+fn handle_key_down_at(state: &mut InputState, vkey: u32, now: Instant) -> KeyResult {
+    let result = process_key(state, vkey, now);
+    if result.eaten && !state.engine.is_empty() {
+        state.last_key = Some(now);
+    }
+    result
+}
+
+fn process_key(state: &mut InputState, vkey: u32, now: Instant) -> KeyResult {
+    let last_space = state.last_space.take();
     match vkey {
         // A-Z
         0x41..=0x5A => {
@@ -62,15 +123,47 @@ pub fn handle_key_down(state: &mut InputState, vkey: u32) -> KeyResult {
                 KeyResult { eaten: true, commit: None, need_refresh: true }
             }
         }
-        // Space → 选第一个（索引 0）
-        0x20 => {
+        // Space / Enter → 上屏高亮候选或原始字母，由 commit_key 决定
+        0x20 | 0x0D => {
             if state.engine.is_empty() {
-                KeyResult { eaten: false, commit: None, need_refresh: false }
+                return KeyResult { eaten: false, commit: None, need_refresh: false };
+            }
+            let is_commit_key = match state.commit_key {
+                CommitKey::Space => vkey == 0x20,
+                CommitKey::Enter => vkey == 0x0D,
+            };
+            if is_commit_key {
+                // 不在这里 clear，由 main.rs 根据选中词的字数决定消耗几个音节；
+                // 紧跟上一次空格（还有剩余拼音）则整句上屏并清空
+                let double = vkey == 0x20 && !state.double_space.is_zero()
+                    && last_space.is_some_and(|t| now.duration_since(t) <= state.double_space);
+                let action = if double {
+                    CommitAction::SelectedWhole
+                } else {
+                    if vkey == 0x20 { state.last_space = Some(now); }
+                    CommitAction::Selected
+                };
+                KeyResult { eaten: true, commit: Some(action), need_refresh: true }
             } else {
-                // 不在这里 clear，由 main.rs 根据选中词的字数决定消耗几个音节
-                KeyResult { eaten: true, commit: Some(CommitAction::Index(0)), need_refresh: true }
+                // 另一个键以原始字母上屏；空格作为非上屏键时保留空格本身
+                let mut raw = state.engine.raw_input().to_string();
+                if vkey == 0x20 { raw.push(' '); }
+                state.engine.clear();
+                KeyResult { eaten: true, commit: Some(CommitAction::Text(raw)), need_refresh: true }
             }
         }
+        // u 模式: 0-9 是码位的一部分，不选字
+        0x30..=0x39 if state.engine.is_unicode_mode() => {
+            state.engine.push(vkey as u8 as char);
+            KeyResult { eaten: true, commit: None, need_refresh: true }
+        }
+        // 数字不选字: 已输入的字母加上数字原样上屏（"a4"），选字改用空格 / Tab / 方向键
+        0x30..=0x39 if state.literal_digits() => {
+            let mut raw = state.engine.raw_input().to_string();
+            raw.push(vkey as u8 as char);
+            state.engine.clear();
+            KeyResult { eaten: true, commit: Some(CommitAction::Text(raw)), need_refresh: true }
+        }
         // 1-9 → 选对应索引
         0x31..=0x39 => {
             if state.engine.is_empty() {
@@ -81,6 +174,12 @@ pub fn handle_key_down(state: &mut InputState, vkey: u32) -> KeyResult {
                 KeyResult { eaten: true, commit: Some(CommitAction::Index(idx)), need_refresh: true }
             }
         }
+        // 符号面板键: 输入为空时进入符号输入（候选窗列出分类符号）
+        vk if state.symbol_vk == Some(vk) && state.engine.is_empty() => {
+            state.engine.push(crate::symbols::SYMBOL_PREFIX);
+            let eaten = !state.engine.is_empty();
+            KeyResult { eaten, commit: None, need_refresh: eaten }
+        }
         // Escape → 取消，不输出任何内容
         0x1B => {
             if state.engine.is_empty() {
@@ -90,16 +189,6 @@ pub fn handle_key_down(state: &mut InputState, vkey: u32) -> KeyResult {
                 KeyResult { eaten: true, commit: None, need_refresh: true }
             }
         }
-        // Enter → 以原始字母形式上屏
-        0x0D => {
-            if state.engine.is_empty() {
-                KeyResult { eaten: false, commit: None, need_refresh: false }
-            } else {
-                let raw = state.engine.raw_input().to_string();
-                state.engine.clear();
-                KeyResult { eaten: true, commit: Some(CommitAction::Text(raw)), need_refresh: true }
-            }
-        }
         _ => KeyResult { eaten: false, commit: None, need_refresh: false },
     }
 }
@@ -133,6 +222,8 @@ impl ITfKeyEventSink_Impl for AiPinyinKeyEventSink_Impl {
             0x41..=0x5A => true,
             0x08 | 0x0D | 0x20 | 0x1B => !state.engine.is_empty(),
             0x31..=0x39 => !state.engine.is_empty(),
+            0x30 => state.engine.is_unicode_mode() || state.literal_digits(),
+            vk if state.symbol_vk == Some(vk) => state.engine.is_empty(),
             _ => false,
         };
         Ok(BOOL::from(eat))
@@ -164,3 +255,176 @@ impl ITfKeyEventSink_Impl for AiPinyinKeyEventSink_Impl {
         Ok(FALSE)
     }
 }
+
+/// 一个字符的 KEYEVENTF_UNICODE 事件序列: 每个 UTF-16 单元一对 (单元, 是否 keyup)
+///
+/// BMP 外的字符（emoji 等）是代理对，产生两个单元共四个事件，须在同一次 SendInput 中发出。
+pub fn unicode_key_events(ch: char) -> Vec<(u16, bool)> {
+    let mut buf = [0u16; 2];
+    ch.encode_utf16(&mut buf).iter()
+        .flat_map(|&unit| [(unit, false), (unit, true)])
+        .collect()
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(commit_key: CommitKey, text: &str) -> InputState {
+        let mut state = InputState::new();
+        state.commit_key = commit_key;
+        for ch in text.chars() {
+            handle_key_down(&mut state, ch.to_ascii_uppercase() as u32);
+        }
+        state
+    }
+
+    #[test]
+    fn test_commit_key_space() {
+        let mut state = typed(CommitKey::Space, "nihao");
+        let r = handle_key_down(&mut state, 0x20);
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+
+        let mut state = typed(CommitKey::Space, "nihao");
+        let r = handle_key_down(&mut state, 0x0D);
+        assert!(matches!(r.commit, Some(CommitAction::Text(ref t)) if t == "nihao"));
+        assert!(state.engine.is_empty());
+    }
+
+    #[test]
+    fn test_commit_key_enter() {
+        let mut state = typed(CommitKey::Enter, "nihao");
+        let r = handle_key_down(&mut state, 0x0D);
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+
+        let mut state = typed(CommitKey::Enter, "hello");
+        let r = handle_key_down(&mut state, 0x20);
+        assert!(matches!(r.commit, Some(CommitAction::Text(ref t)) if t == "hello "));
+        assert!(state.engine.is_empty());
+    }
+
+    #[test]
+    fn test_double_space_commits_whole() {
+        let mut state = typed(CommitKey::Space, "nihaoshijian");
+        state.double_space = Duration::from_millis(300);
+        let t0 = Instant::now();
+        let r = handle_key_down_at(&mut state, 0x20, t0);
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+        let r = handle_key_down_at(&mut state, 0x20, t0 + Duration::from_millis(200));
+        assert!(matches!(r.commit, Some(CommitAction::SelectedWhole)));
+        // 第三次空格重新计起
+        let r = handle_key_down_at(&mut state, 0x20, t0 + Duration::from_millis(250));
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+
+        // 超时 / 中间打了字母 → 普通空格
+        let r = handle_key_down_at(&mut state, 0x20, t0 + Duration::from_millis(900));
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+        handle_key_down_at(&mut state, 0x41, t0 + Duration::from_millis(950));
+        let r = handle_key_down_at(&mut state, 0x20, t0 + Duration::from_millis(1000));
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+
+        // 未启用
+        let mut state = typed(CommitKey::Space, "nihao");
+        handle_key_down_at(&mut state, 0x20, t0);
+        let r = handle_key_down_at(&mut state, 0x20, t0 + Duration::from_millis(10));
+        assert!(matches!(r.commit, Some(CommitAction::Selected)));
+    }
+
+    #[test]
+    fn test_compose_timeout_clears_stale_input() {
+        let mut state = InputState::new();
+        state.compose_timeout = Duration::from_secs(30);
+        let t0 = Instant::now();
+        for (i, vk) in [0x4E, 0x49].into_iter().enumerate() {
+            handle_key_down_at(&mut state, vk, t0 + Duration::from_secs(i as u64 * 20));
+        }
+        // 每次按键都重新计时: 距第一个键 45 秒，但距上一个键只有 25 秒
+        assert!(!state.expire_stale_at(t0 + Duration::from_secs(45)));
+        assert_eq!(state.engine.raw_input(), "ni");
+
+        assert!(state.expire_stale_at(t0 + Duration::from_secs(60)));
+        assert!(state.engine.is_empty());
+        // 新输入从头开始，不会接在旧拼音后面
+        handle_key_down_at(&mut state, 0x48, t0 + Duration::from_secs(60));
+        assert_eq!(state.engine.raw_input(), "h");
+
+        // 未启用
+        let mut state = typed(CommitKey::Space, "nihao");
+        assert!(!state.expire_stale_at(t0 + Duration::from_secs(3600)));
+        assert_eq!(state.engine.raw_input(), "nihao");
+    }
+
+    #[test]
+    fn test_symbol_key_opens_panel() {
+        let mut state = InputState::new();
+        state.engine.set_symbols(crate::symbols::builtin());
+        // 未配置按键: 放行
+        assert!(!handle_key_down(&mut state, 0xBF).eaten);
+
+        state.symbol_vk = Some(0xBF);
+        let r = handle_key_down(&mut state, 0xBF);
+        assert!(r.eaten && r.need_refresh);
+        assert!(state.engine.is_symbol_mode());
+        handle_key_down(&mut state, 0x53);
+        handle_key_down(&mut state, 0x58);
+        assert_eq!(state.engine.raw_input(), "/sx");
+        assert!(matches!(handle_key_down(&mut state, 0x32).commit, Some(CommitAction::Index(1))));
+
+        // Esc 关闭，不输出
+        let r = handle_key_down(&mut state, 0x1B);
+        assert!(r.eaten && r.commit.is_none());
+        assert!(state.engine.is_empty());
+        // 有拼音时不打开
+        let mut state = typed(CommitKey::Space, "ni");
+        state.symbol_vk = Some(0xBF);
+        assert!(!handle_key_down(&mut state, 0xBF).eaten);
+    }
+
+    #[test]
+    fn test_digit_select_modes() {
+        // 默认: 数字选字，输入为空时放行
+        let mut state = typed(CommitKey::Space, "a");
+        assert!(matches!(handle_key_down(&mut state, 0x34).commit, Some(CommitAction::Index(3))));
+        assert!(!handle_key_down(&mut typed(CommitKey::Space, ""), 0x34).eaten);
+
+        // 关闭: 字母连同数字原样上屏
+        for (vk, expect) in [(0x34, "a4"), (0x30, "a0")] {
+            let mut state = typed(CommitKey::Space, "a");
+            state.digit_select = false;
+            let r = handle_key_down(&mut state, vk);
+            assert!(r.eaten);
+            assert!(matches!(r.commit, Some(CommitAction::Text(ref t)) if t == expect));
+            assert!(state.engine.is_empty());
+        }
+        let mut state = typed(CommitKey::Space, "");
+        state.digit_select = false;
+        assert!(!handle_key_down(&mut state, 0x34).eaten);
+
+        // u 模式的码位数字不受影响
+        let mut state = typed(CommitKey::Space, "u4f");
+        state.digit_select = false;
+        handle_key_down(&mut state, 0x36);
+        assert_eq!(state.engine.raw_input(), "u4f6");
+    }
+
+    #[test]
+    fn test_unicode_key_events_surrogate_pair() {
+        assert_eq!(unicode_key_events('你'), vec![(0x4F60, false), (0x4F60, true)]);
+        // U+1F600 😀 → D83D DE00
+        assert_eq!(unicode_key_events('😀'),
+            vec![(0xD83D, false), (0xD83D, true), (0xDE00, false), (0xDE00, true)]);
+    }
+
+    #[test]
+    fn test_commit_key_empty_passthrough() {
+        for key in [CommitKey::Space, CommitKey::Enter] {
+            let mut state = typed(key, "");
+            assert!(!handle_key_down(&mut state, 0x20).eaten);
+            assert!(!handle_key_down(&mut state, 0x0D).eaten);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! # AiPinyin 引擎库
+//!
+//! 不依赖 Win32 的部分: 拼音切分、字典、AI 推理、用户词典、配置与本地 HTTP 服务。
+//! 非 Windows 平台也能编译和测试 (`cargo test --lib`)。
+//!
+//! 想在自己的程序里用拼音引擎，从 [`engine::Engine`] 开始；
+//! `aipinyin.exe`（main.rs）是建立在这些模块之上的 Windows 前端（键盘钩子 + 候选窗口）。
+
+pub mod ai_engine;
+pub mod ai_server;
+pub mod autocaps;
+pub mod bench;
+pub mod cand_cache;
+pub mod config;
+pub mod crash;
+pub mod engine;
+pub mod focus_stash;
+pub mod guardian;
+pub mod i18n;
+pub mod import;
+pub mod lifetime;
+pub mod logger;
+pub mod pinyin;
+pub mod plugin_host;
+pub mod punct;
+pub mod shuangpin;
+pub mod stream;
+pub mod symbols;
+pub mod user_dict;
+
+pub use engine::Engine;
@@ -0,0 +1,102 @@
+//! # 全局状态的拆除同步
+//!
+//! 前端的全局状态是一个裸指针，后台线程（AI 推理、整句请求、自动上屏、配置热重载等）
+//! 可能在退出时仍在运行。后台线程通过 [`Lifetime::enter`] 访问状态，退出流程先 [`Lifetime::end`]
+//! 再释放: `end` 会等正在 `enter` 里的访问结束，之后的 `enter` 一律返回 None，不会用到已释放的内存。
+//!
+//! 访问只应覆盖读写状态的那一段；推理、网络请求等耗时操作放在 `enter` 之外，免得拖住退出。
+
+use std::sync::RwLock;
+
+pub struct Lifetime {
+    alive: RwLock<bool>,
+}
+
+impl Lifetime {
+    pub const fn new() -> Self {
+        Self { alive: RwLock::new(true) }
+    }
+
+    /// 状态仍在时执行 `f`（期间 `end` 会等待），已拆除返回 None
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        let alive = self.alive.read().unwrap_or_else(|e| e.into_inner());
+        if !*alive { return None; }
+        Some(f())
+    }
+
+    /// 标记拆除: 等所有进行中的 `enter` 结束后返回
+    pub fn end(&self) {
+        *self.alive.write().unwrap_or_else(|e| e.into_inner()) = false;
+    }
+
+    pub fn is_alive(&self) -> bool {
+        *self.alive.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Lifetime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// 模拟前端状态: 释放后再访问会被发现
+    struct State {
+        freed: Arc<AtomicBool>,
+        raw: String,
+    }
+
+    impl Drop for State {
+        fn drop(&mut self) {
+            self.freed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_teardown_waits_for_background_access() {
+        static LIFETIME: Lifetime = Lifetime::new();
+        static STATE: AtomicPtr<State> = AtomicPtr::new(std::ptr::null_mut());
+
+        let freed = Arc::new(AtomicBool::new(false));
+        STATE.store(Box::into_raw(Box::new(State { freed: Arc::clone(&freed), raw: "nihao".into() })), Ordering::SeqCst);
+
+        // 大量后台"刷新"线程在拆除前后不停访问状态，直到被拒绝
+        let accesses = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..16).map(|_| {
+            let freed = Arc::clone(&freed);
+            let accesses = Arc::clone(&accesses);
+            std::thread::spawn(move || {
+                while LIFETIME.enter(|| {
+                    let ptr = STATE.load(Ordering::SeqCst);
+                    assert!(!ptr.is_null());
+                    assert!(!freed.load(Ordering::SeqCst), "访问了已释放的状态");
+                    assert_eq!(unsafe { (*ptr).raw.as_str() }, "nihao");
+                    std::thread::yield_now();
+                    assert!(!freed.load(Ordering::SeqCst), "访问期间状态被释放");
+                    accesses.fetch_add(1, Ordering::SeqCst);
+                }).is_some() {}
+            })
+        }).collect();
+
+        while accesses.load(Ordering::SeqCst) < 100 { std::thread::yield_now(); }
+        // 退出流程: 先 end 再释放
+        LIFETIME.end();
+        let ptr = STATE.swap(std::ptr::null_mut(), Ordering::SeqCst);
+        drop(unsafe { Box::from_raw(ptr) });
+        assert!(freed.load(Ordering::SeqCst));
+        assert!(!LIFETIME.is_alive());
+
+        for w in workers { w.join().unwrap(); }
+        assert_eq!(LIFETIME.enter(|| ()), None);
+    }
+}
@@ -0,0 +1,162 @@
+//! # 日志后端
+//!
+//! 作为 `log` 宏的后端：写入 exe 同目录的 `aipinyin.log`，
+//! 超过 1 MB 时轮转为 `aipinyin.log.1`（只保留这两个文件）；
+//! 有控制台时同时输出到 stderr。由 `[log]` 配置段控制级别与是否落盘。
+
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::{LevelFilter, Log, Metadata, Record};
+use crate::config::LogConfig;
+
+/// 单个日志文件的大小上限
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// 初始化全局 logger（只能调用一次，重复调用忽略）
+pub fn init(cfg: &LogConfig) {
+    let level = cfg.level.parse::<LevelFilter>().unwrap_or(LevelFilter::Warn);
+    let file = if cfg.to_file {
+        match LogFile::open(log_path(), MAX_LOG_BYTES) {
+            Ok(f) => Some(Mutex::new(f)),
+            Err(e) => {
+                eprintln!("[Log] ⚠ 无法打开日志文件: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let logger = Box::leak(Box::new(FileLogger {
+        level,
+        console: std::io::stderr().is_terminal(),
+        file,
+    }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+fn log_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.join("aipinyin.log")))
+        .unwrap_or_else(|| PathBuf::from("aipinyin.log"))
+}
+
+// ============================================================
+// Logger
+// ============================================================
+
+struct FileLogger {
+    level: LevelFilter,
+    /// 是否有控制台（无控制台运行时不写 stderr）
+    console: bool,
+    file: Option<Mutex<LogFile>>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return; }
+        let line = format!("{} [{}] {}: {}\n",
+            crate::crash::format_timestamp(std::time::SystemTime::now()),
+            record.level(), record.target(), record.args());
+
+        if self.console {
+            eprint!("{}", line);
+        }
+        if let Some(file) = &self.file {
+            if let Ok(mut f) = file.lock() {
+                let _ = f.write_line(&line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut f) = file.lock() {
+                let _ = f.file.flush();
+            }
+        }
+    }
+}
+
+// ============================================================
+// 按大小轮转的日志文件
+// ============================================================
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_bytes: u64) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { path, file, written, max_bytes })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// 当前文件改名为 `.1`（覆盖旧的 `.1`），重新开一个空文件
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let backup = rotated_path(&self.path);
+        let _ = std::fs::remove_file(&backup);
+        std::fs::rename(&self.path, &backup)?;
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_two_files() {
+        let dir = std::env::temp_dir().join(format!("aipinyin_log_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("aipinyin.log");
+
+        let mut log = LogFile::open(path.clone(), 64).unwrap();
+        for i in 0..10 {
+            log.write_line(&format!("line {:02} ..................\n", i)).unwrap();
+        }
+
+        let current = std::fs::read_to_string(&path).unwrap();
+        let backup = std::fs::read_to_string(rotated_path(&path)).unwrap();
+        assert!(current.len() <= 64);
+        assert!(current.ends_with("line 09 ..................\n"));
+        assert!(backup.starts_with("line 0"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,18 +1,23 @@
 //! # AiPinyin — AI 驱动的轻量级本地拼音输入法
 //!
 //! 架构：WH_KEYBOARD_LL 全局键盘钩子 + 多策略光标定位
+//!
+//! 这里只是 Windows 前端；拼音 / 字典 / AI / 用户词典等与平台无关的部分在库 (lib.rs) 里。
 
-mod guardian;
-pub mod ai_engine;
-pub mod ai_server;
-pub mod config;
+pub mod autostart;
+pub mod cand_ui;
+pub mod clipboard;
+pub mod gdi_ui;
 pub mod key_event;
-pub mod pinyin;
 pub mod plugin_system;
-pub mod user_dict;
 pub mod settings;
 pub mod webview_ui;
 
+use aipinyin::{
+    ai_engine, ai_server, autocaps, bench, cand_cache, config, crash, engine, focus_stash, guardian,
+    i18n, import, lifetime, logger, pinyin, plugin_host, punct, stream, symbols, user_dict,
+};
+
 
 use anyhow::Result;
 use windows::core::*;
@@ -20,7 +25,9 @@ use windows::Win32::Foundation::*;
 use windows::Win32::Graphics::Gdi::*;
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows::Win32::UI::WindowsAndMessaging::*;
+use crate::cand_ui::CandidateUi;
 use crate::key_event::{InputState, CommitAction, handle_key_down};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicPtr, AtomicU8, Ordering};
 
 /// 自定义消息: 钩子先拦截按键，然后通过此消息异步处理
 const WM_IME_KEYDOWN: u32 = WM_APP + 1;
@@ -38,11 +45,23 @@ pub const CLSID_AIPINYIN: GUID = GUID::from_u128(0xe0e55f04_f427_45f7_86a1_ac150
 
 struct ImeState {
     input: InputState,
-    cand_win: Option<webview_ui::WebViewUI>,
-    plugins: plugin_system::PluginSystem,
-    ai: ai_engine::AIPredictor,
-    history: ai_engine::HistoryBuffer,
-    cfg: config::Config,
+    cand_win: Box<dyn CandidateUi>,
+    /// UI 事件循环的句柄（重装钩子、插件唤醒），与候选窗后端无关
+    ui_loop: webview_ui::WebViewUI,
+    /// JS 插件；QuickJS 运行时不是线程安全的，按键线程与主线程（pump）都经 [`plugins`] 这把锁进入
+    plugins: std::sync::Mutex<plugin_system::PluginSystem>,
+    /// 与本地 HTTP 服务共用的推理实例；按键路径只 try_lock，拿不到就只出字典候选
+    ai: ai_engine::SharedPredictor,
+    /// 模型是否可用（启动时确定，之后不变，免得为此加锁）
+    ai_available: bool,
+    /// AI 优先 / 字典优先（engine.mode，热键可临时切换）
+    ai_first: bool,
+    /// `/v1/status` 读取的运行数据快照（由 `sync_stats` 更新）
+    stats: std::sync::Arc<ai_server::RuntimeStats>,
+    /// 上屏历史（与本地 HTTP 服务、插件 `host.recentContext` 共享）
+    history: std::sync::Arc<std::sync::Mutex<ai_engine::HistoryBuffer>>,
+    /// 当前生效配置（config.toml 修改后由热重载线程整体替换）
+    cfg: config::SharedConfig,
     user_dict: user_dict::UserDict,
     /// 本地 AI 服务实际监听端口（0 = 服务未启动）
     ai_port: u16,
@@ -51,60 +70,141 @@ struct ImeState {
     current_candidates: Vec<String>,
     all_candidates: Vec<String>,
     page_offset: usize,
+    /// 当前页中高亮的候选（Tab 移动，空格上屏它）
+    selected: usize,
+    /// 每页候选数（ui.page_size，已截断到 3–9）
+    page_size: usize,
     chinese_mode: bool,
     shift_down: bool,
     shift_modified: bool,
+    /// 双击 Shift 锁定英文: 锁定期间忽略单击 Shift 切换
+    shift_locked: bool,
+    /// 上一次单独 Shift 抬起的 (时间戳 KBDLLHOOKSTRUCT.time, 键码)，用于识别同一侧的双击
+    last_shift_tap: Option<(u32, u32)>,
+    /// 临时英文键 (keys.english_hold) 按住中: 字母直接放行，未上屏拼音保留
+    english_hold: bool,
+    /// 本次按住临时英文键期间打过字；没打字的单独点按在抬起时补发给应用
+    english_hold_used: bool,
+    /// 切换 AI / 字典优先的热键（keys.toggle_engine），加载与热重载时解析一次
+    toggle_hotkey: Option<config::Hotkey>,
+    /// 热键的主键按下中: 抬起时一并吃掉，自动重复不再次切换
+    toggle_key_down: bool,
+    /// Caps Lock 作为中英切换键（keys.capslock）被按住中，忽略自动重复
+    caps_down: bool,
     ai_generation: u64,
+    /// 前台全屏而暂停拦截中
+    fullscreen_paused: bool,
+    /// 焦点在密码框（焦点变化时更新，engine.disable_in_password）
+    password_focus: bool,
     last_commit: Option<(String, String)>,
     backspace_count: usize,
+    /// 本次输入各拼音前缀的候选（退格时直接重绘；上屏 / 学习等改变排序后清空）
+    cand_cache: cand_cache::CandidateCache,
+    /// 成对标点开合状态（前台窗口切换时重置）
+    punct: punct::PunctState,
+    /// 英文直通的句首大写状态（前台窗口切换、切换中英文时重置）
+    autocaps: autocaps::AutoCaps,
+    /// 上次按键时的前台窗口 (HWND.0)，用于检测窗口切换
+    last_foreground: isize,
+    /// 焦点被短暂抢走时暂存的拼音，回到原窗口时恢复（engine.focus_restore_ms）
+    focus_stash: focus_stash::FocusStash,
+    /// 前台进程名缓存 (HWND.0, exe 文件名)，窗口不变时不重复查询
+    foreground_app: Option<(isize, String)>,
+    /// 外部接口给出的整句候选 (拼音, 整句)，拼音与当前输入一致时置顶
+    sentence: Option<(String, String)>,
+    /// 上屏后显示中的联想候选（engine.associate），数字键选择、Esc 关闭
+    association: Vec<String>,
 }
 
 static mut GLOBAL_STATE: *mut ImeState = std::ptr::null_mut();
 
+/// GLOBAL_STATE 的拆除同步: 后台线程经 [`with_state`] 访问，退出时先 `end` 再释放
+static STATE_LIFETIME: lifetime::Lifetime = lifetime::Lifetime::new();
+
+/// 后台线程访问全局状态（访问期间不会被释放），已退出返回 None
+///
+/// 钩子本身与 UI 事件循环在主线程上（与释放同一线程），直接用 GLOBAL_STATE 即可；
+/// 钩子派生出去的按键处理线程（`cb_*`）不在主线程上，必须经这里访问
+unsafe fn with_state<R>(f: impl FnOnce(&mut ImeState) -> R) -> Option<R> {
+    STATE_LIFETIME.enter(|| {
+        let ptr = GLOBAL_STATE;
+        if ptr.is_null() { None } else { Some(f(&mut *ptr)) }
+    }).flatten()
+}
+
+/// 当前安装的键盘钩子句柄 (HHOOK.0)
+static KEYBOARD_HOOK: AtomicPtr<std::ffi::c_void> = AtomicPtr::new(std::ptr::null_mut());
+/// 钩子心跳: 最近一次收到按键事件的时间戳 (KBDLLHOOKSTRUCT.time, 与 GetTickCount 同源)
+static HOOK_HEARTBEAT: AtomicI64 = AtomicI64::new(0);
+/// 左右 Ctrl 的物理按下状态（bit0 = 左, bit1 = 右），只计真实按键，不含注入的
+static PHYSICAL_CTRL: AtomicU8 = AtomicU8::new(0);
+/// 退出流程已执行（shutdown 可能被窗口销毁、Ctrl+C、main 返回多处触发）
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
 // ============================================================
 // 主入口
 // ============================================================
 
 fn main() -> Result<()> {
-    env_logger::Builder::from_env(
-        env_logger::Env::default().default_filter_or("warn") // 生产级：减少日志噪音
-    ).init();
+    // 最先装 panic hook: 解析配置时的 panic 也要进 crash.log
+    crash::install_panic_hook();
+    // 加载配置（日志级别/落盘也来自这里）
+    let cfg = config::Config::load();
+    logger::init(&cfg.log);
+    i18n::set_language(cfg.ui.language);
+
+    // 命令行子命令: 不安装键盘钩子, 执行完直接退出
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("--compile-dict") => return run_compile_dict(&args[1..], &cfg),
+        Some("--import-rime") => return run_import(&args[1..], import::import_rime),
+        Some("--import-sogou") => return run_import(&args[1..], import::import_sogou_txt),
+        Some("--bench") => return run_bench(&args[1..], &cfg),
+        _ => {}
+    }
 
     println!();
     println!("  ╔══════════════════════════════════════════╗");
     println!("  ║    AiPinyin 爱拼音 v{}          ║", env!("CARGO_PKG_VERSION"));
-    println!("  ║    AI驱动 · 向量引擎 · 本地推理          ║");
+    println!("  ║    {}", i18n::tr(i18n::Text::BannerTagline));
     println!("  ╚══════════════════════════════════════════╝");
     println!();
-    println!("  在任意窗口直接打拼音即可！");
-    println!("  A-Z: 输入 | 空格/数字: 上屏 | 退格: 删除 | ESC: 取消");
+    println!("  {}", i18n::tr(i18n::Text::BannerHint));
+    println!("  {}", i18n::tr(i18n::Text::BannerKeys));
     println!();
 
-    let _guardian = guardian::start_guardian(guardian::GuardianConfig::default());
+    let history_arc = std::sync::Arc::new(std::sync::Mutex::new(
+        ai_engine::HistoryBuffer::new(100)
+    ));
 
     // 加载 JS 插件（exe 旁的 plugins/ 目录）
-    let mut plugins = plugin_system::PluginSystem::new()?;
+    let mut plugin_sys = plugin_system::PluginSystem::new()?;
+    plugin_sys.set_history(std::sync::Arc::clone(&history_arc));
     let plugins_dir = std::env::current_exe()
         .ok()
         .and_then(|p| p.parent().map(|d| d.join("plugins")))
         .unwrap_or_else(|| std::path::PathBuf::from("plugins"));
-    plugins.load_dir(&plugins_dir);
+    plugin_sys.load_dir(&plugins_dir);
 
-    // 加载配置
-    let cfg = config::Config::load();
+    let _guardian = guardian::start_guardian(cfg.guardian.clone());
+
+    // 开机自启: 每次启动重写 Run 项，exe 移动过也能指向新位置
+    if cfg.engine.autostart {
+        if let Err(e) = autostart::set_enabled(true) {
+            eprintln!("[Autostart] ⚠ {}", e);
+        }
+    }
 
     // 初始化字典（基础 + 额外词库）
     pinyin::init_global_dict(&cfg.dict.extra);
 
-    // 初始化 AI 推理引擎（Arc<Mutex<>> 共享给本地 HTTP 服务线程）
-    let ai_arc = std::sync::Arc::new(std::sync::Mutex::new(ai_engine::AIPredictor::new()));
-    {
-        let mut pred = ai_arc.lock().unwrap();
-        pred.ai_first = cfg.engine.mode == config::EngineMode::Ai;
-    }
-    let history_arc = std::sync::Arc::new(std::sync::Mutex::new(
-        ai_engine::HistoryBuffer::new(100)
-    ));
+    // 初始化 AI 推理引擎（输入法与本地 HTTP 服务共用同一个实例，模型只加载一次）
+    let ai: ai_engine::SharedPredictor = std::sync::Arc::new(std::sync::Mutex::new(ai_engine::AIPredictor::new(&cfg.ai)));
+    let (ai_available, ai_unavailable_reason) = {
+        let mut pred = ai.lock().unwrap();
+        pred.set_segmentation(cfg.segmentation.clone());
+        (pred.is_available(), pred.unavailable_reason())
+    };
 
     // 确定 ui/ 目录（向 ai_server 提供静态文件服务）
     let ui_dir_dev = std::path::PathBuf::from("ui");
@@ -120,17 +220,24 @@ fn main() -> Result<()> {
 
     // 启动本地 AI HTTP 服务（也提供 UI 静态文件）
     let system_prompt = cfg.ai.system_prompt.clone();
+    let stats = std::sync::Arc::new(ai_server::RuntimeStats::default());
     let ai_port = ai_server::start(
-        std::sync::Arc::clone(&ai_arc),
+        std::sync::Arc::clone(&ai),
         std::sync::Arc::clone(&history_arc),
+        std::sync::Arc::clone(&stats),
         ui_dir,
         system_prompt,
     );
+    // 候选推送（外部浮层用，默认关闭）
+    if cfg.stream.enabled {
+        stream::start(&cfg.stream);
+    }
 
-    // main 线程保留一份 AI 实例，用于同步降级
-    let mut ai = ai_engine::AIPredictor::new();
-    ai.ai_first = cfg.engine.mode == config::EngineMode::Ai;
-    let history = ai_engine::HistoryBuffer::new(100);
+    if cfg.engine.mode == config::EngineMode::Ai && cfg.ui.ai_unavailable_notice {
+        if let Some(reason) = ai_unavailable_reason {
+            show_ai_unavailable_notice(reason);
+        }
+    }
 
     // 确定最终 AI endpoint
     let ai_endpoint = if !cfg.ai.endpoint.is_empty() {
@@ -142,50 +249,103 @@ fn main() -> Result<()> {
     };
 
     // Load webview ui instance（传入 ai_port 以便 UI 用 http:// 加载）
-    let (cand_win_ui, event_loop) = webview_ui::WebViewUI::new()?;
+    let (ui_loop, event_loop) = webview_ui::WebViewUI::new()?;
+    webview_ui::set_max_width(cfg.ui.max_width_px);
+    webview_ui::set_opacity(cfg.ui.opacity);
+    webview_ui::set_window_offsets(&cfg.ui);
+    plugin_sys.set_waker(ui_loop.plugin_waker());
+    let cand_win_ui: Box<dyn CandidateUi> = match cfg.ui.renderer {
+        config::Renderer::Webview => Box::new(ui_loop.clone()),
+        config::Renderer::Gdi => match gdi_ui::CandidateWindow::new(cfg.ui.font_size) {
+            Ok(w) => Box::new(w),
+            Err(e) => {
+                eprintln!("[UI] ⚠ GDI 候选窗创建失败，改用 WebView: {}", e);
+                Box::new(ui_loop.clone())
+            }
+        },
+    };
 
     let user_dict = user_dict::UserDict::load();
+    let mut input = InputState::new();
+    input.commit_key = cfg.keys.commit;
+    input.double_space = std::time::Duration::from_millis(cfg.keys.double_space_ms);
+    input.digit_select = cfg.keys.digit_select;
+    input.engine.set_fuzzy(cfg.fuzzy.clone());
+    input.engine.set_shuangpin(cfg.engine.shuangpin);
+    input.engine.set_max_candidates(cfg.engine.max_candidates);
+    input.engine.set_max_ambiguous_splits(cfg.engine.max_ambiguous_splits);
+    input.engine.set_erhua(cfg.engine.erhua);
+    input.engine.set_symbols(cfg.symbol_categories());
+    input.symbol_vk = cfg.engine.symbol_vk();
+    input.compose_timeout = std::time::Duration::from_secs(cfg.engine.compose_timeout_secs);
+    let focus_restore = std::time::Duration::from_millis(cfg.engine.focus_restore_ms);
+    let page_size = cfg.ui.effective_page_size();
+    let toggle_hotkey = cfg.keys.toggle_engine_hotkey();
+    let ai_first = ai_available && cfg.engine.mode == config::EngineMode::Ai;
+    let live_cfg = std::sync::Arc::new(std::sync::Mutex::new(cfg));
 
     let state = Box::new(ImeState {
-        input: InputState::new(),
-        cand_win: Some(cand_win_ui),
-        plugins,
+        input,
+        cand_win: cand_win_ui,
+        ui_loop,
+        plugins: std::sync::Mutex::new(plugin_sys),
         ai,
-        history,
-        cfg,
+        ai_available,
+        ai_first,
+        stats,
+        history: history_arc,
+        cfg: std::sync::Arc::clone(&live_cfg),
         user_dict,
         ai_port,
         ai_endpoint,
         current_candidates: Vec::new(),
         all_candidates: Vec::new(),
         page_offset: 0,
+        selected: 0,
+        page_size,
         chinese_mode: true,
         shift_down: false,
         shift_modified: false,
+        shift_locked: false,
+        last_shift_tap: None,
+        english_hold: false,
+        english_hold_used: false,
+        toggle_hotkey,
+        toggle_key_down: false,
+        caps_down: false,
         ai_generation: 0,
+        fullscreen_paused: false,
+        password_focus: false,
         last_commit: None,
         backspace_count: 0,
+        cand_cache: cand_cache::CandidateCache::new(),
+        punct: punct::PunctState::new(),
+        autocaps: autocaps::AutoCaps::new(),
+        last_foreground: 0,
+        focus_stash: focus_stash::FocusStash::new(focus_restore),
+        foreground_app: None,
+        sentence: None,
+        association: Vec::new(),
     });
 
 
     unsafe {
+        sync_stats(&state);
         GLOBAL_STATE = Box::into_raw(state);
 
         // 初始化 [JS] 按钮状态
         let s = &mut *GLOBAL_STATE;
-        if let Some(cw) = &s.cand_win {
-            cw.set_plugins_active(s.plugins.has_active());
-        }
+        s.cand_win.set_plugins_active(plugins(s).has_active());
+
+        // config.toml 热重载
+        config::watch(std::sync::Arc::clone(&live_cfg), apply_config_change);
 
-        let hinstance = GetModuleHandleW(None)?;
-        let hook = SetWindowsHookExW(
-            WH_KEYBOARD_LL,
-            Some(low_level_keyboard_hook),
-            hinstance,
-            0,
-        )?;
-        println!("  ✅ 全局钩子已安装，请切换到其他窗口打字...");
-        println!("  【Shift】切换中/英文模式");
+        install_keyboard_hook()?;
+        install_foreground_hook();
+        install_console_ctrl_handler();
+        start_hook_watchdog();
+        println!("  {}", i18n::tr(i18n::Text::HookInstalled));
+        println!("  {}", i18n::tr(i18n::Text::HookShiftHelp));
 
         // Webview 主循环
         std::thread::spawn(move || {
@@ -194,7 +354,9 @@ fn main() -> Result<()> {
         
         webview_ui::run_webview_loop(event_loop, ai_port)?;
 
-        let _ = UnhookWindowsHookEx(hook);
+        shutdown();
+        // 等后台线程结束对状态的访问；之后的访问都会看到已退出
+        STATE_LIFETIME.end();
         let _ = Box::from_raw(GLOBAL_STATE);
         GLOBAL_STATE = std::ptr::null_mut();
     }
@@ -202,84 +364,299 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// ============================================================
+// 退出
+// ============================================================
+
+/// 退出前的收尾: 卸载键盘钩子，把未写盘的用户词典保存下来。
+/// 可重复调用，只有第一次生效（ImeState 本身不在这里释放，其他线程可能仍持有引用）
+pub(crate) unsafe fn shutdown() {
+    if SHUT_DOWN.swap(true, Ordering::SeqCst) { return; }
+
+    let hook = KEYBOARD_HOOK.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    if !hook.is_null() {
+        let _ = UnhookWindowsHookEx(HHOOK(hook));
+    }
+    // 也会在控制台 Ctrl+C 的处理线程上调用
+    with_state(|state| state.user_dict.flush());
+    eprintln!("[IME] 已退出，用户词典已保存");
+}
+
+/// 控制台 Ctrl+C / 关闭控制台窗口 / 注销时走同一套收尾再退出
+unsafe fn install_console_ctrl_handler() {
+    use windows::Win32::System::Console::SetConsoleCtrlHandler;
+
+    unsafe extern "system" fn on_console_ctrl(_ctrl_type: u32) -> BOOL {
+        shutdown();
+        std::process::exit(0);
+    }
+
+    if let Err(e) = SetConsoleCtrlHandler(Some(on_console_ctrl), true) {
+        eprintln!("[IME] ⚠ Ctrl+C 处理安装失败: {}", e);
+    }
+}
+
+/// AI 模型未加载时的一次性提示（每次启动最多一次，后台线程弹出不阻塞启动）
+fn show_ai_unavailable_notice(reason: String) {
+    let dir = std::env::current_exe()
+        .ok().and_then(|p| p.parent().map(|d| d.display().to_string()))
+        .unwrap_or_else(|| ".".into());
+    let _ = std::thread::Builder::new()
+        .name("ai-notice".into())
+        .spawn(move || {
+            let msg = i18n::fill(i18n::tr(i18n::Text::AiMissingNotice), &[&reason, &dir]);
+            let msg_w: Vec<u16> = msg.encode_utf16().chain(std::iter::once(0)).collect();
+            let cap_w: Vec<u16> = "AiPinyin".encode_utf16().chain(std::iter::once(0)).collect();
+            let choice = unsafe {
+                MessageBoxW(HWND::default(), PCWSTR(msg_w.as_ptr()), PCWSTR(cap_w.as_ptr()),
+                    MB_YESNO | MB_ICONINFORMATION | MB_DEFBUTTON2)
+            };
+            if choice == IDYES {
+                match config::persist_value("ui", "ai_unavailable_notice", "false") {
+                    Ok(()) => eprintln!("[AI] 已关闭模型缺失提示 (ui.ai_unavailable_notice = false)"),
+                    Err(e) => eprintln!("[AI] ⚠ 写入 config.toml 失败: {}", e),
+                }
+            }
+        });
+}
+
+/// 配置热重载回调: 引擎模式、上屏键、候选窗透明度立即切换 (top_k / rerank / source / endpoint 等每次刷新候选时读取)，
+/// 其余无法热替换的项提示重启
+fn apply_config_change(old: &config::Config, new: &config::Config) {
+    // 在热重载线程上调用
+    unsafe {
+        with_state(|state| {
+            // 只在 engine.mode 真的改了时重置，保留 toggle_engine 热键的临时切换
+            if old.engine.mode != new.engine.mode {
+                state.ai_first = state.ai_available && new.engine.mode == config::EngineMode::Ai;
+            }
+            state.input.commit_key = new.keys.commit;
+            state.input.double_space = std::time::Duration::from_millis(new.keys.double_space_ms);
+            state.input.digit_select = new.keys.digit_select;
+            state.toggle_hotkey = new.keys.toggle_engine_hotkey();
+            state.input.engine.set_fuzzy(new.fuzzy.clone());
+            state.input.engine.set_shuangpin(new.engine.shuangpin);
+            state.input.engine.set_max_candidates(new.engine.max_candidates);
+            state.input.engine.set_max_ambiguous_splits(new.engine.max_ambiguous_splits);
+            state.input.engine.set_erhua(new.engine.erhua);
+            state.input.engine.set_symbols(new.symbol_categories());
+            state.input.symbol_vk = new.engine.symbol_vk();
+            state.input.compose_timeout = std::time::Duration::from_secs(new.engine.compose_timeout_secs);
+            state.focus_stash.set_timeout(std::time::Duration::from_millis(new.engine.focus_restore_ms));
+            // 每页条数变了才回到首页，其他修改（如拖动候选窗写回偏移）不打断正在翻的页
+            let page_size = new.ui.effective_page_size();
+            if page_size != state.page_size {
+                state.page_size = page_size;
+                state.page_offset = 0;
+                state.selected = 0;
+            }
+            state.cand_cache.clear();
+        });
+    }
+    i18n::set_language(new.ui.language);
+    webview_ui::set_max_width(new.ui.max_width_px);
+    webview_ui::set_opacity(new.ui.opacity);
+    webview_ui::set_window_offsets(&new.ui);
+    if old.engine.autostart != new.engine.autostart {
+        if let Err(e) = autostart::set_enabled(new.engine.autostart) {
+            eprintln!("[Autostart] ⚠ {}", e);
+        }
+    }
+    for item in old.restart_required(new) {
+        eprintln!("[Config] ℹ {} 的修改需重启后生效", item);
+    }
+}
+
+// ============================================================
+// 命令行子命令
+// ============================================================
+
+/// `aipinyin.exe --compile-dict [in.txt] [out.bin]`
+///
+/// 离线把 dict.txt 编译为 dict.bin，便于分发预构建的二进制词典。
+/// 缺省路径为 exe 同目录的 dict.txt / dict.bin，额外词库取自 config.toml。
+fn run_compile_dict(args: &[String], cfg: &config::Config) -> Result<()> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+        .unwrap_or_default();
+    let input = args.first().map(std::path::PathBuf::from)
+        .unwrap_or_else(|| exe_dir.join("dict.txt"));
+    let output = args.get(1).map(std::path::PathBuf::from)
+        .unwrap_or_else(|| exe_dir.join("dict.bin"));
+
+    println!("  编译词典 {:?} → {:?}", input, output);
+    let count = pinyin::compile_dict(&input, &output, &cfg.dict.extra)?;
+    println!("  ✅ 完成, {} 条", count);
+    Ok(())
+}
+
+/// `aipinyin.exe --bench [轮数]`
+///
+/// 加载正式的词典与模型，用固定语料测候选生成耗时（见 `bench.rs`），不装钩子、不开窗口。
+fn run_bench(args: &[String], cfg: &config::Config) -> Result<()> {
+    let rounds = match args.first() {
+        Some(n) => n.parse::<usize>().ok().filter(|&n| n > 0)
+            .ok_or_else(|| anyhow::anyhow!("用法: aipinyin.exe --bench [轮数]"))?,
+        None => 10,
+    };
+    pinyin::init_global_dict(&cfg.dict.extra);
+    let mut engine = pinyin::PinyinEngine::new();
+    engine.set_fuzzy(cfg.fuzzy.clone());
+    engine.set_max_candidates(cfg.engine.max_candidates);
+    engine.set_max_ambiguous_splits(cfg.engine.max_ambiguous_splits);
+    engine.set_erhua(cfg.engine.erhua);
+
+    let mut pred = ai_engine::AIPredictor::new(&cfg.ai);
+    pred.set_segmentation(cfg.segmentation.clone());
+    let ai = if pred.is_available() {
+        Some(&mut pred)
+    } else {
+        println!("  AI 模型未加载（{}），只测字典", pred.unavailable_reason().unwrap_or_default());
+        None
+    };
+
+    println!("  候选生成基准: {} 条输入 × {} 轮", bench::CORPUS.len(), rounds);
+    let samples = bench::run(&mut engine, ai, bench::CORPUS, rounds, cfg.ai.top_k.min(9));
+    print!("{}", bench::report(&samples));
+    Ok(())
+}
+
+/// `aipinyin.exe --import-rime <file>` / `--import-sogou <file>`
+///
+/// 把外部词库转换为 `拼音,汉字,权重` 追加到 exe 同目录的 dict.txt，
+/// 并删除过期的 dict.bin，下次启动自动重建。
+fn run_import(args: &[String], convert: fn(&str) -> String) -> Result<()> {
+    let src = args.first()
+        .ok_or_else(|| anyhow::anyhow!("用法: aipinyin.exe --import-rime|--import-sogou <文件>"))?;
+    let text = std::fs::read_to_string(src)?;
+    let lines = convert(&text);
+
+    // 先用 merge_text 校验并统计新增词条
+    let mut probe = pinyin::Dictionary::from_text("");
+    let count = probe.merge_text(&lines);
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+        .unwrap_or_default();
+    let dict_path = exe_dir.join("dict.txt");
+    import::append_to_dict(&dict_path, src, &lines)?;
+    println!("  ✅ 已导入 {} 条 → {:?}", count, dict_path);
+
+    let bin_path = exe_dir.join("dict.bin");
+    if bin_path.exists() {
+        std::fs::remove_file(&bin_path)?;
+        println!("  已删除过期的 {:?}，下次启动自动重建", bin_path);
+    }
+    Ok(())
+}
+
 // ============================================================
 // 插件 UI 回调（由 ui::show_plugin_menu 调用）
 // ============================================================
 
+/// 插件系统的唯一入口: 按键线程的钩子调用与主线程的 `pump` 在这里排队，不会同时进入 JS 运行时
+fn plugins(state: &ImeState) -> std::sync::MutexGuard<'_, plugin_system::PluginSystem> {
+    state.plugins.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 unsafe fn cb_plugin_list() -> Vec<plugin_system::PluginInfo> {
     if GLOBAL_STATE.is_null() { return vec![]; }
-    (*GLOBAL_STATE).plugins.plugin_list()
+    plugins(&*GLOBAL_STATE).plugin_list()
+}
+
+/// 插件后台任务有结果（由 UI 事件循环在主线程调用）
+pub(crate) unsafe fn pump_plugins() {
+    if GLOBAL_STATE.is_null() { return; }
+    plugins(&*GLOBAL_STATE).pump();
+}
+
+/// 把用户词典与插件的计数写入 `/v1/status` 快照
+fn sync_stats(state: &ImeState) {
+    use std::sync::atomic::Ordering::Relaxed;
+    state.stats.user_dict_entries.store(state.user_dict.entry_count(), Relaxed);
+    let p = plugins(state);
+    state.stats.plugins_loaded.store(p.loaded_count(), Relaxed);
+    state.stats.plugins_active.store(p.active_count(), Relaxed);
 }
 
 unsafe fn cb_plugin_toggle(name: &str, hwnd: HWND) -> plugin_system::ToggleResult {
     if GLOBAL_STATE.is_null() { return plugin_system::ToggleResult::Denied; }
     let state = &mut *GLOBAL_STATE;
-    let result = state.plugins.toggle(name, hwnd);
-    if let Some(cw) = &state.cand_win {
-        cw.set_plugins_active(state.plugins.has_active());
-    }
+    let result = plugins(state).toggle(name, hwnd);
+    state.cand_cache.clear();
+    sync_stats(state);
+    state.cand_win.set_plugins_active(plugins(state).has_active());
     result
 }
 
 // ============================================================
-// 异步按键处理回调（由 wnd_proc 收到 WM_IME_KEYDOWN 后调用）
+// 异步按键处理回调（钩子派生的线程上经 with_state 调用）
 // ============================================================
 
-unsafe fn cb_process_key(vkey: u32) {
-    if GLOBAL_STATE.is_null() { return; }
-    let state = &mut *GLOBAL_STATE;
+unsafe fn cb_process_key(state: &mut ImeState, vkey: u32) {
+
+    // 搁置太久的输入（engine.compose_timeout_secs）: 先丢弃；字母键接着开始新输入，其他键只用于取消
+    if state.input.expire_stale() {
+        state.all_candidates.clear();
+        state.current_candidates.clear();
+        state.sentence = None;
+        state.cand_cache.clear();
+        state.cand_win.hide();
+        if !(0x41..=0x5A).contains(&vkey) { return; }
+    }
+
+    // 联想候选显示中: 数字键选择、Esc 关闭，其他键先关掉联想再照常处理
+    if !state.association.is_empty() && state.input.engine.is_empty() {
+        match vkey {
+            0x31..=0x39 => {
+                if let Some(text) = state.association.get((vkey - 0x31) as usize).cloned() {
+                    commit_association(state, &text);
+                }
+                return;
+            }
+            0x1B => { close_association(state); return; }
+            _ => close_association(state),
+        }
+    }
 
-    // 翻页键直接处理
+    // 翻页键直接处理（输入为空时钩子不拦截翻页键，按键照常传给应用）
+    let (page_prev, page_next) = page_keys(state);
+    if vkey == page_next || vkey == 0x22 { page_down(state); return; }
+    if vkey == page_prev || vkey == 0x21 { page_up(state); return; }
+    // 方向键: 左右移动高亮候选，上下翻页（输入为空时钩子不拦截，照常移动光标）
     match vkey {
-        0xBB | 0x22 => { page_down(state); return; }
-        0xBD | 0x21 => { page_up(state); return; }
+        0x25 => { move_selection(state, -1); return; }
+        0x27 => { move_selection(state, 1); return; }
+        0x26 => { page_up(state); return; }
+        0x28 => { page_down(state); return; }
         _ => {}
     }
 
     let raw_before = state.input.engine.raw_input().to_string();
     let result = handle_key_down(&mut state.input, vkey);
+    // 双击空格: 上屏高亮候选后丢掉剩余拼音
+    let whole = matches!(result.commit, Some(CommitAction::SelectedWhole));
+    let commit = match result.commit {
+        Some(CommitAction::Selected | CommitAction::SelectedWhole) => Some(CommitAction::Index(state.selected)),
+        c => c,
+    };
 
-    match result.commit {
+    match commit {
         Some(CommitAction::Index(idx)) => {
-            let text = state.current_candidates.get(idx).cloned().unwrap_or_default();
-            if !text.is_empty() {
-                state.history.push(&text);
-                if !raw_before.is_empty() {
-                    state.user_dict.learn(&raw_before, &text);
-                    if text.chars().count() >= 3 {
-                        crate::pinyin::cache_ai_word(&raw_before, &text);
-                    }
-                }
-                state.last_commit = Some((raw_before.clone(), text.clone()));
-                state.backspace_count = 0;
-                eprintln!("[IME] ↑ {:?}", text);
-                send_unicode_text(&text);
-
-                let char_count = text.chars().count();
-                state.input.engine.consume_syllables(char_count);
-                state.current_candidates.clear();
-
-                if state.input.engine.is_empty() {
-                    state.all_candidates.clear();
-                    state.current_candidates.clear();
-                    if let Some(cw) = &state.cand_win {
-                        cw.hide();
-                    }
-                } else {
-                    refresh_candidates(state);
-                }
-                return;
-            }
+            if commit_candidate(state, &raw_before, idx, whole) { return; }
         }
         Some(CommitAction::Text(text)) => {
-            if let Some(cw) = &state.cand_win {
-                cw.hide();
-            }
+            state.cand_win.hide();
             state.input.engine.clear();
             state.current_candidates.clear();
-            state.history.push(&text);
-            eprintln!("[IME] ↑ {:?}", text);
-            send_unicode_text(&text);
+            state.cand_cache.clear();
+            if let Some(text) = plugin_commit_text(state, &raw_before, &text) {
+                if let Ok(mut h) = state.history.lock() { h.push(&text); }
+                eprintln!("[IME] ↑ {:?}", text);
+                send_unicode_text(&text);
+            }
         }
         None => {}
     }
@@ -289,6 +666,172 @@ unsafe fn cb_process_key(vkey: u32) {
     }
 }
 
+/// 上屏当前页第 `idx` 个候选（`whole` = 连同剩余拼音一起清掉）；候选不存在时返回 false
+unsafe fn commit_candidate(state: &mut ImeState, raw_before: &str, idx: usize, whole: bool) -> bool {
+    let text = state.current_candidates.get(idx).cloned().unwrap_or_default();
+    if text.is_empty() { return false; }
+    // 插件取消上屏: 输入与候选保持不变
+    let Some(sent) = plugin_commit_text(state, raw_before, &text) else { return true };
+    if let Ok(mut h) = state.history.lock() { h.push(&sent); }
+    if !raw_before.is_empty() && !pinyin::is_unicode_input(raw_before)
+        && !symbols::is_symbol_input(raw_before) {
+        state.user_dict.learn(raw_before, &text);
+        if text.chars().count() >= 3 {
+            crate::pinyin::cache_ai_word(raw_before, &text);
+        }
+        sync_stats(state);
+    }
+    // 插件改写过的文字长度不同，不提供撤销
+    state.last_commit = (sent == text).then(|| (raw_before.to_string(), text.clone()));
+    state.backspace_count = 0;
+    state.cand_cache.clear();
+    eprintln!("[IME] ↑ {:?}", sent);
+    send_unicode_text(&sent);
+
+    if whole {
+        state.input.engine.clear();
+    } else {
+        state.input.engine.consume_word(&text);
+    }
+    state.current_candidates.clear();
+
+    if state.input.engine.is_empty() {
+        state.all_candidates.clear();
+        state.current_candidates.clear();
+        state.cand_win.hide();
+        show_association(state);
+    } else {
+        refresh_candidates(state);
+    }
+    true
+}
+
+/// 上屏后按上下文显示联想候选（engine.associate）；推理锁被占用时退回学习词
+unsafe fn show_association(state: &mut ImeState) {
+    if !state.cfg.lock().is_ok_and(|c| c.engine.associate) { return; }
+    let ctx = state.history.lock().map(|h| h.current_sentence()).unwrap_or_default();
+    let mut pred = if state.ai_available { state.ai.try_lock().ok() } else { None };
+    state.association = engine::association(pred.as_deref_mut(), &state.user_dict, &ctx, state.page_size);
+    drop(pred);
+    if state.association.is_empty() { return; }
+    let refs: Vec<&str> = state.association.iter().map(|s| s.as_str()).collect();
+    state.cand_win.update_candidates_with_page("联想", &refs, None, false, &[], 0);
+    show_at_caret(state.cand_win.as_ref());
+}
+
+/// 上屏一个联想候选（只记入上下文，不学习），接着联想下一个
+unsafe fn commit_association(state: &mut ImeState, text: &str) {
+    state.association.clear();
+    if let Some(sent) = plugin_commit_text(state, "", text) {
+        if let Ok(mut h) = state.history.lock() { h.push(&sent); }
+        eprintln!("[IME] ↑ {:?} (联想)", sent);
+        send_unicode_text(&sent);
+    }
+    state.cand_win.hide();
+    show_association(state);
+}
+
+unsafe fn close_association(state: &mut ImeState) {
+    state.association.clear();
+    state.cand_win.hide();
+}
+
+/// 上屏前交给插件的 `on_commit` 改写；None = 插件取消了这次上屏
+unsafe fn plugin_commit_text(state: &mut ImeState, raw: &str, text: &str) -> Option<String> {
+    if !plugins(state).has_active() { return Some(text.to_string()); }
+    let app = foreground_app(state);
+    let mut p = plugins(state);
+    let active = p.active_count();
+    let out = p.transform_commit(raw, text, &app);
+    let auto_disabled = p.active_count() != active;
+    drop(p);
+    if auto_disabled { on_plugins_auto_disabled(state); }
+    out
+}
+
+/// 钩子连续出错自动禁用了插件: 同步状态统计与候选窗的插件标记
+unsafe fn on_plugins_auto_disabled(state: &ImeState) {
+    sync_stats(state);
+    state.cand_win.set_plugins_active(plugins(state).has_active());
+}
+
+/// 移动高亮候选（循环，越过页尾 / 页首时翻页）
+unsafe fn cb_move_selection(state: &mut ImeState, step: isize) {
+    move_selection(state, step);
+}
+
+/// 热键切换 AI 优先 / 字典优先（仅本次运行，配置热重载时恢复 engine.mode）
+unsafe fn cb_toggle_engine(state: &mut ImeState) {
+
+    if !state.ai_available {
+        let reason = state.ai.lock().ok().and_then(|p| p.unavailable_reason());
+        eprintln!("[IME] ⚠ AI 模型未加载，无法切换到 AI 模式（{}）", reason.unwrap_or_default());
+        state.cand_win.set_engine_mode(false, false);
+        return;
+    }
+
+    state.ai_first = !state.ai_first;
+    state.cand_cache.clear();
+    eprintln!("[IME] 🔁 引擎模式 → {}", if state.ai_first { "AI 优先" } else { "字典优先" });
+    state.cand_win.set_engine_mode(state.ai_first, true);
+    if !state.input.engine.is_empty() {
+        refresh_candidates(state);
+    }
+}
+
+/// 撤销上一次上屏: 退格删掉上屏文字，撤销学习，把原拼音放回输入缓冲
+unsafe fn cb_undo_commit(state: &mut ImeState) {
+    let Some((raw, word)) = state.last_commit.take() else { return };
+    state.backspace_count = 0;
+
+    send_backspaces(word.chars().count());
+    state.user_dict.unlearn(&raw, &word);
+    state.cand_cache.clear();
+    sync_stats(state);
+    if let Ok(mut h) = state.history.lock() { h.pop(); }
+    eprintln!("[IME] ↶ 撤销上屏: {} → {}", word, raw);
+
+    // 上屏时可能只消耗了部分音节，剩余拼音是 raw 的后缀，直接整体替换
+    state.input.engine.restore(&raw);
+    refresh_candidates(state);
+}
+
+/// 设置页导出用户词典（由 UI 事件循环在主线程调用）
+pub(crate) unsafe fn export_user_dict() -> Option<String> {
+    if GLOBAL_STATE.is_null() { return None; }
+    Some((*GLOBAL_STATE).user_dict.to_json())
+}
+
+/// 设置页导入用户词典，合并进正在使用的词典（由 UI 事件循环在主线程调用）
+pub(crate) unsafe fn import_user_dict(json: &str) -> Result<usize, String> {
+    if GLOBAL_STATE.is_null() { return Err("输入法未初始化".to_string()); }
+    let state = &mut *GLOBAL_STATE;
+    let result = state.user_dict.merge_json(json);
+    state.cand_cache.clear();
+    sync_stats(state);
+    result
+}
+
+/// 设置页清空学习记录: 内存与 user_dict.txt 一起清空（由 UI 事件循环在主线程调用）
+pub(crate) unsafe fn clear_user_dict() -> Result<usize, String> {
+    if GLOBAL_STATE.is_null() { return Err("输入法未初始化".to_string()); }
+    let state = &mut *GLOBAL_STATE;
+    let n = state.user_dict.clear();
+    // 撤销上屏会去 unlearn，已清空就不必了
+    state.last_commit = None;
+    state.cand_cache.clear();
+    sync_stats(state);
+    Ok(n)
+}
+
+/// 设置页清空运行时 AI 词缓存
+pub(crate) unsafe fn clear_ai_cache() -> Result<usize, String> {
+    if GLOBAL_STATE.is_null() { return Err("输入法未初始化".to_string()); }
+    let n = pinyin::clear_ai_cache();
+    (*GLOBAL_STATE).cand_cache.clear();
+    Ok(n)
+}
+
 // ============================================================
 // 全局低阶键盘钩子
 // ============================================================
@@ -301,14 +844,58 @@ unsafe extern "system" fn low_level_keyboard_hook(
     }
 
     let info = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+    HOOK_HEARTBEAT.store(info.time as i64, Ordering::Relaxed);
+    // 记录 Ctrl 的物理状态（send_backspaces 放开 Ctrl 后据此恢复）
+    if (info.vkCode == 0xA2 || info.vkCode == 0xA3) && info.flags.0 & LLKHF_INJECTED.0 == 0 {
+        let bit = if info.vkCode == 0xA2 { 1 } else { 2 };
+        if info.flags.0 & LLKHF_UP.0 != 0 {
+            PHYSICAL_CTRL.fetch_and(!bit, Ordering::Relaxed);
+        } else {
+            PHYSICAL_CTRL.fetch_or(bit, Ordering::Relaxed);
+        }
+    }
+    // 看门狗探测键: 只用于确认钩子存活，不传给应用
+    if info.dwExtraInfo == HOOK_PROBE_MAGIC {
+        return LRESULT(1);
+    }
+    // 自己注入的编辑键（撤销上屏的退格）直接放行
+    if info.dwExtraInfo == SELF_INJECT_MAGIC {
+        return CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam);
+    }
     let vkey = info.vkCode;
     let state = &mut *GLOBAL_STATE;
 
     // Shift 键（左/右/通用）
     let is_shift = vkey == 0x10 || vkey == 0xA0 || vkey == 0xA1;
+    // 临时英文键（keys.english_hold）: 按住期间吃掉它本身，应用看不到修饰键，字母按原样输入
+    let is_hold_key = state.cfg.lock().ok()
+        .and_then(|c| c.keys.english_hold_vk())
+        .is_some_and(|vk| vk == vkey);
 
     match wparam.0 as u32 {
         WM_KEYDOWN | WM_SYSKEYDOWN => {
+            track_foreground(state);
+            // 全屏游戏中：所有键直接放行（含 Shift 切换）
+            if fullscreen_paused(state) {
+                return CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam);
+            }
+            // 密码框：不拦截、不缓存任何按键
+            if state.password_focus {
+                return CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam);
+            }
+
+            if is_hold_key {
+                // 自动重复的按下不算新的一次
+                if !state.english_hold { state.english_hold_used = false; }
+                state.english_hold = true;
+                return LRESULT(1);
+            }
+            // 按住临时英文键：视同英文模式，拼音缓冲保持不动
+            if state.english_hold {
+                if !is_modifier_key(vkey) { state.english_hold_used = true; }
+                return CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam);
+            }
+
             if is_shift {
                 // 记录 Shift 按下，等待判断是否单独抬起
                 state.shift_down = true;
@@ -322,24 +909,79 @@ unsafe extern "system" fn low_level_keyboard_hook(
                 state.shift_modified = true;
             }
 
-            // 英文直通模式：所有键直接放行
+            // Caps Lock 切换中英文（keys.capslock）: 吃掉按键，系统的大写状态不变；
+            // 带修饰键（Shift+Caps Lock 等）照常放行，用来切换真正的大写锁定
+            if vkey == 0x14 && !is_shift_down() && !is_ctrl_down() && !is_alt_down()
+                && state.cfg.lock().is_ok_and(|c| c.keys.capslock == config::CapsLockAction::ToggleMode)
+            {
+                if !state.caps_down {
+                    state.caps_down = true;
+                    // 英文锁定被 Caps Lock 解除: 收起常驻的锁定提示
+                    if std::mem::take(&mut state.shift_locked) {
+                        state.cand_win.hide();
+                    }
+                    toggle_mode(state);
+                }
+                return LRESULT(1);
+            }
+
+            // 切换 AI / 字典优先的热键（中英文模式下都有效）
+            if state.toggle_hotkey.is_some_and(|k| k.matches(vkey, is_ctrl_down(), is_shift_down(), is_alt_down())) {
+                if !state.toggle_key_down {
+                    state.toggle_key_down = true;
+                    let _ = std::thread::Builder::new()
+                        .stack_size(8 * 1024 * 1024) // 8 MB
+                        .spawn(|| with_state(|state| cb_toggle_engine(state)));
+                }
+                return LRESULT(1);
+            }
+
+            // 英文直通模式：所有键直接放行（开启 english_autocaps 时可能改写句首字母 / 双空格）
             if !state.chinese_mode {
+                if english_autocaps(state, vkey) {
+                    return LRESULT(1);
+                }
                 return CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam);
             }
 
             // 中文模式：先判断是否要拦截，立即返回，再异步处理
             let has_input = !state.input.engine.is_empty();
+            let associating = !has_input && !state.association.is_empty();
+            let (page_prev, page_next) = page_keys(state);
             let should_eat = match vkey {
                 0x41..=0x5A => true,
                 0x08 => has_input,
                 0x20 => has_input,
-                0x31..=0x39 => has_input,
-                0x1B => has_input,
+                0x31..=0x39 => has_input || (associating && !is_shift_down()),
+                0x30 => state.input.engine.is_unicode_mode() || state.input.literal_digits(),
+                // 符号面板键: 只在输入为空、没有按修饰键时打开（Shift+/ 仍是问号）
+                vk if state.input.symbol_vk == Some(vk) =>
+                    !has_input && !is_shift_down() && !is_ctrl_down() && !is_alt_down(),
+                0x1B => has_input || associating,
                 0x0D => has_input,
-                0xBB | 0xBD | 0x21 | 0x22 => has_input,
+                0x21 | 0x22 => has_input,
+                0x09 => has_input,
+                0x25..=0x28 => has_input,
+                vk if vk == page_prev || vk == page_next => has_input,
                 _ => false,
             };
 
+            // 联想候选只响应数字键和 Esc，其他放行的键（标点、回车、光标移动……）关掉联想
+            if associating && !should_eat && !is_modifier_key(vkey) {
+                state.association.clear();
+                state.cand_win.hide();
+            }
+
+            // Ctrl+Z 紧跟上屏（中间没有打字/退格）→ 删掉上屏文字并恢复拼音
+            if vkey == 0x5A && is_ctrl_down()
+                && state.last_commit.is_some() && state.backspace_count == 0
+            {
+                let _ = std::thread::Builder::new()
+                    .stack_size(8 * 1024 * 1024) // 8 MB
+                    .spawn(|| with_state(|state| cb_undo_commit(state)));
+                return LRESULT(1);
+            }
+
             // 退格撤销: 中文模式、引擎为空、按退格 → 可能在删刚才选错的词
             if vkey == 0x08 && !should_eat && state.chinese_mode {
                 if let Some((ref py, ref word)) = state.last_commit.clone() {
@@ -348,37 +990,95 @@ unsafe extern "system" fn low_level_keyboard_hook(
                     if state.backspace_count >= word_len {
                         // 用户删完了刚才上屏的整个词 → 撤销学习
                         state.user_dict.unlearn(py, word);
+                        sync_stats(state);
                         eprintln!("[IME] ⏪ 撤销学习: {} → {} (退格{}次)",
                             py, word, state.backspace_count);
                         state.last_commit = None;
                         state.backspace_count = 0;
                     }
                 }
-            } else if vkey != 0x08 {
-                // 按了非退格键 → 清除退格追踪
+            } else if vkey != 0x08 && !is_modifier_key(vkey) {
+                // 按了非退格键（修饰键除外，留给 Ctrl+Z）→ 清除退格追踪
                 if state.last_commit.is_some() {
                     state.last_commit = None;
                     state.backspace_count = 0;
                 }
             }
 
+            // 直接放行的句末标点 / 回车记入上下文，下一句的 AI 上下文从这里重新开始
+            if !should_eat {
+                if let Some(end) = sentence_end_key(vkey) {
+                    if let Ok(mut h) = state.history.try_lock() { h.push(end); }
+                }
+            }
+
+            // Tab / Shift+Tab: 高亮下一个 / 上一个候选（Shift 状态要在钩子里读）
+            if should_eat && vkey == 0x09 {
+                let step = if is_shift_down() { -1 } else { 1 };
+                let _ = std::thread::Builder::new()
+                    .stack_size(8 * 1024 * 1024) // 8 MB
+                    .spawn(move || with_state(|state| cb_move_selection(state, step)));
+                return LRESULT(1);
+            }
+
             if should_eat {
                 // 给 cb_process_key 线程设置足够大的栈空间，避免 ONNX 推理时栈溢出 (STATUS_STACK_BUFFER_OVERRUN)
                 let _ = std::thread::Builder::new()
                     .stack_size(8 * 1024 * 1024) // 8 MB
                     .spawn(move || {
-                        cb_process_key(vkey as u32);
+                        with_state(|state| cb_process_key(state, vkey as u32));
                     });
                 return LRESULT(1);
             }
         }
 
         WM_KEYUP | WM_SYSKEYUP => {
+            if is_hold_key && state.english_hold {
+                state.english_hold = false;
+                // 单独点按（期间没打字）: 按下时被吃掉了，补发一次给应用
+                if !state.english_hold_used {
+                    send_key_tap(vkey);
+                }
+                return LRESULT(1);
+            }
+            if vkey == 0x14 && state.caps_down {
+                state.caps_down = false;
+                return LRESULT(1);
+            }
+            // 热键按下时被吃掉了，抬起也不让应用看到
+            if state.toggle_key_down && state.toggle_hotkey.is_some_and(|k| k.vk == vkey) {
+                state.toggle_key_down = false;
+                return LRESULT(1);
+            }
             if is_shift && state.shift_down {
                 state.shift_down = false;
                 if !state.shift_modified {
-                    // 单独 Shift → 切换中英文模式
-                    toggle_mode(state);
+                    // 左右 Shift 各自的动作（keys.left_shift / keys.right_shift）
+                    let action = state.cfg.lock().map(|c| c.keys.shift_action(vkey)).unwrap_or_default();
+                    match action {
+                        config::ShiftAction::Mode => {
+                            let double_tap = state.last_shift_tap
+                                .is_some_and(|(t, vk)| vk == vkey && info.time.wrapping_sub(t) <= SHIFT_DOUBLE_TAP_MS);
+                            if double_tap {
+                                // 双击 Shift → 锁定/解锁英文
+                                state.last_shift_tap = None;
+                                toggle_english_lock(state);
+                            } else {
+                                state.last_shift_tap = Some((info.time, vkey));
+                                // 单独 Shift → 切换中英文模式（英文锁定时忽略）
+                                if !state.shift_locked {
+                                    toggle_mode(state);
+                                }
+                            }
+                        }
+                        config::ShiftAction::Engine => {
+                            state.last_shift_tap = None;
+                            let _ = std::thread::Builder::new()
+                                .stack_size(8 * 1024 * 1024) // 8 MB
+                                .spawn(|| with_state(|state| cb_toggle_engine(state)));
+                        }
+                        config::ShiftAction::Off => {}
+                    }
                 }
                 state.shift_modified = false;
             }
@@ -390,112 +1090,847 @@ unsafe extern "system" fn low_level_keyboard_hook(
     CallNextHookEx(HHOOK(std::ptr::null_mut()), code, wparam, lparam)
 }
 
-/// 切换中英文模式
-unsafe fn toggle_mode(state: &mut ImeState) {
-    state.chinese_mode = !state.chinese_mode;
+// ============================================================
+// 钩子看门狗
+// ============================================================
+//
+// Windows 会静默卸掉处理超时的低阶钩子 (LowLevelHooksTimeout)，表现为输入法突然"失灵"。
+// 看门狗比较系统最近输入时间与钩子心跳: 心跳明显落后时发一个探测键，
+// 探测键也没进钩子就判定钩子已失效，通知主线程重装。
+
+/// 巡检间隔
+const HOOK_WATCHDOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// 心跳落后系统输入超过此值 (ms) 才发探测键
+const HOOK_STALE_MS: i32 = 2000;
+/// 等待探测键进入钩子的时间
+const HOOK_PROBE_WAIT: std::time::Duration = std::time::Duration::from_millis(300);
+/// 探测键标记 (dwExtraInfo = "AIPY")
+const HOOK_PROBE_MAGIC: usize = 0x4149_5059;
+/// 探测键虚拟键码 (0x88 属未分配区间，应用不会响应)
+const HOOK_PROBE_VK: u16 = 0x88;
+
+/// 安装键盘钩子并记录句柄（需在主线程调用）
+unsafe fn install_keyboard_hook() -> Result<()> {
+    let hinstance = GetModuleHandleW(None)?;
+    let hook = SetWindowsHookExW(
+        WH_KEYBOARD_LL,
+        Some(low_level_keyboard_hook),
+        hinstance,
+        0,
+    )?;
+    KEYBOARD_HOOK.store(hook.0, Ordering::SeqCst);
+    Ok(())
+}
 
-    if !state.chinese_mode {
-        // 切换到英文：若有未提交的拼音，直接以字母形式输出
-        if !state.input.engine.is_empty() {
-            let raw = state.input.engine.raw_input().to_string();
-            state.input.engine.clear();
-            send_unicode_text(&raw);
-        }
-        if let Some(cw) = &state.cand_win {
-            cw.hide();
+/// WebView 创建失败: 候选窗退回 GDI（由 UI 事件循环在主线程、开始派发消息前调用）
+pub(crate) unsafe fn fallback_to_gdi(reason: &str) {
+    if GLOBAL_STATE.is_null() { return; }
+    let state = &mut *GLOBAL_STATE;
+    let font_size = state.cfg.lock().map(|c| c.ui.font_size).unwrap_or(16);
+    match gdi_ui::CandidateWindow::new(font_size) {
+        Ok(w) => {
+            eprintln!("[UI] ⚠ WebView 创建失败 ({})，候选窗改用 GDI", reason);
+            w.set_plugins_active(plugins(state).has_active());
+            state.cand_win = Box::new(w);
         }
-        eprintln!("[IME] ⌨  EN → 英文直通（按 Shift 切回中文）");
-    } else {
-        eprintln!("[IME] 🀄 CN → 中文拦截（按 Shift 切回英文）");
+        Err(e) => eprintln!("[UI] ❌ WebView 创建失败 ({})，GDI 候选窗也无法创建: {}", reason, e),
     }
 }
 
-/// 向当前焦点应用注入 Unicode 文本，返回实际发送的事件数
-unsafe fn send_unicode_text(text: &str) -> u32 {
-    use windows::Win32::UI::Input::KeyboardAndMouse::*;
-
-    let inputs: Vec<INPUT> = text
-        .encode_utf16()
-        .flat_map(|wchar| {
-            // 每个字符发一个 keydown + keyup
-            [
-                INPUT {
-                    r#type: INPUT_KEYBOARD,
-                    Anonymous: INPUT_0 {
-                        ki: KEYBDINPUT {
-                            wVk: VIRTUAL_KEY(0),
-                            wScan: wchar,
-                            dwFlags: KEYEVENTF_UNICODE,
-                            time: 0,
-                            dwExtraInfo: 0,
-                        },
-                    },
-                },
-                INPUT {
-                    r#type: INPUT_KEYBOARD,
-                    Anonymous: INPUT_0 {
-                        ki: KEYBDINPUT {
-                            wVk: VIRTUAL_KEY(0),
-                            wScan: wchar,
-                            dwFlags: KEYEVENTF_UNICODE | KEYEVENTF_KEYUP,
-                            time: 0,
-                            dwExtraInfo: 0,
-                        },
-                    },
-                },
-            ]
-        })
-        .collect();
-
-    if inputs.is_empty() { return 0; }
-    SendInput(&inputs, std::mem::size_of::<INPUT>() as i32)
+/// 卸载旧钩子并重新安装（由 UI 事件循环在主线程调用）
+pub(crate) unsafe fn reinstall_keyboard_hook() {
+    let old = KEYBOARD_HOOK.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    if !old.is_null() {
+        let _ = UnhookWindowsHookEx(HHOOK(old));
+    }
+    match install_keyboard_hook() {
+        Ok(()) => eprintln!("[Watchdog] ✅ 键盘钩子已重新安装"),
+        Err(e) => eprintln!("[Watchdog] ❌ 重新安装键盘钩子失败: {}", e),
+    }
 }
 
+/// 启动钩子看门狗线程
+fn start_hook_watchdog() {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
 
-// ============================================================
-// 翻页 + 候选词刷新
-// ============================================================
+    std::thread::spawn(|| loop {
+        std::thread::sleep(HOOK_WATCHDOG_INTERVAL);
+        unsafe {
+            let mut lii = LASTINPUTINFO {
+                cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+                dwTime: 0,
+            };
+            if !GetLastInputInfo(&mut lii).as_bool() { continue; }
 
-const PAGE_SIZE: usize = 9;
+            // 心跳不落后于系统输入 → 钩子仍在收事件
+            let beat = HOOK_HEARTBEAT.load(Ordering::Relaxed) as u32;
+            if (lii.dwTime.wrapping_sub(beat) as i32) <= HOOK_STALE_MS { continue; }
 
-/// 显示当前页候选词
-pub(crate) unsafe fn show_current_page(state: &mut ImeState, raw: &str) {
-    let total = state.all_candidates.len();
-    if total == 0 { 
-        if let Some(cw) = &state.cand_win {
-            cw.hide(); 
+            // 落后也可能只是鼠标输入，发探测键确认
+            send_hook_probe();
+            std::thread::sleep(HOOK_PROBE_WAIT);
+            if HOOK_HEARTBEAT.load(Ordering::Relaxed) as u32 != beat { continue; }
+
+            eprintln!("[Watchdog] ⚠️ 键盘钩子无响应，请求重新安装");
+            with_state(|state| state.ui_loop.reinstall_hook());
         }
-        return; 
-    }
+    });
+}
 
-    let offset = state.page_offset.min(total.saturating_sub(1));
-    let end = std::cmp::min(offset + PAGE_SIZE, total);
-    state.current_candidates = state.all_candidates[offset..end].to_vec();
+/// 注入一次带标记的探测按键（钩子存活时会被钩子吞掉）
+unsafe fn send_hook_probe() {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
 
-    let page_num = offset / PAGE_SIZE + 1;
-    let total_pages = (total + PAGE_SIZE - 1) / PAGE_SIZE;
+    let key = |flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(HOOK_PROBE_VK),
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: HOOK_PROBE_MAGIC,
+            },
+        },
+    };
+    let inputs = [key(KEYBD_EVENT_FLAGS(0)), key(KEYEVENTF_KEYUP)];
+    SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+}
+
+// ============================================================
+// 前台窗口切换
+// ============================================================
+//
+// 候选窗口是 WS_EX_NOACTIVATE 的置顶工具窗，自己不会抢焦点；
+// 用户点到别的程序或目标程序最小化时，它会悬在其他窗口上，需要主动隐藏。
+
+/// 监听前台切换与最小化（WINEVENT_SKIPOWNPROCESS: 候选窗口/设置页属于本进程，不触发）
+unsafe fn install_foreground_hook() {
+    use windows::Win32::UI::Accessibility::SetWinEventHook;
+
+    for event in [EVENT_SYSTEM_FOREGROUND, EVENT_SYSTEM_MINIMIZESTART, EVENT_OBJECT_FOCUS] {
+        let hook = SetWinEventHook(
+            event, event, HMODULE::default(), Some(on_foreground_event),
+            0, 0, WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+        );
+        if hook.is_invalid() {
+            eprintln!("[IME] ⚠ 前台切换监听安装失败 (event={})", event);
+        }
+    }
+}
+
+unsafe extern "system" fn on_foreground_event(
+    _hook: windows::Win32::UI::Accessibility::HWINEVENTHOOK,
+    event: u32, hwnd: HWND, id_object: i32, _id_child: i32, _thread: u32, _time: u32,
+) {
+    if GLOBAL_STATE.is_null() { return; }
+    let state = &mut *GLOBAL_STATE;
+    // 同一窗口内的焦点切换（如用户名框 → 密码框）
+    if event == EVENT_OBJECT_FOCUS {
+        refresh_password_focus(state);
+        return;
+    }
+    if id_object != OBJID_WINDOW.0 { return; }
+    let hwnd = hwnd.0 as isize;
+
+    match event {
+        EVENT_SYSTEM_FOREGROUND if hwnd != state.last_foreground => {
+            on_focus_left(state, std::mem::replace(&mut state.last_foreground, hwnd));
+            refresh_password_focus(state);
+            restore_app_mode(state);
+            restore_composition(state, hwnd);
+            if state.shift_locked { show_lock_hint(state); }
+        }
+        EVENT_SYSTEM_MINIMIZESTART if hwnd == state.last_foreground => {
+            state.last_foreground = 0;
+            on_focus_left(state, hwnd);
+        }
+        _ => {}
+    }
+}
+
+/// 按键时再核对一次前台窗口（兜底 WinEvent 丢失的情况）
+unsafe fn track_foreground(state: &mut ImeState) {
+    let fg = GetForegroundWindow().0 as isize;
+    if fg != state.last_foreground {
+        on_focus_left(state, std::mem::replace(&mut state.last_foreground, fg));
+        refresh_password_focus(state);
+        restore_app_mode(state);
+        restore_composition(state, fg);
+        if state.shift_locked { show_lock_hint(state); }
+    }
+}
+
+/// 焦点离开正在输入的程序 `left`: 未上屏拼音暂存后清空，隐藏候选窗，重置成对标点
+unsafe fn on_focus_left(state: &mut ImeState, left: isize) {
+    state.punct.reset();
+    state.association.clear();
+    state.cand_cache.clear();
+    state.autocaps.reset();
+    // 让还在路上的 AI 推理结果作废，避免候选窗又被弹出来
+    state.ai_generation += 1;
+    state.cand_win.hide();
+    if !state.input.engine.is_empty() {
+        state.focus_stash.stash(left, state.input.engine.raw_input());
+        state.input.engine.clear();
+        state.all_candidates.clear();
+        state.current_candidates.clear();
+        state.page_offset = 0;
+        state.selected = 0;
+        eprintln!("[IME] 🔀 前台窗口切换，清空未上屏拼音");
+    }
+}
+
+/// 焦点回到窗口 `hwnd`: 刚才离开它时暂存的拼音还没过期就放回输入缓冲
+unsafe fn restore_composition(state: &mut ImeState, hwnd: isize) {
+    let Some(raw) = state.focus_stash.restore(hwnd) else { return };
+    if !state.chinese_mode || state.password_focus || !state.input.engine.is_empty() { return; }
+    state.input.engine.restore(&raw);
+    eprintln!("[IME] 🔀 焦点回到原窗口，恢复未上屏拼音 {:?}", raw);
+    let _ = std::thread::Builder::new()
+        .stack_size(8 * 1024 * 1024) // 8 MB
+        .spawn(|| with_state(|state| cb_refresh_restored(state)));
+}
+
+/// 恢复拼音后重新出候选（在新线程上，与按键处理一样留足推理用的栈）
+unsafe fn cb_refresh_restored(state: &mut ImeState) {
+    if !state.input.engine.is_empty() {
+        refresh_candidates(state);
+    }
+}
+
+// ============================================================
+// 密码框
+// ============================================================
+
+/// Edit 控件的 ES_PASSWORD 样式位
+const ES_PASSWORD_STYLE: i32 = 0x20;
+
+/// 焦点变化后重新判断是否位于密码框（结果缓存在 password_focus）；进入密码框时丢弃未上屏拼音
+unsafe fn refresh_password_focus(state: &mut ImeState) {
+    let enabled = state.cfg.lock().map(|c| c.engine.disable_in_password).unwrap_or(true);
+    let password = enabled && is_focus_password();
+    if password == state.password_focus { return; }
+    state.password_focus = password;
+    if password {
+        state.input.engine.clear();
+        state.all_candidates.clear();
+        state.current_candidates.clear();
+        state.cand_win.hide();
+        eprintln!("[IME] 🔒 焦点在密码框，暂停拦截");
+    } else {
+        eprintln!("[IME] 🔓 离开密码框，恢复拦截");
+    }
+}
+
+/// 前台线程的焦点控件是否为密码框（Edit / RichEdit / WinForms Edit 且带 ES_PASSWORD）
+///
+/// 浏览器等自绘界面里的密码框没有独立窗口句柄，识别不到
+unsafe fn is_focus_password() -> bool {
+    let fg = GetForegroundWindow();
+    if fg.is_invalid() { return false; }
+    let mut info = GUITHREADINFO {
+        cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+        ..Default::default()
+    };
+    if GetGUIThreadInfo(GetWindowThreadProcessId(fg, None), &mut info).is_err()
+        || info.hwndFocus.is_invalid()
+    {
+        return false;
+    }
+    let mut class = [0u16; 64];
+    let len = GetClassNameW(info.hwndFocus, &mut class).max(0) as usize;
+    let class = String::from_utf16_lossy(&class[..len]).to_ascii_uppercase();
+    // ES_PASSWORD 的值在其他控件类里另有含义，只认 Edit 类
+    class.contains("EDIT") && GetWindowLongW(info.hwndFocus, GWL_STYLE) & ES_PASSWORD_STYLE != 0
+}
+
+// ============================================================
+// 全屏暂停
+// ============================================================
+
+/// 是否因前台全屏而暂停拦截；进入/离开全屏时清空输入并打日志
+unsafe fn fullscreen_paused(state: &mut ImeState) -> bool {
+    let (enabled, borderless) = state.cfg.lock()
+        .map(|c| (c.engine.pause_in_fullscreen, c.engine.pause_in_borderless))
+        .unwrap_or((false, false));
+    let paused = enabled && is_foreground_fullscreen(borderless);
+    if paused != state.fullscreen_paused {
+        state.fullscreen_paused = paused;
+        if paused {
+            state.input.engine.clear();
+            state.cand_win.hide();
+            eprintln!("[IME] 🎮 前台全屏，暂停按键拦截");
+        } else {
+            eprintln!("[IME] 🎮 离开全屏，恢复按键拦截");
+        }
+    }
+    paused
+}
+
+/// 前台是否全屏: 系统报告 D3D 独占全屏 / 全屏程序运行中；
+/// `borderless` 时另外检查前台窗口是否为铺满显示器的无边框窗口
+unsafe fn is_foreground_fullscreen(borderless: bool) -> bool {
+    use windows::Win32::UI::Shell::{SHQueryUserNotificationState, QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN};
+    if SHQueryUserNotificationState().is_ok_and(|s| s == QUNS_RUNNING_D3D_FULL_SCREEN || s == QUNS_BUSY) {
+        return true;
+    }
+    borderless && is_foreground_borderless_fullscreen()
+}
+
+/// 前台窗口是否为铺满所在显示器的无边框窗口；桌面、最大化窗口和带标题栏的窗口除外
+unsafe fn is_foreground_borderless_fullscreen() -> bool {
+    let fg = GetForegroundWindow();
+    if fg.is_invalid() || fg == GetDesktopWindow() || fg == GetShellWindow() {
+        return false;
+    }
+
+    // 点击桌面时前台是 WorkerW / Progman，同样铺满屏幕
+    let mut class = [0u16; 32];
+    let len = GetClassNameW(fg, &mut class).max(0) as usize;
+    let class = String::from_utf16_lossy(&class[..len]);
+    if class == "WorkerW" || class == "Progman" {
+        return false;
+    }
+    // 最大化的普通窗口同样盖住整个显示器（任务栏自动隐藏时）
+    let style = GetWindowLongW(fg, GWL_STYLE) as u32;
+    if IsZoomed(fg).as_bool() || style & WS_CAPTION.0 == WS_CAPTION.0 {
+        return false;
+    }
+
+    let mut rect = RECT::default();
+    if GetWindowRect(fg, &mut rect).is_err() {
+        return false;
+    }
+    let monitor = MonitorFromWindow(fg, MONITOR_DEFAULTTONEAREST);
+    let mut mi = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    if !GetMonitorInfoW(monitor, &mut mi).as_bool() {
+        return false;
+    }
+    let m = mi.rcMonitor;
+    rect.left <= m.left && rect.top <= m.top && rect.right >= m.right && rect.bottom >= m.bottom
+}
+
+/// 切换中英文模式（用户操作: 开启 engine.remember_mode_per_app 时记到当前前台程序名下）
+unsafe fn toggle_mode(state: &mut ImeState) {
+    switch_mode(state);
+    remember_app_mode(state);
+}
+
+/// 记住前台程序当前的模式: 配置锁内只改内存，写回 config.toml 放到后台线程
+unsafe fn remember_app_mode(state: &mut ImeState) {
+    if !state.cfg.lock().is_ok_and(|c| c.engine.remember_mode_per_app) { return; }
+    let app = foreground_app(state);
+    if app.is_empty() { return; }
+    let changed = state.cfg.lock().ok()
+        .and_then(|mut c| c.engine.remember_mode(&app, state.chinese_mode));
+    let Some((key, mode)) = changed else { return };
+    let _ = std::thread::Builder::new().name("remember-mode".into()).spawn(move || {
+        if let Err(e) = config::persist_app_mode(&key, mode) {
+            eprintln!("[Config] ⚠ 记住 {} 的输入模式失败: {}", app, e);
+        }
+    });
+}
+
+/// 焦点切到另一个程序: 恢复该程序上次用的模式（双击 Shift 锁定英文时不动）
+unsafe fn restore_app_mode(state: &mut ImeState) {
+    if state.shift_locked { return; }
+    let app = foreground_app(state);
+    let remembered = state.cfg.lock().ok().and_then(|c| c.engine.remembered_mode(&app));
+    if remembered.is_some_and(|chinese| chinese != state.chinese_mode) {
+        eprintln!("[IME] 🔀 {} 上次用的是{}", app, if state.chinese_mode { "英文" } else { "中文" });
+        switch_mode(state);
+    }
+}
+
+/// 切换中英文模式
+unsafe fn switch_mode(state: &mut ImeState) {
+    state.chinese_mode = !state.chinese_mode;
+    state.autocaps.reset();
+    state.association.clear();
+
+    if !state.chinese_mode {
+        // 切换到英文：若有未提交的拼音，直接以字母形式输出
+        if !state.input.engine.is_empty() {
+            let raw = state.input.engine.raw_input().to_string();
+            state.input.engine.clear();
+            send_unicode_text(&raw);
+        }
+        state.cand_win.hide();
+        eprintln!("[IME] ⌨  EN → 英文直通（按 Shift 切回中文）");
+    } else {
+        eprintln!("[IME] 🀄 CN → 中文拦截（按 Shift 切回英文）");
+    }
+}
+
+/// 英文直通下的句首大写 / 双击空格补句号（engine.english_autocaps）
+///
+/// 返回 true 表示原按键已被改写（调用方吃掉它）
+unsafe fn english_autocaps(state: &mut ImeState, vkey: u32) -> bool {
+    if !state.cfg.lock().map(|c| c.engine.english_autocaps).unwrap_or(false) {
+        return false;
+    }
+    let Some(rewrite) = state.autocaps.on_key(english_key(vkey)) else { return false };
+    send_backspaces(rewrite.backspaces);
+    send_unicode_text(&rewrite.text);
+    true
+}
+
+/// 英文模式下的按键归类（按美式键位换算 Shift / Caps Lock）
+unsafe fn english_key(vkey: u32) -> autocaps::Key {
+    use autocaps::Key;
+    // 0xE7 = VK_PACKET（自己注入的 Unicode 字符），0x14 = Caps Lock
+    if is_modifier_key(vkey) || matches!(vkey, 0xE7 | 0x14) {
+        return Key::Neutral;
+    }
+    if is_ctrl_down() || is_alt_down() {
+        return Key::Other;
+    }
+    let shift = is_shift_down();
+    match vkey {
+        0x41..=0x5A => {
+            let ch = vkey as u8 as char;
+            Key::Char(if shift != is_caps_lock_on() { ch } else { ch.to_ascii_lowercase() })
+        }
+        0x30..=0x39 if !shift => Key::Char(vkey as u8 as char),
+        // Shift+1 = '!', Shift+/ = '?', '.'
+        0x31 | 0xBF if shift => Key::Terminator,
+        0xBE if !shift => Key::Terminator,
+        0x20 => Key::Space,
+        0x0D => Key::Enter,
+        0x08 => Key::Backspace,
+        _ => Key::Other,
+    }
+}
+
+/// 中文模式下放行给应用的句末按键: 回车、`.`、Shift+1 (`!`)、Shift+/ (`?`)
+unsafe fn sentence_end_key(vkey: u32) -> Option<&'static str> {
+    if is_ctrl_down() || is_alt_down() { return None; }
+    match (vkey, is_shift_down()) {
+        (0x0D, _) => Some("\n"),
+        (0xBE, false) => Some("."),
+        (0x31, true) => Some("!"),
+        (0xBF, true) => Some("?"),
+        _ => None,
+    }
+}
+
+/// 两次单独 Shift 间隔不超过此值 (ms) 视为双击
+const SHIFT_DOUBLE_TAP_MS: u32 = 300;
+/// 模式提示在候选窗中停留的时间
+const MODE_HINT_DURATION: std::time::Duration = std::time::Duration::from_millis(800);
+
+/// 双击 Shift: 锁定英文（忽略单击切换），再次双击解锁回中文
+unsafe fn toggle_english_lock(state: &mut ImeState) {
+    state.shift_locked = !state.shift_locked;
+    // 第一次单击已切换过模式，这里只需保证最终落在 锁定→英文 / 解锁→中文
+    if state.shift_locked == state.chinese_mode {
+        toggle_mode(state);
+    }
+    if state.shift_locked {
+        eprintln!("[IME] 🔒 英文锁定（双击 Shift 解锁）");
+        show_lock_hint(state);
+    } else {
+        eprintln!("[IME] 🔓 解除英文锁定");
+        flash_mode_hint(state, "中");
+    }
+}
+
+/// 英文锁定期间常驻的提示
+const ENGLISH_LOCK_HINT: &str = "🔒 EN";
+
+/// 英文锁定期间在光标处常驻锁定提示（不自动隐藏，切换窗口后跟到新光标处，解锁时收起）
+unsafe fn show_lock_hint(state: &ImeState) {
+    state.cand_win.show_text(ENGLISH_LOCK_HINT);
+    show_at_caret(state.cand_win.as_ref());
+}
+
+/// 在光标处短暂显示模式提示（复用候选窗的拼音行）；英文锁定中则换回锁定提示
+unsafe fn flash_mode_hint(state: &ImeState, text: &str) {
+    state.cand_win.show_text(text);
+    show_at_caret(state.cand_win.as_ref());
+
+    std::thread::spawn(|| {
+        std::thread::sleep(MODE_HINT_DURATION);
+        with_state(|state| {
+            if state.shift_locked {
+                show_lock_hint(state);
+            } else if state.input.engine.is_empty() {
+                state.cand_win.hide();
+            }
+        });
+    });
+}
+
+/// 注入按键标记 (dwExtraInfo = "AIPI")，钩子见到直接放行
+const SELF_INJECT_MAGIC: usize = 0x4149_5049;
+
+/// Ctrl 当前是否按下
+unsafe fn is_ctrl_down() -> bool {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_CONTROL};
+    GetAsyncKeyState(VK_CONTROL.0 as i32) as u16 & 0x8000 != 0
+}
+
+unsafe fn is_shift_down() -> bool {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_SHIFT};
+    GetAsyncKeyState(VK_SHIFT.0 as i32) as u16 & 0x8000 != 0
+}
+
+unsafe fn is_alt_down() -> bool {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_MENU};
+    GetAsyncKeyState(VK_MENU.0 as i32) as u16 & 0x8000 != 0
+}
+
+/// Caps Lock 是否处于开启状态
+unsafe fn is_caps_lock_on() -> bool {
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetKeyState, VK_CAPITAL};
+    GetKeyState(VK_CAPITAL.0 as i32) & 1 != 0
+}
+
+/// Shift / Ctrl / Alt / Win 等修饰键
+fn is_modifier_key(vkey: u32) -> bool {
+    matches!(vkey, 0x10..=0x12 | 0xA0..=0xA5 | 0x5B | 0x5C)
+}
+
+/// 向当前焦点应用注入一次按键（按下 + 抬起），钩子见到标记直接放行
+unsafe fn send_key_tap(vkey: u32) {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+    let key = |flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(vkey as u16),
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: SELF_INJECT_MAGIC,
+            },
+        },
+    };
+    let inputs = [key(KEYBD_EVENT_FLAGS(0)), key(KEYEVENTF_KEYUP)];
+    SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+}
+
+/// 向当前焦点应用注入 n 次退格
+///
+/// 仍按着的 Ctrl（如 Ctrl+Z 撤销上屏）会让退格变成按词删除: 先注入 Ctrl 抬起，
+/// 退格发完后对仍被物理按住的 Ctrl 再注入按下，恢复原状态
+unsafe fn send_backspaces(n: usize) -> u32 {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+    if n == 0 { return 0; }
+    invalidate_caret_cache();
+    let key = |vk: VIRTUAL_KEY, flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: SELF_INJECT_MAGIC,
+            },
+        },
+    };
+    let held: Vec<(VIRTUAL_KEY, u8)> = [(VK_LCONTROL, 1), (VK_RCONTROL, 2)].into_iter()
+        .filter(|(vk, _)| GetAsyncKeyState(vk.0 as i32) as u16 & 0x8000 != 0)
+        .collect();
+    let inputs: Vec<INPUT> = held.iter().map(|&(vk, _)| key(vk, KEYEVENTF_KEYUP))
+        .chain((0..n).flat_map(|_| [key(VK_BACK, KEYBD_EVENT_FLAGS(0)), key(VK_BACK, KEYEVENTF_KEYUP)]))
+        .collect();
+    let sent = SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+
+    let physical = PHYSICAL_CTRL.load(Ordering::Relaxed);
+    let restore: Vec<INPUT> = held.iter()
+        .filter(|&&(_, bit)| physical & bit != 0)
+        .map(|&(vk, _)| key(vk, KEYBD_EVENT_FLAGS(0)))
+        .collect();
+    if !restore.is_empty() {
+        SendInput(&restore, std::mem::size_of::<INPUT>() as i32);
+    }
+    sent
+}
+
+/// 逐字发送间隔上限（在钩子线程里 sleep，过长会被系统判定钩子超时）
+const MAX_CHAR_DELAY_MS: u64 = 30;
+
+/// 向当前焦点应用注入 Unicode 文本，返回实际发送的事件数
+///
+/// 默认整段一次 SendInput；`inject.char_delay_ms > 0` 时逐字发送（代理对不拆开）。
+/// 前台程序在 `inject.wm_char_apps` 中时直接用 WM_CHAR，在 `inject.paste_fallback_apps` 中时经剪贴板粘贴；
+/// SendInput 没发完时剩余文字走 WM_CHAR 兜底。
+unsafe fn send_unicode_text(text: &str) -> u32 {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
+    use crate::key_event::unicode_key_events;
+
+    invalidate_caret_cache();
+    if text.is_empty() { return 0; }
+
+    let cfg = if GLOBAL_STATE.is_null() {
+        config::InjectConfig::default()
+    } else {
+        (*GLOBAL_STATE).cfg.lock().map(|c| c.inject.clone()).unwrap_or_default()
+    };
+
+    if !cfg.wm_char_apps.is_empty() || !cfg.paste_fallback_apps.is_empty() {
+        if let Some(name) = foreground_process_name() {
+            if cfg.wm_char_apps.iter().any(|a| a.eq_ignore_ascii_case(&name)) {
+                return post_wm_chars(text);
+            }
+            if cfg.paste_fallback_apps.iter().any(|a| a.eq_ignore_ascii_case(&name)) {
+                if let Some(sent) = paste_text(text, &name) { return sent; }
+            }
+        }
+    }
+
+    let to_input = |(unit, up): (u16, bool)| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(0),
+                wScan: unit,
+                dwFlags: if up { KEYEVENTF_UNICODE | KEYEVENTF_KEYUP } else { KEYEVENTF_UNICODE },
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let size = std::mem::size_of::<INPUT>() as i32;
+    let expected = text.encode_utf16().count() as u32 * 2;
+
+    let sent = if cfg.char_delay_ms == 0 {
+        let inputs: Vec<INPUT> = text.chars().flat_map(unicode_key_events).map(to_input).collect();
+        SendInput(&inputs, size)
+    } else {
+        let delay = std::time::Duration::from_millis(cfg.char_delay_ms.min(MAX_CHAR_DELAY_MS));
+        let mut sent = 0;
+        for (i, ch) in text.chars().enumerate() {
+            if i > 0 { std::thread::sleep(delay); }
+            let inputs: Vec<INPUT> = unicode_key_events(ch).into_iter().map(to_input).collect();
+            let n = SendInput(&inputs, size);
+            sent += n;
+            if n as usize != inputs.len() { break; }
+        }
+        sent
+    };
+
+    if sent != expected {
+        eprintln!("[IME] ⚠ SendInput 只发送了 {}/{} 个事件: {:?}", sent, expected, text);
+        if cfg.wm_char_fallback {
+            // 跳过已完整发出的字符，剩余部分改走 WM_CHAR
+            let mut budget = sent as usize;
+            let rest: String = text.chars()
+                .skip_while(|ch| {
+                    let n = ch.len_utf16() * 2;
+                    if budget >= n { budget -= n; true } else { false }
+                })
+                .collect();
+            return sent + post_wm_chars(&rest) * 2;
+        }
+    }
+    sent
+}
+
+/// 剪贴板粘贴上屏（见 `clipboard.rs`）: 写入文字后发送 Ctrl+V，原剪贴板内容稍后在后台线程恢复
+///
+/// 原内容暂存不了（剪贴板被占用）时不覆盖它，返回 None 让调用方照常走 SendInput。
+unsafe fn paste_text(text: &str, app: &str) -> Option<u32> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+    let Some(saved) = clipboard::snapshot() else {
+        eprintln!("[Paste] ⚠ 剪贴板被占用，改用 SendInput");
+        return None;
+    };
+    let Some(seq) = clipboard::set_text(text) else {
+        eprintln!("[Paste] ⚠ 写入剪贴板失败，改用 SendInput");
+        return None;
+    };
+
+    let key = |vk: VIRTUAL_KEY, flags: KEYBD_EVENT_FLAGS| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: SELF_INJECT_MAGIC,
+            },
+        },
+    };
+    let inputs = [
+        key(VK_CONTROL, KEYBD_EVENT_FLAGS(0)), key(VK_V, KEYBD_EVENT_FLAGS(0)),
+        key(VK_V, KEYEVENTF_KEYUP), key(VK_CONTROL, KEYEVENTF_KEYUP),
+    ];
+    let sent = SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
+    eprintln!("[Paste] 📋 {} 经剪贴板粘贴 {} 字", app, text.chars().count());
+
+    let _ = std::thread::Builder::new().name("clipboard-restore".into()).spawn(move || unsafe {
+        std::thread::sleep(clipboard::RESTORE_DELAY);
+        clipboard::restore(saved, seq);
+    });
+    Some(sent)
+}
+
+/// WM_CHAR 兜底: 把 UTF-16 单元逐个投递到前台线程的焦点窗口，返回投递成功的单元数
+unsafe fn post_wm_chars(text: &str) -> u32 {
+    let fg = GetForegroundWindow();
+    if fg.is_invalid() { return 0; }
+
+    let mut gi = GUITHREADINFO {
+        cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+        ..Default::default()
+    };
+    let target = if GetGUIThreadInfo(GetWindowThreadProcessId(fg, None), &mut gi).is_ok()
+        && !gi.hwndFocus.is_invalid()
+    {
+        gi.hwndFocus
+    } else {
+        fg
+    };
+
+    let mut posted = 0;
+    for unit in text.encode_utf16() {
+        if PostMessageW(target, WM_CHAR, WPARAM(unit as usize), LPARAM(1)).is_ok() {
+            posted += 1;
+        }
+    }
+    if posted > 0 {
+        eprintln!("[IME] ↪ WM_CHAR 投递 {} 个单元", posted);
+    }
+    posted
+}
+
+/// 前台窗口所属进程的 exe 文件名（如 "notepad.exe"）
+unsafe fn foreground_process_name() -> Option<String> {
+    process_name_of(GetForegroundWindow())
+}
+
+/// 前台进程名，按 HWND 缓存（供插件按程序区分处理，每次按键都会调用）
+unsafe fn foreground_app(state: &mut ImeState) -> String {
+    let fg = GetForegroundWindow();
+    let key = fg.0 as isize;
+    match &state.foreground_app {
+        Some((hwnd, name)) if *hwnd == key => name.clone(),
+        _ => {
+            let name = process_name_of(fg).unwrap_or_default();
+            state.foreground_app = Some((key, name.clone()));
+            name
+        }
+    }
+}
+
+/// 窗口所属进程的 exe 文件名
+unsafe fn process_name_of(hwnd: HWND) -> Option<String> {
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    if hwnd.is_invalid() { return None; }
+    let mut pid = 0u32;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+    if pid == 0 { return None; }
+
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, BOOL::from(false), pid).ok()?;
+    let mut buf = [0u16; 260];
+    let mut len = buf.len() as u32;
+    let ok = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buf.as_mut_ptr()), &mut len).is_ok();
+    let _ = CloseHandle(process);
+    if !ok { return None; }
+
+    let path = String::from_utf16_lossy(&buf[..len as usize]);
+    path.rsplit(['\\', '/']).next().map(|s| s.to_string())
+}
+
+
+// ============================================================
+// 翻页 + 候选词刷新
+// ============================================================
+
+/// 显示当前页候选词
+pub(crate) unsafe fn show_current_page(state: &mut ImeState, raw: &str) {
+    let total = state.all_candidates.len();
+    if total == 0 { 
+        state.cand_win.hide();
+        return; 
+    }
+    // 符号输入的拼音行附带匹配的分类名
+    let label = state.input.engine.is_symbol_mode().then(|| state.input.engine.symbol_label());
+
+    let page_size = state.page_size;
+    let offset = state.page_offset.min(total.saturating_sub(1));
+    let end = std::cmp::min(offset + page_size, total);
+    state.current_candidates = state.all_candidates[offset..end].to_vec();
+    state.selected = state.selected.min(state.current_candidates.len() - 1);
+
+    let page_num = offset / page_size + 1;
+    let total_pages = total.div_ceil(page_size);
     let page_info = if total_pages > 1 { Some((page_num, total_pages)) } else { None };
 
     let refs: Vec<&str> = state.current_candidates.iter().map(|s| s.as_str()).collect();
-    if let Some(cw) = &state.cand_win {
-        cw.update_candidates_with_page(raw, &refs, page_info);
-    }
+    let sentence = offset == 0 && state.sentence.as_ref()
+        .is_some_and(|(py, s)| py == raw && state.current_candidates.first() == Some(s));
+    let frequency = if state.cfg.lock().map(|c| c.ui.show_frequency).unwrap_or(false) {
+        page_frequency(state)
+    } else {
+        vec![]
+    };
+    let header = label.as_deref().unwrap_or(raw);
+    state.cand_win.update_candidates_with_page(header, &refs, page_info, sentence, &frequency, state.selected);
+}
+
+/// 当前页各候选的相对词频: 字典权重除以本页最大值（AI 生成等不在字典中的为 0）
+fn page_frequency(state: &ImeState) -> Vec<f32> {
+    let weights: Vec<u32> = state.current_candidates.iter()
+        .map(|w| state.input.engine.candidate_weight(w))
+        .collect();
+    let max = weights.iter().copied().max().unwrap_or(0).max(1) as f32;
+    weights.into_iter().map(|w| w as f32 / max).collect()
+}
+
+/// 高亮候选前后移动 `step` 项，在全部候选中循环，所在页随之切换
+unsafe fn move_selection(state: &mut ImeState, step: isize) {
+    let total = state.all_candidates.len();
+    if total == 0 || state.page_size == 0 { return; }
+    let (offset, selected) = moved_selection(
+        state.page_offset + state.selected, step, total, state.page_size);
+    state.page_offset = offset;
+    state.selected = selected;
+    let raw = state.input.engine.raw_input().to_string();
+    show_current_page(state, &raw);
+}
+
+/// 从绝对位置 `current` 移动 `step` 后的 (页起点, 页内序号)
+fn moved_selection(current: usize, step: isize, total: usize, page_size: usize) -> (usize, usize) {
+    let next = (current as isize + step).rem_euclid(total as isize) as usize;
+    (next / page_size * page_size, next % page_size)
 }
 
 /// 下一页
 unsafe fn page_down(state: &mut ImeState) {
     let total = state.all_candidates.len();
-    if state.page_offset + PAGE_SIZE < total {
-        state.page_offset += PAGE_SIZE;
+    if state.page_offset + state.page_size < total {
+        state.page_offset += state.page_size;
+        state.selected = 0;
         let raw = state.input.engine.raw_input().to_string();
         show_current_page(state, &raw);
     }
 }
 
+/// 当前配置的翻页键 (上一页, 下一页)，PageUp / PageDown 另外固定可用
+fn page_keys(state: &ImeState) -> (u32, u32) {
+    state.cfg.lock().map(|c| c.keys.page_keys()).unwrap_or((0xBD, 0xBB))
+}
+
 /// 上一页
 unsafe fn page_up(state: &mut ImeState) {
-    if state.page_offset >= PAGE_SIZE {
-        state.page_offset -= PAGE_SIZE;
+    if state.page_offset >= state.page_size {
+        state.page_offset -= state.page_size;
+        state.selected = 0;
         let raw = state.input.engine.raw_input().to_string();
         show_current_page(state, &raw);
     }
@@ -503,150 +1938,358 @@ unsafe fn page_up(state: &mut ImeState) {
 
 unsafe fn refresh_candidates(state: &mut ImeState) {
     if state.input.engine.is_empty() {
-        if let Some(cw) = &state.cand_win {
-            cw.hide();
-        }
+        state.cand_cache.clear();
+        state.cand_win.hide();
         return;
     }
 
     let raw = state.input.engine.raw_input().to_string();
     let syllables = state.input.engine.syllables().to_vec();
+    if state.sentence.as_ref().is_some_and(|(py, _)| *py != raw) {
+        state.sentence = None;
+    }
+
+    // u 模式（u + 十六进制码位）: 只出码位对应的字符，不经插件 / AI；
+    // 码位还不完整时候选为空，只显示已输入的编码
+    if state.input.engine.is_unicode_mode() {
+        state.ai_generation += 1;
+        state.all_candidates = state.input.engine.get_candidates();
+        state.current_candidates = state.all_candidates.clone();
+        state.page_offset = 0;
+        state.selected = 0;
+        let refs: Vec<&str> = state.current_candidates.iter().map(|s| s.as_str()).collect();
+        state.cand_win.update_candidates_with_page(&raw, &refs, None, false, &[], 0);
+        show_at_caret(state.cand_win.as_ref());
+        return;
+    }
+
+    // 符号输入: 只列分类符号，可翻页，不经插件 / AI
+    if state.input.engine.is_symbol_mode() {
+        state.ai_generation += 1;
+        state.all_candidates = state.input.engine.get_candidates();
+        state.page_offset = 0;
+        state.selected = 0;
+        if state.all_candidates.is_empty() {
+            // 编码不对应任何分类: 只显示已输入的内容
+            state.current_candidates.clear();
+            state.cand_win.show_text(&raw);
+        } else {
+            show_current_page(state, &raw);
+        }
+        show_at_caret(state.cand_win.as_ref());
+        return;
+    }
+
+    // 回到本次输入出现过的拼音（退格）: 直接用缓存的候选重绘，不查字典、不跑 AI
+    if let Some(cached) = state.cand_cache.get(&raw) {
+        state.ai_generation += 1; // 作废还在路上的、针对更长拼音的推理
+        state.all_candidates = cached.to_vec();
+        state.page_offset = 0;
+        state.selected = 0;
+        show_current_page(state, &raw);
+        show_at_caret(state.cand_win.as_ref());
+        return;
+    }
 
     // Phase 1: 立即显示候选 (同步, <5ms)
     let dict_cands = state.input.engine.get_candidates();
-    let dict_after = state.plugins.transform_candidates(&raw, dict_cands);
+    let dict_after = if plugins(state).has_active() {
+        let app = foreground_app(state);
+        let mut p = plugins(state);
+        let active = p.active_count();
+        let out = p.transform_candidates(&raw, dict_cands, &app);
+        // 插件可能因连续出错被自动禁用
+        let auto_disabled = p.active_count() != active;
+        drop(p);
+        if auto_disabled { on_plugins_auto_disabled(state); }
+        out
+    } else {
+        dict_cands
+    };
 
     // 改动4: 单音节时同步运行一次 AI 推理（单次推理 <2ms, 用户无感知延迟）
     // 让用户第一时间看到 AI 排序的结果，而不是等待异步更新
-    let sync_ai_cands: Vec<String> = if syllables.len() == 1 && state.ai.is_available() {
-        let ctx = state.history.context_string();
-        state.ai.predict(&raw, &ctx, 9, &dict_after)
+    //   模型正被本地 HTTP 服务占用时不等待，本次只出字典候选
+    let sync_ai_cands: Vec<String> = if syllables.len() == 1 && state.ai_available {
+        match state.ai.try_lock() {
+            Ok(mut pred) => {
+                let ctx = state.history.lock().map(|h| h.current_sentence()).unwrap_or_default();
+                let min_confidence = state.cfg.lock().map(|c| c.ai.min_confidence).unwrap_or(None);
+                let scored = pred.predict_scored(&raw, &ctx, 9, &dict_after);
+                ai_engine::filter_confident(scored, min_confidence)
+            }
+            Err(_) => {
+                eprintln!("[AI] 模型忙，本次只出字典候选");
+                vec![]
+            }
+        }
     } else {
         vec![]
     };
 
-    // 用户自学习提权 + 合并
-    // 改动1: 顺序 = 用户词 → AI词 → 字典词（字典只补充不重复的）
-    let display_cands = {
-        let learned = state.user_dict.get_learned_words(&raw);
-        let mut merged: Vec<String> = Vec::new();
-        let mut seen = std::collections::HashSet::new();
-
-        // 0. 用户学习词（最高优先级）
-        for (word, _) in &learned {
-            if seen.insert(word.clone()) { merged.push(word.clone()); }
-        }
-        // 1. AI 同步推理结果（单音节时）
-        for w in &sync_ai_cands {
-            if seen.insert(w.clone()) { merged.push(w.clone()); }
-        }
-        // 2. 字典候选（补充剩余位置）
-        for word in &dict_after {
-            if seen.insert(word.clone()) { merged.push(word.clone()); }
-        }
-        merged
-    };
+    // 合并 + 用户自学习提权
+    // 按 [merge] 权重给 AI词 / 字典词 / 学习词打分，几路重合的词靠前，再按学习次数前移
+    let display_cands = merge_with_learned(state, &raw, &sync_ai_cands, &dict_after);
 
     if display_cands.is_empty() { 
-        if let Some(cw) = &state.cand_win {
-            cw.hide();
-        }
+        state.cand_win.hide();
         return; 
     }
 
     // 保存所有候选, 显示当前页
     state.all_candidates = display_cands;
+    state.cand_cache.put(&raw, &state.all_candidates);
     state.page_offset = 0;
+    state.selected = 0;
     show_current_page(state, &raw);
 
-    let pt = get_caret_screen_pos();
-    if let Some(cw) = &state.cand_win {
-        cw.show(pt.x, pt.y + 4);
+    show_at_caret(state.cand_win.as_ref());
+
+    // 唯一候选自动上屏: 只在音节闭合时触发，避免前缀阶段误提交
+    if state.all_candidates.len() == 1
+        && state.input.engine.is_closed()
+        && state.cfg.lock().map(|c| c.engine.auto_commit_single).unwrap_or(false)
+    {
+        schedule_auto_commit(raw.clone());
     }
 
     // Phase 2: AI 推理在后台线程 (异步, 用于多音节/长句上下文感知更新)
     // 单音节已在 Phase 1 同步处理，这里重点处理多音节和上下文感知重排
-    if state.ai.ai_first && state.ai.is_available() {
+    // 候选来源由 ai.source 决定:
+    //   local    — 本地模型
+    //   external — 外部接口，失败则保留 Phase 1 的本地候选
+    //   ensemble — 本地结果先上屏，外部结果到达后按 ai_server::ensemble_merge 合并重排
+    // ai.sentence 开启且 ≥4 音节时另起线程请求整句，到达后置顶
+    let ai_cfg = state.cfg.lock().unwrap().ai.clone();
+    let source = ai_cfg.effective_source();
+    let local_ok = state.ai_first && state.ai_available;
+    state.ai_generation += 1;
+    if ai_cfg.wants_sentence(syllables.len()) && state.sentence.is_none() {
+        request_sentence(state, &raw, syllables.len(), ai_cfg.clone());
+    }
+    if source != config::AiSource::Local || local_ok {
         let raw_clone = raw.clone();
-        let dict_clone = dict_after;
-        let ai_top_k = std::cmp::min(state.cfg.ai.top_k, 9);
-        
-        let hwnd_raw = if let Some(cw) = &state.cand_win {
-            cw.hwnd().0 as isize
-        } else {
-            0
-        };
-
-        state.ai_generation += 1;
+        let mut dict_clone = dict_after;
+        let ai_top_k = std::cmp::min(ai_cfg.top_k, 9);
+        let debounce = std::time::Duration::from_millis(ai_cfg.debounce_ms);
         let gen = state.ai_generation;
 
         // 给 AI 推理线程设置足够大的栈空间 (ONNX Runtime beam search 资源开销大)
         let _ = std::thread::Builder::new()
             .stack_size(8 * 1024 * 1024) // 8 MB
             .spawn(move || {
-                let state_ptr = GLOBAL_STATE;
-                if state_ptr.is_null() { return; }
-                let state = &mut *state_ptr;
-
-                let ctx = state.history.context_string();
-                let ai_scored = state.ai.predict(
-                    &raw_clone, &ctx, ai_top_k, &dict_clone,
-                );
+                // 防抖: 静默期内又有按键 (ai_generation 已变) 则放弃本次推理
+                if !debounce.is_zero() {
+                    std::thread::sleep(debounce);
+                }
+                // 只在读写状态时持有它（退出时等这几段结束）；推理与网络请求期间不持有
+                let Some((ai, ctx)) = with_state(|state| {
+                    if state.ai_generation != gen { return None; }
+                    let ctx = state.history.lock().map(|h| h.current_sentence()).unwrap_or_default();
+                    Some((std::sync::Arc::clone(&state.ai), ctx))
+                }).flatten() else { return };
+
+                // 后台线程可以等锁（不在钩子 / 按键路径上）
+                let local = if source != config::AiSource::External && local_ok {
+                    match ai.lock() {
+                        Ok(mut pred) => {
+                            // ai.rerank: 字典候选先按上下文重排（配置每次刷新时读取，热重载即时生效）
+                            if ai_cfg.rerank {
+                                dict_clone = pred.rerank(&raw_clone, std::mem::take(&mut dict_clone), &ctx);
+                            }
+                            let scored = pred.predict_scored(&raw_clone, &ctx, ai_top_k, &dict_clone);
+                            ai_engine::filter_confident(scored, ai_cfg.min_confidence)
+                        }
+                        Err(_) => Vec::new(),
+                    }
+                } else {
+                    Vec::new()
+                };
+                if source == config::AiSource::Local || !local.is_empty() {
+                    let shown = with_state(|state| {
+                        if state.ai_generation != gen { return false; }
+                        show_ai_candidates(state, &raw_clone, &local, &dict_clone);
+                        true
+                    });
+                    if shown != Some(true) { return; }
+                }
+                if source == config::AiSource::Local { return; }
 
+                let external = match ai_server::query_external(&ai_cfg, &raw_clone, &ctx, &dict_clone, ai_top_k) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        eprintln!("[AI] 外部接口失败, 保留本地候选: {}", e);
+                        return;
+                    }
+                };
 
-                if state.ai_generation != gen { return; }
+                let ai_scored = if source == config::AiSource::Ensemble {
+                    ai_server::ensemble_merge(&local, &external)
+                } else {
+                    external
+                };
+                with_state(|state| {
+                    if state.ai_generation == gen {
+                        show_ai_candidates(state, &raw_clone, &ai_scored, &dict_clone);
+                    }
+                });
+            });
+    }
 
-                let mut merged = Vec::new();
-                let mut seen = std::collections::HashSet::new();
+    eprintln!("[IME] pinyin={:?}  cands={}  mode={}",
+        raw, state.all_candidates.len(), if state.ai_first { "AI" } else { "字典" });
+}
 
-                let learned = state.user_dict.get_learned_words(&raw_clone);
-                for (word, _) in &learned {
-                    if seen.insert(word.clone()) { merged.push(word.clone()); }
-                }
-                for w in &ai_scored {
-                    if seen.insert(w.clone()) { merged.push(w.clone()); }
-                }
-                for w in &dict_clone {
-                    if seen.insert(w.clone()) { merged.push(w.clone()); }
+/// 后台请求整句候选（云拼音）；与词级 AI 推理并行，同样防抖并以 ai_generation 判断过期
+///
+/// 结果不可用（非中文 / 字数不符 / 请求失败）时静默放弃，不影响已显示的候选
+unsafe fn request_sentence(state: &ImeState, raw: &str, syllable_count: usize, ai_cfg: config::AiConfig) {
+    let raw = raw.to_string();
+    let gen = state.ai_generation;
+    let debounce = std::time::Duration::from_millis(ai_cfg.debounce_ms);
+    let _ = std::thread::Builder::new()
+        .name("ai-sentence".into())
+        .spawn(move || {
+            if !debounce.is_zero() {
+                std::thread::sleep(debounce);
+            }
+            let Some(ctx) = with_state(|state| {
+                if state.ai_generation != gen { return None; }
+                Some(state.history.lock().map(|h| h.current_sentence()).unwrap_or_default())
+            }).flatten() else { return };
+
+            match ai_server::query_sentence(&ai_cfg, &raw, &ctx, syllable_count) {
+                Ok(Some(sentence)) => {
+                    with_state(|state| {
+                        if state.ai_generation != gen || state.input.engine.raw_input() != raw { return; }
+                        show_sentence_candidate(state, &raw, sentence);
+                    });
                 }
+                Ok(None) => eprintln!("[AI] 整句结果不可用, 忽略"),
+                Err(e) => eprintln!("[AI] 整句请求失败: {}", e),
+            }
+        });
+}
 
-                if let Some(cw) = &state.cand_win {
-                    state.all_candidates = merged;
-                    state.page_offset = 0;
-                    let raw_string = raw_clone;
-                    let refs: Vec<&str> = state.all_candidates.iter().take(PAGE_SIZE).map(|s| s.as_str()).collect();
-                    let page_info = if state.all_candidates.len() > PAGE_SIZE {
-                        Some((1, (state.all_candidates.len() + PAGE_SIZE - 1) / PAGE_SIZE))
-                    } else {
-                        None
-                    };
-                    cw.update_candidates_with_page(&raw_string, &refs, page_info);
-                    if state.input.engine.is_empty() {
-                        let pt = get_caret_screen_pos();
-                        cw.show(pt.x, pt.y + 4);
-                    }
+/// 唯一候选自动上屏前的等待时间（留给继续输入）
+const AUTO_COMMIT_DELAY: std::time::Duration = std::time::Duration::from_millis(400);
+
+/// 延迟后仍是同一拼音且仍只有一个候选 → 直接上屏这个候选
+unsafe fn schedule_auto_commit(raw: String) {
+    let _ = std::thread::Builder::new()
+        .stack_size(8 * 1024 * 1024) // 8 MB, 上屏后可能接着出联想 / 刷新候选
+        .spawn(move || {
+            std::thread::sleep(AUTO_COMMIT_DELAY);
+            with_state(|state| {
+                if state.input.engine.raw_input() != raw || state.all_candidates.len() != 1 {
+                    return;
                 }
+                eprintln!("[IME] ⏎ 唯一候选自动上屏: {}", raw);
+                commit_candidate(state, &raw, 0, false);
             });
+        });
+}
+
+/// AI 候选、字典候选、学习词按来源打分合并（见 `engine::score_merge`），再按用户学习次数调整顺序；当前输入有整句候选时置顶
+fn merge_with_learned(state: &ImeState, raw: &str, ai: &[String], dict: &[String]) -> Vec<String> {
+    let (merge_cfg, cfg) = state.cfg.lock()
+        .map(|c| (c.merge.clone(), c.user_dict.clone()))
+        .unwrap_or_default();
+    let learned = state.user_dict.get_learned_words(raw);
+    let merged = engine::score_merge(ai, dict, &learned, &merge_cfg);
+    let mut ranked = state.user_dict.rank(raw, merged, &cfg);
+    if let Some((_, sentence)) = state.sentence.as_ref().filter(|(py, _)| py == raw) {
+        ranked.retain(|w| w != sentence);
+        ranked.insert(0, sentence.clone());
     }
+    ranked
+}
 
-    eprintln!("[IME] pinyin={:?}  cands={}  mode={}",
-        raw, state.all_candidates.len(), if state.ai.ai_first { "AI" } else { "字典" });
+/// AI 线程结果上屏: AI 候选 > 字典候选 (学习词按次数提权), 替换当前候选页
+unsafe fn show_ai_candidates(state: &mut ImeState, raw: &str, ai_scored: &[String], dict: &[String]) {
+    state.all_candidates = merge_with_learned(state, raw, ai_scored, dict);
+    state.cand_cache.put(raw, &state.all_candidates);
+    state.page_offset = 0;
+    state.selected = 0;
+    show_current_page(state, raw);
+    if state.input.engine.is_empty() {
+        show_at_caret(state.cand_win.as_ref());
+    }
 }
 
+/// 整句候选到达: 记录并插到首位（保留当前已显示的 AI/字典候选）
+unsafe fn show_sentence_candidate(state: &mut ImeState, raw: &str, sentence: String) {
+    eprintln!("[AI] 整句候选: {} → {}", raw, sentence);
+    state.all_candidates.retain(|w| *w != sentence);
+    state.all_candidates.insert(0, sentence.clone());
+    state.sentence = Some((raw.to_string(), sentence));
+    state.cand_cache.put(raw, &state.all_candidates);
+    state.page_offset = 0;
+    state.selected = 0;
+    show_current_page(state, raw);
+}
+
+
+
+/// 光标位置缓存有效期: 期间同一前台窗口直接复用上次结果，避免重复 MSAA 查询
+const CARET_CACHE_TTL: std::time::Duration = std::time::Duration::from_millis(500);
 
+/// 上次成功查询到的光标位置 (前台 HWND, 屏幕坐标, 查询时刻)
+static CARET_CACHE: std::sync::Mutex<Option<(isize, POINT, std::time::Instant)>> =
+    std::sync::Mutex::new(None);
+
+/// 清空光标缓存（上屏后光标必然移动）
+fn invalidate_caret_cache() {
+    if let Ok(mut cache) = CARET_CACHE.lock() {
+        *cache = None;
+    }
+}
 
 /// 多策略获取光标屏幕坐标
 ///
+/// 缓存: 前台窗口未变且未超过 CARET_CACHE_TTL 时直接返回上次结果
 /// 策略1: OBJID_CARET (Accessibility) — 精确屏幕坐标，适用于所有支持 MSAA 的应用
 /// 策略2: GetGUIThreadInfo — 旧式 Win32 Caret API（记事本/WordPad 等）
-/// 策略3: 鼠标位置 — 通用回退
-pub(crate) unsafe fn get_caret_screen_pos() -> POINT {
+/// 策略3: 鼠标位置 — 通用回退（不缓存）
+///
+/// 第二个值表示是否找到了真实光标（策略3 为 false）
+pub(crate) unsafe fn get_caret_screen_pos() -> (POINT, bool) {
+    let fg = GetForegroundWindow();
+    let key = fg.0 as isize;
+
+    if let Ok(cache) = CARET_CACHE.lock() {
+        if let Some((hwnd, pt, at)) = *cache {
+            if hwnd == key && at.elapsed() < CARET_CACHE_TTL {
+                return (pt, true);
+            }
+        }
+    }
+
+    if let Some(pt) = query_caret_pos(fg) {
+        if let Ok(mut cache) = CARET_CACHE.lock() {
+            *cache = Some((key, pt, std::time::Instant::now()));
+        }
+        return (pt, true);
+    }
+
+    // ── 策略3: 鼠标光标位置 ────────────────────────────────────────────
+    let mut pt = POINT::default();
+    let _ = GetCursorPos(&mut pt);
+    (POINT { x: pt.x, y: pt.y + 20 }, false)
+}
+
+/// 把候选窗显示在光标下方（找到真实光标时由候选窗叠加用户拖出来的偏移）
+unsafe fn show_at_caret(cw: &dyn CandidateUi) {
+    let (pt, caret) = get_caret_screen_pos();
+    cw.show(pt.x, pt.y + 4, caret);
+}
+
+/// 查询前台窗口的真实光标位置（策略1/2），都失败返回 None
+unsafe fn query_caret_pos(fg: HWND) -> Option<POINT> {
     use windows::Win32::UI::Accessibility::{
         AccessibleObjectFromWindow, IAccessible,
     };
 
-    let fg = GetForegroundWindow();
-
     // ── 策略1: Accessibility OBJID_CARET ──────────────────────────────
     // OBJID_CARET = -8i32 (0xFFFFFFF8)
     const OBJID_CARET: u32 = 0xFFFFFFF8u32;
@@ -667,7 +2310,7 @@ pub(crate) unsafe fn get_caret_screen_pos() -> POINT {
                 if acc.accLocation(&mut left, &mut top, &mut width, &mut height, &child).is_ok()
                     && (left != 0 || top != 0)
                 {
-                    return POINT { x: left, y: top + height };
+                    return Some(POINT { x: left, y: top + height });
                 }
             }
         }
@@ -690,15 +2333,12 @@ pub(crate) unsafe fn get_caret_screen_pos() -> POINT {
                 let mut mouse = POINT::default();
                 let _ = GetCursorPos(&mut mouse);
                 if pt.x >= 0 && pt.y >= 0 && (pt.y - mouse.y).abs() < 400 {
-                    return pt;
+                    return Some(pt);
                 }
             }
         }
     }
 
-    // ── 策略3: 鼠标光标位置 ────────────────────────────────────────────
-    let mut pt = POINT::default();
-    let _ = GetCursorPos(&mut pt);
-    POINT { x: pt.x, y: pt.y + 20 }
+    None
 }
 
@@ -59,8 +59,17 @@ const VALID_SYLLABLES: &[&str] = &[
     "ya", "ye", "yi", "yo", "yu", "yao", "you", "yan", "yin", "yang", "ying",
     "yong", "yuan", "yun", "yue",
     "wa", "wo", "wu", "wai", "wei", "wan", "wen", "wang", "weng",
+    // 自成音节的鼻音叹词: 呣 / 嗯 / 噷
+    "m", "ng", "hm",
 ];
 
+/// 自成音节的叹词 (m / ng / hm)
+///
+/// 它们是合法音节，但不参与歧义切分，避免 "man" 多出 ["m","an"] 之类的备选
+fn is_interjection(s: &str) -> bool {
+    matches!(s, "m" | "ng" | "hm")
+}
+
 // ============================================================
 // 拼音切分 — 贪心最长匹配（纯 ASCII bytes 操作）
 // ============================================================
@@ -102,63 +111,202 @@ pub fn split_pinyin_pub(input: &str) -> Vec<String> {
     split_pinyin(input)
 }
 
-/// 获取歧义切分: 返回所有合理的备选切分方案 (不含贪心主方案)
+/// 歧义切分备选数上限默认值（engine.max_ambiguous_splits）
+pub const DEFAULT_MAX_AMBIGUOUS_SPLITS: usize = 5;
+
+/// 获取歧义切分: 全部合法切分中除贪心主方案外的备选，最多 `limit` 个
+///
+/// 顺序: 音节少的在前，音节数相同按字典序。叹词 (m / ng / hm) 不参与切分。
 ///
 /// 例: "xian" 贪心=["xian"], 歧义备选=["xi","an"]
-///     "fangan" 贪心=["fang","an"], 歧义备选=["fan","gan"]
+///     "fangan" 贪心=["fang","an"], 歧义备选=["fan","gan"], ["fang","a","n"]...
 ///     "nihao" 贪心=["ni","hao"], 歧义备选=[] (无歧义)
-fn split_pinyin_ambiguous(input: &str) -> Vec<Vec<String>> {
-    if !input.is_ascii() || input.len() < 3 { return vec![]; }
-    let greedy = split_pinyin(input);
-    let mut alternatives = Vec::new();
-
-    // 对每个贪心音节，尝试在不同位置截短，看剩余能否形成合法音节
-    try_split_recursive(input.as_bytes(), 0, &mut Vec::new(), &greedy, &mut alternatives);
+fn split_pinyin_ambiguous(input: &str, limit: usize) -> Vec<Vec<String>> {
+    if !input.is_ascii() || input.len() < 3 || limit == 0 { return vec![]; }
+    let n = input.len();
+    // 主方案可能占掉一个名额，每个位置多留一个
+    let keep = limit + 1;
+
+    // suffix[i]: input[i..] 的切分，按上述顺序只留前 keep 个。
+    // 前面拼上同一个音节不改变后缀之间的先后，所以各位置截断后结果仍是全局最优的前几个，
+    // 长输入也不会指数爆炸
+    let mut suffix: Vec<Vec<Vec<String>>> = vec![vec![]; n + 1];
+    suffix[n] = vec![vec![]];
+    for i in (0..n).rev() {
+        let mut splits = Vec::new();
+        for len in 1..=std::cmp::min(6, n - i) {
+            let syl = &input[i..i + len];
+            if !is_valid_syllable(syl) || is_interjection(syl) { continue; }
+            for rest in &suffix[i + len] {
+                let mut split = Vec::with_capacity(rest.len() + 1);
+                split.push(syl.to_string());
+                split.extend(rest.iter().cloned());
+                splits.push(split);
+            }
+        }
+        splits.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        splits.truncate(keep);
+        suffix[i] = splits;
+    }
 
-    // 去重 + 去掉和贪心一样的
+    let greedy = split_pinyin(input);
+    let mut alternatives = std::mem::take(&mut suffix[0]);
     alternatives.retain(|alt| *alt != greedy);
-    alternatives.sort();
-    alternatives.dedup();
+    alternatives.truncate(limit);
     alternatives
 }
 
-fn try_split_recursive(
-    bytes: &[u8],
-    pos: usize,
-    current: &mut Vec<String>,
-    greedy: &[String],
-    results: &mut Vec<Vec<String>>,
-) {
-    if pos >= bytes.len() {
-        if current.len() >= 2 && *current != *greedy {
-            results.push(current.clone());
+/// 公开接口: 获取歧义切分结果
+pub fn split_pinyin_ambiguous_pub(input: &str) -> Vec<Vec<String>> {
+    split_pinyin_ambiguous(input, DEFAULT_MAX_AMBIGUOUS_SPLITS)
+}
+
+/// 中文分词（jieba 精确模式），供插件 / 工具复用；首次调用时才加载 jieba 词典
+pub fn segment(text: &str) -> Vec<String> {
+    crate::ai_engine::get_jieba().cut(text, false).into_iter().map(str::to_string).collect()
+}
+
+pub(crate) fn is_valid_syllable(s: &str) -> bool {
+    VALID_SYLLABLES.contains(&s)
+}
+
+/// 音节边界是否"闭合": 全部是合法音节，且末音节再加字母也不会变成更长的音节
+///
+/// 例: ["zhuang"] 闭合; ["zhuan"] 还可能继续成 "zhuang", 未闭合; ["ni","h"] 未闭合
+fn syllables_closed(syllables: &[String]) -> bool {
+    let Some(last) = syllables.last() else { return false };
+    syllables.iter().all(|s| is_valid_syllable(s))
+        && !VALID_SYLLABLES.iter().any(|s| s.len() > last.len() && s.starts_with(last.as_str()))
+}
+
+/// 模糊音组合上限（多音节输入时各音节变体的笛卡尔积可能很大）
+const MAX_FUZZY_KEYS: usize = 16;
+
+/// 单个音节的模糊音变体（含自身，只保留合法音节）
+fn syllable_variants(syl: &str, fuzzy: &FuzzyConfig) -> Vec<String> {
+    let split = ["zh", "ch", "sh"].iter().find(|p| syl.starts_with(*p)).map(|p| p.len())
+        .unwrap_or_else(|| if syl.starts_with(['a', 'e', 'o']) { 0 } else { 1.min(syl.len()) });
+    let (initial, fin) = syl.split_at(split);
+
+    let mut initials = vec![initial];
+    for (a, b) in fuzzy.initial_pairs() {
+        if initial == a { initials.push(b); }
+        if initial == b { initials.push(a); }
+    }
+    let mut finals = vec![fin];
+    for (a, b) in fuzzy.final_pairs() {
+        if fin == a { finals.push(b); }
+        if fin == b { finals.push(a); }
+    }
+
+    let mut out = Vec::new();
+    for i in &initials {
+        for f in &finals {
+            let v = format!("{}{}", i, f);
+            if is_valid_syllable(&v) && !out.contains(&v) { out.push(v); }
         }
-        return;
     }
-    // 限制结果数量
-    if results.len() >= 5 { return; }
+    out
+}
 
-    let remaining = bytes.len() - pos;
-    let max_try = std::cmp::min(6, remaining);
+/// 整串拼音的模糊音变体（不含原拼音本身）；含不完整音节时不做模糊
+pub(crate) fn fuzzy_keys(syllables: &[String], fuzzy: &FuzzyConfig) -> Vec<String> {
+    if !fuzzy.is_enabled() || syllables.is_empty()
+        || !syllables.iter().all(|s| is_valid_syllable(s)) {
+        return vec![];
+    }
+    let mut keys = vec![String::new()];
+    for syl in syllables {
+        let variants = syllable_variants(syl, fuzzy);
+        keys = keys.iter()
+            .flat_map(|k| variants.iter().map(move |v| format!("{}{}", k, v)))
+            .take(MAX_FUZZY_KEYS + 1)
+            .collect();
+    }
+    let original = syllables.concat();
+    keys.retain(|k| *k != original);
+    keys.truncate(MAX_FUZZY_KEYS);
+    keys
+}
 
-    // 尝试每种合法音节长度 (不只是最长)
-    for try_len in (1..=max_try).rev() {
-        let s = unsafe { std::str::from_utf8_unchecked(&bytes[pos..pos + try_len]) };
-        if is_valid_syllable(s) {
-            current.push(s.to_string());
-            try_split_recursive(bytes, pos + try_len, current, greedy, results);
-            current.pop();
-        }
+/// 精确匹配与模糊音匹配合并排序: 模糊音的词频先减去 `fuzzy.penalty`，同分时精确在前
+///
+/// `exact_limit` / `fuzzy_limit`: 精确匹配取前几个、每个模糊音变体取前几个
+fn rank_with_fuzzy<'a>(
+    dict: &'a Dictionary, raw: &str, syllables: &[String], fuzzy: &FuzzyConfig,
+    exact_limit: usize, fuzzy_limit: usize,
+) -> Vec<&'a Candidate> {
+    let exact = dict.lookup(raw).into_iter().take(exact_limit).map(|c| (c.weight, c));
+    let fuzzed = fuzzy_keys(syllables, fuzzy).into_iter()
+        .flat_map(|key| dict.lookup(&key).into_iter().take(fuzzy_limit))
+        .map(|c| (c.weight.saturating_sub(fuzzy.penalty), c));
+    let mut ranked: Vec<(u32, &Candidate)> = exact.chain(fuzzed).collect();
+    // 稳定排序: 同分保持 精确 → 模糊 的先后
+    ranked.sort_by_key(|&(w, _)| std::cmp::Reverse(w));
+    ranked.into_iter().map(|(_, c)| c).collect()
+}
+
+/// 儿化候选上限（字典无现成儿化词时，由基础拼音的词 + 儿 合成）
+const MAX_ERHUA_SYNTH: usize = 3;
+
+/// 候选在字典中的权重: 先按整串 / 候选字数对应的音节精确查找，再查前缀；
+/// AI 生成、插件添加等不在字典中的候选为 0
+fn dict_weight(dict: &Dictionary, raw: &str, syllables: &[String], word: &str) -> u32 {
+    let weight_in = |cands: Vec<&Candidate>| {
+        cands.into_iter().filter(|c| c.word == word).map(|c| c.weight).max()
+    };
+    let n = word.chars().count();
+    let mut keys = vec![raw.to_string()];
+    if n <= syllables.len() {
+        keys.push(syllables[..n].concat());
     }
+    keys.iter()
+        .filter_map(|k| weight_in(dict.lookup(k)))
+        .max()
+        .or_else(|| weight_in(dict.lookup_prefix(raw)))
+        .unwrap_or(0)
 }
 
-/// 公开接口: 获取歧义切分结果
-pub fn split_pinyin_ambiguous_pub(input: &str) -> Vec<Vec<String>> {
-    split_pinyin_ambiguous(input)
+/// 儿化候选: 末尾是落单的 r 且前面都是合法音节时，
+/// 先查字典里的儿化词 ("nar" → "naer" → 哪儿)，没有再用基础拼音的词补 儿
+///
+/// "er" 本身切分为 ["er"]，不会走到这里
+fn erhua_candidates(dict: &Dictionary, syllables: &[String]) -> Vec<String> {
+    let Some((last, base)) = syllables.split_last() else { return vec![] };
+    if last != "r" || base.is_empty() || !base.iter().all(|s| is_valid_syllable(s)) {
+        return vec![];
+    }
+    let base = base.concat();
+    let words: Vec<String> = dict.lookup(&format!("{}er", base))
+        .iter().map(|c| c.word.clone()).collect();
+    if !words.is_empty() { return words; }
+    dict.lookup(&base).iter()
+        .take(MAX_ERHUA_SYNTH)
+        .map(|c| format!("{}儿", c.word))
+        .collect()
 }
 
-fn is_valid_syllable(s: &str) -> bool {
-    VALID_SYLLABLES.contains(&s)
+/// 码位最少 / 最多几位十六进制（"u4f60"，"u1f600"）
+const UNICODE_HEX_LEN: std::ops::RangeInclusive<usize> = 2..=6;
+
+/// u 模式: 没有以 u 开头的拼音，`u` 后面跟十六进制码位直接输入对应字符
+pub fn is_unicode_input(raw: &str) -> bool {
+    raw.starts_with('u')
+}
+
+/// "u4f60" → "你"；码位不足两位、不是十六进制、不是合法字符或是控制字符时返回 None
+fn unicode_candidate(raw: &str) -> Option<String> {
+    let hex = raw.strip_prefix('u')?;
+    if !UNICODE_HEX_LEN.contains(&hex.len()) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let ch = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+    (!ch.is_control()).then(|| ch.to_string())
+}
+
+/// 输入只有一个声母（单字母或 zh/ch/sh）
+fn is_bare_initial(raw: &str) -> bool {
+    raw.len() == 1 || matches!(raw, "zh" | "ch" | "sh")
 }
 
 /// 从纯 ASCII 拼音提取首字母缩写: "shijian" -> "sj"
@@ -173,7 +321,7 @@ fn make_abbreviation(pinyin: &str) -> String {
 /// - ü / µ / 眉 / lv类似乱码 → v
 /// - 只保留 a-z 字符
 /// - 返回 None 表示清洗后为空
-fn sanitize_pinyin(raw: &str) -> Option<String> {
+pub(crate) fn sanitize_pinyin(raw: &str) -> Option<String> {
     let mut out = String::with_capacity(raw.len());
     let mut chars = raw.chars();
 
@@ -196,17 +344,25 @@ fn sanitize_pinyin(raw: &str) -> Option<String> {
 // 词典 — 三级预索引
 // ============================================================
 
-use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use crate::config::{FuzzyConfig, ShuangpinScheme};
+use crate::symbols::{is_symbol_input, SymbolCategory, SYMBOL_PREFIX};
 use serde::{Serialize, Deserialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Candidate {
     pub word: String,
     pub weight: u32,
-    pub pinyin: String,
+    /// 同一拼音的条目共享一份字符串；dict.bin 中不存，加载后由精确索引的键补回
+    #[serde(skip, default = "empty_pinyin")]
+    pub pinyin: Arc<str>,
+    /// 来源额外词库 id (None = 基础词典 / AI 缓存), 名称见 `Dictionary::sources`
+    pub source: Option<u16>,
 }
 
+fn empty_pinyin() -> Arc<str> { Arc::from("") }
+
 static DICT: OnceLock<Dictionary> = OnceLock::new();
 
 /// AI 生成词缓存 (运行时动态添加)
@@ -242,7 +398,8 @@ pub fn cache_ai_word(pinyin: &str, word: &str) {
         cache.entry(pinyin.to_string()).or_default().push(Candidate {
             word: word.to_string(),
             weight: 880,
-            pinyin: pinyin.to_string(),
+            pinyin: Arc::from(pinyin),
+            source: None,
         });
     }
 
@@ -260,13 +417,24 @@ pub fn cache_ai_word(pinyin: &str, word: &str) {
     }
 }
 
+/// 清空运行时的 AI 词缓存（设置页调用），返回清掉的词数
+///
+/// 只清内存；`cache_ai_word` 已追加进 dict.txt 的行不动，下次启动仍会作为字典词加载
+pub fn clear_ai_cache() -> usize {
+    let Ok(mut cache) = AI_CACHE.write() else { return 0 };
+    let n = cache.values().map(Vec::len).sum();
+    cache.clear();
+    eprintln!("[Dict] 🧹 清空AI词缓存 {} 条", n);
+    n
+}
+
 /// 从缓存补充查询结果
 pub fn lookup_with_cache(pinyin: &str) -> Vec<Candidate> {
     let mut result = Vec::new();
     
     // 主字典
     if let Some(dict) = DICT.get() {
-        result.extend_from_slice(dict.lookup(pinyin));
+        result.extend(dict.lookup(pinyin).into_iter().cloned());
     }
     
     // AI 缓存
@@ -283,24 +451,47 @@ pub fn lookup_with_cache(pinyin: &str) -> Vec<Candidate> {
     result
 }
 
+/// 候选排序: 权重降序, 同权重按词长升序、再按 Unicode 码位升序
+///
+/// 保证同权重候选在每次加载 / dict.bin 重建后顺序一致
+pub(crate) fn cmp_candidates(a: &Candidate, b: &Candidate) -> std::cmp::Ordering {
+    b.weight.cmp(&a.weight)
+        .then_with(|| a.word.chars().count().cmp(&b.word.chars().count()))
+        .then_with(|| a.word.cmp(&b.word))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Dictionary {
-    /// 精确匹配: "shi" -> [是, 时, ...]
-    exact: HashMap<String, Vec<Candidate>>,
+    /// 精确匹配: "shi" -> [是, 时, ...]（已按 cmp_candidates 排序）
+    exact: HashMap<String, Vec<usize>>,  // usize = index into `all`
     /// 前缀索引: "s" -> [是, 时, 上, ...]  "sh" -> [是, 时, ...]
     prefix: HashMap<String, Vec<usize>>,  // usize = index into `all`
     /// 缩写索引: "sj" -> [时间, 世界, 司机, ...]
     abbrev: HashMap<String, Vec<usize>>,
     /// 所有候选词的扁平数组
     all: Vec<Candidate>,
+    /// 额外词库名表: source id → 词库名 (如 "sogou_medical")
+    sources: Vec<String>,
+    /// 运行时禁用的词库 id (不写入 dict.bin, 默认全部启用)
+    #[serde(skip)]
+    disabled: std::sync::RwLock<HashSet<u16>>,
+}
+
+/// 候选所属词库是否启用 (基础词典条目始终启用)
+fn source_enabled(disabled: &HashSet<u16>, c: &Candidate) -> bool {
+    c.source.is_none_or(|s| !disabled.contains(&s))
 }
 
 impl Dictionary {
     pub fn from_text(text: &str) -> Self {
-        let mut exact: HashMap<String, Vec<Candidate>> = HashMap::new();
+        let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
         let mut all: Vec<Candidate> = Vec::new();
+        // (拼音, 词) → all 下标, 用于合并重复行
+        let mut seen: HashMap<(Arc<str>, String), usize> = HashMap::new();
+        // 拼音串池: 几十万条目只有几万种拼音, 同一拼音共享一个 Arc<str>
+        let mut pool: HashSet<Arc<str>> = HashSet::new();
 
-        // 第一遍: 解析所有条目
+        // 第一遍: 解析所有条目 (重复的 拼音+词 累加权重)
         for line in text.lines() {
             let line = line.trim();
             if line.is_empty() || line.starts_with('#') { continue; }
@@ -320,18 +511,38 @@ impl Dictionary {
                 None => continue,
             };
 
-            let cand = Candidate {
+            let pinyin = match pool.get(pinyin.as_str()) {
+                Some(p) => p.clone(),
+                None => {
+                    let p: Arc<str> = Arc::from(pinyin);
+                    pool.insert(p.clone());
+                    p
+                }
+            };
+            let key = (pinyin.clone(), word.to_string());
+            if let Some(&i) = seen.get(&key) {
+                all[i].weight = all[i].weight.saturating_add(weight);
+                continue;
+            }
+            seen.insert(key, all.len());
+            all.push(Candidate {
                 word: word.to_string(),
                 weight,
-                pinyin: pinyin.to_string(),
-            };
-            exact.entry(pinyin.to_string()).or_default().push(cand.clone());
-            all.push(cand);
+                pinyin,
+                source: None,
+            });
+        }
+
+        for (i, cand) in all.iter().enumerate() {
+            match exact.get_mut(&*cand.pinyin) {
+                Some(v) => v.push(i),
+                None => { exact.insert(cand.pinyin.to_string(), vec![i]); }
+            }
         }
 
         // 排序每个精确组
         for v in exact.values_mut() {
-            v.sort_by(|a, b| b.weight.cmp(&a.weight));
+            v.sort_by(|&a, &b| cmp_candidates(&all[a], &all[b]));
         }
 
         // 第二遍: 构建前缀索引 + 缩写索引
@@ -339,7 +550,7 @@ impl Dictionary {
         let mut abbrev: HashMap<String, Vec<usize>> = HashMap::new();
 
         for (i, cand) in all.iter().enumerate() {
-            let py = &cand.pinyin;
+            let py: &str = &cand.pinyin;
             // 前缀: 为拼音的每个前缀子串建索引 (1..len)
             let max_prefix = py.len().min(6);
             for plen in 1..=max_prefix {
@@ -349,7 +560,7 @@ impl Dictionary {
 
             // 缩写: 切分音节取首字母
             let ab = make_abbreviation(py);
-            if ab.len() >= 2 && ab != *py {
+            if ab.len() >= 2 && ab != py {
                 abbrev.entry(ab).or_default().push(i);
             }
         }
@@ -357,22 +568,51 @@ impl Dictionary {
         eprintln!("[Dict] {} 个精确键, {} 条词, {} 个前缀, {} 个缩写",
             exact.len(), all.len(), prefix.len(), abbrev.len());
 
-        Dictionary { exact, prefix, abbrev, all }
+        Dictionary {
+            exact, prefix, abbrev, all,
+            sources: Vec::new(),
+            disabled: Default::default(),
+        }
     }
 
-    /// 精确匹配 (O(1))
-    pub fn lookup(&self, pinyin: &str) -> &[Candidate] {
-        self.exact.get(pinyin).map(|v| v.as_slice()).unwrap_or(&[])
+    /// 词条总数（含额外词库）
+    pub fn entry_count(&self) -> usize { self.all.len() }
+
+    /// dict.bin 不存每条的拼音，加载后按精确索引的键补回（同一拼音共享一个 Arc）；
+    /// 下标越界说明文件损坏
+    fn restore_pinyin(&mut self) -> Result<(), String> {
+        for (py, indices) in &self.exact {
+            let shared: Arc<str> = Arc::from(py.as_str());
+            for &i in indices {
+                let cand = self.all.get_mut(i).ok_or("精确索引越界")?;
+                cand.pinyin = shared.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// 精确匹配 (O(1), 跳过已禁用词库的条目)
+    pub fn lookup(&self, pinyin: &str) -> Vec<&Candidate> {
+        let disabled = self.disabled.read().unwrap();
+        match self.exact.get(pinyin) {
+            Some(indices) => indices.iter()
+                .map(|&i| &self.all[i])
+                .filter(|c| source_enabled(&disabled, c))
+                .collect(),
+            None => vec![],
+        }
     }
 
     /// 前缀匹配 (O(1) 查索引 + 排序)
     pub fn lookup_prefix(&self, pre: &str) -> Vec<&Candidate> {
+        let disabled = self.disabled.read().unwrap();
         match self.prefix.get(pre) {
             Some(indices) => {
                 let mut result: Vec<&Candidate> = indices.iter()
                     .map(|&i| &self.all[i])
+                    .filter(|c| source_enabled(&disabled, c))
                     .collect();
-                result.sort_by(|a, b| b.weight.cmp(&a.weight));
+                result.sort_by(|a, b| cmp_candidates(a, b));
                 result
             }
             None => vec![],
@@ -381,12 +621,30 @@ impl Dictionary {
 
     /// 缩写匹配 (O(1))
     pub fn lookup_abbreviation(&self, abbrev: &str) -> Vec<&Candidate> {
+        let disabled = self.disabled.read().unwrap();
         match self.abbrev.get(abbrev) {
             Some(indices) => {
                 let mut result: Vec<&Candidate> = indices.iter()
                     .map(|&i| &self.all[i])
+                    .filter(|c| source_enabled(&disabled, c))
                     .collect();
-                result.sort_by(|a, b| b.weight.cmp(&a.weight));
+                result.sort_by(|a, b| cmp_candidates(a, b));
+                result
+            }
+            None => vec![],
+        }
+    }
+
+    /// 某声母下权重最高的单字（只打了声母时用，避免前缀匹配涌出大量词组）
+    pub fn lookup_initial_chars(&self, initial: &str) -> Vec<&Candidate> {
+        let disabled = self.disabled.read().unwrap();
+        match self.prefix.get(initial) {
+            Some(indices) => {
+                let mut result: Vec<&Candidate> = indices.iter()
+                    .map(|&i| &self.all[i])
+                    .filter(|c| c.word.chars().count() == 1 && source_enabled(&disabled, c))
+                    .collect();
+                result.sort_by(|a, b| cmp_candidates(a, b));
                 result
             }
             None => vec![],
@@ -396,8 +654,10 @@ impl Dictionary {
     /// 以汉字开头的常用词（用于联想扩展，如「时」→「时间」「时候」）
     pub fn lookup_prefix_char(&self, ch: &str) -> Vec<String> {
         // 直接遍历 all 中首字匹配的 2 字词，取权重最高的前 3 条
+        let disabled = self.disabled.read().unwrap();
         let mut result: Vec<(&Candidate, u32)> = self.all.iter()
             .filter(|c| c.word.chars().count() == 2 && c.word.starts_with(ch))
+            .filter(|c| source_enabled(&disabled, c))
             .map(|c| (c, c.weight))
             .collect();
         result.sort_by(|a, b| b.1.cmp(&a.1));
@@ -406,20 +666,60 @@ impl Dictionary {
 
     /// 提升候选词权重
     pub fn boost_weight(&mut self, pinyin: &str, word: &str, amount: u32) {
-        if let Some(cands) = self.exact.get_mut(pinyin) {
-            for c in cands.iter_mut() {
-                if c.word == word {
-                    c.weight = c.weight.saturating_add(amount);
-                    break;
-                }
+        let all = &mut self.all;
+        if let Some(indices) = self.exact.get_mut(pinyin) {
+            if let Some(&i) = indices.iter().find(|&&i| all[i].word == word) {
+                all[i].weight = all[i].weight.saturating_add(amount);
             }
-            cands.sort_by(|a, b| b.weight.cmp(&a.weight));
+            indices.sort_by(|&a, &b| cmp_candidates(&all[a], &all[b]));
         }
     }
 
     /// 合并额外词典文本到当前字典, 返回新增条目数
+    ///
+    /// 已存在的 (拼音, 词) 不再追加, 而是把权重累加到原条目上
     pub fn merge_text(&mut self, text: &str) -> usize {
+        self.merge_entries(text, None)
+    }
+
+    /// 合并一个具名额外词库, 新增条目标记为该来源, 可用 set_source_enabled 单独开关
+    ///
+    /// 已存在的条目只累加权重, 保留原来源（基础词典里已有的词不会因禁用额外词库而消失）
+    pub fn merge_source(&mut self, name: &str, text: &str) -> usize {
+        let id = match self.sources.iter().position(|s| s == name) {
+            Some(i) => i as u16,
+            None => {
+                self.sources.push(name.to_string());
+                (self.sources.len() - 1) as u16
+            }
+        };
+        self.merge_entries(text, Some(id))
+    }
+
+    /// 运行时启用/禁用某个额外词库, 返回该词库是否存在
+    pub fn set_source_enabled(&self, name: &str, enabled: bool) -> bool {
+        let id = match self.sources.iter().position(|s| s == name) {
+            Some(i) => i as u16,
+            None => return false,
+        };
+        let mut disabled = self.disabled.write().unwrap();
+        if enabled { disabled.remove(&id); } else { disabled.insert(id); }
+        true
+    }
+
+    /// 已加载的额外词库及其启用状态
+    pub fn sources(&self) -> Vec<(String, bool)> {
+        let disabled = self.disabled.read().unwrap();
+        self.sources.iter().enumerate()
+            .map(|(i, name)| (name.clone(), !disabled.contains(&(i as u16))))
+            .collect()
+    }
+
+    fn merge_entries(&mut self, text: &str, source: Option<u16>) -> usize {
         let mut added = 0;
+        let mut index: HashMap<(String, String), usize> = self.all.iter().enumerate()
+            .map(|(i, c)| ((c.pinyin.to_string(), c.word.clone()), i))
+            .collect();
 
         for line in text.lines() {
             let line = line.trim();
@@ -436,23 +736,29 @@ impl Dictionary {
 
             if raw_py.is_empty() || word.is_empty() { continue; }
 
-            // 检查是否已存在 (避免重复)
-            let exists = self.exact.get(&raw_py)
-                .map(|v| v.iter().any(|c| c.word == word))
-                .unwrap_or(false);
-            if exists { continue; }
+            // 已存在 → 累加权重 (避免重复条目)
+            let key = (raw_py.clone(), word.to_string());
+            if let Some(&i) = index.get(&key) {
+                self.all[i].weight = self.all[i].weight.saturating_add(weight);
+                continue;
+            }
 
-            let cand = Candidate {
+            // 已有该拼音的条目 → 共享它的拼音串
+            let pinyin = self.exact.get(&raw_py)
+                .and_then(|v| v.first())
+                .map(|&i| self.all[i].pinyin.clone())
+                .unwrap_or_else(|| Arc::from(raw_py.as_str()));
+            let idx = self.all.len();
+            index.insert(key, idx);
+            self.all.push(Candidate {
                 word: word.to_string(),
                 weight,
-                pinyin: raw_py.clone(),
-            };
-
-            let idx = self.all.len();
-            self.all.push(cand.clone());
+                pinyin,
+                source,
+            });
 
             // 精确索引
-            self.exact.entry(raw_py.clone()).or_default().push(cand);
+            self.exact.entry(raw_py.clone()).or_default().push(idx);
 
             // 前缀索引
             let max_prefix = raw_py.len().min(6);
@@ -471,14 +777,38 @@ impl Dictionary {
         }
 
         // 重排精确组
+        let all = &self.all;
         for v in self.exact.values_mut() {
-            v.sort_by(|a, b| b.weight.cmp(&a.weight));
+            v.sort_by(|&a, &b| cmp_candidates(&all[a], &all[b]));
         }
 
         added
     }
 }
 
+/// dict.bin 文件头: 魔数 + 格式版本。格式变化时旧缓存读取失败，回退文本并重建
+const DICT_BIN_MAGIC: &[u8; 4] = b"AIPD";
+const DICT_BIN_VERSION: u32 = 2;
+
+fn encode_bin(dict: &Dictionary) -> bincode::Result<Vec<u8>> {
+    let mut bytes = DICT_BIN_MAGIC.to_vec();
+    bytes.extend_from_slice(&DICT_BIN_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, dict)?;
+    Ok(bytes)
+}
+
+fn decode_bin(bytes: &[u8]) -> Result<Dictionary, String> {
+    let body = bytes.strip_prefix(DICT_BIN_MAGIC.as_slice()).ok_or("不是 dict.bin 格式")?;
+    let (version, body) = body.split_at_checked(4).ok_or("文件过短")?;
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    if version != DICT_BIN_VERSION {
+        return Err(format!("格式版本 {} 已过期 (当前 {})", version, DICT_BIN_VERSION));
+    }
+    let mut dict: Dictionary = bincode::deserialize(body).map_err(|e| e.to_string())?;
+    dict.restore_pinyin()?;
+    Ok(dict)
+}
+
 pub fn global_dict() -> &'static Dictionary {
     DICT.get_or_init(|| load_dictionary(&[]))
 }
@@ -499,7 +829,7 @@ fn load_dictionary(extra_names: &[String]) -> Dictionary {
         if bp.exists() {
             let start = std::time::Instant::now();
             match std::fs::read(bp) {
-                Ok(bytes) => match bincode::deserialize::<Dictionary>(&bytes) {
+                Ok(bytes) => match decode_bin(&bytes) {
                     Ok(d) => {
                         eprintln!("[Dict] 二进制缓存加载: {:?} ({} 条)",
                             start.elapsed(), d.all.len());
@@ -544,35 +874,15 @@ fn load_dictionary(extra_names: &[String]) -> Dictionary {
     };
 
     // 2. 加载额外词库 (dict/*.txt)
-    if !extra_names.is_empty() {
-        let dict_dir = exe_dir.as_ref()
-            .map(|d| d.join("dict"))
-            .or_else(|| Some(std::path::PathBuf::from("dict")));
-
-        for name in extra_names {
-            let ext_path = dict_dir.as_ref()
-                .map(|d| d.join(format!("{}.txt", name)));
-
-            if let Some(path) = ext_path.filter(|p| p.exists()) {
-                match std::fs::read_to_string(&path) {
-                    Ok(text) => {
-                        let count = dict.merge_text(&text);
-                        eprintln!("[Dict] +{}: {} 条", name, count);
-                    }
-                    Err(e) => {
-                        eprintln!("[Dict] ⚠ {}: {}", name, e);
-                    }
-                }
-            } else {
-                eprintln!("[Dict] ⚠ 未找到词库: {}", name);
-            }
-        }
-    }
+    let dict_dir = exe_dir.as_ref()
+        .map(|d| d.join("dict"))
+        .unwrap_or_else(|| std::path::PathBuf::from("dict"));
+    merge_extra_dicts(&mut dict, &dict_dir, extra_names);
 
     // 自动生成二进制缓存
     if let Some(ref bp) = bin_path {
         let start = std::time::Instant::now();
-        match bincode::serialize(&dict) {
+        match encode_bin(&dict) {
             Ok(bytes) => {
                 match std::fs::write(bp, &bytes) {
                     Ok(_) => eprintln!("[Dict] 已生成二进制缓存: {:?} ({:.1} MB, {:?})",
@@ -587,6 +897,65 @@ fn load_dictionary(extra_names: &[String]) -> Dictionary {
     dict
 }
 
+/// 从 dict_dir 合并额外词库 (dict/<name>.txt)
+fn merge_extra_dicts(dict: &mut Dictionary, dict_dir: &std::path::Path, extra_names: &[String]) {
+    for name in extra_names {
+        let path = dict_dir.join(format!("{}.txt", name));
+        if !path.exists() {
+            eprintln!("[Dict] ⚠ 未找到词库: {}", name);
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let count = dict.merge_source(name, &text);
+                eprintln!("[Dict] +{}: {} 条", name, count);
+            }
+            Err(e) => {
+                eprintln!("[Dict] ⚠ {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// 离线编译文本词典为二进制缓存 (`aipinyin.exe --compile-dict`)
+///
+/// 与首次启动时自动生成的 dict.bin 一致: 基础词典 + 输入文件旁 dict/ 下的额外词库。
+/// 返回写入的词条数。
+pub fn compile_dict(
+    input: &std::path::Path,
+    output: &std::path::Path,
+    extra_names: &[String],
+) -> anyhow::Result<usize> {
+    let start = std::time::Instant::now();
+    let text = std::fs::read_to_string(input)?;
+    let mut dict = Dictionary::from_text(&text);
+    println!("  解析 {:?}: {} 条 ({:?})", input, dict.all.len(), start.elapsed());
+
+    let dict_dir = input.parent()
+        .map(|d| d.join("dict"))
+        .unwrap_or_else(|| std::path::PathBuf::from("dict"));
+    merge_extra_dicts(&mut dict, &dict_dir, extra_names);
+
+    let ser_start = std::time::Instant::now();
+    let bytes = encode_bin(&dict)?;
+    std::fs::write(output, &bytes)?;
+    println!("  写入 {:?}: {:.1} MB ({:?})",
+        output, bytes.len() as f64 / 1_048_576.0, ser_start.elapsed());
+    println!("  共 {} 条, {} 个精确键, 总耗时 {:?}",
+        dict.all.len(), dict.exact.len(), start.elapsed());
+    Ok(dict.all.len())
+}
+
+/// 运行时启用/禁用某个额外词库 (设置界面开关调用), 查询结果立即生效
+pub fn set_dict_enabled(name: &str, enabled: bool) {
+    match DICT.get() {
+        Some(dict) if dict.set_source_enabled(name, enabled) => {
+            eprintln!("[Dict] {} 词库 {}", if enabled { "✅ 启用" } else { "⏸ 禁用" }, name);
+        }
+        _ => eprintln!("[Dict] ⚠ 未加载的词库: {}", name),
+    }
+}
+
 const BUILTIN_DICT: &str = "\
 de,的,999
 shi,是,998
@@ -615,6 +984,9 @@ shijian,时间,100
 women,我们,100
 nihao,你好,70
 zaijian,再见,70
+ng,嗯,60
+m,呣,50
+hm,噷,50
 ";
 
 // ============================================================
@@ -622,35 +994,186 @@ zaijian,再见,70
 // ============================================================
 
 pub struct PinyinEngine {
+    /// 全拼串（双拼模式下由 keys 解码得到）
     raw: String,
     syllables: Vec<String>,
+    /// 双拼模式下用户实际敲的按键
+    keys: String,
+    fuzzy: FuzzyConfig,
+    shuangpin: ShuangpinScheme,
+    /// 候选总数上限（engine.max_candidates）
+    max_candidates: usize,
+    /// 末尾 r 按儿化处理（engine.erhua）
+    erhua: bool,
+    /// 歧义切分备选数上限（engine.max_ambiguous_splits）
+    max_ambiguous_splits: usize,
+    /// 符号面板的分类（为空 = 不启用符号输入）
+    symbols: Vec<SymbolCategory>,
+    /// 自带的字典（`with_dict`）；None = 全局字典
+    dict: Option<Box<Dictionary>>,
 }
 
+/// 各查找策略的条数上限（20/15/9/5 等）按此总数设定，max_candidates 不同时按比例缩放
+pub const DEFAULT_MAX_CANDIDATES: usize = 50;
+
 impl PinyinEngine {
     pub fn new() -> Self {
         let _ = global_dict();
-        Self { raw: String::new(), syllables: vec![] }
+        Self::with_source(None)
+    }
+
+    /// 使用指定字典而不是全局字典（不读磁盘，测试里给出确定的词表）
+    pub fn with_dict(dict: Dictionary) -> Self {
+        Self::with_source(Some(Box::new(dict)))
+    }
+
+    fn with_source(dict: Option<Box<Dictionary>>) -> Self {
+        Self {
+            raw: String::new(),
+            syllables: vec![],
+            keys: String::new(),
+            fuzzy: FuzzyConfig::default(),
+            shuangpin: ShuangpinScheme::Off,
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+            erhua: false,
+            max_ambiguous_splits: DEFAULT_MAX_AMBIGUOUS_SPLITS,
+            symbols: vec![],
+            dict,
+        }
+    }
+
+    fn dict(&self) -> &Dictionary {
+        self.dict.as_deref().unwrap_or_else(|| global_dict())
+    }
+
+    pub fn set_max_candidates(&mut self, max: usize) {
+        self.max_candidates = max.max(1);
+    }
+
+    pub fn set_max_ambiguous_splits(&mut self, max: usize) {
+        self.max_ambiguous_splits = max;
+    }
+
+    pub fn set_fuzzy(&mut self, fuzzy: FuzzyConfig) {
+        self.fuzzy = fuzzy;
+    }
+
+    pub fn set_erhua(&mut self, on: bool) {
+        self.erhua = on;
+    }
+
+    /// 设置符号面板分类（`Config::symbol_categories`，为空则 `/` 不进入符号输入）
+    pub fn set_symbols(&mut self, categories: Vec<SymbolCategory>) {
+        self.symbols = categories;
+    }
+
+    /// 切换双拼方案（切换时清空当前输入，避免按键按新方案重新解码）
+    pub fn set_shuangpin(&mut self, scheme: ShuangpinScheme) {
+        if self.shuangpin != scheme {
+            self.shuangpin = scheme;
+            self.clear();
+        }
     }
 
     pub fn push(&mut self, ch: char) {
-        if ch.is_ascii_lowercase() {
+        // 符号输入: 空输入时的前缀；之后的字母是分类编码（双拼下同样原样记录）
+        if ch == SYMBOL_PREFIX {
+            if self.raw.is_empty() && !self.symbols.is_empty() {
+                self.raw.push(ch);
+                self.resplit();
+            }
+            return;
+        }
+        if self.is_symbol_mode() {
+            if ch.is_ascii_lowercase() {
+                self.raw.push(ch);
+                self.resplit();
+            }
+            return;
+        }
+        // u 模式下数字也是码位的一部分
+        if ch.is_ascii_digit() && self.is_unicode_mode() {
             self.raw.push(ch);
-            self.syllables = split_pinyin(&self.raw);
+            self.resplit();
+            return;
+        }
+        if ch.is_ascii_lowercase() {
+            if self.shuangpin == ShuangpinScheme::Off {
+                self.raw.push(ch);
+                self.resplit();
+            } else {
+                self.keys.push(ch);
+                self.decode_keys();
+            }
         }
     }
 
     pub fn pop(&mut self) {
+        if self.shuangpin != ShuangpinScheme::Off && !self.is_symbol_mode() {
+            self.keys.pop();
+            self.decode_keys();
+            return;
+        }
         self.raw.pop();
+        self.resplit();
+    }
+
+    /// 全拼模式下按 raw 重新切分；u 模式与符号输入整串视为一个"音节"，上屏一个字即全部消耗
+    ///
+    /// raw 只应含 ASCII（切分、消耗都按字节算）；混进非 ASCII 字符时丢掉它们
+    fn resplit(&mut self) {
+        if !self.raw.is_ascii() {
+            eprintln!("[Pinyin] ⚠ 输入含非 ASCII 字符，已丢弃: {:?}", self.raw);
+            self.raw.retain(|c| c.is_ascii());
+        }
         self.syllables = if self.raw.is_empty() {
             vec![]
+        } else if self.is_unicode_mode() || self.is_symbol_mode() {
+            vec![self.raw.clone()]
         } else {
             split_pinyin(&self.raw)
         };
     }
 
+    /// 是否处于 u 模式（Unicode 码位输入，仅全拼）
+    pub fn is_unicode_mode(&self) -> bool {
+        self.shuangpin == ShuangpinScheme::Off && is_unicode_input(&self.raw)
+    }
+
+    /// 是否处于符号输入（`/` 开头，全拼 / 双拼都可用）
+    pub fn is_symbol_mode(&self) -> bool {
+        is_symbol_input(&self.raw)
+    }
+
+    /// 符号输入时候选窗拼音行显示的内容（输入 + 匹配的分类名）
+    pub fn symbol_label(&self) -> String {
+        crate::symbols::label(&self.symbols, &self.raw)
+    }
+
     pub fn clear(&mut self) {
         self.raw.clear();
         self.syllables.clear();
+        self.keys.clear();
+    }
+
+    /// 用全拼串整体替换当前输入（撤销上屏时放回原拼音，双拼模式下反向编码为按键）
+    pub fn restore(&mut self, raw: &str) {
+        self.clear();
+        if self.shuangpin == ShuangpinScheme::Off {
+            raw.chars().for_each(|ch| self.push(ch));
+        } else {
+            self.keys = crate::shuangpin::encode(&split_pinyin(raw), self.shuangpin);
+            self.decode_keys();
+        }
+    }
+
+    fn decode_keys(&mut self) {
+        self.syllables = if self.keys.is_empty() {
+            vec![]
+        } else {
+            crate::shuangpin::decode(&self.keys, self.shuangpin)
+        };
+        self.raw = self.syllables.concat();
     }
 
     /// 消耗前 n 个音节 (选字后只吃掉已用音节, 剩余保留)
@@ -664,54 +1187,162 @@ impl PinyinEngine {
             self.clear();
             return;
         }
+        // 双拼: 每个音节固定两键
+        if self.shuangpin != ShuangpinScheme::Off {
+            let keys_to_consume = (n * 2).min(self.keys.len());
+            self.keys.drain(..keys_to_consume);
+            self.decode_keys();
+            return;
+        }
         // 计算前 n 个音节占了多少 raw 字符
         let chars_to_consume: usize = self.syllables[..n]
             .iter().map(|s| s.len()).sum();
-        if chars_to_consume >= self.raw.len() {
+        self.drop_raw_prefix(chars_to_consume);
+    }
+
+    /// 上屏 `word` 后消耗它对应的那段拼音 (剩余保留)
+    ///
+    /// 字典候选按生成时所查的拼音段消耗；不在字典候选里的词（AI / 插件 / 学习词 / 整句）
+    /// 按字数消耗音节。例: raw="fangan" 选 "饭"（来自备选切分 fan|gan）→ raw="gan"
+    pub fn consume_word(&mut self, word: &str) {
+        match self.candidate_spans().into_iter().find(|(w, _)| w == word) {
+            Some((_, len)) => self.consume_raw(len),
+            None => self.consume_syllables(word.chars().count()),
+        }
+    }
+
+    /// 消耗 raw 的前 len 个字母；双拼按键与音节绑定，折算为覆盖到的音节数
+    fn consume_raw(&mut self, len: usize) {
+        if len >= self.raw.len() {
             self.clear();
-        } else {
-            self.raw = self.raw[chars_to_consume..].to_string();
-            self.syllables = split_pinyin(&self.raw);
+            return;
+        }
+        if self.shuangpin != ShuangpinScheme::Off {
+            let mut covered = 0;
+            let n = self.syllables.iter()
+                .take_while(|s| { let before = covered; covered += s.len(); before < len })
+                .count();
+            self.consume_syllables(n.max(1));
+            return;
+        }
+        self.drop_raw_prefix(len);
+    }
+
+    /// 去掉 raw 的前 len 字节（落在多字节字符中间时顺延到字符边界，不会 panic）
+    fn drop_raw_prefix(&mut self, len: usize) {
+        match (len..self.raw.len()).find(|&i| self.raw.is_char_boundary(i)) {
+            Some(at) => {
+                self.raw.drain(..at);
+                self.resplit();
+            }
+            None => self.clear(),
         }
     }
 
     pub fn raw_input(&self) -> &str { &self.raw }
     pub fn syllables(&self) -> &[String] { &self.syllables }
     pub fn is_empty(&self) -> bool { self.raw.is_empty() }
+    /// 当前输入的音节边界是否闭合（见 `syllables_closed`）
+    pub fn is_closed(&self) -> bool { syllables_closed(&self.syllables) }
+
+    /// 候选在字典中的词频权重（候选窗的相对词频条用）
+    pub fn candidate_weight(&self, word: &str) -> u32 {
+        dict_weight(self.dict(), &self.raw, &self.syllables, word)
+    }
 
     /// 多策略候选搜索 (全部 O(1), 无遍历)
     pub fn get_candidates(&self) -> Vec<String> {
+        self.candidate_spans().into_iter().map(|(w, _)| w).collect()
+    }
+
+    /// 同 `get_candidates`，附带每个候选对应 raw 的前几个字母
+    ///
+    /// 上屏时按它消耗拼音: 歧义切分 / 首音节 / 缩写得到的词，字数不一定等于所占音节数
+    /// (例: "fangan" 的备选切分 fan|gan 给出 "饭"，只占前 3 个字母)
+    fn candidate_spans(&self) -> Vec<(String, usize)> {
         if self.raw.is_empty() { return vec![]; }
+        let all = self.raw.len();
+        // u 模式只出码位对应的字符
+        if self.is_unicode_mode() {
+            return unicode_candidate(&self.raw).into_iter().map(|w| (w, all)).collect();
+        }
+        if self.is_symbol_mode() {
+            return crate::symbols::candidates(&self.symbols, &self.raw).into_iter().map(|w| (w, all)).collect();
+        }
 
-        let dict = global_dict();
+        let dict = self.dict();
         let mut seen = std::collections::HashSet::new();
         let mut result = Vec::new();
 
-        // 辅助: 去重添加
+        let max = self.max_candidates;
+        // 一页的量: 不足时继续用更宽泛的策略补
+        let page_fill = 9.min(max);
+
+        // 前 n 个音节占的字母数
+        let syllables_len = |n: usize| -> usize {
+            self.syllables.iter().take(n).map(|s| s.len()).sum()
+        };
+
+        // 辅助: 去重添加，各策略条数按 max_candidates 等比缩放，总数到上限即停
+        //   $span: 这一策略的词对应 raw 的前几个字母；不给则按字数占音节（前缀补全的词）
         macro_rules! add {
+            ($cands:expr, $limit:expr, $span:expr) => {
+                let limit = ($limit * max / DEFAULT_MAX_CANDIDATES).max(1);
+                for c in $cands.iter().take(limit) {
+                    if result.len() >= max { break; }
+                    if seen.insert(c.word.clone()) {
+                        result.push((c.word.clone(), $span));
+                    }
+                }
+            };
             ($cands:expr, $limit:expr) => {
-                for c in $cands.iter().take($limit) {
+                let limit = ($limit * max / DEFAULT_MAX_CANDIDATES).max(1);
+                for c in $cands.iter().take(limit) {
+                    if result.len() >= max { break; }
                     if seen.insert(c.word.clone()) {
-                        result.push(c.word.clone());
+                        result.push((c.word.clone(), syllables_len(c.word.chars().count())));
                     }
                 }
             };
         }
 
         // 1. 整体精确匹配: "wo" -> 我; "shijian" -> 时间
+        //    叹词 (m / ng / hm) 同时也是声母或缩写，精确匹配放到缩写与单字之后
+        // 1.5 模糊音: [fuzzy] 开启 z_zh 时 "zi" 也查 "zhi"，词频减去 fuzzy.penalty 后与精确匹配一起排
         let exact = dict.lookup(&self.raw);
-        add!(exact, 20);
+        let interjection = is_interjection(&self.raw);
+        if !interjection {
+            let scaled = |limit: usize| (limit * max / DEFAULT_MAX_CANDIDATES).max(1);
+            let ranked = rank_with_fuzzy(dict, &self.raw, &self.syllables, &self.fuzzy, scaled(20), scaled(5));
+            add!(ranked, DEFAULT_MAX_CANDIDATES, all);
+        }
+
+        // 1.2 儿化: "nar" → 哪儿 / 那儿
+        if self.erhua {
+            for w in erhua_candidates(dict, &self.syllables) {
+                if result.len() >= max { break; }
+                if seen.insert(w.clone()) { result.push((w, all)); }
+            }
+        }
+
+        // 叹词的模糊音仍放在这里（精确匹配见下方）
+        if interjection {
+            for key in fuzzy_keys(&self.syllables, &self.fuzzy) {
+                let fz = dict.lookup(&key);
+                add!(fz, 5, all);
+            }
+        }
 
         // 2. 第一音节精确匹配 (仅当与 raw 不同)
         if let Some(first) = self.syllables.first() {
             if first.as_str() != self.raw {
                 let first_exact = dict.lookup(first);
-                add!(first_exact, 9);
+                add!(first_exact, 9, first.len());
             }
         }
 
         // 2.5 歧义切分候选: "xian" → 贪心["xian"], 备选["xi","an"] → 查 "xian" 的词
-        let alt_splits = split_pinyin_ambiguous(&self.raw);
+        let alt_splits = split_pinyin_ambiguous(&self.raw, self.max_ambiguous_splits);
         for alt in &alt_splits {
             // 尝试将备选切分拼成完整拼音key查字典
             let alt_key: String = alt.join("");
@@ -722,7 +1353,7 @@ impl PinyinEngine {
             if let Some(first) = alt.first() {
                 if first.as_str() != self.syllables.first().map(|s| s.as_str()).unwrap_or("") {
                     let alt_exact = dict.lookup(first);
-                    add!(alt_exact, 5);
+                    add!(alt_exact, 5, first.len());
                 }
             }
             // 多音节: 查找完整拼音组合 "xi"+"an" → "xian" 已查过,
@@ -730,24 +1361,33 @@ impl PinyinEngine {
             if alt.len() >= 2 {
                 let multi_key: String = alt.iter().map(|s| s.as_str()).collect();
                 let multi_exact = dict.lookup(&multi_key);
-                add!(multi_exact, 5);
+                add!(multi_exact, 5, all);
             }
         }
 
         // 3. 首字母缩写: "wm" -> 我们, "sj" -> 时间
         if self.raw.len() >= 2 && self.raw.len() <= 10 {
             let ab = dict.lookup_abbreviation(&self.raw);
-            add!(ab, 15);
+            add!(ab, 15, all);
         }
 
         // 4. 前缀匹配 (保底)
-        if result.len() < 9 {
-            let pfx = dict.lookup_prefix(&self.raw);
-            add!(pfx, 20);
+        //    只打了声母 ("d" / "zh") 时前缀会涌出成千上万个词组，改出该声母的高频单字
+        if result.len() < page_fill {
+            if is_bare_initial(&self.raw) {
+                let chars = dict.lookup_initial_chars(&self.raw);
+                add!(chars, 9, all);
+            } else {
+                let pfx = dict.lookup_prefix(&self.raw);
+                add!(pfx, 20, all);
+            }
+        }
+        if interjection {
+            add!(exact, 20, all);
         }
 
         // 5. 第一音节前缀或备用策略 (再保底)
-        if result.len() < 9 {
+        if result.len() < page_fill {
             if let Some(first) = self.syllables.first() {
                 let first_str = first.as_str();
                 if first_str.len() >= 2 && first_str != self.raw {
@@ -759,7 +1399,7 @@ impl PinyinEngine {
                     // a) 第二音节精确匹配 → 提供合法的第二字候选 (为/位/维...)
                     let second = &self.syllables[1];
                     let second_exact = dict.lookup(second.as_str());
-                    add!(second_exact, 8);
+                    add!(second_exact, 8, syllables_len(2));
                     // b) 前两个声母缩写查找 → 找2字词 (dw→大为/等)
                     if self.raw.len() >= 2 {
                         let two_initials: String = self.syllables.iter()
@@ -767,7 +1407,7 @@ impl PinyinEngine {
                             .map(|s| s.chars().next().unwrap_or('_'))
                             .collect();
                         let ab2 = dict.lookup_abbreviation(&two_initials);
-                        add!(ab2, 10);
+                        add!(ab2, 10, syllables_len(2));
                     }
                 }
             }
@@ -784,13 +1424,13 @@ impl PinyinEngine {
             // 6b. Raw 前缀
             if result.is_empty() {
                 let pfx = dict.lookup_prefix(&self.raw);
-                add!(pfx, 9);
+                add!(pfx, 9, all);
             }
             // 6c. 最后防线：常用高频单字
             if result.is_empty() {
-                for ch in &["的", "了", "是", "在", "我", "你", "他", "大", "小", "不"] {
+                for ch in ["的", "了", "是", "在", "我", "你", "他", "大", "小", "不"].iter().take(max) {
                     if seen.insert(ch.to_string()) {
-                        result.push(ch.to_string());
+                        result.push((ch.to_string(), syllables_len(1)));
                     }
                 }
             }
@@ -815,19 +1455,293 @@ mod tests {
         assert_eq!(split_pinyin("zhuang"), vec!["zhuang"]);
     }
 
+    #[test]
+    fn test_interjection_syllables() {
+        assert_eq!(split_pinyin("ng"), vec!["ng"]);
+        assert_eq!(split_pinyin("hm"), vec!["hm"]);
+        assert_eq!(split_pinyin("nga"), vec!["ng", "a"]);
+        // 正常音节不受影响
+        assert_eq!(split_pinyin("ming"), vec!["ming"]);
+        assert_eq!(split_pinyin("mingtian"), vec!["ming", "tian"]);
+        assert!(!split_pinyin_ambiguous("mang", DEFAULT_MAX_AMBIGUOUS_SPLITS).iter().any(|alt| alt[0] == "m"));
+    }
+
+    #[test]
+    fn test_interjection_candidates() {
+        let cands = |raw: &str| {
+            let mut engine = PinyinEngine::new();
+            for ch in raw.chars() { engine.push(ch); }
+            engine.get_candidates()
+        };
+        assert!(cands("ng").contains(&"嗯".to_string()));
+        // "m" 仍以声母单字为先，叹词排在后面
+        let m = cands("m");
+        assert!(m.iter().position(|w| w == "呣").is_none_or(|i| i == m.len() - 1), "{:?}", m);
+        assert!(m.iter().all(|w| w.chars().count() == 1), "{:?}", m);
+    }
+
+    #[test]
+    fn test_erhua_candidates() {
+        let syl = |s: &str| split_pinyin(s);
+        let dict = Dictionary::from_text("naer,哪儿,800\nna,那,100\nzhe,这,100\nwan,玩,90\nwan,完,80\ner,二,100\n");
+        assert_eq!(syl("nar"), vec!["na", "r"]);
+        // 字典有现成儿化词
+        assert_eq!(erhua_candidates(&dict, &syl("nar")), vec!["哪儿"]);
+        // 没有则合成
+        assert_eq!(erhua_candidates(&dict, &syl("zher")), vec!["这儿"]);
+        assert_eq!(erhua_candidates(&dict, &syl("wanr")), vec!["玩儿", "完儿"]);
+        // "er" 本身 / 末尾不是 r / 前面不完整 → 不处理
+        assert!(erhua_candidates(&dict, &syl("er")).is_empty());
+        assert!(erhua_candidates(&dict, &syl("nan")).is_empty());
+        assert!(erhua_candidates(&dict, &syl("r")).is_empty());
+        assert!(erhua_candidates(&dict, &syl("nhr")).is_empty());
+    }
+
+    #[test]
+    fn test_dict_weight() {
+        let dict = Dictionary::from_text("nihao,你好,900\nni,你,800\nni,泥,100\nhao,好,700\n");
+        let syl = split_pinyin("nihao");
+        assert_eq!(dict_weight(&dict, "nihao", &syl, "你好"), 900);
+        // 单字按首音节查
+        assert_eq!(dict_weight(&dict, "nihao", &syl, "你"), 800);
+        assert_eq!(dict_weight(&dict, "nihao", &syl, "泥"), 100);
+        // 字典外（AI 整句等）为 0
+        assert_eq!(dict_weight(&dict, "nihao", &syl, "拟好"), 0);
+    }
+
+    #[test]
+    fn test_unicode_mode() {
+        assert_eq!(unicode_candidate("u4f60").as_deref(), Some("你"));
+        assert_eq!(unicode_candidate("u1f600").as_deref(), Some("😀"));
+        assert_eq!(unicode_candidate("u41").as_deref(), Some("A"));
+        // 太短 / 非十六进制 / 控制字符 / 超出范围
+        assert_eq!(unicode_candidate("u4"), None);
+        assert_eq!(unicode_candidate("u4g"), None);
+        assert_eq!(unicode_candidate("u0a"), None);
+        assert_eq!(unicode_candidate("ud800"), None);
+
+        let mut engine = PinyinEngine::new();
+        "u4f60".chars().for_each(|c| engine.push(c));
+        assert!(engine.is_unicode_mode());
+        assert_eq!(engine.get_candidates(), vec!["你"]);
+        // 上屏一个字即消耗全部输入
+        engine.consume_syllables(1);
+        assert!(engine.is_empty());
+        // 拼音中的数字仍被忽略
+        "ni4".chars().for_each(|c| engine.push(c));
+        assert_eq!(engine.raw_input(), "ni");
+    }
+
+    #[test]
+    fn test_symbol_mode() {
+        let mut engine = PinyinEngine::new();
+        // 未启用时 / 被忽略
+        engine.push('/');
+        assert!(engine.is_empty());
+
+        engine.set_symbols(crate::symbols::builtin());
+        engine.set_shuangpin(ShuangpinScheme::Xiaohe);
+        "/sx".chars().for_each(|c| engine.push(c));
+        assert!(engine.is_symbol_mode());
+        assert_eq!(engine.raw_input(), "/sx");
+        assert_eq!(engine.get_candidates()[..3], ["±", "×", "÷"]);
+        assert_eq!(engine.symbol_label(), "/sx  数学 sx");
+        // 只有输入为空时 / 才进入符号输入
+        engine.push('/');
+        assert_eq!(engine.raw_input(), "/sx");
+
+        engine.pop();
+        engine.pop();
+        assert!(engine.get_candidates().contains(&"——".to_string()));
+        engine.consume_word("——");
+        assert!(engine.is_empty());
+    }
+
+    #[test]
+    fn test_consume_word_by_span() {
+        // 固定词表，不依赖磁盘上的 dict.bin
+        let dict = || Dictionary::from_text(
+            "zhe,这,900\nzhen,真,900\nna,那,900\nwo,我,900\nmen,们,800\nwomen,我们,900\nzai,在,900\n\
+             shi,是,900\njian,见,800\nshijian,时间,900\nde,的,900\nzaijian,再见,900\n\
+             ni,你,900\nhao,好,900\nma,吗,900\nnihao,你好,900\n");
+        let commit = |raw: &str, word: &str| {
+            let mut engine = PinyinEngine::with_dict(dict());
+            raw.chars().for_each(|c| engine.push(c));
+            assert!(engine.get_candidates().iter().any(|w| w == word), "{} 不在 {} 的候选中", word, raw);
+            engine.consume_word(word);
+            engine.raw_input().to_string()
+        };
+        // 备选切分 zhe|na 的首音节: 只吃掉 "zhe"（按字数会吃掉整个 "zhen"）
+        assert_eq!(commit("zhena", "这"), "na");
+        // 缩写: 两个字母对应两个字
+        assert_eq!(commit("wmzai", "我们"), "zai");
+        // 缩写 + 完整音节: "s" + "jian"
+        assert_eq!(commit("sjiande", "时间"), "de");
+        assert_eq!(commit("shijiande", "时间"), "de");
+        assert_eq!(commit("zaijian", "再见"), "");
+
+        // 不是字典候选（AI / 整句等）: 按字数消耗音节
+        let mut engine = PinyinEngine::with_dict(dict());
+        "nihaoma".chars().for_each(|c| engine.push(c));
+        engine.consume_word("拟好");
+        assert_eq!(engine.raw_input(), "ma");
+    }
+
+    #[test]
+    fn test_non_ascii_raw_no_panic() {
+        let mut engine = PinyinEngine::with_dict(Dictionary::from_text("hao,好,100\n"));
+        // 音节与 raw 已不一致、切点落在 "你" 的中间: 顺延到字符边界
+        engine.raw = "你hao".to_string();
+        engine.syllables = vec!["ni".to_string(), "hao".to_string()];
+        engine.consume_syllables(1);
+        assert_eq!(engine.raw_input(), "hao");
+        assert_eq!(engine.syllables(), ["hao"]);
+
+        engine.raw = "ni你".to_string();
+        engine.syllables = vec!["ni".to_string(), "你".to_string()];
+        engine.consume_raw(3);
+        assert!(engine.is_empty());
+
+        // 混进来的非 ASCII 在重新切分时被丢掉
+        engine.raw = "ni你hao".to_string();
+        engine.resplit();
+        assert_eq!(engine.raw_input(), "nihao");
+        assert_eq!(engine.syllables(), ["ni", "hao"]);
+        engine.consume_word("你");
+        assert_eq!(engine.raw_input(), "hao");
+
+        // 经 push / restore 进来的非 ASCII 字符直接忽略
+        engine.restore("ni好ma");
+        assert_eq!(engine.raw_input(), "nima");
+        assert_eq!(split_pinyin_pub("ni好"), ["ni好"]);
+    }
+
+    #[test]
+    fn test_syllables_closed() {
+        let closed = |s: &str| syllables_closed(&split_pinyin(s));
+        assert!(closed("zhuang"));
+        assert!(closed("wangxiaoer"));
+        assert!(!closed("zhuan"));
+        assert!(!closed("nih"));
+        assert!(!closed(""));
+    }
+
+    #[test]
+    fn test_max_candidates_caps_list() {
+        let mut engine = PinyinEngine::new();
+        for ch in "s".chars() { engine.push(ch); }
+        let full = engine.get_candidates();
+
+        engine.set_max_candidates(3);
+        let small = engine.get_candidates();
+        assert!(!small.is_empty() && small.len() <= 3);
+        assert!(small.len() <= full.len());
+        // 精确匹配优先级不变，首位不受上限影响
+        assert_eq!(small[0], full[0]);
+    }
+
+    #[test]
+    fn test_with_dict_candidates() {
+        let dict = || Dictionary::from_text("\
+            shijian,时间,900\nshijian,事件,800\nshi,是,900\nshi,时,700\nshij,试剂,10\n\
+            xian,先,900\nxian,现,800\nxi,西,700\nxian,西安,500\n\
+            zhi,知,900\nzi,字,500\nzi,子,300\n");
+        let cands = |engine: &mut PinyinEngine, input: &str| {
+            engine.clear();
+            for ch in input.chars() { engine.push(ch); }
+            engine.get_candidates()
+        };
+        // 词表完全由测试给出，与磁盘上的 dict.txt 无关
+        let mut engine = PinyinEngine::with_dict(dict());
+        assert_eq!(cands(&mut engine, "shijian"), ["时间", "事件", "是", "时", "试剂"]);
+        // 缩写
+        assert_eq!(cands(&mut engine, "sj"), ["时间", "事件", "试剂"]);
+        // 歧义切分: xi'an 的首音节补在精确匹配之后
+        assert_eq!(cands(&mut engine, "xian"), ["先", "现", "西安", "西"]);
+        // 模糊音 z=zh: 惩罚小时高频的 "知" 排到前面
+        assert_eq!(cands(&mut engine, "zi"), ["字", "子"]);
+        engine.set_fuzzy(FuzzyConfig { z_zh: true, penalty: 350, ..Default::default() });
+        assert_eq!(cands(&mut engine, "zi"), ["知", "字", "子"]);
+        assert_eq!(engine.candidate_weight("字"), 500);
+    }
+
+    #[test]
+    fn test_bare_initial_gives_single_chars() {
+        let mut engine = PinyinEngine::new();
+        engine.push('d');
+        let cands = engine.get_candidates();
+        assert!(!cands.is_empty() && cands.len() <= 9, "{:?}", cands);
+        assert!(cands.iter().all(|w| w.chars().count() == 1), "{:?}", cands);
+        assert_eq!(cands[0], "的");
+    }
+
+    #[test]
+    fn test_fuzzy_keys() {
+        let syl = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let fuzzy = FuzzyConfig { z_zh: true, in_ing: true, ..Default::default() };
+        assert_eq!(fuzzy_keys(&syl(&["zi"]), &fuzzy), vec!["zhi"]);
+        assert_eq!(fuzzy_keys(&syl(&["zi", "xin"]), &fuzzy), vec!["zixing", "zhixin", "zhixing"]);
+        // 未开启 / 含不完整音节 → 不做模糊
+        assert!(fuzzy_keys(&syl(&["zi"]), &FuzzyConfig::default()).is_empty());
+        assert!(fuzzy_keys(&syl(&["zi", "x"]), &fuzzy).is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_penalty() {
+        let dict = Dictionary::from_text("zi,字,500\nzi,子,300\nzhi,知,900\n");
+        let syl = split_pinyin("zi");
+        let words = |penalty: u32| {
+            let fuzzy = FuzzyConfig { z_zh: true, penalty, ..Default::default() };
+            rank_with_fuzzy(&dict, "zi", &syl, &fuzzy, 20, 5).iter().map(|c| c.word.as_str()).collect::<Vec<_>>()
+        };
+        // 默认（大惩罚）: 精确匹配始终在前
+        assert_eq!(words(FuzzyConfig::default().penalty), ["字", "子", "知"]);
+        // 惩罚小: 高频的模糊音候选可以排到精确匹配之前
+        assert_eq!(words(350), ["知", "字", "子"]);
+        assert_eq!(words(0), ["知", "字", "子"]);
+        // 同分时精确在前
+        assert_eq!(words(400), ["字", "知", "子"]);
+    }
+
+    fn alts(input: &str, limit: usize) -> Vec<String> {
+        split_pinyin_ambiguous(input, limit).iter().map(|a| a.join("'")).collect()
+    }
+
     #[test]
     fn test_ambiguous_split() {
-        // xian → 贪心[xian], 歧义[xi,an]
-        let alts = split_pinyin_ambiguous("xian");
-        assert!(alts.contains(&vec!["xi".to_string(), "an".to_string()]));
+        let all = |input: &str| alts(input, 10);
+        // 4–6 字母: 单一备选
+        assert_eq!(all("xian"), vec!["xi'an"]);
+        assert_eq!(all("kuai"), vec!["ku'ai"]);
+        assert_eq!(all("fangan"), vec!["fan'gan"]);
+        assert_eq!(all("zhuang"), vec!["zhu'ang"]);
+        assert_eq!(all("nihao"), vec!["ni'ha'o"]);
+        // 无备选（含只能借叹词 / 落单字母才能切开的）
+        assert!(all("nanji").is_empty());
+        assert!(all("sheng").is_empty());
+        assert!(all("ean").is_empty());
+        assert!(all("ni").is_empty());
+        // 多个备选: 音节少的在前，同音节数按字典序，不含贪心主方案
+        assert_eq!(all("tiane"), vec!["ti'a'ne", "ti'an'e"]);
+        assert_eq!(all("xianan"), vec!["xia'nan", "xi'a'nan", "xi'an'an"]);
+        assert_eq!(all("xiangan"), vec!["xian'gan", "xi'an'gan", "xi'ang'an"]);
+        assert_eq!(all("qiaoa"), vec!["qi'ao'a", "qia'o'a", "qi'a'o'a"]);
+    }
 
-        // fangan → 贪心[fang,an], 歧义[fan,gan]
-        let alts = split_pinyin_ambiguous("fangan");
-        assert!(alts.contains(&vec!["fan".to_string(), "gan".to_string()]));
+    #[test]
+    fn test_ambiguous_split_limit() {
+        assert_eq!(alts("xianan", 2), vec!["xia'nan", "xi'a'nan"]);
+        assert!(alts("xianan", 0).is_empty());
+        // 长输入只保留前几个，且与不截断时的开头一致
+        let input = "xianxianxianxianxian";
+        let full = alts(input, 64);
+        assert_eq!(alts(input, DEFAULT_MAX_AMBIGUOUS_SPLITS), full[..DEFAULT_MAX_AMBIGUOUS_SPLITS]);
+    }
 
-        // nihao → 无歧义
-        let alts = split_pinyin_ambiguous("nihao");
-        assert!(alts.is_empty() || !alts.contains(&vec!["ni".to_string(), "hao".to_string()]));
+    #[test]
+    fn test_segment() {
+        assert_eq!(segment("我们今天去公园"), vec!["我们", "今天", "去", "公园"]);
+        assert!(segment("").is_empty());
     }
 
     #[test]
@@ -844,6 +1758,89 @@ mod tests {
         assert_eq!(r[0].word, "是");
     }
 
+    #[test]
+    fn test_polyphone_lookup() {
+        // 同一个字可出现在多个拼音键下, 两种读音都能查到
+        let dict = Dictionary::from_text("xing,行,100\nhang,行,60\nyinhang,银行,80\n");
+        assert!(dict.lookup("xing").iter().any(|c| c.word == "行"));
+        assert!(dict.lookup("hang").iter().any(|c| c.word == "行"));
+        assert!(dict.lookup("yinhang").iter().any(|c| c.word == "银行"));
+    }
+
+    #[test]
+    fn test_stable_tie_order() {
+        // 同权重: 词长升序, 再按码位升序, 与插入顺序无关
+        let dict = Dictionary::from_text("shi,诗,100\nshi,是,100\nshijian,时间,100\n");
+        let words: Vec<&str> = dict.lookup("shi").iter().map(|c| c.word.as_str()).collect();
+        assert_eq!(words, vec!["是", "诗"]);
+
+        let dict = Dictionary::from_text("shi,时,100\nshi,是,100\nshi,十,100\n");
+        let words: Vec<&str> = dict.lookup("shi").iter().map(|c| c.word.as_str()).collect();
+        assert_eq!(words, vec!["十", "时", "是"]);
+
+        let pfx: Vec<String> = Dictionary::from_text("shijian,时间,100\nshi,诗,100\nsha,沙,100\n")
+            .lookup_prefix("sh").iter().map(|c| c.word.clone()).collect();
+        assert_eq!(pfx, vec!["沙", "诗", "时间"]);
+    }
+
+    #[test]
+    fn test_merge_duplicate_lines() {
+        let dict = Dictionary::from_text("shi,是,100\nshi,是,50\n");
+        let r = dict.lookup("shi");
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].word, "是");
+        assert_eq!(r[0].weight, 150);
+        assert_eq!(dict.lookup_prefix("sh").len(), 1);
+
+        let mut dict = Dictionary::from_text("shi,是,100\nshi,时,120\n");
+        assert_eq!(dict.merge_text("shi,是,50\nshi,十,10\n"), 1);
+        let r = dict.lookup("shi");
+        assert_eq!(r.len(), 3);
+        assert_eq!((r[0].word.as_str(), r[0].weight), ("是", 150));
+        assert_eq!(dict.lookup_prefix("shi")[0].weight, 150);
+    }
+
+    #[test]
+    fn test_bin_roundtrip_shares_pinyin() {
+        let mut dict = Dictionary::from_text("shi,是,100\nshi,时,120\nshijian,时间,90\n");
+        dict.merge_source("sogou_it", "shi,式,30\n");
+        let r = dict.lookup("shi");
+        assert!(Arc::ptr_eq(&r[0].pinyin, &r[1].pinyin));
+        assert!(Arc::ptr_eq(&r[0].pinyin, &r[2].pinyin));
+
+        let bytes = encode_bin(&dict).unwrap();
+        let loaded = decode_bin(&bytes).unwrap();
+        let words: Vec<_> = loaded.lookup("shi").iter().map(|c| (c.word.as_str(), &*c.pinyin)).collect();
+        assert_eq!(words, vec![("时", "shi"), ("是", "shi"), ("式", "shi")]);
+        assert_eq!(&*loaded.lookup_abbreviation("sj")[0].pinyin, "shijian");
+        assert_eq!(loaded.sources(), vec![("sogou_it".to_string(), true)]);
+
+        // 旧格式 / 版本不符 → 读取失败（调用方回退文本并重建）
+        assert!(decode_bin(&bytes[4..]).is_err());
+        let mut old = bytes.clone();
+        old[4] = 1;
+        assert!(decode_bin(&old).is_err());
+    }
+
+    #[test]
+    fn test_source_toggle() {
+        let mut dict = Dictionary::from_text("shi,是,100\n");
+        dict.merge_source("sogou_medical", "shiguan,食管,90\nshi,是,10\n");
+        assert!(dict.lookup("shiguan").iter().any(|c| c.word == "食管"));
+
+        assert!(dict.set_source_enabled("sogou_medical", false));
+        assert!(dict.lookup("shiguan").is_empty());
+        assert!(dict.lookup_prefix("shi").iter().all(|c| c.word != "食管"));
+        assert!(dict.lookup_abbreviation("sg").is_empty());
+        // 基础词典已有的词不受影响
+        assert_eq!(dict.lookup("shi")[0].word, "是");
+        assert_eq!(dict.sources(), vec![("sogou_medical".to_string(), false)]);
+
+        assert!(dict.set_source_enabled("sogou_medical", true));
+        assert_eq!(dict.lookup("shiguan").len(), 1);
+        assert!(!dict.set_source_enabled("sogou_legal", false));
+    }
+
     #[test]
     fn test_abbreviation_search() {
         let dict = Dictionary::from_text(
@@ -0,0 +1,210 @@
+//! # 插件宿主 — 与平台无关的部分
+//!
+//! JS 插件系统（`plugin_system`，Windows 前端）用到的计时器队列、钩子出错计数、
+//! fetch 并发名额与请求头过滤、URL 主机解析。
+//! 不依赖 QuickJS 与 Win32，放在库里以便 `cargo test --lib` 覆盖。
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 钩子连续抛错达到此次数后自动禁用（保护输入热路径）
+pub const MAX_CONSECUTIVE_ERRORS: u32 = 5;
+/// 每个插件同时进行中的 fetch 上限（每个请求占一个后台线程）
+pub const MAX_INFLIGHT_FETCHES: usize = 4;
+
+// ============================================================
+// 计时器队列
+// ============================================================
+
+/// 计时线程队列中的一项: (到期时刻, 插件名, 计时器 id)
+pub type TimerEntry = (Instant, String, u32);
+
+/// 交给计时线程的请求
+pub enum TimerRequest {
+    Set(TimerEntry),
+    /// clearTimeout: 立即移出队列，取消的计时器不会堆积到原定到期时刻
+    Cancel(String, u32),
+}
+
+/// 按到期时刻排序的计时器队列（计时线程独占）
+#[derive(Default)]
+pub struct TimerQueue(BinaryHeap<Reverse<TimerEntry>>);
+
+impl TimerQueue {
+    pub fn apply(&mut self, req: TimerRequest) {
+        match req {
+            TimerRequest::Set(entry) => self.0.push(Reverse(entry)),
+            TimerRequest::Cancel(plugin, id) => self.0.retain(|Reverse((_, p, i))| !(*i == id && *p == plugin)),
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.0.peek().map(|Reverse((at, _, _))| *at)
+    }
+
+    /// 取出 `now` 之前到期的全部计时器 (插件名, id)
+    pub fn pop_due(&mut self, now: Instant) -> Vec<(String, u32)> {
+        let mut due = Vec::new();
+        while self.next_deadline().is_some_and(|at| at <= now) {
+            let Some(Reverse((_, plugin, id))) = self.0.pop() else { break };
+            due.push((plugin, id));
+        }
+        due
+    }
+}
+
+// ============================================================
+// 钩子出错计数
+// ============================================================
+
+/// `on_candidates` / `on_commit` 共用的连续出错计数，成功一次即清零
+#[derive(Debug, Default)]
+pub struct ErrorCounter(u32);
+
+impl ErrorCounter {
+    pub fn succeed(&mut self) {
+        self.0 = 0;
+    }
+
+    /// 记一次出错，返回连续出错次数
+    pub fn fail(&mut self) -> u32 {
+        self.0 += 1;
+        self.0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.0
+    }
+
+    /// 已达 [`MAX_CONSECUTIVE_ERRORS`]，插件应自动禁用
+    pub fn exhausted(&self) -> bool {
+        self.0 >= MAX_CONSECUTIVE_ERRORS
+    }
+}
+
+// ============================================================
+// fetch
+// ============================================================
+
+/// 单个插件进行中的 fetch 计数
+#[derive(Clone, Default)]
+pub struct FetchSlots(Arc<AtomicUsize>);
+
+/// 一个 fetch 名额，请求结束（drop）时归还
+pub struct FetchSlot(Arc<AtomicUsize>);
+
+impl FetchSlots {
+    /// 占一个名额；已有 [`MAX_INFLIGHT_FETCHES`] 个请求进行中时返回 None
+    pub fn acquire(&self) -> Option<FetchSlot> {
+        self.0.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_INFLIGHT_FETCHES).then_some(n + 1))
+            .ok()
+            .map(|_| FetchSlot(Arc::clone(&self.0)))
+    }
+}
+
+impl Drop for FetchSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// 插件能否设置该请求头
+///
+/// `Host` 会让请求发往授权主机、却按另一个站点的虚拟主机处理，绕过 `@connect`；
+/// 长度 / 分块 / 连接相关的头由 HTTP 客户端自己决定。与浏览器 fetch 的禁用头一致，直接忽略
+pub fn is_allowed_header(name: &str) -> bool {
+    const FORBIDDEN: [&str; 4] = ["host", "content-length", "transfer-encoding", "connection"];
+    !FORBIDDEN.iter().any(|f| name.trim().eq_ignore_ascii_case(f))
+}
+
+// ============================================================
+// URL
+// ============================================================
+
+/// 取 http/https URL 的主机名（小写，不含端口）；带用户信息的地址视为无效
+pub fn url_host(url: &str) -> Option<String> {
+    let lower = url.to_ascii_lowercase();
+    let rest = lower.strip_prefix("https://").or_else(|| lower.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    if authority.contains('@') { return None; }
+    let host = authority.rsplit_once(':').map_or(authority, |(h, _)| h);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("https://API.example.com/v1?q=1").as_deref(), Some("api.example.com"));
+        assert_eq!(url_host("http://localhost:8080").as_deref(), Some("localhost"));
+        assert_eq!(url_host("https://evil.com@api.example.com/"), None);
+        assert_eq!(url_host("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_timer_queue_cancel_removes_entry() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut q = TimerQueue::default();
+        q.apply(TimerRequest::Set((at(60_000), "a".into(), 1)));
+        q.apply(TimerRequest::Set((at(10), "a".into(), 2)));
+        q.apply(TimerRequest::Set((at(10), "b".into(), 1)));
+
+        // 取消后立即移出队列（不等到原定的 60 秒后）
+        q.apply(TimerRequest::Cancel("a".into(), 1));
+        assert_eq!(q.next_deadline(), Some(at(10)));
+
+        assert!(q.pop_due(at(5)).is_empty());
+        let mut due = q.pop_due(at(60_000));
+        due.sort();
+        assert_eq!(due, vec![("a".to_string(), 2), ("b".to_string(), 1)]);
+        assert_eq!(q.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_error_counter() {
+        let mut errors = ErrorCounter::default();
+        // 偶发错误: 成功一次即清零
+        for _ in 0..MAX_CONSECUTIVE_ERRORS - 1 {
+            errors.fail();
+        }
+        assert!(!errors.exhausted());
+        errors.succeed();
+        assert_eq!(errors.count(), 0);
+
+        for i in 1..=MAX_CONSECUTIVE_ERRORS {
+            assert_eq!(errors.fail(), i);
+        }
+        assert!(errors.exhausted());
+    }
+
+    #[test]
+    fn test_fetch_slots() {
+        let slots = FetchSlots::default();
+        let held: Vec<FetchSlot> = (0..MAX_INFLIGHT_FETCHES).filter_map(|_| slots.acquire()).collect();
+        assert_eq!(held.len(), MAX_INFLIGHT_FETCHES);
+        assert!(slots.acquire().is_none());
+        // 请求结束归还名额
+        drop(held);
+        assert!(slots.acquire().is_some());
+    }
+
+    #[test]
+    fn test_forbidden_headers() {
+        assert!(is_allowed_header("Content-Type"));
+        assert!(is_allowed_header("Authorization"));
+        assert!(!is_allowed_header("Host"));
+        assert!(!is_allowed_header(" HOST "));
+        assert!(!is_allowed_header("Transfer-Encoding"));
+    }
+}
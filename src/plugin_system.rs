@@ -4,11 +4,39 @@
 //! - 每个 .js 文件在独立 Context（沙箱隔离）中运行
 //! - 最多同时启用 5 个插件（MAX_ACTIVE）
 //! - 首次启用时需用户授权（持久化到 plugins/.authorized）
-//! - 提供 `on_candidates(raw, candidates)` 钩子
+//! - 提供 `on_candidates(raw, candidates, app)` 钩子（app 为前台进程名，如 "Code.exe"）；连续出错 `MAX_CONSECUTIVE_ERRORS` 次自动禁用
+//! - 提供 `on_commit(raw, text, app)` 钩子: 上屏前改写文字（返回字符串替换，`null` 保持原样，
+//!   `false` / `""` 取消这次上屏）；替换结果超过 `MAX_COMMIT_CHARS` 字视为出错，与 `on_candidates` 共用出错计数
+//! - 文件头 `// @name / @version / @description / @author` 声明元数据（`PluginMeta`），
+//!   缺省时名称取文件名，其余留空并在加载时提示
+//!
+//! ## 宿主 API
+//! - `console.log/warn/error`
+//! - `fetch(url, opts)` → `Promise<{status, text}>`: 只能访问授权时同意的主机。
+//!   插件在文件头用 `// @connect api.example.com` 声明，授权对话框中列出，
+//!   同意后与插件名一起写入 `.authorized`（`名称: 主机 主机`），也可手动编辑；
+//!   每个插件同时最多 `MAX_INFLIGHT_FETCHES` 个请求，`Host` 等禁用头被忽略
+//! - `host.recentContext(n)`: 最近 n 条上屏文字（旧→新，最多 `MAX_RECENT_CONTEXT` 条），只读；
+//!   输入历史对已授权插件可见，授权对话框中已说明
+//! - `setTimeout(fn, ms, ...args)` / `clearTimeout(id)`: 由宿主计时线程驱动，
+//!   每个插件最多 `MAX_TIMERS` 个未触发的计时器，间隔不小于 `MIN_TIMER_MS`
+//!
+//! 异步结果（HTTP 响应、计时器到期）由后台线程经 `HostEvent` 交回，调用 `pump()` 时才进入 JS。
+//! QuickJS 运行时不是线程安全的: 同一时刻只能有一个线程调用 `PluginSystem` 的方法，
+//! 输入法把它放在 Mutex 里，按键线程的钩子调用与主线程的 `pump()` 经同一把锁排队。
 
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use rquickjs::{Context, Ctx, Function, Object, Runtime, Value};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::ai_engine::HistoryBuffer;
+use crate::i18n::{fill, tr, Text};
+use crate::plugin_host::{is_allowed_header, url_host, ErrorCounter, FetchSlots, TimerQueue, TimerRequest, MAX_INFLIGHT_FETCHES};
+use rquickjs::{Context, Ctx, Exception, Function, Object, Runtime, Value};
 use windows::Win32::Foundation::HWND;
 use windows::Win32::UI::WindowsAndMessaging::*;
 use windows::core::PCWSTR;
@@ -16,6 +44,18 @@ use windows::core::PCWSTR;
 // ── 常量 ──────────────────────────────────────────────────────
 pub const MAX_ACTIVE: usize = 5;
 const AUTH_FILE: &str = ".authorized";
+/// 插件 fetch 单次请求超时
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// 插件 fetch 响应体上限
+const FETCH_MAX_BYTES: u64 = 256 * 1024;
+/// 每个插件未触发的计时器上限
+const MAX_TIMERS: usize = 16;
+/// 计时器最小间隔（更小的值按此值处理）
+const MIN_TIMER_MS: u64 = 10;
+/// `host.recentContext(n)` 单次最多返回的条数
+const MAX_RECENT_CONTEXT: usize = 20;
+/// `on_commit` 替换文字的长度上限（字符数），防止失控的展开
+pub const MAX_COMMIT_CHARS: usize = 1000;
 
 // ============================================================
 // 公开类型
@@ -26,6 +66,51 @@ pub struct PluginInfo {
     pub name: String,
     pub enabled: bool,
     pub authorized: bool,
+    /// 因连续运行出错被自动禁用（手动重新启用后清除）
+    pub error_disabled: bool,
+    pub meta: PluginMeta,
+}
+
+/// 插件文件头声明的元数据
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PluginMeta {
+    /// 显示名称（未声明时为文件名）
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub author: String,
+}
+
+impl PluginMeta {
+    /// 从文件头 `// @标签 值` 解析，`file_stem` 作为名称缺省值
+    pub fn parse(code: &str, file_stem: &str) -> Self {
+        let mut meta = Self::default();
+        for (tag, value) in header_tags(code) {
+            let field = match tag {
+                "name" => &mut meta.name,
+                "version" => &mut meta.version,
+                "description" => &mut meta.description,
+                "author" => &mut meta.author,
+                _ => continue,
+            };
+            if field.is_empty() {
+                *field = value.to_string();
+            }
+        }
+        if meta.name.is_empty() {
+            meta.name = file_stem.to_string();
+        }
+        meta
+    }
+
+    /// 未声明的元数据字段（名称有缺省值，不计入）
+    pub fn missing(&self) -> Vec<&'static str> {
+        [("version", &self.version), ("description", &self.description), ("author", &self.author)]
+            .into_iter()
+            .filter(|(_, v)| v.is_empty())
+            .map(|(k, _)| k)
+            .collect()
+    }
 }
 
 /// toggle() 操作的结果
@@ -45,29 +130,159 @@ pub struct PluginSystem {
     plugins: Vec<LoadedPlugin>,
     /// 已授权的插件名称集合（持久化）
     authorized: HashSet<String>,
+    /// 已授权插件允许 fetch 的主机（持久化，与授权同一文件）
+    net_allow: HashMap<String, Vec<String>>,
     plugins_dir: PathBuf,
+    host: HostChannel,
+    host_rx: Receiver<HostEvent>,
+    /// 上屏历史（`host.recentContext` 只读访问）
+    history: Arc<Mutex<HistoryBuffer>>,
 }
 
 struct LoadedPlugin {
     name: String,
     ctx: Context,
     enabled: bool,
+    errors: ErrorCounter,
+    error_disabled: bool,
+    meta: PluginMeta,
+    /// 文件头 `// @connect` 声明的主机
+    connect: Vec<String>,
+    /// 当前允许 fetch 的主机（授权后更新，fetch 时读取）
+    allow: Rc<RefCell<Vec<String>>>,
+    /// 未触发且未取消的计时器 id
+    timers: Rc<RefCell<HashSet<u32>>>,
+}
+
+/// 宿主后台线程完成的工作，由 `pump()` 交给 JS
+enum HostEvent {
+    /// fetch 完成: Ok((status, text)) / Err(原因)
+    Fetch { plugin: String, id: u32, result: Result<(u16, String), String> },
+    /// 计时器到期
+    Timer { plugin: String, id: u32 },
+}
+
+/// 唤醒回调
+pub type WakeFn = Box<dyn Fn() + Send>;
+
+/// 唤醒宿主调用 `PluginSystem::pump`（由 UI 事件循环提供）
+#[derive(Clone, Default)]
+struct Waker(Arc<Mutex<Option<WakeFn>>>);
+
+impl Waker {
+    fn wake(&self) {
+        if let Ok(w) = self.0.lock() {
+            if let Some(f) = w.as_ref() { f(); }
+        }
+    }
+}
+
+/// 后台线程 → `pump()` 的通道
+#[derive(Clone)]
+struct HostChannel {
+    tx: Sender<HostEvent>,
+    waker: Waker,
+    /// 计时线程的请求端
+    timer_tx: Sender<TimerRequest>,
+}
+
+impl HostChannel {
+    fn send(&self, ev: HostEvent) {
+        let _ = self.tx.send(ev);
+        self.waker.wake();
+    }
+}
+
+/// 计时线程: 按到期时刻排队，到期后发回 `HostEvent::Timer`；所有请求端释放后退出
+fn spawn_timer_thread(tx: Sender<HostEvent>, waker: Waker) -> Sender<TimerRequest> {
+    let (timer_tx, timer_rx) = mpsc::channel::<TimerRequest>();
+    let _ = std::thread::Builder::new()
+        .name("plugin-timer".into())
+        .spawn(move || {
+            let mut queue = TimerQueue::default();
+            loop {
+                let next = match queue.next_deadline() {
+                    Some(at) => timer_rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+                    None => timer_rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                };
+                match next {
+                    Ok(req) => queue.apply(req),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                let due = queue.pop_due(Instant::now());
+                for (plugin, id) in &due {
+                    let _ = tx.send(HostEvent::Timer { plugin: plugin.clone(), id: *id });
+                }
+                if !due.is_empty() { waker.wake(); }
+            }
+        });
+    timer_tx
 }
 
 impl PluginSystem {
     pub fn new() -> anyhow::Result<Self> {
+        let (tx, host_rx) = mpsc::channel();
+        let waker = Waker::default();
+        let timer_tx = spawn_timer_thread(tx.clone(), waker.clone());
         Ok(Self {
             _runtime: Runtime::new()?,
             plugins: Vec::new(),
             authorized: HashSet::new(),
+            net_allow: HashMap::new(),
             plugins_dir: PathBuf::new(),
+            host: HostChannel { tx, waker, timer_tx },
+            host_rx,
+            history: Arc::new(Mutex::new(HistoryBuffer::new(0))),
         })
     }
 
+    /// 共享输入法的上屏历史，须在 `load_dir` 之前调用（已加载的插件仍读旧的空历史）
+    pub fn set_history(&mut self, history: Arc<Mutex<HistoryBuffer>>) {
+        self.history = history;
+    }
+
+    /// 设置唤醒回调: 后台结果到达时调用，回调负责让宿主执行 `pump()`
+    pub fn set_waker(&mut self, wake: WakeFn) {
+        if let Ok(mut w) = self.host.waker.0.lock() {
+            *w = Some(wake);
+        }
+    }
+
+    /// 处理后台线程交回的结果并执行 JS 待处理任务（Promise 回调）
+    pub fn pump(&mut self) {
+        let events: Vec<HostEvent> = self.host_rx.try_iter().collect();
+        for ev in events {
+            match ev {
+                HostEvent::Fetch { plugin, id, result } => {
+                    if let Some(p) = self.plugins.iter().find(|p| p.name == plugin) {
+                        p.settle(id, result);
+                    }
+                }
+                HostEvent::Timer { plugin, id } => {
+                    if let Some(p) = self.plugins.iter().find(|p| p.name == plugin) {
+                        p.fire_timer(id);
+                    }
+                }
+            }
+        }
+        self.run_jobs();
+    }
+
+    fn run_jobs(&self) {
+        loop {
+            match self._runtime.execute_pending_job() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => eprintln!("[Plugin] ✖ 异步任务异常: {:?}", e),
+            }
+        }
+    }
+
     /// 扫描并加载目录中的所有 .js 文件
     pub fn load_dir(&mut self, dir: &Path) {
         self.plugins_dir = dir.to_path_buf();
-        self.authorized = Self::read_authorized(dir);
+        (self.authorized, self.net_allow) = Self::read_authorized(dir);
 
         if !dir.exists() { return; }
 
@@ -104,21 +319,53 @@ impl PluginSystem {
             .to_string();
 
         let code = std::fs::read_to_string(path)?;
+        self.load_source(name, &code)
+    }
+
+    fn load_source(&mut self, name: String, code: &str) -> anyhow::Result<()> {
+        let meta = PluginMeta::parse(code, &name);
+        let missing_meta = meta.missing();
+        if !missing_meta.is_empty() {
+            eprintln!("[Plugin] ⚠ {}.js 缺少元数据: {}（在文件头用 // @{} 声明）",
+                name, missing_meta.join(", "), missing_meta[0]);
+        }
+        let connect: Vec<String> = header_tags(code).into_iter()
+            .filter(|(tag, _)| *tag == "connect")
+            .map(|(_, host)| host.to_ascii_lowercase())
+            .collect();
+        let allow = Rc::new(RefCell::new(self.net_allow.get(&name).cloned().unwrap_or_default()));
+        let missing: Vec<&String> = connect.iter().filter(|h| !allow.borrow().contains(h)).collect();
+        if self.authorized.contains(&name) && !missing.is_empty() {
+            eprintln!("[Plugin] ⚠ {} 声明的网络访问未授权: {:?}（可在 {} 中添加）", name, missing, AUTH_FILE);
+        }
+
         let ctx = Context::full(&self._runtime)?;
-        let pname = name.clone();
+        let timers = Rc::new(RefCell::new(HashSet::new()));
+        let bridge = Rc::new(HostBridge {
+            plugin: name.clone(),
+            allow: Rc::clone(&allow),
+            timers: Rc::clone(&timers),
+            fetches: FetchSlots::default(),
+            host: self.host.clone(),
+            history: Arc::clone(&self.history),
+            next_id: Cell::new(0),
+        });
 
         ctx.with(|ctx| -> rquickjs::Result<()> {
-            inject_globals(ctx.clone(), &pname)?;
+            inject_globals(ctx.clone(), &name, bridge)?;
             ctx.eval::<(), _>(code.as_bytes())?;
             Ok(())
         })?;
 
         // 已授权的插件默认启用
         let enabled = self.authorized.contains(&name);
-        eprintln!("[Plugin] ✅ {}.js  ({})", name,
+        let version = if meta.version.is_empty() { String::new() } else { format!(" v{}", meta.version) };
+        eprintln!("[Plugin] ✅ {}.js  {}{}  ({})", name, meta.name, version,
             if enabled { "已启用" } else { "待授权/已禁用" });
 
-        self.plugins.push(LoadedPlugin { name, ctx, enabled });
+        self.plugins.push(LoadedPlugin {
+            name, ctx, enabled, errors: ErrorCounter::default(), error_disabled: false, meta, connect, allow, timers,
+        });
         Ok(())
     }
 
@@ -129,6 +376,8 @@ impl PluginSystem {
             name: p.name.clone(),
             enabled: p.enabled,
             authorized: self.authorized.contains(&p.name),
+            error_disabled: p.error_disabled,
+            meta: p.meta.clone(),
         }).collect()
     }
 
@@ -138,6 +387,7 @@ impl PluginSystem {
 
     pub fn has_active(&self) -> bool { self.active_count() > 0 }
     pub fn is_loaded(&self) -> bool { !self.plugins.is_empty() }
+    pub fn loaded_count(&self) -> usize { self.plugins.len() }
 
     // ── 启用/禁用切换 ─────────────────────────────────────────
 
@@ -161,12 +411,22 @@ impl PluginSystem {
 
         // 启用前：检查授权
         if !self.authorized.contains(name) {
-            let msg = format!(
-                "插件「{}」将访问您的输入流，读取并可能修改每次输入的候\
-选词。\n\n是否授权该插件？", name
-            );
+            let meta = &self.plugins[idx].meta;
+            let mut msg = fill(tr(Text::PluginAuthName), &[&meta.name]);
+            if !meta.version.is_empty() { msg.push_str(&format!(" v{}", meta.version)); }
+            if !meta.author.is_empty() { msg.push_str(&fill(tr(Text::PluginAuthAuthor), &[&meta.author])); }
+            if !meta.description.is_empty() { msg.push_str(&format!("\n{}", meta.description)); }
+            if !meta.missing().is_empty() {
+                msg.push_str(tr(Text::PluginAuthMissingMeta));
+            }
+            msg.push_str(tr(Text::PluginAuthAccess));
+            let connect = self.plugins[idx].connect.clone();
+            if !connect.is_empty() {
+                msg.push_str(&fill(tr(Text::PluginAuthNetwork), &[&connect.join("\n")]));
+            }
+            msg.push_str(tr(Text::PluginAuthQuestion));
             let msg_w: Vec<u16> = msg.encode_utf16().chain(std::iter::once(0)).collect();
-            let caption_w: Vec<u16> = "AiPinyin 插件授权"
+            let caption_w: Vec<u16> = tr(Text::PluginAuthCaption)
                 .encode_utf16().chain(std::iter::once(0)).collect();
 
             let result = unsafe {
@@ -184,17 +444,19 @@ impl PluginSystem {
             }
 
             self.authorized.insert(name.to_string());
+            if !connect.is_empty() {
+                self.net_allow.insert(name.to_string(), connect.clone());
+                *self.plugins[idx].allow.borrow_mut() = connect;
+            }
             self.write_authorized();
             eprintln!("[Plugin] 🔑 {} 已授权并持久化", name);
         }
 
         // 检查槽位
         if self.active_count() >= MAX_ACTIVE {
-            let msg_w: Vec<u16> = format!(
-                "插件槽位已满（最多 {} 个同时激活）。\n请先禁用一个插件再启用新插件。",
-                MAX_ACTIVE
-            ).encode_utf16().chain(std::iter::once(0)).collect();
-            let cap_w: Vec<u16> = "AiPinyin 插件管理"
+            let msg_w: Vec<u16> = fill(tr(Text::PluginSlotsFull), &[&MAX_ACTIVE.to_string()])
+                .encode_utf16().chain(std::iter::once(0)).collect();
+            let cap_w: Vec<u16> = tr(Text::PluginManageCaption)
                 .encode_utf16().chain(std::iter::once(0)).collect();
 
             unsafe {
@@ -205,7 +467,10 @@ impl PluginSystem {
             return ToggleResult::SlotsFull;
         }
 
-        self.plugins[idx].enabled = true;
+        let p = &mut self.plugins[idx];
+        p.enabled = true;
+        p.errors.succeed();
+        p.error_disabled = false;
         eprintln!("[Plugin] ▶ {} 已启用 ({}/{}活跃)",
             name, self.active_count(), MAX_ACTIVE);
         ToggleResult::Enabled
@@ -214,26 +479,63 @@ impl PluginSystem {
     // ── 候选词处理 ────────────────────────────────────────────
 
     /// 依次通过所有已启用的插件处理候选词（流水线）
-    pub fn transform_candidates(&self, raw: &str, mut cands: Vec<String>) -> Vec<String> {
-        for p in self.plugins.iter().filter(|p| p.enabled) {
-            cands = p.call_on_candidates(raw, cands);
+    ///
+    /// 出错的插件本次跳过（保留输入的候选），连续出错过多则自动禁用
+    pub fn transform_candidates(&mut self, raw: &str, mut cands: Vec<String>, app: &str) -> Vec<String> {
+        for p in self.plugins.iter_mut().filter(|p| p.enabled) {
+            match p.call_on_candidates(raw, &cands, app) {
+                Ok(out) => {
+                    p.errors.succeed();
+                    cands = out;
+                }
+                Err(e) => p.record_error("on_candidates", &e),
+            }
         }
+        self.run_jobs();
         cands
     }
 
+    /// 上屏前依次交给已启用插件的 `on_commit` 改写；返回 None 表示有插件取消了这次上屏
+    ///
+    /// 出错或替换过长的插件本次跳过（保留输入的文字），出错计数同 `transform_candidates`
+    pub fn transform_commit(&mut self, raw: &str, text: &str, app: &str) -> Option<String> {
+        let mut text = text.to_string();
+        for p in self.plugins.iter_mut().filter(|p| p.enabled) {
+            match p.call_on_commit(raw, &text, app) {
+                Ok(out) => {
+                    p.errors.succeed();
+                    match out {
+                        CommitEdit::Keep => {}
+                        CommitEdit::Replace(s) => text = s,
+                        CommitEdit::Veto => {
+                            eprintln!("[Plugin] {} 取消上屏: {:?}", p.name, text);
+                            self.run_jobs();
+                            return None;
+                        }
+                    }
+                }
+                Err(e) => p.record_error("on_commit", &e),
+            }
+        }
+        self.run_jobs();
+        Some(text)
+    }
+
     // ── 授权持久化 ────────────────────────────────────────────
 
-    fn read_authorized(dir: &Path) -> HashSet<String> {
-        std::fs::read_to_string(dir.join(AUTH_FILE))
-            .unwrap_or_default()
-            .lines()
-            .map(|l| l.trim().to_string())
-            .filter(|l| !l.is_empty() && !l.starts_with('#'))
-            .collect()
+    /// 每行 `名称` 或 `名称: 主机 主机`（冒号后为允许 fetch 的主机）
+    fn read_authorized(dir: &Path) -> (HashSet<String>, HashMap<String, Vec<String>>) {
+        let text = std::fs::read_to_string(dir.join(AUTH_FILE)).unwrap_or_default();
+        parse_authorized(&text)
     }
 
     fn write_authorized(&self) {
-        let mut lines: Vec<&str> = self.authorized.iter().map(|s| s.as_str()).collect();
+        let mut lines: Vec<String> = self.authorized.iter()
+            .map(|name| match self.net_allow.get(name) {
+                Some(hosts) if !hosts.is_empty() => format!("{}: {}", name, hosts.join(" ")),
+                _ => name.clone(),
+            })
+            .collect();
         lines.sort();
         let content = format!("# AiPinyin 已授权插件列表（自动生成）\n{}\n", lines.join("\n"));
         let _ = std::fs::write(self.plugins_dir.join(AUTH_FILE), content);
@@ -244,34 +546,123 @@ impl PluginSystem {
 // LoadedPlugin — JS 执行
 // ============================================================
 
-impl LoadedPlugin {
-    fn call_on_candidates(&self, raw: &str, candidates: Vec<String>) -> Vec<String> {
-        let fallback = candidates.clone();
-        let raw_owned = raw.to_string();
-
-        let result = self.ctx.with(|ctx| -> rquickjs::Result<Vec<String>> {
-            let globals = ctx.globals();
-            let val: Value = globals.get("on_candidates")?;
-            if !val.is_function() { return Ok(candidates); }
-            let func = Function::from_value(val)?;
-
-            let js_arr = rquickjs::Array::new(ctx.clone())?;
-            for (i, c) in candidates.iter().enumerate() {
-                js_arr.set(i, c.as_str())?;
-            }
+/// `on_commit` 的返回
+#[derive(Debug, PartialEq)]
+enum CommitEdit {
+    Keep,
+    Replace(String),
+    Veto,
+}
 
-            let ret: Value = func.call((raw_owned.as_str(), js_arr))?;
+impl LoadedPlugin {
+    /// 钩子出错: 第一次打印，连续出错过多则自动禁用
+    fn record_error(&mut self, hook: &str, e: &str) {
+        if self.errors.fail() == 1 {
+            eprintln!("[Plugin] ⚠ {} {} 出错: {}", self.name, hook, e);
+        }
+        if self.errors.exhausted() {
+            self.enabled = false;
+            self.error_disabled = true;
+            eprintln!("[Plugin] ⏸ {} 连续出错 {} 次，已自动禁用（最近: {}）",
+                self.name, self.errors.count(), e);
+        }
+    }
 
-            if !ret.is_array() { return Ok(candidates); }
-            let arr = rquickjs::Array::from_value(ret)?;
-            let mut out: Vec<String> = Vec::new();
-            for i in 0..arr.len() {
-                if let Ok(s) = arr.get::<String>(i) { out.push(s); }
+    /// 把 fetch 结果交给 JS 端兑现对应的 Promise
+    fn settle(&self, id: u32, result: Result<(u16, String), String>) {
+        let r = self.ctx.with(|ctx| -> rquickjs::Result<()> {
+            let settle: Function = ctx.globals().get(SETTLE_FN)?;
+            match result {
+                Ok((status, text)) => {
+                    let resp = Object::new(ctx.clone())?;
+                    resp.set("status", status)?;
+                    resp.set("text", text)?;
+                    settle.call((id, true, resp))
+                }
+                Err(e) => settle.call((id, false, e)),
             }
-            if out.is_empty() { Ok(candidates) } else { Ok(out) }
         });
+        if let Err(e) = r {
+            eprintln!("[{}] ✖ fetch 回调失败: {}", self.name, e);
+        }
+    }
+
+    /// 计时器到期: 未被 clearTimeout 取消才执行回调
+    fn fire_timer(&self, id: u32) {
+        if !self.timers.borrow_mut().remove(&id) { return; }
+        let r = self.ctx.with(|ctx| -> rquickjs::Result<()> {
+            let fire: Function = ctx.globals().get(FIRE_FN)?;
+            fire.call((id,))
+        });
+        if let Err(e) = r {
+            eprintln!("[{}] ✖ 计时器回调异常: {}", self.name, e);
+        }
+    }
+
+    /// 调用 `on_candidates`；未定义或返回非数组/空数组时原样返回，抛错时返回错误信息
+    fn call_on_candidates(&self, raw: &str, candidates: &[String], app: &str) -> Result<Vec<String>, String> {
+        self.call_hook(|ctx| Self::run_on_candidates(ctx, raw, candidates, app))
+    }
+
+    /// 调用 `on_commit`；未定义时保持原样，抛错或替换过长时返回错误信息
+    fn call_on_commit(&self, raw: &str, text: &str, app: &str) -> Result<CommitEdit, String> {
+        let edit = self.call_hook(|ctx| Self::run_on_commit(ctx, raw, text, app))?;
+        match edit {
+            CommitEdit::Replace(s) if s.chars().count() > MAX_COMMIT_CHARS =>
+                Err(format!("on_commit 返回 {} 字，超过上限 {}", s.chars().count(), MAX_COMMIT_CHARS)),
+            CommitEdit::Replace(s) if s.is_empty() => Ok(CommitEdit::Veto),
+            edit => Ok(edit),
+        }
+    }
+
+    /// 在插件 Context 里执行钩子，JS 异常转为错误信息
+    fn call_hook<T>(&self, f: impl FnOnce(Ctx<'_>) -> rquickjs::Result<T>) -> Result<T, String> {
+        self.ctx.with(|ctx| {
+            let result = f(ctx.clone());
+            result.map_err(|e| match e {
+                rquickjs::Error::Exception => {
+                    let exc = ctx.catch();
+                    exc.as_exception()
+                        .and_then(|x| x.message())
+                        .or_else(|| exc.get::<rquickjs::Coerced<String>>().ok().map(|s| s.0))
+                        .unwrap_or_else(|| e.to_string())
+                }
+                other => other.to_string(),
+            })
+        })
+    }
+
+    fn run_on_candidates(ctx: Ctx<'_>, raw: &str, candidates: &[String], app: &str) -> rquickjs::Result<Vec<String>> {
+        let val: Value = ctx.globals().get("on_candidates")?;
+        if !val.is_function() { return Ok(candidates.to_vec()); }
+        let func = Function::from_value(val)?;
+
+        let js_arr = rquickjs::Array::new(ctx.clone())?;
+        for (i, c) in candidates.iter().enumerate() {
+            js_arr.set(i, c.as_str())?;
+        }
 
-        result.unwrap_or(fallback)
+        let ret: Value = func.call((raw, js_arr, app))?;
+
+        if !ret.is_array() { return Ok(candidates.to_vec()); }
+        let arr = rquickjs::Array::from_value(ret)?;
+        let mut out: Vec<String> = Vec::new();
+        for i in 0..arr.len() {
+            if let Ok(s) = arr.get::<String>(i) { out.push(s); }
+        }
+        if out.is_empty() { Ok(candidates.to_vec()) } else { Ok(out) }
+    }
+
+    fn run_on_commit(ctx: Ctx<'_>, raw: &str, text: &str, app: &str) -> rquickjs::Result<CommitEdit> {
+        let val: Value = ctx.globals().get("on_commit")?;
+        if !val.is_function() { return Ok(CommitEdit::Keep); }
+        let func = Function::from_value(val)?;
+
+        let ret: Value = func.call((raw, text, app))?;
+        if let Some(s) = ret.as_string() {
+            return Ok(CommitEdit::Replace(s.to_string()?));
+        }
+        if ret.as_bool() == Some(false) { Ok(CommitEdit::Veto) } else { Ok(CommitEdit::Keep) }
     }
 }
 
@@ -279,7 +670,7 @@ impl LoadedPlugin {
 // inject_globals — 向沙箱注入宿主 API
 // ============================================================
 
-fn inject_globals(ctx: Ctx<'_>, plugin_name: &str) -> rquickjs::Result<()> {
+fn inject_globals(ctx: Ctx<'_>, plugin_name: &str, bridge: Rc<HostBridge>) -> rquickjs::Result<()> {
     let console = Object::new(ctx.clone())?;
 
     let n = plugin_name.to_string();
@@ -298,5 +689,401 @@ fn inject_globals(ctx: Ctx<'_>, plugin_name: &str) -> rquickjs::Result<()> {
     })?)?;
 
     ctx.globals().set("console", console)?;
+
+    let host = Object::new(ctx.clone())?;
+    let b = Rc::clone(&bridge);
+    host.set("recentContext", Function::new(ctx.clone(),
+        move |n: rquickjs::function::Opt<f64>| b.recent_context(n.0.unwrap_or(1.0).max(0.0) as usize))?)?;
+    ctx.globals().set("host", host)?;
+
+    let b = Rc::clone(&bridge);
+    ctx.globals().set("__host_fetch", Function::new(ctx.clone(),
+        move |ctx: Ctx<'_>, url: String, opts: String| b.start_fetch(&ctx, url, &opts))?)?;
+    let b = Rc::clone(&bridge);
+    ctx.globals().set("__host_set_timer", Function::new(ctx.clone(),
+        move |ctx: Ctx<'_>, ms: f64| b.start_timer(&ctx, ms))?)?;
+    let b = bridge;
+    ctx.globals().set("__host_clear_timer", Function::new(ctx.clone(),
+        move |id: u32| b.clear_timer(id))?)?;
+    ctx.eval::<(), _>(HOST_PRELUDE)?;
     Ok(())
 }
+
+/// JS 端兑现 Promise 的函数名（由 HOST_PRELUDE 定义，宿主在 pump 时调用）
+const SETTLE_FN: &str = "__host_settle";
+/// JS 端执行到期计时器的函数名
+const FIRE_FN: &str = "__host_fire";
+
+/// 在原生函数之上包装出 Promise 形式的 fetch 和 setTimeout/clearTimeout；
+/// 待兑现的 resolve/reject 与计时器回调保存在 JS 闭包里
+const HOST_PRELUDE: &str = r#"
+(() => {
+    const hostFetch = globalThis.__host_fetch;
+    const hostSetTimer = globalThis.__host_set_timer;
+    const hostClearTimer = globalThis.__host_clear_timer;
+    delete globalThis.__host_fetch;
+    delete globalThis.__host_set_timer;
+    delete globalThis.__host_clear_timer;
+
+    const pending = new Map();
+    globalThis.fetch = (url, opts) => new Promise((resolve, reject) => {
+        const id = hostFetch(String(url), JSON.stringify(opts || {}));
+        pending.set(id, { resolve, reject });
+    });
+    Object.defineProperty(globalThis, '__host_settle', {
+        value: (id, ok, value) => {
+            const p = pending.get(id);
+            if (!p) return;
+            pending.delete(id);
+            if (ok) p.resolve(value); else p.reject(new Error(value));
+        },
+    });
+
+    const timers = new Map();
+    globalThis.setTimeout = (fn, ms, ...args) => {
+        if (typeof fn !== 'function') throw new TypeError('setTimeout 需要函数参数');
+        const id = hostSetTimer(Number(ms) || 0);
+        timers.set(id, () => fn(...args));
+        return id;
+    };
+    globalThis.clearTimeout = (id) => {
+        if (timers.delete(id)) hostClearTimer(id);
+    };
+    Object.defineProperty(globalThis, '__host_fire', {
+        value: (id) => {
+            const f = timers.get(id);
+            if (!f) return;
+            timers.delete(id);
+            f();
+        },
+    });
+})();
+"#;
+
+// ============================================================
+// 宿主异步 API — fetch / 计时器
+// ============================================================
+
+/// 单个插件的宿主端（fetch 与计时器共用 id 序列）
+struct HostBridge {
+    plugin: String,
+    allow: Rc<RefCell<Vec<String>>>,
+    timers: Rc<RefCell<HashSet<u32>>>,
+    /// 进行中的 fetch（超过 MAX_INFLIGHT_FETCHES 个时新请求直接 reject）
+    fetches: FetchSlots,
+    host: HostChannel,
+    history: Arc<Mutex<HistoryBuffer>>,
+    next_id: Cell<u32>,
+}
+
+impl HostBridge {
+    /// 最近 n 条上屏文字（旧→新），n 截断到 MAX_RECENT_CONTEXT
+    fn recent_context(&self, n: usize) -> Vec<String> {
+        let n = n.min(MAX_RECENT_CONTEXT);
+        self.history.lock()
+            .map(|h| h.recent(n).into_iter().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn next_id(&self) -> u32 {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        id
+    }
+
+    /// 登记计时器并交给计时线程，返回 id；超过 MAX_TIMERS 个未触发时抛出异常
+    fn start_timer(&self, ctx: &Ctx<'_>, ms: f64) -> rquickjs::Result<u32> {
+        if self.timers.borrow().len() >= MAX_TIMERS {
+            return Err(Exception::throw_message(ctx,
+                &format!("setTimeout: 未触发的计时器已达上限 {}", MAX_TIMERS)));
+        }
+        let ms = if ms.is_finite() { (ms.max(0.0) as u64).max(MIN_TIMER_MS) } else { MIN_TIMER_MS };
+        let id = self.next_id();
+        self.timers.borrow_mut().insert(id);
+        let at = Instant::now() + Duration::from_millis(ms);
+        let _ = self.host.timer_tx.send(TimerRequest::Set((at, self.plugin.clone(), id)));
+        Ok(id)
+    }
+
+    /// clearTimeout: 注销并让计时线程移出队列
+    fn clear_timer(&self, id: u32) {
+        if self.timers.borrow_mut().remove(&id) {
+            let _ = self.host.timer_tx.send(TimerRequest::Cancel(self.plugin.clone(), id));
+        }
+    }
+
+    /// 校验主机后在后台线程发起请求，返回请求 id；未授权时抛出异常（Promise 随之 reject）
+    fn start_fetch(&self, ctx: &Ctx<'_>, url: String, opts: &str) -> rquickjs::Result<u32> {
+        let Some(host) = url_host(&url) else {
+            return Err(Exception::throw_message(ctx, "fetch: 只支持 http/https 地址"));
+        };
+        if !self.allow.borrow().contains(&host) {
+            return Err(Exception::throw_message(ctx,
+                &format!("fetch: 主机 {} 未授权（需在插件头部 // @connect 声明并授权）", host)));
+        }
+        let Some(slot) = self.fetches.acquire() else {
+            return Err(Exception::throw_message(ctx,
+                &format!("fetch: 同时进行的请求已达上限 {}", MAX_INFLIGHT_FETCHES)));
+        };
+
+        let id = self.next_id();
+        let opts: serde_json::Value = serde_json::from_str(opts).unwrap_or_default();
+        let (plugin, host_ch) = (self.plugin.clone(), self.host.clone());
+        let _ = std::thread::Builder::new()
+            .name("plugin-fetch".into())
+            .spawn(move || {
+                let result = http_request(&url, &opts);
+                drop(slot);
+                host_ch.send(HostEvent::Fetch { plugin, id, result });
+            });
+        Ok(id)
+    }
+}
+
+/// 发起请求（不跟随重定向，避免跳到未授权主机；忽略 `Host` 等禁用头），HTTP 错误码也作为正常响应返回
+fn http_request(url: &str, opts: &serde_json::Value) -> Result<(u16, String), String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(FETCH_TIMEOUT)
+        .redirects(0)
+        .build();
+    let method = opts["method"].as_str().unwrap_or("GET").to_ascii_uppercase();
+    let mut req = agent.request(&method, url);
+    if let Some(headers) = opts["headers"].as_object() {
+        for (k, v) in headers {
+            match v.as_str() {
+                Some(v) if is_allowed_header(k) => req = req.set(k, v),
+                _ => {}
+            }
+        }
+    }
+    let resp = match opts["body"].as_str() {
+        Some(body) => req.send_string(body),
+        None => req.call(),
+    };
+    let resp = match resp {
+        Ok(r) | Err(ureq::Error::Status(_, r)) => r,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let status = resp.status();
+    let mut buf = Vec::new();
+    resp.into_reader()
+        .take(FETCH_MAX_BYTES + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| e.to_string())?;
+    if buf.len() as u64 > FETCH_MAX_BYTES {
+        return Err(format!("响应超过 {} KB", FETCH_MAX_BYTES / 1024));
+    }
+    Ok((status, String::from_utf8_lossy(&buf).into_owned()))
+}
+
+// ============================================================
+// 文件头标签与授权文件解析
+// ============================================================
+
+/// 解析插件开头连续注释行中的 `// @标签 值`
+fn header_tags(code: &str) -> Vec<(&str, &str)> {
+    code.lines()
+        .map(str::trim)
+        .take_while(|l| l.is_empty() || l.starts_with("//"))
+        .filter_map(|l| l.trim_start_matches('/').trim().strip_prefix('@'))
+        .filter_map(|l| {
+            let (tag, value) = l.split_once(char::is_whitespace)?;
+            Some((tag, value.trim()))
+        })
+        .collect()
+}
+
+fn parse_authorized(text: &str) -> (HashSet<String>, HashMap<String, Vec<String>>) {
+    let mut names = HashSet::new();
+    let mut hosts = HashMap::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') { continue; }
+        match line.split_once(':') {
+            Some((name, list)) => {
+                let name = name.trim().to_string();
+                hosts.insert(name.clone(),
+                    list.split_whitespace().map(|h| h.to_ascii_lowercase()).collect());
+                names.insert(name);
+            }
+            None => { names.insert(line.to_string()); }
+        }
+    }
+    (names, hosts)
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_host::MAX_CONSECUTIVE_ERRORS;
+
+    #[test]
+    fn test_header_tags_and_authorized_file() {
+        let code = "// @connect api.example.com\n//   @connect dict.example.org\n\nfunction f() {}\n// @connect late.com\n";
+        assert_eq!(header_tags(code), vec![("connect", "api.example.com"), ("connect", "dict.example.org")]);
+
+        let (names, hosts) = parse_authorized("# 注释\ntranslate: api.example.com DICT.example.org\nupper\n");
+        assert!(names.contains("translate") && names.contains("upper"));
+        assert_eq!(hosts["translate"], vec!["api.example.com", "dict.example.org"]);
+        assert!(!hosts.contains_key("upper"));
+    }
+
+    #[test]
+    fn test_plugin_meta() {
+        let code = "// @name 快捷词\n// @version 1.2.0\n// @author tang\n// @name 重复\n\nfunction on_candidates(r, c) { return c; }\n";
+        let meta = PluginMeta::parse(code, "shortcuts");
+        assert_eq!(meta.name, "快捷词");
+        assert_eq!(meta.version, "1.2.0");
+        assert_eq!(meta.author, "tang");
+        assert_eq!(meta.missing(), vec!["description"]);
+
+        // 无文件头: 名称回退为文件名
+        let bare = PluginMeta::parse("function f() {}", "upper");
+        assert_eq!(bare.name, "upper");
+        assert_eq!(bare.missing(), vec!["version", "description", "author"]);
+    }
+
+    #[test]
+    fn test_auto_disable_after_errors() {
+        let mut sys = PluginSystem::new().unwrap();
+        sys.load_source("flaky".into(), r#"
+            globalThis.calls = 0;
+            function on_candidates(raw, c) {
+                calls++;
+                if (raw === "bad") throw new Error("boom");
+                return ["插件"].concat(c);
+            }
+        "#).unwrap();
+        sys.plugins[0].enabled = true;
+        let base = vec!["a".to_string()];
+
+        // 偶发错误: 成功一次即清零
+        for _ in 0..MAX_CONSECUTIVE_ERRORS - 1 {
+            assert_eq!(sys.transform_candidates("bad", base.clone(), ""), base);
+        }
+        assert_eq!(sys.transform_candidates("ok", base.clone(), ""), vec!["插件", "a"]);
+        assert_eq!(sys.plugins[0].errors.count(), 0);
+
+        for _ in 0..MAX_CONSECUTIVE_ERRORS {
+            sys.transform_candidates("bad", base.clone(), "");
+        }
+        let info = &sys.plugin_list()[0];
+        assert!(!info.enabled && info.error_disabled);
+
+        // 已禁用: 不再调用 JS
+        assert_eq!(sys.transform_candidates("ok", base.clone(), ""), base);
+        let calls: u32 = sys.plugins[0].ctx.with(|ctx| ctx.globals().get("calls")).unwrap();
+        assert_eq!(calls, 2 * MAX_CONSECUTIVE_ERRORS);
+    }
+
+    #[test]
+    fn test_on_candidates_receives_app() {
+        let mut sys = PluginSystem::new().unwrap();
+        sys.load_source("editor".into(), r#"
+            function on_candidates(raw, c, app) {
+                return app === "Code.exe" ? [raw].concat(c) : c;
+            }
+        "#).unwrap();
+        sys.plugins[0].enabled = true;
+        let base = vec!["好".to_string()];
+        assert_eq!(sys.transform_candidates("hao", base.clone(), "Code.exe"), vec!["hao", "好"]);
+        assert_eq!(sys.transform_candidates("hao", base.clone(), "notepad.exe"), base);
+    }
+
+    #[test]
+    fn test_on_commit_transform_and_veto() {
+        let mut sys = PluginSystem::new().unwrap();
+        sys.load_source("expand".into(), r#"
+            const table = { brb: "be right back", long: "啊".repeat(5000) };
+            function on_commit(raw, text, app) {
+                if (text === "禁止") return false;
+                if (text === "boom") throw new Error("boom");
+                return table[text] ?? null;
+            }
+        "#).unwrap();
+        sys.load_source("punct".into(), r#"
+            function on_commit(raw, text) { return text.endsWith("back") ? text + "!" : undefined; }
+        "#).unwrap();
+        for p in &mut sys.plugins { p.enabled = true; }
+
+        // 依次改写
+        assert_eq!(sys.transform_commit("brb", "brb", "").as_deref(), Some("be right back!"));
+        assert_eq!(sys.transform_commit("nihao", "你好", "").as_deref(), Some("你好"));
+        // 取消上屏
+        assert_eq!(sys.transform_commit("jinzhi", "禁止", ""), None);
+        // 出错 / 展开过长: 保持原文并计入出错次数
+        assert_eq!(sys.transform_commit("boom", "boom", "").as_deref(), Some("boom"));
+        assert_eq!(sys.transform_commit("long", "long", "").as_deref(), Some("long"));
+        assert_eq!(sys.plugins[0].errors.count(), 2);
+        // 没有 on_commit 的插件不受影响
+        sys.load_source("plain".into(), "function on_candidates(r, c) { return c; }").unwrap();
+        sys.plugins[2].enabled = true;
+        assert_eq!(sys.transform_commit("nihao", "你好", "").as_deref(), Some("你好"));
+        assert_eq!(sys.plugins[0].errors.count(), 0);
+    }
+
+    #[test]
+    fn test_recent_context() {
+        let history = Arc::new(Mutex::new(HistoryBuffer::new(100)));
+        let mut sys = PluginSystem::new().unwrap();
+        sys.set_history(Arc::clone(&history));
+        sys.load_source("ctx".into(), r#"
+            function on_candidates(raw, c) { return host.recentContext(2).concat(c); }
+            globalThis.all = () => host.recentContext(1000).length;
+        "#).unwrap();
+        sys.plugins[0].enabled = true;
+
+        assert_eq!(sys.transform_candidates("a", vec!["x".into()], ""), vec!["x"]);
+        for i in 0..30 {
+            history.lock().unwrap().push(&format!("词{}", i));
+        }
+        assert_eq!(sys.transform_candidates("a", vec!["x".into()], ""), vec!["词28", "词29", "x"]);
+        let all: usize = sys.plugins[0].ctx.with(|ctx| {
+            ctx.globals().get::<_, Function>("all").unwrap().call(()).unwrap()
+        });
+        assert_eq!(all, MAX_RECENT_CONTEXT);
+    }
+
+    #[test]
+    fn test_fetch_rejects_unlisted_host() {
+        let mut sys = PluginSystem::new().unwrap();
+        sys.load_source("net".into(), r#"
+            // @connect api.example.com
+            globalThis.result = "pending";
+            fetch("https://other.example.com/").then(
+                () => { globalThis.result = "resolved"; },
+                (e) => { globalThis.result = e.message; });
+        "#).unwrap();
+        sys.pump();
+        let result: String = sys.plugins[0].ctx.with(|ctx| ctx.globals().get("result")).unwrap();
+        assert!(result.contains("other.example.com"), "{}", result);
+    }
+
+    #[test]
+    fn test_timers_fire_and_clear() {
+        let mut sys = PluginSystem::new().unwrap();
+        sys.load_source("timer".into(), r#"
+            globalThis.log = [];
+            setTimeout((x) => log.push(x), 0, "a");
+            const id = setTimeout(() => log.push("cleared"), 5);
+            clearTimeout(id);
+            globalThis.overflow = "";
+            try { for (let i = 0; i < 20; i++) setTimeout(() => {}, 60000); }
+            catch (e) { overflow = e.message; }
+        "#).unwrap();
+
+        std::thread::sleep(Duration::from_millis(MIN_TIMER_MS * 5));
+        sys.pump();
+        let (log, overflow): (Vec<String>, String) = sys.plugins[0].ctx.with(|ctx| {
+            let g = ctx.globals();
+            (g.get("log").unwrap(), g.get("overflow").unwrap())
+        });
+        assert_eq!(log, vec!["a"]);
+        assert!(overflow.contains("上限"), "{}", overflow);
+    }
+
+}
@@ -0,0 +1,101 @@
+//! # 中文标点配对
+//!
+//! 成对标点的开合状态: `"` 交替输出 “ ”，`'` 交替输出 ‘ ’，
+//! `<` `>` 对应 《 》，`[` `]` 对应 【 】 并记录未闭合层数。
+//! 状态跟随一次输入会话，前台窗口切换时重置。
+
+/// 成对标点的开合状态
+#[derive(Debug, Default, Clone)]
+pub struct PunctState {
+    /// 已输出 “ 尚未闭合
+    double_open: bool,
+    /// 已输出 ‘ 尚未闭合
+    single_open: bool,
+    /// 未闭合的 《 层数
+    book_depth: u32,
+    /// 未闭合的 【 层数
+    bracket_depth: u32,
+}
+
+impl PunctState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 清空配对状态（切换窗口后从"开"重新开始）
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// 处理一个成对出现的 ASCII 标点，返回对应的中文标点；非成对标点返回 None
+    pub fn pair(&mut self, ch: char) -> Option<char> {
+        match ch {
+            '"' => {
+                self.double_open = !self.double_open;
+                Some(if self.double_open { '“' } else { '”' })
+            }
+            '\'' => {
+                self.single_open = !self.single_open;
+                Some(if self.single_open { '‘' } else { '’' })
+            }
+            '<' => {
+                self.book_depth += 1;
+                Some('《')
+            }
+            '>' => {
+                self.book_depth = self.book_depth.saturating_sub(1);
+                Some('》')
+            }
+            '[' => {
+                self.bracket_depth += 1;
+                Some('【')
+            }
+            ']' => {
+                self.bracket_depth = self.bracket_depth.saturating_sub(1);
+                Some('】')
+            }
+            _ => None,
+        }
+    }
+
+    /// 是否还有未闭合的成对标点
+    pub fn has_open(&self) -> bool {
+        self.double_open || self.single_open || self.book_depth > 0 || self.bracket_depth > 0
+    }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(state: &mut PunctState, keys: &str) -> String {
+        keys.chars().map(|c| state.pair(c).unwrap_or(c)).collect()
+    }
+
+    #[test]
+    fn test_quotes_alternate() {
+        let mut p = PunctState::new();
+        assert_eq!(typed(&mut p, "\"a\"\"b\""), "“a”“b”");
+        assert_eq!(typed(&mut p, "'x'"), "‘x’");
+        assert!(!p.has_open());
+    }
+
+    #[test]
+    fn test_pairs_and_reset() {
+        let mut p = PunctState::new();
+        assert_eq!(typed(&mut p, "<[\""), "《【“");
+        assert!(p.has_open());
+        assert_eq!(typed(&mut p, "]>"), "】》");
+        assert!(p.has_open());
+
+        // 换窗口后重新从开引号开始
+        p.reset();
+        assert!(!p.has_open());
+        assert_eq!(typed(&mut p, "\""), "“");
+        assert_eq!(p.pair('a'), None);
+    }
+}
@@ -1,9 +1,10 @@
 //! # 设置窗口 (WebView2)
 //!
 //! 使用 wry + tao 创建 WebView2 窗口，加载 settings.html。
-//! 配置数据在加载时注入 HTML，IPC 仅用于 save/toggle/delete。
+//! 配置数据在加载时注入 HTML，IPC 用于 save/toggle/delete 与 AI 接口连通性测试。
 
 use std::path::PathBuf;
+use crate::plugin_system::PluginMeta;
 
 /// 获取 exe 所在目录
 fn exe_dir() -> PathBuf {
@@ -34,19 +35,33 @@ pub fn load_config_json() -> String {
         .and_then(|v| v.as_array())
         .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
         .unwrap_or_default();
+    let ai_str = |key: &str| -> String {
+        config.get("ai").and_then(|a| a.get(key))
+            .and_then(|v| v.as_str()).unwrap_or("").to_string()
+    };
+    // 用户输入的自由文本，经 serde_json 转义后再拼进 JSON
+    let to_json = |s: &str| serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_string());
+    let endpoint = to_json(&ai_str("endpoint"));
+    let api_key = to_json(&mask_api_key(&ai_str("api_key")));
+    let system_prompt = to_json(&ai_str("system_prompt"));
+    let shuangpin = to_json(config.get("engine").and_then(|e| e.get("shuangpin"))
+        .and_then(|v| v.as_str()).unwrap_or("none"));
+    let fuzzy: serde_json::Map<String, serde_json::Value> = crate::config::FuzzyConfig::KEYS.iter()
+        .map(|k| {
+            let on = config.get("fuzzy").and_then(|f| f.get(*k))
+                .and_then(|v| v.as_bool()).unwrap_or(false);
+            (k.to_string(), serde_json::Value::Bool(on))
+        })
+        .collect();
+    let fuzzy = serde_json::Value::Object(fuzzy).to_string();
+    // 以注册表实际状态为准（用户可能在任务管理器里关掉了启动项）
+    let autostart = crate::autostart::is_enabled();
 
     // 读 style.css → 解析 CSS 变量
     let style_path = dir.join("style.css");
     let css = std::fs::read_to_string(&style_path).unwrap_or_default();
     let parse_css_var = |name: &str, default: &str| -> String {
-        css.lines()
-            .find(|line| line.contains(name))
-            .and_then(|line| {
-                let start = line.find(':')?;
-                let end = line.find(';')?;
-                Some(line[start+1..end].trim().to_string())
-            })
-            .unwrap_or_else(|| default.to_string())
+        css_var(&css, name).unwrap_or_else(|| default.to_string())
     };
 
     let bg_color = parse_css_var("--bg-color", "#2E313E");
@@ -55,9 +70,25 @@ pub fn load_config_json() -> String {
     let index_color = parse_css_var("--index-color", "#82869C");
     let highlight_bg = parse_css_var("--highlight-bg", "#7AA2F7");
     let highlight_text = parse_css_var("--highlight-text", "#FFFFFF");
-    let font_size = parse_css_var("--font-size", "20px");
-    let pinyin_size = parse_css_var("--pinyin-size", "20px");
-    let corner_radius = parse_css_var("--corner-radius", "14px");
+    // 尺寸统一换算成 "Npx"（设置页按整数读取），不支持的单位给出提示后用默认值
+    let dpi = screen_dpi();
+    let size_var = |name: &str, default_px: u32| -> String {
+        let px = css_var(&css, name).and_then(|v| {
+            let px = parse_px(&v, dpi);
+            if px.is_none() {
+                eprintln!("[Settings] ⚠ style.css {}: {} 无法识别（支持 px / pt / 纯数字），使用默认 {}px",
+                    name, v, default_px);
+            }
+            px
+        });
+        format!("{}px", px.unwrap_or(default_px))
+    };
+    let font_size = size_var("--font-size", 20);
+    let pinyin_size = size_var("--pinyin-size", 20);
+    let corner_radius = size_var("--corner-radius", 14);
+    let border_color = parse_css_var("--border-color", "transparent");
+    let border_width = size_var("--border-width", 0);
+    let shadow = parse_css_var("--shadow", "on");
 
     // 读 plugins/
     let plugins_dir = dir.join("plugins");
@@ -68,8 +99,18 @@ pub fn load_config_json() -> String {
                 .filter(|e| e.path().extension().map(|ext| ext == "js").unwrap_or(false))
                 .map(|e| {
                     let name = e.file_name().to_string_lossy().to_string();
-                    let enabled = authorized.lines().any(|l| l.trim() == name);
-                    format!(r#"{{"name":"{}","enabled":{}}}"#, name, enabled)
+                    let enabled = authorized.lines().any(|l| auth_entry_matches(l, &name));
+                    let code = std::fs::read_to_string(e.path()).unwrap_or_default();
+                    let meta = PluginMeta::parse(&code, plugin_stem(&name));
+                    serde_json::json!({
+                        "name": name,
+                        "enabled": enabled,
+                        "title": meta.name,
+                        "version": meta.version,
+                        "description": meta.description,
+                        "author": meta.author,
+                        "missing_meta": !meta.missing().is_empty(),
+                    }).to_string()
                 })
                 .collect())
             .unwrap_or_default()
@@ -83,7 +124,13 @@ pub fn load_config_json() -> String {
     "top_k": {},
     "rerank": {},
     "opacity": {},
-    "extra": [{}]
+    "extra": [{}],
+    "endpoint": {},
+    "api_key": {},
+    "system_prompt": {},
+    "shuangpin": {},
+    "fuzzy": {},
+    "autostart": {}
   }},
   "style": {{
     "bg_color": "{}",
@@ -94,51 +141,117 @@ pub fn load_config_json() -> String {
     "highlight_text": "{}",
     "font_size": "{}",
     "pinyin_size": "{}",
-    "corner_radius": "{}"
+    "corner_radius": "{}",
+    "border_color": "{}",
+    "border_width": "{}",
+    "shadow": "{}"
   }},
   "plugins": [{}]
 }}"#,
         engine_mode, top_k, rerank, opacity, extra_json.join(","),
+        endpoint, api_key, system_prompt, shuangpin, fuzzy, autostart,
         bg_color, text_color, pinyin_color, index_color,
         highlight_bg, highlight_text, font_size, pinyin_size, corner_radius,
+        border_color, border_width, shadow,
         plugins.join(","))
 }
 
 /// 保存 config.toml
+///
+/// 只改写设置界面管理的键，其余配置段（guardian / keys / log 等）原样保留。
 pub fn save_config(data: &serde_json::Value) {
-    let dir = exe_dir();
-    let config = &data["config"];
-
-    let engine_mode = config["engine_mode"].as_str().unwrap_or("ai");
-    let top_k = config["top_k"].as_i64().unwrap_or(5);
-    let rerank = config["rerank"].as_bool().unwrap_or(true);
-    let opacity = config["opacity"].as_i64().unwrap_or(240);
-    let extra: Vec<&str> = config["extra"].as_array()
-        .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+    let path = exe_dir().join("config.toml");
+    let old_text = std::fs::read_to_string(&path).unwrap_or_default();
+    let toml_content = merge_config(&old_text, &data["config"]);
+
+    match std::fs::write(&path, toml_content) {
+        Ok(()) => eprintln!("[Settings] ✅ config.toml 已保存"),
+        Err(e) => eprintln!("[Settings] ❌ config.toml 保存失败: {}", e),
+    }
+}
+
+/// 把表单值合并进现有 config.toml 文本
+fn merge_config(old_text: &str, config: &serde_json::Value) -> String {
+    let mut root: toml::value::Table = old_text.parse::<toml::Value>().ok()
+        .and_then(|v| v.as_table().cloned())
         .unwrap_or_default();
-    let extra_str: Vec<String> = extra.iter().map(|s| format!("\"{}\"", s)).collect();
 
-    let toml_content = format!(
-r#"# AiPinyin 配置文件
-# 放置于 aipinyin.exe 同目录
+    let old_key = root.get("ai").and_then(|a| a.get("api_key"))
+        .and_then(|v| v.as_str()).unwrap_or("").to_string();
+    // 表单回传的仍是掩码时说明用户没改，保留原 key
+    let api_key = match config["api_key"].as_str() {
+        Some(k) if k == mask_api_key(&old_key) => old_key,
+        Some(k) => k.trim().to_string(),
+        None => old_key,
+    };
+    let extra: Vec<toml::Value> = config["extra"].as_array()
+        .map(|arr| arr.iter().filter_map(|v| v.as_str())
+            .map(|s| toml::Value::String(s.to_string())).collect())
+        .unwrap_or_default();
 
-[engine]
-mode = "{}"
+    let mut set = |section: &str, key: &str, value: toml::Value| {
+        let table = root.entry(section.to_string())
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+        if let Some(t) = table.as_table_mut() {
+            t.insert(key.to_string(), value);
+        }
+    };
+    set("engine", "mode", toml::Value::String(config["engine_mode"].as_str().unwrap_or("ai").to_string()));
+    set("engine", "shuangpin", toml::Value::String(config["shuangpin"].as_str().unwrap_or("none").to_string()));
+    for key in crate::config::FuzzyConfig::KEYS {
+        set("fuzzy", key, toml::Value::Boolean(config["fuzzy"][key].as_bool().unwrap_or(false)));
+    }
+    set("ai", "top_k", toml::Value::Integer(config["top_k"].as_i64().unwrap_or(5)));
+    set("ai", "rerank", toml::Value::Boolean(config["rerank"].as_bool().unwrap_or(true)));
+    set("ai", "endpoint", toml::Value::String(config["endpoint"].as_str().unwrap_or("").trim().to_string()));
+    set("ai", "api_key", toml::Value::String(api_key));
+    set("ai", "system_prompt", toml::Value::String(config["system_prompt"].as_str().unwrap_or("").to_string()));
+    set("ui", "opacity", toml::Value::Integer(config["opacity"].as_i64().unwrap_or(240)));
+    set("dict", "extra", toml::Value::Array(extra));
 
-[ai]
-top_k = {}
-rerank = {}
+    let body = toml::to_string(&toml::Value::Table(root)).unwrap_or_default();
+    format!("# AiPinyin 配置文件\n# 放置于 aipinyin.exe 同目录\n\n{}", body)
+}
 
-[ui]
-font_size = {}
-opacity = {}
+/// API Key 掩码：只露出首尾各 3 个字符，短 key 全部遮住
+pub fn mask_api_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.is_empty() {
+        String::new()
+    } else if chars.len() <= 8 {
+        "••••".to_string()
+    } else {
+        let head: String = chars[..3].iter().collect();
+        let tail: String = chars[chars.len() - 3..].iter().collect();
+        format!("{}••••{}", head, tail)
+    }
+}
 
-[dict]
-extra = [{}]
-"#, engine_mode, top_k, rerank, top_k, opacity, extra_str.join(", "));
+/// 测试外部 AI 接口连通性：GET `{endpoint}/models`
+///
+/// `api_key` 为掩码时使用 config.toml 中已保存的 key。阻塞调用，需在后台线程执行。
+pub fn test_ai_connection(endpoint: &str, api_key: &str) -> Result<String, String> {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    if endpoint.is_empty() {
+        return Err("接口地址为空（留空即使用本地模型）".to_string());
+    }
+    let saved = crate::config::Config::load().ai.api_key;
+    let key = if api_key == mask_api_key(&saved) { saved } else { api_key.trim().to_string() };
 
-    let _ = std::fs::write(dir.join("config.toml"), toml_content);
-    eprintln!("[Settings] ✅ config.toml 已保存");
+    let url = format!("{}/models", endpoint);
+    let mut req = ureq::get(&url).timeout(std::time::Duration::from_secs(5));
+    if !key.is_empty() {
+        req = req.set("Authorization", &format!("Bearer {}", key));
+    }
+    match req.call() {
+        Ok(resp) => {
+            let body: serde_json::Value = resp.into_json().unwrap_or_default();
+            let models = body["data"].as_array().map(|a| a.len()).unwrap_or(0);
+            Ok(format!("连接成功，可用模型 {} 个", models))
+        }
+        Err(ureq::Error::Status(code, _)) => Err(format!("HTTP {}（检查地址与 API Key）", code)),
+        Err(e) => Err(format!("连接失败: {}", e)),
+    }
 }
 
 /// 保存 style.css
@@ -163,6 +276,9 @@ r#"/* AiPinyin 候选词窗口样式表
     --font-size: {};
     --pinyin-size: {};
     --corner-radius: {};
+    --border-color: {};
+    --border-width: {};
+    --shadow: {};
     --padding-h: 14px;
 }}
 "#,
@@ -174,12 +290,62 @@ r#"/* AiPinyin 候选词窗口样式表
         s["highlight_text"].as_str().unwrap_or("#FFFFFF"),
         s["font_size"].as_str().unwrap_or("20px"),
         s["pinyin_size"].as_str().unwrap_or("20px"),
-        s["corner_radius"].as_str().unwrap_or("14px"));
+        s["corner_radius"].as_str().unwrap_or("14px"),
+        s["border_color"].as_str().unwrap_or("transparent"),
+        s["border_width"].as_str().unwrap_or("0px"),
+        s["shadow"].as_str().unwrap_or("on"));
 
     let _ = std::fs::write(dir.join("style.css"), css);
     eprintln!("[Settings] ✅ style.css 已保存");
 }
 
+/// 取 CSS 变量的值: `--name: value;`（按行查找，取第一处）
+fn css_var(css: &str, name: &str) -> Option<String> {
+    css.lines()
+        .find(|line| line.contains(name))
+        .and_then(|line| {
+            let start = line.find(':')?;
+            let end = line.find(';')?;
+            Some(line[start+1..end].trim().to_string())
+        })
+}
+
+/// CSS 尺寸 → 像素: "18px"、无单位的 "18"（按 px）、"12pt"（按 `dpi` 换算）；
+/// em / rem / % 等依赖上下文的单位返回 None
+fn parse_px(value: &str, dpi: u32) -> Option<u32> {
+    let value = value.trim().to_ascii_lowercase();
+    let (num, per_unit) = if let Some(n) = value.strip_suffix("px") {
+        (n, 1.0)
+    } else if let Some(n) = value.strip_suffix("pt") {
+        (n, dpi as f32 / 72.0)
+    } else {
+        (value.as_str(), 1.0)
+    };
+    let n: f32 = num.trim().parse().ok()?;
+    (n.is_finite() && n >= 0.0).then(|| (n * per_unit).round() as u32)
+}
+
+/// 屏幕 DPI（pt 换算用，取不到按 96）
+fn screen_dpi() -> u32 {
+    use windows::Win32::Graphics::Gdi::{GetDC, GetDeviceCaps, ReleaseDC, LOGPIXELSY};
+    unsafe {
+        let hdc = GetDC(None);
+        let dpi = GetDeviceCaps(hdc, LOGPIXELSY);
+        ReleaseDC(None, hdc);
+        if dpi > 0 { dpi as u32 } else { 96 }
+    }
+}
+
+/// 候选窗是否保留系统阴影（style.css 中 `--shadow: none` / `off` 关闭）
+pub fn window_shadow() -> bool {
+    let css = std::fs::read_to_string(exe_dir().join("style.css")).unwrap_or_default();
+    shadow_enabled(css_var(&css, "--shadow").as_deref())
+}
+
+fn shadow_enabled(value: Option<&str>) -> bool {
+    !matches!(value, Some("none" | "off" | "0"))
+}
+
 /// 删除插件文件
 pub fn delete_plugin(name: &str) {
     let path = exe_dir().join("plugins").join(name);
@@ -193,17 +359,107 @@ pub fn delete_plugin(name: &str) {
 pub fn toggle_plugin(name: &str, enabled: bool) {
     let dir = exe_dir().join("plugins");
     let auth_path = dir.join(".authorized");
-    let mut lines: Vec<String> = std::fs::read_to_string(&auth_path)
-        .unwrap_or_default()
-        .lines()
+    let text = std::fs::read_to_string(&auth_path).unwrap_or_default();
+    // 已有条目（可能带联网主机）原样保留，避免重新启用时丢失网络授权
+    let existing = text.lines().map(str::trim).find(|l| auth_entry_matches(l, name)).map(str::to_string);
+    let mut lines: Vec<String> = text.lines()
         .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty() && s != name)
+        .filter(|s| !s.is_empty() && !auth_entry_matches(s, name))
         .collect();
     if enabled {
-        lines.push(name.to_string());
+        lines.push(existing.unwrap_or_else(|| plugin_stem(name).to_string()));
     }
     let _ = std::fs::write(&auth_path, lines.join("\n"));
     eprintln!("[Settings] {} 插件: {} = {}", if enabled { "✅" } else { "❌" }, name, enabled);
 }
 
+fn plugin_stem(file_name: &str) -> &str {
+    file_name.strip_suffix(".js").unwrap_or(file_name)
+}
+
+/// `.authorized` 的一行（`名称` 或 `名称: 主机 …`）是否对应该插件文件
+fn auth_entry_matches(line: &str, file_name: &str) -> bool {
+    let entry = line.split(':').next().unwrap_or("").trim();
+    !entry.is_empty() && plugin_stem(entry) == plugin_stem(file_name)
+}
+
 // The separate settings window has been replaced by the unified WebView2 frontend.
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_api_key() {
+        assert_eq!(mask_api_key(""), "");
+        assert_eq!(mask_api_key("short"), "••••");
+        assert_eq!(mask_api_key("sk-abcdef123456"), "sk-••••456");
+    }
+
+    #[test]
+    fn test_auth_entry_matches() {
+        assert!(auth_entry_matches("hello_world", "hello_world.js"));
+        assert!(auth_entry_matches("hello_world.js", "hello_world.js"));
+        assert!(auth_entry_matches("translate: api.example.com", "translate.js"));
+        assert!(!auth_entry_matches("translate_v2", "translate.js"));
+        assert!(!auth_entry_matches("", "translate.js"));
+    }
+
+    #[test]
+    fn test_merge_config_keeps_other_sections() {
+        let old = "[ai]\napi_key = \"sk-abcdef123456\"\nmodel = \"qwen\"\n\n[guardian]\nenabled = false\n";
+        let form = serde_json::json!({
+            "engine_mode": "dict", "top_k": 7, "rerank": false, "opacity": 200,
+            "extra": ["sogou_it"], "endpoint": "http://localhost:11434/v1",
+            "api_key": "sk-••••456", "system_prompt": "say \"hi\"",
+            "shuangpin": "xiaohe", "fuzzy": { "z_zh": true, "in_ing": true },
+        });
+        let cfg: crate::config::Config = toml::from_str(&merge_config(old, &form)).unwrap();
+        assert_eq!(cfg.ai.api_key, "sk-abcdef123456");
+        assert_eq!(cfg.ai.model, "qwen");
+        assert_eq!(cfg.ai.top_k, 7);
+        assert_eq!(cfg.ai.endpoint, "http://localhost:11434/v1");
+        assert_eq!(cfg.ai.system_prompt, "say \"hi\"");
+        assert_eq!(cfg.dict.extra, vec!["sogou_it"]);
+        assert!(!cfg.guardian.enabled);
+        assert_eq!(cfg.engine.shuangpin, crate::config::ShuangpinScheme::Xiaohe);
+        assert!(cfg.fuzzy.z_zh && cfg.fuzzy.in_ing && !cfg.fuzzy.n_l);
+    }
+
+    #[test]
+    fn test_border_and_shadow_vars() {
+        let css = ":root {\n    --border-color: #7AA2F7;\n    --border-width: 1px;\n    --shadow: none;\n}\n";
+        assert_eq!(css_var(css, "--border-color").as_deref(), Some("#7AA2F7"));
+        assert_eq!(css_var(css, "--border-width").as_deref(), Some("1px"));
+        assert!(!shadow_enabled(css_var(css, "--shadow").as_deref()));
+        // 旧样式表没有这些变量: 无边框，保留阴影
+        assert_eq!(css_var(":root {\n    --bg-color: #000;\n}\n", "--border-color"), None);
+        assert!(shadow_enabled(None));
+        assert!(shadow_enabled(Some("on")));
+    }
+
+    #[test]
+    fn test_parse_px() {
+        assert_eq!(parse_px("18", 96), Some(18));
+        assert_eq!(parse_px("18px", 96), Some(18));
+        assert_eq!(parse_px(" 18.4PX ", 96), Some(18));
+        assert_eq!(parse_px("12pt", 96), Some(16));
+        assert_eq!(parse_px("12pt", 144), Some(24));
+        assert_eq!(parse_px("1.5em", 96), None);
+        assert_eq!(parse_px("50%", 96), None);
+        assert_eq!(parse_px("-2px", 96), None);
+        assert_eq!(parse_px("", 96), None);
+    }
+
+    #[test]
+    fn test_legacy_config_defaults() {
+        // 旧配置没有 [fuzzy] / engine.shuangpin，也能加载
+        let cfg: crate::config::Config = toml::from_str("[engine]\nmode = \"dict\"\n").unwrap();
+        assert_eq!(cfg.engine.shuangpin, crate::config::ShuangpinScheme::Off);
+        assert!(!cfg.fuzzy.is_enabled());
+    }
+}
@@ -0,0 +1,162 @@
+//! # 双拼
+//!
+//! 把双拼按键序列解码为全拼音节（每两键一个音节），供 `PinyinEngine` 使用；
+//! 以及反向编码，用于撤销上屏时把全拼放回按键缓冲。
+//!
+//! ## 支持方案
+//! - 小鹤双拼 (`xiaohe`)
+//! - 自然码 (`ziranma`)
+
+use crate::config::ShuangpinScheme;
+use crate::pinyin::is_valid_syllable;
+
+/// 双拼方案键位表
+struct Scheme {
+    /// 韵母键位: (键, 该键上的韵母)；一键多韵母时按顺序取第一个能与声母拼成合法音节的
+    finals: &'static [(char, &'static [&'static str])],
+    /// 零声母音节: (两键, 音节)
+    zero: &'static [(&'static str, &'static str)],
+}
+
+/// 两个方案共用的零声母键位
+const ZERO_INITIAL: &[(&str, &str)] = &[
+    ("aa", "a"), ("ai", "ai"), ("an", "an"), ("ah", "ang"), ("ao", "ao"),
+    ("ee", "e"), ("ei", "ei"), ("en", "en"), ("eg", "eng"), ("er", "er"),
+    ("oo", "o"), ("ou", "ou"),
+];
+
+const XIAOHE: Scheme = Scheme {
+    finals: &[
+        ('q', &["iu"]), ('w', &["ei"]), ('e', &["e"]), ('r', &["uan"]), ('t', &["ve", "ue"]),
+        ('y', &["un"]), ('u', &["u"]), ('i', &["i"]), ('o', &["uo", "o"]), ('p', &["ie"]),
+        ('a', &["a"]), ('s', &["ong", "iong"]), ('d', &["ai"]), ('f', &["en"]), ('g', &["eng"]),
+        ('h', &["ang"]), ('j', &["an"]), ('k', &["ing", "uai"]), ('l', &["iang", "uang"]),
+        ('z', &["ou"]), ('x', &["ia", "ua"]), ('c', &["ao"]), ('v', &["v", "ui"]),
+        ('b', &["in"]), ('n', &["iao"]), ('m', &["ian"]),
+    ],
+    zero: ZERO_INITIAL,
+};
+
+const ZIRANMA: Scheme = Scheme {
+    finals: &[
+        ('q', &["iu"]), ('w', &["ia", "ua"]), ('e', &["e"]), ('r', &["uan"]), ('t', &["ve", "ue"]),
+        ('y', &["ing", "uai"]), ('u', &["u"]), ('i', &["i"]), ('o', &["uo", "o"]), ('p', &["un"]),
+        ('a', &["a"]), ('s', &["ong", "iong"]), ('d', &["iang", "uang"]), ('f', &["en"]),
+        ('g', &["eng"]), ('h', &["ang"]), ('j', &["an"]), ('k', &["ao"]), ('l', &["ai"]),
+        ('z', &["ei"]), ('x', &["ie"]), ('c', &["iao"]), ('v', &["v", "ui"]),
+        ('b', &["ou"]), ('n', &["in"]), ('m', &["ian"]),
+    ],
+    zero: ZERO_INITIAL,
+};
+
+fn scheme(s: ShuangpinScheme) -> Option<&'static Scheme> {
+    match s {
+        ShuangpinScheme::Off => None,
+        ShuangpinScheme::Xiaohe => Some(&XIAOHE),
+        ShuangpinScheme::Ziranma => Some(&ZIRANMA),
+    }
+}
+
+/// 声母键 → 声母（zh/ch/sh 占用 v/i/u 键）
+fn initial_of(key: char) -> String {
+    match key {
+        'v' => "zh".to_string(),
+        'i' => "ch".to_string(),
+        'u' => "sh".to_string(),
+        c => c.to_string(),
+    }
+}
+
+/// 按键序列解码为音节列表
+///
+/// 每两键一个音节；末尾落单的键只给出声母。无法拼出合法音节的两键原样保留，
+/// 保证第 i 个音节始终对应按键 `[2i, 2i+2)`。
+pub fn decode(keys: &str, s: ShuangpinScheme) -> Vec<String> {
+    let Some(scheme) = scheme(s) else { return vec![keys.to_string()] };
+    let chars: Vec<char> = keys.chars().collect();
+
+    chars.chunks(2).map(|pair| match *pair {
+        [k1, k2] => decode_pair(scheme, k1, k2)
+            .unwrap_or_else(|| pair.iter().collect()),
+        [k1] => if matches!(k1, 'a' | 'e' | 'o') { k1.to_string() } else { initial_of(k1) },
+        _ => String::new(),
+    }).collect()
+}
+
+fn decode_pair(scheme: &Scheme, k1: char, k2: char) -> Option<String> {
+    if matches!(k1, 'a' | 'e' | 'o') {
+        let pair: String = [k1, k2].iter().collect();
+        return scheme.zero.iter().find(|(k, _)| *k == pair).map(|(_, syl)| syl.to_string());
+    }
+    let initial = initial_of(k1);
+    let (_, finals) = scheme.finals.iter().find(|(k, _)| *k == k2)?;
+    finals.iter()
+        .map(|f| format!("{}{}", initial, f))
+        .find(|syl| is_valid_syllable(syl))
+}
+
+/// 全拼音节编码回双拼按键（`decode` 的逆运算）
+pub fn encode(syllables: &[String], s: ShuangpinScheme) -> String {
+    let Some(scheme) = scheme(s) else { return syllables.concat() };
+    let mut out = String::new();
+
+    for syl in syllables {
+        if let Some((keys, _)) = scheme.zero.iter().find(|(_, z)| z == syl) {
+            out.push_str(keys);
+            continue;
+        }
+        let (key1, rest) = match syl.get(..2) {
+            Some("zh") => ('v', &syl[2..]),
+            Some("ch") => ('i', &syl[2..]),
+            Some("sh") => ('u', &syl[2..]),
+            _ => match syl.chars().next() {
+                Some(c) => (c, &syl[1..]),
+                None => continue,
+            },
+        };
+        if rest.is_empty() {
+            out.push(key1);
+            continue;
+        }
+        match scheme.finals.iter().find(|(_, fs)| fs.contains(&rest)) {
+            Some((key2, _)) => { out.push(key1); out.push(*key2); }
+            None => out.push_str(syl),
+        }
+    }
+    out
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_xiaohe() {
+        let s = ShuangpinScheme::Xiaohe;
+        assert_eq!(decode("nihc", s), vec!["ni", "hao"]);
+        assert_eq!(decode("vggouiir", s), vec!["zheng", "guo", "shi", "chuan"]);
+        assert_eq!(decode("dkgk", s), vec!["ding", "guai"]);
+        assert_eq!(decode("ahlvu", s), vec!["ang", "lv", "sh"]);
+    }
+
+    #[test]
+    fn test_decode_ziranma() {
+        let s = ShuangpinScheme::Ziranma;
+        assert_eq!(decode("nihk", s), vec!["ni", "hao"]);
+        assert_eq!(decode("xdgd", s), vec!["xiang", "guang"]);
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let syllables: Vec<String> = ["zhuang", "xiong", "ang", "e", "lve", "kuai", "sh"]
+            .iter().map(|s| s.to_string()).collect();
+        for s in [ShuangpinScheme::Xiaohe, ShuangpinScheme::Ziranma] {
+            assert_eq!(decode(&encode(&syllables, s), s), syllables);
+        }
+        assert_eq!(encode(&syllables[..2], ShuangpinScheme::Xiaohe), "vlxs");
+    }
+}
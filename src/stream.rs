@@ -0,0 +1,601 @@
+//! # 候选推送 (WebSocket)
+//!
+//! 开启 `[stream]` 后在 `127.0.0.1:<stream.port>` 单独监听，`GET /v1/stream` 升级为 WebSocket，
+//! 每次候选窗内容变化都推送一条 JSON，内容与内置候选窗收到的消息相同（`show_ime` / `hide`），
+//! 供外部浮层自行渲染。
+//!
+//! 推送内容就是用户正在打的字，所以默认关闭，且握手必须带上本机令牌
+//! （`?token=<令牌>` 或子协议 `Sec-WebSocket-Protocol: <令牌>`）；
+//! 浏览器发起的连接带 `Origin` 头，只放行 `allowed_origins` 里列出的来源。
+//!
+//! - 支持多个客户端同时连接，各自一个推送线程与一个读线程（各持有一份 `try_clone` 的 TcpStream）
+//! - 新连接先收到当前输入的最近一条候选；没有正在进行的输入时不补发
+//! - 客户端的 ping 回 pong，close 回 close 后断开；文本 / 二进制帧忽略
+//! - 握手所需的 SHA-1 / Base64 在本模块内实现（不引入额外依赖）
+
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 候选推送配置（对应 config.toml 的 `[stream]` 段）
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StreamConfig {
+    /// 是否开启推送接口
+    pub enabled: bool,
+    /// 监听端口（只绑定 127.0.0.1）
+    pub port: u16,
+    /// 握手令牌；留空则首次开启时随机生成并写回 config.toml
+    pub token: String,
+    /// 允许的浏览器来源（`Origin` 头，如 `http://localhost:3000`）；不带 Origin 的本地程序不受限
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8800,
+            token: String::new(),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+/// 发给某个客户端的帧
+#[derive(Debug, PartialEq)]
+enum Outgoing {
+    Text(Arc<str>),
+    Pong(Vec<u8>),
+    /// 回 close 帧（载荷为客户端给的状态码）后断开；None = 连接已断，直接结束
+    Close(Option<Vec<u8>>),
+}
+
+/// 已连接客户端的发送端
+static SUBSCRIBERS: Mutex<Vec<Sender<Outgoing>>> = Mutex::new(Vec::new());
+/// 当前输入的最近一条候选（新连接先补发）；输入结束（hide）时清空
+static SNAPSHOT: Mutex<Option<Arc<str>>> = Mutex::new(None);
+
+/// RFC 6455 握手固定 GUID
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// 客户端帧载荷上限（本接口不收数据，超过视为异常直接断开）
+const MAX_CLIENT_PAYLOAD: u64 = 64 * 1024;
+/// 握手请求头上限
+const MAX_HANDSHAKE: u64 = 8 * 1024;
+/// 握手读超时（连上不说话的连接不占线程）
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 是否有客户端连接（无人订阅时调用方可跳过序列化）
+pub fn has_subscribers() -> bool {
+    SUBSCRIBERS.lock().map(|s| !s.is_empty()).unwrap_or(false)
+}
+
+/// 推送当前输入的候选，并记为快照；已断开的客户端顺带移除
+pub fn publish(json: String) {
+    let msg: Arc<str> = json.into();
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        *snapshot = Some(Arc::clone(&msg));
+    }
+    broadcast(&msg);
+}
+
+/// 输入结束: 作废快照（之后连上的客户端不会收到已结束的输入），`json` 有值时推送给现有客户端
+pub fn publish_end(json: Option<String>) {
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        *snapshot = None;
+    }
+    if let Some(json) = json {
+        broadcast(&json.into());
+    }
+}
+
+fn broadcast(msg: &Arc<str>) {
+    if let Ok(mut subs) = SUBSCRIBERS.lock() {
+        subs.retain(|tx| tx.send(Outgoing::Text(Arc::clone(msg))).is_ok());
+    }
+}
+
+fn subscribe() -> (Sender<Outgoing>, Receiver<Outgoing>) {
+    let (tx, rx) = mpsc::channel();
+    if let Ok(snapshot) = SNAPSHOT.lock() {
+        if let Some(msg) = snapshot.as_ref() {
+            let _ = tx.send(Outgoing::Text(Arc::clone(msg)));
+        }
+    }
+    if let Ok(mut subs) = SUBSCRIBERS.lock() {
+        subs.push(tx.clone());
+    }
+    (tx, rx)
+}
+
+// ============================================================
+// 监听与握手
+// ============================================================
+
+/// 启动推送服务，返回实际监听端口（0 = 失败）
+///
+/// `token` 为空时生成一个并写回 `[stream] token`，外部浮层从 config.toml 读取
+pub fn start(cfg: &StreamConfig) -> u16 {
+    let mut cfg = cfg.clone();
+    if cfg.token.is_empty() {
+        cfg.token = generate_token();
+        if let Err(e) = crate::config::persist_value("stream", "token", &format!("\"{}\"", cfg.token)) {
+            eprintln!("[Stream] ⚠ 令牌写回 config.toml 失败: {}", e);
+        }
+    }
+
+    let listener = match TcpListener::bind(("127.0.0.1", cfg.port)) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("[Stream] ⚠ 端口 {} 监听失败: {}", cfg.port, e);
+            return 0;
+        }
+    };
+    let port = listener.local_addr().map(|a| a.port()).unwrap_or(0);
+    eprintln!("[Stream] ✅ ws://127.0.0.1:{}/v1/stream (令牌见 config.toml [stream])", port);
+
+    let cfg = Arc::new(cfg);
+    let _ = std::thread::Builder::new()
+        .name("ws-listen".into())
+        .spawn(move || {
+            for conn in listener.incoming().flatten() {
+                let cfg = Arc::clone(&cfg);
+                let _ = std::thread::Builder::new()
+                    .name("ws-handshake".into())
+                    .spawn(move || accept(conn, &cfg));
+            }
+        });
+    port
+}
+
+/// 校验握手，通过后升级连接并启动推送线程与读线程
+fn accept(stream: TcpStream, cfg: &StreamConfig) {
+    let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+    let Ok(read_half) = stream.try_clone() else { return };
+    let mut reader = BufReader::new(read_half);
+    let Some(request) = read_head(&mut reader).ok().and_then(|head| Handshake::parse(&head)) else {
+        let _ = respond_error(&stream, 400, "Bad Request");
+        return;
+    };
+    let protocol = match check_handshake(&request, cfg) {
+        Ok(protocol) => protocol,
+        Err((code, reason)) => {
+            eprintln!("[Stream] 拒绝连接: {} {}", code, reason);
+            let _ = respond_error(&stream, code, reason);
+            return;
+        }
+    };
+
+    let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+    let mut resp = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n",
+        accept_key(key)
+    );
+    if let Some(p) = protocol {
+        resp.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", p));
+    }
+    resp.push_str("\r\n");
+    let mut writer = stream;
+    if writer.write_all(resp.as_bytes()).is_err() { return; }
+    let _ = reader.get_ref().set_read_timeout(None);
+
+    let (tx, rx) = subscribe();
+    eprintln!("[Stream] 客户端已连接");
+
+    let _ = std::thread::Builder::new()
+        .name("ws-stream".into())
+        .spawn(move || {
+            for out in rx {
+                let frame = match out {
+                    Outgoing::Text(msg) => frame(0x1, msg.as_bytes()),
+                    Outgoing::Pong(payload) => frame(0xA, &payload),
+                    Outgoing::Close(Some(payload)) => {
+                        let _ = writer.write_all(&frame(0x8, &payload));
+                        break;
+                    }
+                    Outgoing::Close(None) => break,
+                };
+                if writer.write_all(&frame).is_err() { break; }
+            }
+            // 读线程阻塞在 read 上，关闭连接让它退出
+            let _ = writer.shutdown(Shutdown::Both);
+            eprintln!("[Stream] 客户端已断开");
+        });
+
+    let _ = std::thread::Builder::new()
+        .name("ws-read".into())
+        .spawn(move || loop {
+            let reply = match read_frame(&mut reader) {
+                Ok(frame) => handle_client_frame(frame),
+                Err(_) => Some(Outgoing::Close(None)),
+            };
+            let Some(reply) = reply else { continue };
+            let done = matches!(reply, Outgoing::Close(_));
+            if tx.send(reply).is_err() || done { break; }
+        });
+}
+
+fn respond_error(mut stream: &TcpStream, code: u16, reason: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", code, reason)
+}
+
+/// 读到空行为止的请求头（超过 [`MAX_HANDSHAKE`] 视为异常）
+fn read_head(r: &mut impl BufRead) -> io::Result<String> {
+    let mut head = String::new();
+    loop {
+        let left = MAX_HANDSHAKE.saturating_sub(head.len() as u64);
+        let n = r.by_ref().take(left).read_line(&mut head)?;
+        if head.ends_with("\r\n\r\n") || head.ends_with("\n\n") { return Ok(head); }
+        if n == 0 || head.len() as u64 >= MAX_HANDSHAKE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "握手请求不完整"));
+        }
+    }
+}
+
+/// 握手请求: 请求行的目标与头部
+struct Handshake {
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Handshake {
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split_whitespace();
+        if request_line.next()? != "GET" { return None; }
+        let target = request_line.next()?.to_string();
+        let headers = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect();
+        Some(Self { target, headers })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    fn query(&self, key: &str) -> Option<&str> {
+        let (_, query) = self.target.split_once('?')?;
+        query.split('&').find_map(|kv| kv.split_once('=').filter(|(k, _)| *k == key).map(|(_, v)| v))
+    }
+}
+
+/// 校验握手: 路径 → 升级头 → Origin → 令牌；通过时返回要回显的子协议（令牌经子协议传入时）
+fn check_handshake(req: &Handshake, cfg: &StreamConfig) -> Result<Option<String>, (u16, &'static str)> {
+    if req.path() != "/v1/stream" {
+        return Err((404, "Not Found"));
+    }
+    let is_ws = req.header("Upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if !is_ws || req.header("Sec-WebSocket-Key").is_none() {
+        return Err((400, "Bad Request"));
+    }
+    if let Some(origin) = req.header("Origin") {
+        let origin = origin.trim_end_matches('/');
+        if !cfg.allowed_origins.iter().any(|o| o.trim_end_matches('/').eq_ignore_ascii_case(origin)) {
+            return Err((403, "Forbidden"));
+        }
+    }
+    if cfg.token.is_empty() {
+        return Err((401, "Unauthorized"));
+    }
+    if req.query("token").is_some_and(|t| token_eq(t, &cfg.token)) {
+        return Ok(None);
+    }
+    let protocol = req.header("Sec-WebSocket-Protocol")
+        .and_then(|list| list.split(',').map(str::trim).find(|p| token_eq(p, &cfg.token)));
+    match protocol {
+        Some(p) => Ok(Some(p.to_string())),
+        None => Err((401, "Unauthorized")),
+    }
+}
+
+/// 比较令牌（逐字节比较到底，不因前缀匹配长度泄露耗时差异）
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 32 位十六进制随机令牌（种子来自系统随机源的 RandomState）
+fn generate_token() -> String {
+    use std::hash::BuildHasher;
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    (0..2u8)
+        .map(|i| format!("{:016x}", std::collections::hash_map::RandomState::new().hash_one((seed, i))))
+        .collect()
+}
+
+/// 客户端帧 (opcode, 载荷) 需要的回应；数据帧与 pong 不回应
+fn handle_client_frame((opcode, payload): (u8, Vec<u8>)) -> Option<Outgoing> {
+    match opcode {
+        0x9 => Some(Outgoing::Pong(payload)),
+        // 回显状态码（没有则空载荷）
+        0x8 => Some(Outgoing::Close(Some(payload.into_iter().take(2).collect()))),
+        _ => None,
+    }
+}
+
+/// 读一个客户端帧，返回 (opcode, 去掉掩码的载荷)
+fn read_frame(r: &mut (impl Read + ?Sized)) -> io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    r.read_exact(&mut head)?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut b = [0u8; 2];
+            r.read_exact(&mut b)?;
+            u16::from_be_bytes(b) as u64
+        }
+        127 => {
+            let mut b = [0u8; 8];
+            r.read_exact(&mut b)?;
+            u64::from_be_bytes(b)
+        }
+        n => n as u64,
+    };
+    if len > MAX_CLIENT_PAYLOAD {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "帧过大"));
+    }
+    let mut mask = [0u8; 4];
+    if masked { r.read_exact(&mut mask)?; }
+    let mut payload = vec![0u8; len as usize];
+    r.read_exact(&mut payload)?;
+    if masked {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok((opcode, payload))
+}
+
+/// `Sec-WebSocket-Accept` = Base64(SHA-1(key + GUID))
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), WS_GUID).as_bytes()))
+}
+
+/// 服务端帧（FIN + opcode，不加掩码）
+fn frame(opcode: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(bytes.len() + 10);
+    frame.push(0x80 | opcode);
+    match bytes.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(bytes);
+    frame
+}
+
+// ============================================================
+// SHA-1 / Base64
+// ============================================================
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key_rfc6455() {
+        // RFC 6455 §1.3 示例
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn test_frame_lengths() {
+        assert_eq!(frame(0x1, b"hi"), vec![0x81, 2, b'h', b'i']);
+        let long = "x".repeat(300);
+        assert_eq!(&frame(0x1, long.as_bytes())[..4], &[0x81, 126, 0x01, 0x2C]);
+        assert_eq!(frame(0xA, b""), vec![0x8A, 0]);
+    }
+
+    /// 客户端帧: 带掩码
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1u8, 2, 3, 4];
+        let mut f = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        f.extend_from_slice(&mask);
+        f.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        f
+    }
+
+    #[test]
+    fn test_client_frames() {
+        let mut input = io::Cursor::new([client_frame(0x9, b"ping"), client_frame(0x1, b"{}"), client_frame(0x8, &[0x03, 0xE8, b'x'])].concat());
+        let mut replies = vec![];
+        while let Ok(f) = read_frame(&mut input) {
+            replies.push(handle_client_frame(f));
+        }
+        assert_eq!(replies, vec![
+            Some(Outgoing::Pong(b"ping".to_vec())),
+            None,
+            // close 只回显状态码
+            Some(Outgoing::Close(Some(vec![0x03, 0xE8]))),
+        ]);
+
+        // 超大帧直接判为异常
+        let mut huge = io::Cursor::new(vec![0x82, 0xFF, 0, 0, 0, 0, 0, 0x10, 0, 0]);
+        assert!(read_frame(&mut huge).is_err());
+    }
+
+    fn text(out: Outgoing) -> String {
+        match out {
+            Outgoing::Text(msg) => msg.to_string(),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_publish_and_snapshot() {
+        let (_, a) = subscribe();
+        let (_, b) = subscribe();
+        publish(r#"{"type":"show_ime"}"#.to_string());
+        assert_eq!(text(a.recv().unwrap()), r#"{"type":"show_ime"}"#);
+        assert_eq!(text(b.recv().unwrap()), r#"{"type":"show_ime"}"#);
+
+        // 输入中连上的客户端先收到当前候选
+        let (_, c) = subscribe();
+        assert_eq!(text(c.try_recv().unwrap()), r#"{"type":"show_ime"}"#);
+
+        // 输入结束后连上的客户端不补发
+        publish_end(Some(r#"{"type":"hide"}"#.to_string()));
+        assert_eq!(text(b.recv().unwrap()), r#"{"type":"hide"}"#);
+        let (_, d) = subscribe();
+        assert!(d.try_recv().is_err());
+
+        // 断开的客户端在下次推送时移除
+        drop((a, c, d));
+        publish("{}".to_string());
+        assert_eq!(text(b.recv().unwrap()), "{}");
+    }
+
+    fn cfg(origins: &[&str]) -> StreamConfig {
+        StreamConfig {
+            enabled: true,
+            port: 0,
+            token: "secret".into(),
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+
+    fn handshake(target: &str, extra: &str) -> Handshake {
+        let head = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\n\
+Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n", target, extra);
+        Handshake::parse(&head).unwrap()
+    }
+
+    #[test]
+    fn test_handshake_requires_token() {
+        let c = cfg(&[]);
+        assert_eq!(check_handshake(&handshake("/v1/stream", ""), &c), Err((401, "Unauthorized")));
+        assert_eq!(check_handshake(&handshake("/v1/stream?token=wrong", ""), &c), Err((401, "Unauthorized")));
+        assert_eq!(check_handshake(&handshake("/v1/stream?token=secret", ""), &c), Ok(None));
+        // 子协议传令牌时回显
+        let sub = handshake("/v1/stream", "Sec-WebSocket-Protocol: json, secret\r\n");
+        assert_eq!(check_handshake(&sub, &c), Ok(Some("secret".to_string())));
+        assert_eq!(check_handshake(&handshake("/v1/other?token=secret", ""), &c), Err((404, "Not Found")));
+
+        // 没有令牌的配置一律拒绝
+        let empty = StreamConfig { token: String::new(), ..cfg(&[]) };
+        assert_eq!(check_handshake(&handshake("/v1/stream?token=", ""), &empty), Err((401, "Unauthorized")));
+    }
+
+    #[test]
+    fn test_handshake_checks_origin() {
+        let page = handshake("/v1/stream?token=secret", "Origin: https://evil.example\r\n");
+        assert_eq!(check_handshake(&page, &cfg(&[])), Err((403, "Forbidden")));
+        let overlay = handshake("/v1/stream?token=secret", "Origin: http://localhost:3000\r\n");
+        assert_eq!(check_handshake(&overlay, &cfg(&["http://localhost:3000/"])), Ok(None));
+        assert_eq!(check_handshake(&page, &cfg(&["http://localhost:3000"])), Err((403, "Forbidden")));
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let (a, b) = (generate_token(), generate_token());
+        assert_eq!(a.len(), 32);
+        assert!(a.bytes().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+        assert!(token_eq(&a, &a.clone()) && !token_eq(&a, &b));
+    }
+
+    #[test]
+    fn test_ping_over_socket() {
+        let port = start(&cfg(&[]));
+        assert_ne!(port, 0);
+        let mut conn = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(conn, "GET /v1/stream?token=secret HTTP/1.1\r\nUpgrade: websocket\r\n\
+Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(conn.try_clone().unwrap());
+        let head = read_head(&mut reader).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+        assert!(head.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        // 读线程收到 ping，经推送线程回 pong（其他用例的推送可能先到）
+        conn.write_all(&client_frame(0x9, b"hi")).unwrap();
+        let pong = std::iter::from_fn(|| read_frame(&mut reader).ok()).find(|(op, _)| *op == 0xA);
+        assert_eq!(pong, Some((0xA, b"hi".to_vec())));
+
+        conn.write_all(&client_frame(0x8, &[0x03, 0xE8])).unwrap();
+        let close = std::iter::from_fn(|| read_frame(&mut reader).ok()).find(|(op, _)| *op == 0x8);
+        assert_eq!(close, Some((0x8, vec![0x03, 0xE8])));
+    }
+}
@@ -0,0 +1,113 @@
+//! # 符号面板
+//!
+//! 中文模式下输入为空时按 `engine.symbol_key`（默认 `/`）进入符号输入: 候选窗列出分类符号，
+//! 数字键选择、翻页键翻页、Esc 退出。`/` 之后再敲分类编码只看该类，如 `/sx` 只列数学符号。
+//!
+//! 分类可在 `[symbols]` 里自定义（见 [`SymbolCategory`]），不配置时用内置的 [`builtin`]。
+
+use serde::Deserialize;
+
+/// 符号输入在拼音缓冲里的前缀（与按哪个键进入无关）
+pub const SYMBOL_PREFIX: char = '/';
+
+/// 一类符号: `{ code = "sx", name = "数学", symbols = "± × ÷ ≈" }`
+///
+/// `symbols` 以空白分隔，一项可以有多个字符（`——`、`「」`）。
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SymbolCategory {
+    /// 分类编码（小写字母），`/` 之后输入它筛选
+    pub code: String,
+    pub name: String,
+    pub symbols: String,
+}
+
+impl SymbolCategory {
+    fn new(code: &str, name: &str, symbols: &str) -> Self {
+        Self { code: code.to_string(), name: name.to_string(), symbols: symbols.to_string() }
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &str> {
+        self.symbols.split_whitespace()
+    }
+}
+
+/// 内置分类
+pub fn builtin() -> Vec<SymbolCategory> {
+    vec![
+        SymbolCategory::new("bd", "标点", "—— …… 、 · ～ ‖ 〃 ※ § ¥ ° ′ ″"),
+        SymbolCategory::new("kh", "括号", "「」 『』 《》 〈〉 【】 〔〕 〖〗 「 」 『 』 《 》 【 】"),
+        SymbolCategory::new("sx", "数学", "± × ÷ ≈ ≠ ≤ ≥ ∞ √ ∑ ∏ ∫ ∂ ∈ ∉ ⊂ ⊆ ∪ ∩ ∀ ∃ ∅ ∠ ⊥ ∵ ∴ ‰ ℃"),
+        SymbolCategory::new("jt", "箭头", "→ ← ↑ ↓ ↔ ⇒ ⇐ ⇔ ↗ ↘ ↙ ↖"),
+        SymbolCategory::new("xh", "序号", "① ② ③ ④ ⑤ ⑥ ⑦ ⑧ ⑨ ⑩ ⑴ ⑵ ⑶ Ⅰ Ⅱ Ⅲ Ⅳ Ⅴ Ⅵ Ⅶ Ⅷ Ⅸ Ⅹ"),
+        SymbolCategory::new("xl", "希腊", "α β γ δ ε ζ η θ λ μ π ρ σ τ φ χ ψ ω Δ Σ Ω"),
+        SymbolCategory::new("ts", "特殊", "★ ☆ ● ○ ◆ ◇ ■ □ ▲ △ ✓ ✗ ♀ ♂ ♪ © ® ™"),
+    ]
+}
+
+/// 输入是否为符号输入（"/"、"/sx"）
+pub fn is_symbol_input(raw: &str) -> bool {
+    raw.starts_with(SYMBOL_PREFIX)
+}
+
+/// 编码前缀匹配的分类（`code` 为空时全部）
+fn matching<'a>(categories: &'a [SymbolCategory], code: &'a str) -> impl Iterator<Item = &'a SymbolCategory> {
+    categories.iter().filter(move |c| c.code.starts_with(code))
+}
+
+/// "/sx" → 数学类的全部符号；"/" → 各类依次排列；按分类顺序去重
+pub fn candidates(categories: &[SymbolCategory], raw: &str) -> Vec<String> {
+    let Some(code) = raw.strip_prefix(SYMBOL_PREFIX) else { return vec![] };
+    let mut seen = std::collections::HashSet::new();
+    matching(categories, code)
+        .flat_map(SymbolCategory::items)
+        .filter(|s| seen.insert(*s))
+        .map(str::to_string)
+        .collect()
+}
+
+/// 候选窗拼音行: 输入之后列出匹配的分类，"/  标点 bd · 数学 sx"
+pub fn label(categories: &[SymbolCategory], raw: &str) -> String {
+    let code = raw.strip_prefix(SYMBOL_PREFIX).unwrap_or(raw);
+    let names: Vec<String> = matching(categories, code)
+        .map(|c| format!("{} {}", c.name, c.code))
+        .collect();
+    if names.is_empty() { raw.to_string() } else { format!("{}  {}", raw, names.join(" · ")) }
+}
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_by_category() {
+        let cats = vec![
+            SymbolCategory::new("bd", "标点", "—— …… 、"),
+            SymbolCategory::new("bk", "括号", "「」 、"),
+            SymbolCategory::new("sx", "数学", "± ×"),
+        ];
+        assert_eq!(candidates(&cats, "/sx"), ["±", "×"]);
+        // 前缀匹配多个分类时依次排列并去重
+        assert_eq!(candidates(&cats, "/b"), ["——", "……", "、", "「」"]);
+        assert_eq!(candidates(&cats, "/").len(), 6);
+        assert!(candidates(&cats, "/zz").is_empty());
+        assert!(candidates(&cats, "sx").is_empty());
+
+        assert_eq!(label(&cats, "/s"), "/s  数学 sx");
+        assert_eq!(label(&cats, "/zz"), "/zz");
+        assert!(is_symbol_input("/") && !is_symbol_input("u4f60"));
+    }
+
+    #[test]
+    fn test_builtin_codes_unique() {
+        let cats = builtin();
+        for (i, c) in cats.iter().enumerate() {
+            assert!(c.code.bytes().all(|b| b.is_ascii_lowercase()), "{}", c.code);
+            assert!(cats[i + 1..].iter().all(|o| !o.code.starts_with(&c.code)), "{}", c.code);
+            assert!(c.items().count() > 0);
+        }
+    }
+}
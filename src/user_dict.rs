@@ -4,18 +4,22 @@
 //!
 //! ## 机制
 //! - 每次用户选词上屏时记录 (拼音, 汉字, 次数)
-//! - 数据持久化到 `user_dict.txt`（exe 同目录）
+//! - 数据持久化到 `user_dict.txt`（exe 同目录），每行 `拼音\t汉字\t次数`；
+//!   次数之后的列（将来的时间戳、来源等）原样保留，旧版本读新文件也不会丢数据
 //! - 启动时加载，选词时增量写入
-//! - 权重会叠加到主词典的查询结果中
+//! - 次数折算为名次加分叠加到 AI/字典候选排序中（`[user_dict]` 配置）
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::io::Write;
+use crate::config::UserDictConfig;
 
 /// 用户自学习词典
 pub struct UserDict {
     /// (拼音, 汉字) -> 使用次数
     entries: HashMap<(String, String), u32>,
+    /// (拼音, 汉字) -> 次数之后不认识的列（tab 分隔原文），保存时原样写回
+    extra_columns: HashMap<(String, String), String>,
     /// 文件路径
     path: PathBuf,
     /// 脏标记：是否有未保存的修改
@@ -23,25 +27,34 @@ pub struct UserDict {
 }
 
 impl UserDict {
-    /// 加载或创建用户词典
+    /// 加载或创建用户词典（exe 同目录）
     pub fn load() -> Self {
-        let path = Self::dict_path();
+        Self::load_from(Self::dict_path())
+    }
+
+    /// 从指定文件加载用户词典，不存在时在第一次学习时创建
+    pub fn load_from(path: PathBuf) -> Self {
         let mut entries = HashMap::new();
+        let mut extra_columns = HashMap::new();
 
         if path.exists() {
             match std::fs::read_to_string(&path) {
                 Ok(text) => {
-                    for line in text.lines() {
+                    for (no, line) in text.lines().enumerate() {
                         let line = line.trim();
                         if line.is_empty() || line.starts_with('#') { continue; }
-                        // 格式: 拼音\t汉字\t次数
-                        let parts: Vec<&str> = line.split('\t').collect();
-                        if parts.len() >= 3 {
-                            let pinyin = parts[0].to_string();
-                            let word = parts[1].to_string();
-                            let count: u32 = parts[2].parse().unwrap_or(1);
-                            entries.insert((pinyin, word), count);
+                        // 格式: 拼音\t汉字[\t次数[\t其他列...]]，缺次数按 1 次
+                        let mut parts = line.splitn(4, '\t');
+                        let (Some(pinyin), Some(word)) = (parts.next(), parts.next()) else {
+                            eprintln!("[UserDict] ⚠ 第 {} 行不足两列, 跳过: {}", no + 1, line);
+                            continue;
+                        };
+                        let key = (pinyin.to_string(), word.to_string());
+                        let count: u32 = parts.next().and_then(|c| c.parse().ok()).unwrap_or(1);
+                        if let Some(rest) = parts.next() {
+                            extra_columns.insert(key.clone(), rest.to_string());
                         }
+                        entries.insert(key, count);
                     }
                     eprintln!("[UserDict] ✅ 已加载 {} 条用户词 {:?}", entries.len(), path);
                 }
@@ -53,7 +66,7 @@ impl UserDict {
             eprintln!("[UserDict] ℹ user_dict.txt 不存在, 将在学习时创建");
         }
 
-        Self { entries, path, dirty: false }
+        Self { entries, extra_columns, path, dirty: false }
     }
 
     /// 学习一次选词：增加计数，如果是新词则添加
@@ -77,6 +90,7 @@ impl UserDict {
         if let Some(count) = self.entries.get_mut(&key) {
             if *count <= 1 {
                 self.entries.remove(&key);
+                self.extra_columns.remove(&key);
                 eprintln!("[UserDict] 🗑 移除 {} → {}", pinyin, word);
             } else {
                 *count -= 1;
@@ -87,22 +101,143 @@ impl UserDict {
         }
     }
 
+    /// 清空全部学习记录并立即写盘（文件只剩表头），返回清掉的条数
+    pub fn clear(&mut self) -> usize {
+        let n = self.entries.len();
+        self.entries.clear();
+        self.extra_columns.clear();
+        self.dirty = true;
+        self.save();
+        eprintln!("[UserDict] 🧹 清空学习记录 {} 条", n);
+        n
+    }
+
+    /// 把尚未写盘的改动保存下来（退出时调用，没有改动则什么都不做）
+    pub fn flush(&mut self) {
+        self.save();
+    }
+
     /// 获取某个词的用户权重（0 = 未学习过）
     pub fn get_weight(&self, pinyin: &str, word: &str) -> u32 {
         let key = (pinyin.to_string(), word.to_string());
         self.entries.get(&key).copied().unwrap_or(0)
     }
 
+    /// 已学习的 (拼音, 词) 条数
+    pub fn entry_count(&self) -> usize { self.entries.len() }
+
     /// 获取某个拼音下所有用户学过的词（用于补充候选）
     pub fn get_learned_words(&self, pinyin: &str) -> Vec<(String, u32)> {
         let mut result: Vec<(String, u32)> = self.entries.iter()
             .filter(|((py, _), _)| py == pinyin)
             .map(|((_, word), &count)| (word.clone(), count))
             .collect();
-        result.sort_by(|a, b| b.1.cmp(&a.1));
+        result.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
         result
     }
 
+    /// 按学习次数调整候选顺序
+    ///
+    /// `base` 为 AI/字典给出的有序候选，名次即基础分；每学习一次前移 `boost_scale` 名，
+    /// 同分时保持原顺序。不在 `base` 中的学习词从列表末尾开始前移。
+    /// 次数最多的学习词达到 `pin_threshold` 次才直接置顶，偶尔选一次的词不会压过常用词。
+    pub fn rank(&self, pinyin: &str, base: Vec<String>, cfg: &UserDictConfig) -> Vec<String> {
+        let learned = self.get_learned_words(pinyin);
+        if learned.is_empty() { return base; }
+
+        let mut scored: Vec<(f32, String)> = base.into_iter().enumerate()
+            .map(|(i, w)| {
+                let bonus = cfg.boost_scale * self.get_weight(pinyin, &w) as f32;
+                (i as f32 - bonus, w)
+            })
+            .collect();
+        let end = scored.len() as f32;
+        for (word, count) in &learned {
+            if !scored.iter().any(|(_, w)| w == word) {
+                scored.push((end - cfg.boost_scale * *count as f32, word.clone()));
+            }
+        }
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut ranked: Vec<String> = scored.into_iter().map(|(_, w)| w).collect();
+        let (top, count) = &learned[0];
+        if *count >= cfg.pin_threshold {
+            if let Some(pos) = ranked.iter().position(|w| w == top) {
+                let word = ranked.remove(pos);
+                ranked.insert(0, word);
+            }
+        }
+        ranked
+    }
+
+    /// 联想（AI 不可用时）: 首字是上下文末字的学习词，返回其余部分，按次数降序
+    ///
+    /// 例: 学过 "你好"，刚上屏 "…你" → ["好"]
+    pub fn predict_next(&self, context: &str, n: usize) -> Vec<String> {
+        let Some(last) = context.chars().last() else { return vec![] };
+        let mut words: Vec<(&str, u32)> = self.entries.iter()
+            .filter_map(|((_, word), &count)| {
+                let rest = word.strip_prefix(last)?;
+                (!rest.is_empty()).then_some((rest, count))
+            })
+            .collect();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        let mut seen = std::collections::HashSet::new();
+        words.into_iter()
+            .filter(|(w, _)| seen.insert(*w))
+            .take(n)
+            .map(|(w, _)| w.to_string())
+            .collect()
+    }
+
+    /// 导出为可分享的 JSON: `{"version":1,"entries":[{"pinyin","word","count"}]}`
+    pub fn to_json(&self) -> String {
+        let mut sorted: Vec<_> = self.entries.iter().collect();
+        sorted.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let entries: Vec<serde_json::Value> = sorted.iter()
+            .map(|((pinyin, word), count)| serde_json::json!({
+                "pinyin": pinyin, "word": word, "count": count,
+            }))
+            .collect();
+        serde_json::json!({ "version": 1, "entries": entries }).to_string()
+    }
+
+    /// 合并导出的 JSON，同一 (拼音, 汉字) 取较大的次数；格式不对的条目跳过
+    ///
+    /// 返回新增或提高了次数的条目数。顶层结构不对时返回错误。
+    pub fn merge_json(&mut self, json: &str) -> Result<usize, String> {
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| format!("JSON 解析失败: {}", e))?;
+        let entries = value["entries"].as_array()
+            .ok_or_else(|| "缺少 entries 数组".to_string())?;
+
+        let mut changed = 0;
+        for e in entries {
+            let (Some(pinyin), Some(word), Some(count)) =
+                (e["pinyin"].as_str(), e["word"].as_str(), e["count"].as_u64()) else { continue };
+            let pinyin = pinyin.trim();
+            let word = word.trim();
+            if pinyin.is_empty() || word.is_empty() || count == 0
+                || !pinyin.bytes().all(|b| b.is_ascii_lowercase())
+                || word.contains(['\t', '\n']) {
+                continue;
+            }
+            let count = count.min(u32::MAX as u64) as u32;
+            let slot = self.entries.entry((pinyin.to_string(), word.to_string())).or_insert(0);
+            if count > *slot {
+                *slot = count;
+                changed += 1;
+            }
+        }
+
+        if changed > 0 {
+            self.dirty = true;
+            self.save();
+        }
+        eprintln!("[UserDict] 📥 导入合并 {} 条", changed);
+        Ok(changed)
+    }
+
     /// 保存到文件
     fn save(&mut self) {
         if !self.dirty { return; }
@@ -110,14 +245,18 @@ impl UserDict {
         match std::fs::File::create(&self.path) {
             Ok(mut f) => {
                 let _ = writeln!(f, "# AiPinyin 用户词典 — 自动生成，请勿手动编辑");
-                let _ = writeln!(f, "# 格式: 拼音\\t汉字\\t次数");
+                let _ = writeln!(f, "# 格式: 拼音\\t汉字\\t次数（之后的列原样保留）");
 
                 // 按次数降序排列
                 let mut sorted: Vec<_> = self.entries.iter().collect();
                 sorted.sort_by(|a, b| b.1.cmp(a.1));
 
-                for ((pinyin, word), count) in &sorted {
-                    let _ = writeln!(f, "{}\t{}\t{}", pinyin, word, count);
+                for (key, count) in &sorted {
+                    let (pinyin, word) = key;
+                    match self.extra_columns.get(*key) {
+                        Some(extra) => { let _ = writeln!(f, "{}\t{}\t{}\t{}", pinyin, word, count, extra); }
+                        None => { let _ = writeln!(f, "{}\t{}\t{}", pinyin, word, count); }
+                    }
                 }
 
                 self.dirty = false;
@@ -136,3 +275,138 @@ impl UserDict {
             .unwrap_or_else(|| PathBuf::from("user_dict.txt"))
     }
 }
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dict(name: &str) -> UserDict {
+        let path = std::env::temp_dir().join(format!("aipinyin_{}_{}.txt", name, std::process::id()));
+        UserDict { entries: HashMap::new(), extra_columns: HashMap::new(), path, dirty: false }
+    }
+
+    #[test]
+    fn test_json_roundtrip_merge_max() {
+        let mut a = temp_dict("ud_a");
+        a.entries.insert(("nihao".into(), "你好".into()), 5);
+        a.entries.insert(("shijian".into(), "时间".into()), 1);
+        let json = a.to_json();
+
+        let mut b = temp_dict("ud_b");
+        b.entries.insert(("nihao".into(), "你好".into()), 9);
+        assert_eq!(b.merge_json(&json), Ok(1));
+        assert_eq!(b.get_weight("nihao", "你好"), 9);
+        assert_eq!(b.get_weight("shijian", "时间"), 1);
+        let _ = std::fs::remove_file(&b.path);
+    }
+
+    #[test]
+    fn test_flush_writes_only_when_dirty() {
+        let mut d = temp_dict("ud_flush");
+        let _ = std::fs::remove_file(&d.path);
+        d.flush();
+        assert!(!d.path.exists());
+
+        d.entries.insert(("nihao".into(), "你好".into()), 2);
+        d.dirty = true;
+        d.flush();
+        d.flush();
+        assert!(!d.dirty);
+        let text = std::fs::read_to_string(&d.path).unwrap();
+        assert!(text.contains("nihao\t你好\t2"));
+        let _ = std::fs::remove_file(&d.path);
+    }
+
+    #[test]
+    fn test_extra_columns_roundtrip() {
+        let path = std::env::temp_dir().join(format!("aipinyin_ud_cols_{}.txt", std::process::id()));
+        std::fs::write(&path, "# 注释\n\nnihao\t你好\t3\t1735689600\tsync\nshijian\t时间\nbroken\n").unwrap();
+
+        let mut d = UserDict::load_from(path.clone());
+        assert_eq!(d.entry_count(), 2);
+        assert_eq!(d.get_weight("nihao", "你好"), 3);
+        // 只有两列: 按 1 次
+        assert_eq!(d.get_weight("shijian", "时间"), 1);
+
+        d.learn("nihao", "你好");
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("nihao\t你好\t4\t1735689600\tsync\n"));
+        assert!(text.contains("shijian\t时间\t1\n"));
+
+        let d = UserDict::load_from(path.clone());
+        assert_eq!(d.get_weight("nihao", "你好"), 4);
+        assert_eq!(d.extra_columns.get(&("nihao".into(), "你好".into())).map(String::as_str), Some("1735689600\tsync"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_merge_json_skips_malformed() {
+        let mut d = temp_dict("ud_bad");
+        let json = r#"{"entries":[
+            {"pinyin":"nihao","word":"你好","count":3},
+            {"pinyin":"ni hao","word":"你好","count":3},
+            {"pinyin":"women","word":"","count":2},
+            {"pinyin":"women","word":"我们","count":-1},
+            {"word":"他们","count":1},
+            "garbage"
+        ]}"#;
+        assert_eq!(d.merge_json(json), Ok(1));
+        assert!(d.merge_json("[1,2,3]").is_err());
+        assert!(d.merge_json("not json").is_err());
+        let _ = std::fs::remove_file(&d.path);
+    }
+
+    #[test]
+    fn test_clear_truncates_file() {
+        let mut d = temp_dict("ud_clear");
+        d.learn("nihao", "你好");
+        d.learn("nihao", "你好");
+        d.extra_columns.insert(("nihao".into(), "你好".into()), "2024".into());
+        assert_eq!(d.clear(), 1);
+        assert_eq!(d.get_weight("nihao", "你好"), 0);
+
+        let reloaded = UserDict::load_from(d.path.clone());
+        assert_eq!(reloaded.entry_count(), 0);
+        assert!(std::fs::read_to_string(&d.path).unwrap().lines().all(|l| l.starts_with('#')));
+        let _ = std::fs::remove_file(&d.path);
+    }
+
+    #[test]
+    fn test_predict_next() {
+        let mut d = temp_dict("ud_next");
+        d.entries.insert(("nihao".into(), "你好".into()), 3);
+        d.entries.insert(("nimen".into(), "你们".into()), 5);
+        d.entries.insert(("nimen".into(), "妳们".into()), 9);
+        d.entries.insert(("ni".into(), "你".into()), 9);
+        assert_eq!(d.predict_next("谢谢你", 9), ["们", "好"]);
+        assert_eq!(d.predict_next("你", 1), ["们"]);
+        assert!(d.predict_next("", 9).is_empty());
+        assert!(d.predict_next("他", 9).is_empty());
+    }
+
+    #[test]
+    fn test_rank_boost_and_pin() {
+        let cfg = UserDictConfig { boost_scale: 2.0, pin_threshold: 3 };
+        let base: Vec<String> = ["是", "时", "事", "十", "使", "市"].iter().map(|s| s.to_string()).collect();
+        let mut d = temp_dict("ud_rank");
+
+        // 选过一次的生僻词只前移两名，不压过首选
+        d.entries.insert(("shi".into(), "市".into()), 1);
+        assert_eq!(d.rank("shi", base.clone(), &cfg), vec!["是", "时", "事", "十", "市", "使"]);
+
+        // 达到置顶次数后直接排第一
+        d.entries.insert(("shi".into(), "市".into()), 3);
+        assert_eq!(d.rank("shi", base.clone(), &cfg)[0], "市");
+
+        // 不在候选中的学习词从末尾前移
+        let mut d = temp_dict("ud_rank2");
+        d.entries.insert(("shi".into(), "湿".into()), 2);
+        assert_eq!(d.rank("shi", base.clone(), &cfg),
+            vec!["是", "时", "事", "湿", "十", "使", "市"].into_iter().map(String::from).collect::<Vec<_>>());
+        assert_eq!(d.rank("ni", base.clone(), &cfg), base);
+    }
+}
@@ -4,6 +4,8 @@
 
 use anyhow::Result;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
 use tao::event::{Event, WindowEvent};
 use tao::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy};
 use tao::platform::windows::{EventLoopBuilderExtWindows, WindowExtWindows};
@@ -12,6 +14,7 @@ use wry::WebViewBuilder;
 use windows::Win32::Foundation::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 use serde::Serialize;
+use crate::cand_ui::CandidateUi;
 
 // JSON IPC structures
 #[derive(Serialize)]
@@ -22,6 +25,95 @@ struct ImeUpdateMsg<'a> {
     candidates: &'a [String],
     page: usize,
     total_pages: usize,
+    /// 首个候选是外部接口给出的整句
+    sentence: bool,
+    /// 各候选在本页内的相对词频 (0~1)，`ui.show_frequency` 关闭时为空
+    #[serde(skip_serializing_if = "<[f32]>::is_empty")]
+    frequency: &'a [f32],
+    /// 高亮的候选（页内序号）
+    selected: usize,
+    /// 候选窗最大宽度（`ui.max_width_px`，0 = 不限制），超出时前端截短最长的候选
+    #[serde(skip_serializing_if = "is_zero")]
+    max_width: u32,
+    /// 候选窗不透明度（`ui.opacity`，255 = 不透明时不发送）
+    #[serde(skip_serializing_if = "is_opaque")]
+    opacity: u8,
+}
+
+fn is_zero(v: &u32) -> bool { *v == 0 }
+fn is_opaque(v: &u8) -> bool { *v == u8::MAX }
+
+/// 候选窗最大宽度（`ui.max_width_px`，启动与配置热重载时设置）
+static MAX_WIDTH: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_max_width(px: u32) {
+    MAX_WIDTH.store(px, Ordering::Relaxed);
+}
+
+/// 候选窗不透明度（`ui.opacity`，启动与配置热重载时设置；GDI 候选窗也用）
+static OPACITY: AtomicU8 = AtomicU8::new(u8::MAX);
+
+pub fn set_opacity(alpha: u8) {
+    OPACITY.store(alpha, Ordering::Relaxed);
+}
+
+pub(crate) fn opacity() -> u8 {
+    OPACITY.load(Ordering::Relaxed)
+}
+
+/// 候选窗相对光标的偏移（`ui.offset_x` / `offset_y` / `monitor_offsets`，启动与配置热重载时设置）
+static WINDOW_OFFSETS: Mutex<Option<crate::config::UiConfig>> = Mutex::new(None);
+
+pub fn set_window_offsets(ui: &crate::config::UiConfig) {
+    if let Ok(mut o) = WINDOW_OFFSETS.lock() { *o = Some(ui.clone()); }
+}
+
+/// 光标 (x, y) 所在显示器上记住的偏移（GDI 候选窗也用）
+pub(crate) unsafe fn caret_offset(x: i32, y: i32) -> (i32, i32) {
+    window_offset(&monitor_at(x, y))
+}
+
+fn window_offset(monitor: &str) -> (i32, i32) {
+    WINDOW_OFFSETS.lock().ok()
+        .and_then(|o| o.as_ref().map(|ui| ui.window_offset(monitor)))
+        .unwrap_or((0, 0))
+}
+
+/// 拖动结束: 记住新偏移（立即生效并写回 config.toml）
+fn remember_window_offset(monitor: &str, offset: (i32, i32)) {
+    if let Ok(mut o) = WINDOW_OFFSETS.lock() {
+        if let Some(ui) = o.as_mut() {
+            ui.offset_x = offset.0;
+            ui.offset_y = offset.1;
+            if !monitor.is_empty() {
+                ui.monitor_offsets.insert(monitor.to_string(), [offset.0, offset.1]);
+            }
+        }
+    }
+    match crate::config::UiConfig::persist_window_offset(monitor, offset) {
+        Ok(()) => eprintln!("[UI] 记住候选窗偏移 {:?} ({})", offset, monitor),
+        Err(e) => eprintln!("[UI] ⚠ 候选窗偏移写入失败: {}", e),
+    }
+}
+
+/// 坐标所在显示器的名字（`config::monitor_key` 处理过），取不到时为空
+unsafe fn monitor_at(x: i32, y: i32) -> String {
+    use windows::Win32::Graphics::Gdi::{
+        GetMonitorInfoW, MonitorFromPoint, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+    };
+    let hmon = MonitorFromPoint(POINT { x, y }, MONITOR_DEFAULTTONEAREST);
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    if !GetMonitorInfoW(hmon, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO).as_bool() {
+        return String::new();
+    }
+    let len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+    crate::config::monitor_key(&String::from_utf16_lossy(&info.szDevice[..len]))
+}
+
+/// 按 `ui.max_width_px` 限制窗口宽度（0 = 不限制）
+fn cap_width(width: f64, max_width: u32) -> f64 {
+    if max_width == 0 { width } else { width.min(max_width as f64) }
 }
 
 #[derive(Serialize)]
@@ -36,6 +128,15 @@ struct ShowSettingsMsg {
     msg_type: &'static str,
 }
 
+#[derive(Serialize)]
+struct EngineModeMsg {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    ai: bool,
+    /// AI 模型是否已加载（false 时切换热键无效）
+    available: bool,
+}
+
 #[derive(Serialize)]
 struct PluginsActiveMsg {
     #[serde(rename = "type")]
@@ -43,16 +144,82 @@ struct PluginsActiveMsg {
     active: bool,
 }
 
+/// 设置页异步操作的结果（连接测试 / 词典导入 / 清空）
+#[derive(Serialize)]
+struct ResultMsg<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    ok: bool,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct UserDictExportMsg<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    data: &'a str,
+}
+
 pub enum ImeEvent {
-    ShowAt(i32, i32),
+    /// 坐标 + 是否来自真实光标
+    ShowAt(i32, i32, bool),
     Hide,
-    UpdateCandidates { raw: String, candidates: Vec<String>, page_info: Option<(usize, usize)> },
+    UpdateCandidates { raw: String, candidates: Vec<String>, page_info: Option<(usize, usize)>, sentence: bool, frequency: Vec<f32>, selected: usize },
     ShowSettings,
     PluginsActive(bool),
+    /// 热键切换引擎模式后更新候选窗上的模式标记
+    EngineMode { ai: bool, available: bool },
     LayoutUpdate { width: f64, height: f64 },
     DragWindow { dx: f64, dy: f64 },
+    /// 拖动结束（松开鼠标），记住候选窗相对光标的偏移
+    DragEnd,
+    /// 看门狗发现键盘钩子失效，回到主线程重装
+    ReinstallHook,
+    /// 设置页 AI 接口连通性测试结果
+    AiTestResult { ok: bool, message: String },
+    /// 设置页导出的用户词典 JSON
+    UserDictExport(String),
+    /// 设置页导入用户词典的结果
+    UserDictImport { ok: bool, message: String },
+    /// 设置页清空学习记录 / AI 词缓存的结果
+    ClearResult { ok: bool, message: String },
+    /// 插件后台任务（fetch 等）有结果，回到主线程交给插件
+    PluginWake,
+}
+
+/// 有外部订阅者时推送候选（`stream` 接口，与候选窗后端无关）
+pub(crate) fn publish_update(
+    raw: &str, candidates: &[String], page_info: Option<(usize, usize)>,
+    sentence: bool, frequency: &[f32], selected: usize,
+) {
+    if !crate::stream::has_subscribers() { return; }
+    let (page, total_pages) = page_info.unwrap_or((1, 1));
+    let msg = ImeUpdateMsg {
+        msg_type: "show_ime",
+        raw: raw.to_string(),
+        candidates,
+        page,
+        total_pages,
+        sentence,
+        frequency,
+        selected,
+        max_width: 0,
+        opacity: u8::MAX,
+    };
+    if let Ok(json) = serde_json::to_string(&msg) {
+        crate::stream::publish(json);
+    }
 }
 
+/// 输入结束: 没有订阅者也要作废 `stream` 的快照，之后连上的客户端不会收到已结束的输入
+pub(crate) fn publish_hide() {
+    let json = crate::stream::has_subscribers()
+        .then(|| serde_json::to_string(&HideMsg { msg_type: "hide" }).ok())
+        .flatten();
+    crate::stream::publish_end(json);
+}
+
+#[derive(Clone)]
 pub struct WebViewUI {
     proxy: EventLoopProxy<ImeEvent>,
     hwnd: HWND,
@@ -84,6 +251,9 @@ impl WebViewUI {
             raw: String::new(),
             candidates: candidates.iter().map(|s| s.to_string()).collect(),
             page_info: None,
+            sentence: false,
+            frequency: vec![],
+            selected: 0,
         });
     }
 
@@ -92,31 +262,60 @@ impl WebViewUI {
             raw: raw.to_string(),
             candidates: vec![],
             page_info: None,
+            sentence: false,
+            frequency: vec![],
+            selected: 0,
         });
     }
 
-    pub fn update_candidates_with_page(&self, raw: &str, candidates: &[&str], page_info: Option<(usize, usize)>) {
+    pub fn open_settings(&self) {
+        let _ = self.proxy.send_event(ImeEvent::ShowSettings);
+    }
+
+    /// 插件后台任务的唤醒回调: 在主线程执行 `PluginSystem::pump`
+    pub fn plugin_waker(&self) -> crate::plugin_system::WakeFn {
+        let proxy = self.proxy.clone();
+        Box::new(move || { let _ = proxy.send_event(ImeEvent::PluginWake); })
+    }
+
+    /// 请求主线程重装键盘钩子（LL 钩子必须由带消息循环的线程安装）
+    pub fn reinstall_hook(&self) {
+        let _ = self.proxy.send_event(ImeEvent::ReinstallHook);
+    }
+}
+
+impl CandidateUi for WebViewUI {
+    fn update_candidates_with_page(
+        &self, raw: &str, candidates: &[&str], page_info: Option<(usize, usize)>,
+        sentence: bool, frequency: &[f32], selected: usize,
+    ) {
+        let candidates: Vec<String> = candidates.iter().map(|s| s.to_string()).collect();
+        publish_update(raw, &candidates, page_info, sentence, frequency, selected);
         let _ = self.proxy.send_event(ImeEvent::UpdateCandidates {
             raw: raw.to_string(),
-            candidates: candidates.iter().map(|s| s.to_string()).collect(),
+            candidates,
             page_info,
+            sentence,
+            frequency: frequency.to_vec(),
+            selected,
         });
     }
 
-    pub fn set_plugins_active(&self, active: bool) {
+    fn set_plugins_active(&self, active: bool) {
         let _ = self.proxy.send_event(ImeEvent::PluginsActive(active));
     }
 
-    pub fn hide(&self) {
-        let _ = self.proxy.send_event(ImeEvent::Hide);
+    fn set_engine_mode(&self, ai: bool, available: bool) {
+        let _ = self.proxy.send_event(ImeEvent::EngineMode { ai, available });
     }
 
-    pub fn show(&self, x: i32, y: i32) {
-        let _ = self.proxy.send_event(ImeEvent::ShowAt(x, y));
+    fn hide(&self) {
+        publish_hide();
+        let _ = self.proxy.send_event(ImeEvent::Hide);
     }
 
-    pub fn open_settings(&self) {
-        let _ = self.proxy.send_event(ImeEvent::ShowSettings);
+    fn show(&self, x: i32, y: i32, caret: bool) {
+        let _ = self.proxy.send_event(ImeEvent::ShowAt(x, y, caret));
     }
 }
 
@@ -143,6 +342,18 @@ pub fn run_webview_loop(
     unsafe {
         let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
         SetWindowLongW(hwnd, GWL_EXSTYLE, ex_style | (WS_EX_LAYERED | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE).0 as i32);
+        start_click_through(hwnd.0 as isize);
+
+        // style.css `--shadow: none` → 关闭 DWM 非客户区渲染（连同窗口阴影）
+        if !crate::settings::window_shadow() {
+            use windows::Win32::Graphics::Dwm::{DwmSetWindowAttribute, DWMNCRP_DISABLED, DWMWA_NCRENDERING_POLICY};
+            let policy = DWMNCRP_DISABLED;
+            let _ = DwmSetWindowAttribute(
+                hwnd, DWMWA_NCRENDERING_POLICY,
+                &policy as *const _ as *const std::ffi::c_void,
+                std::mem::size_of_val(&policy) as u32,
+            );
+        }
     }
 
     // JS 初始化脚本注入配置和 ai_port
@@ -192,12 +403,69 @@ pub fn run_webview_loop(
                                 crate::settings::toggle_plugin(name, enabled);
                             }
                         }
+                        "set_autostart" => {
+                            let enabled = data["enabled"].as_bool().unwrap_or(false);
+                            match crate::autostart::set_enabled(enabled) {
+                                Ok(()) => {
+                                    if let Err(e) = crate::config::persist_value("engine", "autostart", &enabled.to_string()) {
+                                        eprintln!("[Settings] ⚠ 写回 engine.autostart 失败: {}", e);
+                                    }
+                                }
+                                Err(e) => eprintln!("[Autostart] ⚠ {}", e),
+                            }
+                        }
+                        "toggle_dict" => {
+                            if let Some(name) = data["name"].as_str() {
+                                let enabled = data["enabled"].as_bool().unwrap_or(true);
+                                crate::pinyin::set_dict_enabled(name, enabled);
+                            }
+                        }
                         "delete_plugin" => {
                             if let Some(name) = data["name"].as_str() {
                                 crate::settings::delete_plugin(name);
                             }
                         }
+                        "test_ai_connection" => {
+                            let endpoint = data["endpoint"].as_str().unwrap_or("").to_string();
+                            let api_key = data["api_key"].as_str().unwrap_or("").to_string();
+                            let proxy = proxy.clone();
+                            std::thread::spawn(move || {
+                                let (ok, message) = match crate::settings::test_ai_connection(&endpoint, &api_key) {
+                                    Ok(m) => (true, m),
+                                    Err(m) => (false, m),
+                                };
+                                let _ = proxy.send_event(ImeEvent::AiTestResult { ok, message });
+                            });
+                        }
+                        "export_userdict" => {
+                            if let Some(json) = unsafe { crate::export_user_dict() } {
+                                let _ = proxy.send_event(ImeEvent::UserDictExport(json));
+                            }
+                        }
+                        "import_userdict" => {
+                            let json = data["json"].as_str().unwrap_or("");
+                            let (ok, message) = match unsafe { crate::import_user_dict(json) } {
+                                Ok(n) => (true, format!("已合并 {} 条", n)),
+                                Err(e) => (false, e),
+                            };
+                            let _ = proxy.send_event(ImeEvent::UserDictImport { ok, message });
+                        }
+                        "clear_userdict" | "clear_ai_cache" => {
+                            let (result, what) = if action == "clear_userdict" {
+                                (unsafe { crate::clear_user_dict() }, "学习记录")
+                            } else {
+                                (unsafe { crate::clear_ai_cache() }, "AI 词缓存")
+                            };
+                            let (ok, message) = match result {
+                                Ok(n) => (true, format!("已清空{} {} 条", what, n)),
+                                Err(e) => (false, e),
+                            };
+                            let _ = proxy.send_event(ImeEvent::ClearResult { ok, message });
+                        }
                         "layout_update" => {
+                            if let Some(hit) = data["hit"].as_array() {
+                                set_hit_map(Some(parse_hit_map(hit)));
+                            }
                             if let (Some(w), Some(h)) = (data["width"].as_f64(), data["height"].as_f64()) {
                                 let _ = proxy.send_event(ImeEvent::LayoutUpdate { width: w, height: h });
                             }
@@ -207,20 +475,37 @@ pub fn run_webview_loop(
                                 let _ = proxy.send_event(ImeEvent::DragWindow { dx, dy });
                             }
                         }
+                        "drag_end" => {
+                            let _ = proxy.send_event(ImeEvent::DragEnd);
+                        }
                         _ => {}
                     }
                 }
                 Err(e) => eprintln!("[WebView UI] IPC parse error: {}", e),
             }
         })
-        .build(&window)?;
-
-    // Keep it alive
-    let _webview_keep = webview;
+        .build(&window);
+
+    // WebView2 创建失败（运行时缺失等）: 候选窗退回 GDI，事件循环照常运行（钩子、插件、热重载），设置页不可用
+    let has_webview = webview.is_ok();
+    let webview = match webview {
+        Ok(w) => Some(w),
+        Err(e) => {
+            unsafe { crate::fallback_to_gdi(&e.to_string()); }
+            None
+        }
+    };
+    let post = move |json: &str| {
+        if let Some(w) = &webview {
+            let _ = w.evaluate_script(&format!("window.postMessage({}, '*');", json));
+        }
+    };
 
     // Track current position to enable dragging correctly
     let mut current_x: f64 = 0.0;
     let mut current_y: f64 = 0.0;
+    // 最近一次显示时的光标位置与所在显示器（光标没找到时为 None）
+    let mut anchor: Option<(i32, i32, String)> = None;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -228,9 +513,12 @@ pub fn run_webview_loop(
         match event {
             Event::UserEvent(ime_event) => {
                 match ime_event {
-                    ImeEvent::ShowAt(x, y) => {
-                        current_x = x as f64;
-                        current_y = y as f64;
+                    ImeEvent::ShowAt(x, y, caret) => {
+                        // 找到光标时叠加该显示器记住的偏移；光标位置留作拖动结束时计算新偏移的基准
+                        anchor = if caret { Some((x, y, unsafe { monitor_at(x, y) })) } else { None };
+                        let (dx, dy) = anchor.as_ref().map(|(_, _, m)| window_offset(m)).unwrap_or((0, 0));
+                        current_x = (x + dx) as f64;
+                        current_y = (y + dy) as f64;
                         window.set_outer_position(tao::dpi::LogicalPosition::new(current_x, current_y));
                         window.set_visible(true);
                     }
@@ -238,10 +526,10 @@ pub fn run_webview_loop(
                         window.set_visible(false);
                         let msg = HideMsg { msg_type: "hide" };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = _webview_keep.evaluate_script(&format!("window.postMessage({}, '*');", json));
+                            post(&json);
                         }
                     }
-                    ImeEvent::UpdateCandidates { raw, candidates, page_info } => {
+                    ImeEvent::UpdateCandidates { raw, candidates, page_info, sentence, frequency, selected } => {
                         let (page, total_pages) = page_info.unwrap_or((1, 1));
                         let msg = ImeUpdateMsg {
                             msg_type: "show_ime",
@@ -249,22 +537,33 @@ pub fn run_webview_loop(
                             candidates: &candidates,
                             page,
                             total_pages,
+                            sentence,
+                            frequency: &frequency,
+                            selected,
+                            max_width: MAX_WIDTH.load(Ordering::Relaxed),
+                            opacity: opacity(),
                         };
                         
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let script = format!("window.postMessage({}, '*');", json);
-                            let _ = _webview_keep.evaluate_script(&script);
+                            post(&json);
                             
                             // Rough estimation to expand window so JS flexbox doesn't wrap lines prematurely
                             // before the layout_update message computes the exact bounding box.
                             let est_w = 60.0 + (candidates.len() as f64 * 35.0);
-                            window.set_inner_size(tao::dpi::LogicalSize::new(est_w.min(1500.0), 80.0));
+                            let est_w = cap_width(est_w.min(1500.0), msg.max_width);
+                            window.set_inner_size(tao::dpi::LogicalSize::new(est_w, 80.0));
                         }
                     }
                     ImeEvent::ShowSettings => {
+                        if !has_webview {
+                            eprintln!("[WebView UI] ⚠ WebView 未能创建，设置页不可用（可直接编辑 config.toml）");
+                            return;
+                        }
+                        // 设置页整窗可点击
+                        set_hit_map(None);
                         let msg = ShowSettingsMsg { msg_type: "show_settings" };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = _webview_keep.evaluate_script(&format!("window.postMessage({}, '*');", json));
+                            post(&json);
                         }
                         // Center window and make it larger
                         window.set_inner_size(tao::dpi::LogicalSize::new(520.0, 720.0));
@@ -285,12 +584,20 @@ pub fn run_webview_loop(
                     ImeEvent::PluginsActive(active) => {
                         let msg = PluginsActiveMsg { msg_type: "plugins_active", active };
                         if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = _webview_keep.evaluate_script(&format!("window.postMessage({}, '*');", json));
+                            post(&json);
+                        }
+                    }
+                    ImeEvent::EngineMode { ai, available } => {
+                        let msg = EngineModeMsg { msg_type: "engine_mode", ai, available };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            post(&json);
                         }
                     }
                     ImeEvent::LayoutUpdate { width, height } => {
                         // Dynamically snap the tao window tightly to the content size
                         // This entirely removes any "white OS background" spillage since the window matches the UI bounds
+                        // 前端已按 max_width 截短候选，这里再兜底一次（主题脚本可能没处理）
+                        let width = cap_width(width, MAX_WIDTH.load(Ordering::Relaxed));
                         window.set_inner_size(tao::dpi::LogicalSize::new(width, height));
                         
                         // Detect and prevent right-edge overflow
@@ -308,6 +615,43 @@ pub fn run_webview_loop(
                         current_y += dy;
                         window.set_outer_position(tao::dpi::LogicalPosition::new(current_x, current_y));
                     }
+                    ImeEvent::DragEnd => {
+                        // 鼠标回退位置不可靠，不据此记偏移
+                        if let Some((x, y, monitor)) = &anchor {
+                            let offset = ((current_x - *x as f64).round() as i32, (current_y - *y as f64).round() as i32);
+                            remember_window_offset(monitor, offset);
+                        }
+                    }
+                    ImeEvent::ReinstallHook => unsafe {
+                        crate::reinstall_keyboard_hook();
+                    },
+                    ImeEvent::PluginWake => unsafe {
+                        crate::pump_plugins();
+                    },
+                    ImeEvent::AiTestResult { ok, message } => {
+                        let msg = ResultMsg { msg_type: "ai_test_result", ok, message: &message };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            post(&json);
+                        }
+                    }
+                    ImeEvent::UserDictExport(data) => {
+                        let msg = UserDictExportMsg { msg_type: "userdict_export", data: &data };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            post(&json);
+                        }
+                    }
+                    ImeEvent::UserDictImport { ok, message } => {
+                        let msg = ResultMsg { msg_type: "userdict_import", ok, message: &message };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            post(&json);
+                        }
+                    }
+                    ImeEvent::ClearResult { ok, message } => {
+                        let msg = ResultMsg { msg_type: "clear_result", ok, message: &message };
+                        if let Ok(json) = serde_json::to_string(&msg) {
+                            post(&json);
+                        }
+                    }
                 }
             }
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
@@ -315,14 +659,108 @@ pub fn run_webview_loop(
                 window.set_visible(false);
                 let msg = HideMsg { msg_type: "hide" };
                 if let Ok(json) = serde_json::to_string(&msg) {
-                    let _ = _webview_keep.evaluate_script(&format!("window.postMessage({}, '*');", json));
+                    post(&json);
                 }
             }
+            Event::WindowEvent { event: WindowEvent::Destroyed, .. } => {
+                // 窗口被系统销毁（WM_DESTROY）→ 整个进程退出
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::LoopDestroyed => unsafe {
+                crate::shutdown();
+            },
             _ => {}
         }
     });
 }
 
+// ============================================================
+// 点击穿透
+// ============================================================
+//
+// 输入条上只有候选项和左上角的拖动手柄响应鼠标，其余区域点击穿透给下面的应用:
+//
+//   区域                      命中
+//   候选项 (.candidate)       可点击
+//   拖动手柄 (#drag-grip)     可点击，按住拖动窗口
+//   拼音、页码、边距等        穿透
+//   设置页                    整窗可点击
+//
+// 输入条的内容画在 WebView2 子窗口里，顶层窗口的 WM_NCHITTEST 管不到，
+// 所以由后台线程按光标位置切换 WS_EX_TRANSPARENT（分层窗口带此样式时整窗不接收鼠标）。
+// 可点击区域由 script.js 随 layout_update 上报。
+
+/// 光标位置检查间隔
+const CLICK_THROUGH_POLL: std::time::Duration = std::time::Duration::from_millis(30);
+
+/// 可点击区域（客户区物理像素 [x, y, w, h]）；None = 整窗可点击（设置页 / 尚未收到布局）
+static HIT_MAP: Mutex<Option<Vec<[f64; 4]>>> = Mutex::new(None);
+
+fn set_hit_map(map: Option<Vec<[f64; 4]>>) {
+    if let Ok(mut m) = HIT_MAP.lock() { *m = map; }
+}
+
+/// 解析 script.js 上报的 `hit: [[x, y, w, h], ...]`，格式不对的项丢弃
+fn parse_hit_map(hit: &[serde_json::Value]) -> Vec<[f64; 4]> {
+    hit.iter()
+        .filter_map(|r| {
+            let r = r.as_array()?;
+            let v: Vec<f64> = r.iter().filter_map(|x| x.as_f64()).collect();
+            (v.len() == 4).then(|| [v[0], v[1], v[2], v[3]])
+        })
+        .collect()
+}
+
+/// 客户区坐标 (x, y) 是否落在可点击区域
+fn hit_interactive(map: Option<&[[f64; 4]]>, x: f64, y: f64) -> bool {
+    match map {
+        None => true,
+        Some(rects) => rects.iter().any(|&[rx, ry, rw, rh]| {
+            x >= rx && x < rx + rw && y >= ry && y < ry + rh
+        }),
+    }
+}
+
+/// 启动点击穿透线程（窗口销毁后退出）
+fn start_click_through(hwnd: isize) {
+    use windows::Win32::Graphics::Gdi::ClientToScreen;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_LBUTTON};
+
+    std::thread::spawn(move || {
+        let hwnd = HWND(hwnd as *mut _);
+        let mut transparent = false;
+        loop {
+            std::thread::sleep(CLICK_THROUGH_POLL);
+            unsafe {
+                if !IsWindow(hwnd).as_bool() { break; }
+                if !IsWindowVisible(hwnd).as_bool() { continue; }
+                // 按住左键时（拖动中）保持不变，避免光标滑出手柄后丢掉拖动
+                if GetAsyncKeyState(VK_LBUTTON.0 as i32) < 0 { continue; }
+
+                let mut cursor = POINT::default();
+                let mut origin = POINT::default();
+                if GetCursorPos(&mut cursor).is_err() || !ClientToScreen(hwnd, &mut origin).as_bool() {
+                    continue;
+                }
+                let want = match HIT_MAP.lock() {
+                    Ok(map) => !hit_interactive(
+                        map.as_deref(),
+                        (cursor.x - origin.x) as f64,
+                        (cursor.y - origin.y) as f64,
+                    ),
+                    Err(_) => false,
+                };
+                if want != transparent {
+                    let ex_style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+                    let flag = WS_EX_TRANSPARENT.0 as i32;
+                    SetWindowLongW(hwnd, GWL_EXSTYLE, if want { ex_style | flag } else { ex_style & !flag });
+                    transparent = want;
+                }
+            }
+        }
+    });
+}
+
 // ============================================================
 // 辅助常量和函数
 // ============================================================
@@ -350,3 +788,34 @@ fn mime_type(path: &str) -> &'static str {
     else if path.ends_with(".woff2") { "font/woff2" }
     else { "application/octet-stream" }
 }
+
+// ============================================================
+// 测试
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_map() {
+        let hit = serde_json::json!([[0, 0, 10, 20], [30.5, 0, 20, 20], [1, 2], "x"]);
+        let rects = parse_hit_map(hit.as_array().unwrap());
+        assert_eq!(rects, vec![[0.0, 0.0, 10.0, 20.0], [30.5, 0.0, 20.0, 20.0]]);
+
+        assert!(hit_interactive(Some(&rects), 5.0, 5.0));
+        assert!(hit_interactive(Some(&rects), 31.0, 19.0));
+        // 两个候选之间的空隙 / 下边缘 → 穿透
+        assert!(!hit_interactive(Some(&rects), 20.0, 5.0));
+        assert!(!hit_interactive(Some(&rects), 5.0, 20.0));
+        // 未上报区域（设置页）整窗可点击
+        assert!(hit_interactive(None, 20.0, 5.0));
+    }
+
+    #[test]
+    fn test_cap_width() {
+        assert_eq!(cap_width(1200.0, 0), 1200.0);
+        assert_eq!(cap_width(1200.0, 800), 800.0);
+        assert_eq!(cap_width(300.0, 800), 300.0);
+    }
+}
@@ -1,3 +1,11 @@
+// style.css 中的边框设置（--border-color / --border-width）
+(() => {
+    const style = (window.__INIT_CONFIG__ || {}).style || {};
+    const root = document.documentElement.style;
+    if (style.border_color) root.setProperty('--border-color', style.border_color);
+    if (style.border_width) root.setProperty('--border-width', style.border_width);
+})();
+
 window.addEventListener('message', (e) => {
     const data = e.data;
     if (data.type === 'show_ime') {
@@ -11,7 +19,7 @@ window.addEventListener('message', (e) => {
 
         data.candidates.forEach((cand, idx) => {
             const el = document.createElement('div');
-            el.className = 'candidate' + (idx === 0 ? ' selected' : '');
+            el.className = 'candidate' + (idx === (data.selected || 0) ? ' selected' : '');
 
             const spanIdx = document.createElement('span');
             spanIdx.className = 'cand-idx';
@@ -23,6 +31,22 @@ window.addEventListener('message', (e) => {
 
             el.appendChild(spanIdx);
             el.appendChild(spanText);
+
+            // 外部接口给出的整句建议
+            if (idx === 0 && data.sentence) {
+                const tag = document.createElement('span');
+                tag.className = 'cand-tag';
+                tag.textContent = '整句';
+                el.appendChild(tag);
+            }
+
+            // 本页内的相对词频（ui.show_frequency）
+            if (data.frequency && data.frequency[idx] !== undefined) {
+                const bar = document.createElement('span');
+                bar.className = 'cand-freq';
+                bar.style.width = Math.round(data.frequency[idx] * 100) + '%';
+                el.appendChild(bar);
+            }
             candsDiv.appendChild(el);
         });
 
@@ -33,17 +57,33 @@ window.addEventListener('message', (e) => {
             candsDiv.appendChild(pi);
         }
 
+        fitMaxWidth(data.max_width);
+        // ui.opacity（0-255，不透明时不发送）
+        document.getElementById('ime-bar').style.opacity = (data.opacity ?? 255) / 255;
+
         // Delay slightly to let the browser compute layout, then report bounds to Rust
         setTimeout(() => {
             const bar = document.getElementById('ime-bar');
             const rect = bar.getBoundingClientRect();
+            // 可点击区域（物理像素），其余部分点击穿透给下面的应用
+            const dpr = window.devicePixelRatio || 1;
+            const hit = [...document.querySelectorAll('.candidate, #drag-grip')].map((el) => {
+                const r = el.getBoundingClientRect();
+                return [r.left * dpr, r.top * dpr, r.width * dpr, r.height * dpr];
+            });
             window.chrome.webview.postMessage(JSON.stringify({
                 action: 'layout_update',
                 width: Math.ceil(rect.width) + 1,
-                height: Math.ceil(rect.height) + 1
+                height: Math.ceil(rect.height) + 1,
+                hit: hit
             }));
         }, 10);
 
+    } else if (data.type === 'engine_mode') {
+        // 热键切换 AI / 字典优先后的模式标记（输入条显示时可见）
+        const badge = document.getElementById('engine-mode');
+        badge.textContent = !data.available ? 'AI 不可用' : (data.ai ? 'AI' : '词库');
+        badge.className = data.available ? '' : 'unavailable';
     } else if (data.type === 'show_settings') {
         document.getElementById('ime-bar').style.display = 'none';
         document.getElementById('settings-panel').style.display = 'block';
@@ -55,21 +95,41 @@ window.addEventListener('message', (e) => {
     }
 });
 
-// Drag support
+// ui.max_width_px: 输入条超宽时逐字截短最长的候选并加省略号，直到放得下
+// 只改显示，上屏的仍是 Rust 侧保存的完整候选；悬停可看完整文字
+function fitMaxWidth(maxWidth) {
+    if (!maxWidth) return;
+    const bar = document.getElementById('ime-bar');
+    const texts = [...document.querySelectorAll('.cand-text')];
+    texts.forEach((el) => { el.title = el.textContent; });
+    const shown = (el) => [...el.textContent.replace(/…$/, '')];
+    while (texts.length && bar.getBoundingClientRect().width > maxWidth) {
+        const longest = texts.reduce((a, b) => (shown(b).length > shown(a).length ? b : a));
+        const chars = shown(longest);
+        if (chars.length <= 2) break;
+        longest.textContent = chars.slice(0, -1).join('') + '…';
+    }
+}
+
+// Drag support（只能按住左上角的手柄拖动，输入条其他地方点击穿透）
 let isDragging = false;
 let startX = 0;
 let startY = 0;
 
-document.getElementById('ime-bar').addEventListener('mousedown', (e) => {
+document.getElementById('drag-grip').addEventListener('mousedown', (e) => {
     isDragging = true;
     startX = e.screenX;
     startY = e.screenY;
-    document.getElementById('ime-bar').style.cursor = 'grabbing';
+    document.body.style.cursor = 'grabbing';
 });
 
 window.addEventListener('mouseup', () => {
+    if (isDragging) {
+        // 松手后由后端记住候选窗相对光标的偏移
+        window.chrome.webview.postMessage(JSON.stringify({ action: 'drag_end' }));
+    }
     isDragging = false;
-    document.getElementById('ime-bar').style.cursor = 'default';
+    document.body.style.cursor = '';
 });
 
 window.addEventListener('mousemove', (e) => {